@@ -0,0 +1,2 @@
+ALTER TABLE products DROP COLUMN IF EXISTS off_rev;
+ALTER TABLE products DROP COLUMN IF EXISTS last_verified_at;
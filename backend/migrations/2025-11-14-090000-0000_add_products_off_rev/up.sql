@@ -0,0 +1,9 @@
+ALTER TABLE products ADD COLUMN off_rev INTEGER;
+ALTER TABLE products ADD COLUMN last_verified_at TIMESTAMP DEFAULT NOW();
+
+-- Backfill from the stored OpenFoodFacts payload
+UPDATE products
+SET off_rev = (full_response->>'rev')::INTEGER
+WHERE jsonb_typeof(full_response->'rev') = 'number';
+
+UPDATE products SET last_verified_at = updated_at;
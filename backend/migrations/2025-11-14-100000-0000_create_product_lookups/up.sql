@@ -0,0 +1,7 @@
+CREATE TABLE product_lookups (
+    barcode VARCHAR(255) PRIMARY KEY,
+    found BOOLEAN NOT NULL,
+    checked_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_product_lookups_checked_at ON product_lookups(checked_at);
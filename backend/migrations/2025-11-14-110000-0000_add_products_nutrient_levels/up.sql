@@ -0,0 +1,5 @@
+ALTER TABLE products ADD COLUMN nutrient_levels JSONB;
+
+UPDATE products
+SET nutrient_levels = full_response->'nutrient_levels'
+WHERE jsonb_typeof(full_response->'nutrient_levels') = 'object';
@@ -0,0 +1,10 @@
+CREATE TABLE product_history (
+    id SERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    barcode VARCHAR(255) NOT NULL,
+    off_rev INTEGER,
+    full_response JSONB NOT NULL,
+    captured_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_product_history_barcode ON product_history(barcode, captured_at);
@@ -0,0 +1,2 @@
+ALTER TABLE products DROP COLUMN IF EXISTS labels;
+ALTER TABLE products DROP COLUMN IF EXISTS diet;
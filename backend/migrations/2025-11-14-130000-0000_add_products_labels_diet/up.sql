@@ -0,0 +1,10 @@
+ALTER TABLE products ADD COLUMN labels JSONB;
+ALTER TABLE products ADD COLUMN diet JSONB;
+
+-- Backfill labels from the stored payload; diet is computed on the next refresh
+UPDATE products
+SET labels = (
+    SELECT COALESCE(jsonb_agg(DISTINCT regexp_replace(lower(tag), '^[a-z]+:', '')), '[]'::jsonb)
+    FROM jsonb_array_elements_text(full_response->'labels_tags') AS tag
+)
+WHERE jsonb_typeof(full_response->'labels_tags') = 'array';
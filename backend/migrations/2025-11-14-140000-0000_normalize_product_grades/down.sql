@@ -0,0 +1,2 @@
+-- Normalization is not reversible; the original values remain in full_response
+SELECT 1;
@@ -0,0 +1,16 @@
+-- Grades are stored lowercase ("a"-"e" or "unknown"); OFF's "not-applicable" maps to "unknown"
+UPDATE products
+SET nutriscore_grade = CASE
+        WHEN lower(trim(nutriscore_grade)) IN ('a', 'b', 'c', 'd', 'e', 'unknown') THEN lower(trim(nutriscore_grade))
+        WHEN lower(trim(nutriscore_grade)) = 'not-applicable' THEN 'unknown'
+        ELSE NULL
+    END
+WHERE nutriscore_grade IS NOT NULL;
+
+UPDATE products
+SET ecoscore_grade = CASE
+        WHEN lower(trim(ecoscore_grade)) IN ('a', 'b', 'c', 'd', 'e', 'unknown') THEN lower(trim(ecoscore_grade))
+        WHEN lower(trim(ecoscore_grade)) = 'not-applicable' THEN 'unknown'
+        ELSE NULL
+    END
+WHERE ecoscore_grade IS NOT NULL;
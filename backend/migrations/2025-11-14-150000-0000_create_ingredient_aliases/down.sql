@@ -0,0 +1,2 @@
+-- Seeded canonical ingredients are left in place; they may be referenced elsewhere by now
+DROP TABLE IF EXISTS ingredient_aliases;
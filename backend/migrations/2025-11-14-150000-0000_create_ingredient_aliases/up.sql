@@ -0,0 +1,30 @@
+-- Synonyms that resolve to one canonical ingredient. Aliases are stored trimmed and lowercase.
+CREATE TABLE ingredient_aliases (
+    alias VARCHAR(500) PRIMARY KEY,
+    ingredient_id INTEGER NOT NULL REFERENCES ingredients(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_ingredient_aliases_ingredient_id ON ingredient_aliases(ingredient_id);
+
+-- Seed well-known synonyms, creating the canonical ingredients if they don't exist yet
+INSERT INTO ingredients (name)
+SELECT canonical
+FROM (VALUES ('Vitamin C'), ('Salt'), ('Sugar'), ('Water'), ('Vitamin E'), ('Baking Soda')) AS seed(canonical)
+WHERE NOT EXISTS (SELECT 1 FROM ingredients WHERE LOWER(name) = LOWER(seed.canonical));
+
+INSERT INTO ingredient_aliases (alias, ingredient_id)
+SELECT seed.alias, (SELECT id FROM ingredients WHERE LOWER(name) = LOWER(seed.canonical) ORDER BY id LIMIT 1)
+FROM (VALUES
+    ('ascorbic acid', 'Vitamin C'),
+    ('l-ascorbic acid', 'Vitamin C'),
+    ('sodium chloride', 'Salt'),
+    ('sea salt', 'Salt'),
+    ('sucrose', 'Sugar'),
+    ('cane sugar', 'Sugar'),
+    ('aqua', 'Water'),
+    ('tocopherol', 'Vitamin E'),
+    ('sodium bicarbonate', 'Baking Soda'),
+    ('bicarbonate of soda', 'Baking Soda')
+) AS seed(alias, canonical)
+ON CONFLICT (alias) DO NOTHING;
@@ -0,0 +1,11 @@
+DROP INDEX IF EXISTS idx_ingredients_gram_protein;
+DROP INDEX IF EXISTS idx_ingredients_gram_carbs;
+DROP INDEX IF EXISTS idx_ingredients_gram_fat;
+DROP INDEX IF EXISTS idx_ingredients_gram_fiber;
+
+ALTER TABLE ingredients
+    ALTER COLUMN gram_protein_per_gram TYPE DOUBLE PRECISION,
+    ALTER COLUMN gram_carbs_per_gram TYPE DOUBLE PRECISION,
+    ALTER COLUMN gram_fat_per_gram TYPE DOUBLE PRECISION,
+    ALTER COLUMN gram_fiber_per_gram TYPE DOUBLE PRECISION,
+    ALTER COLUMN gram_trans_fat_per_gram TYPE DOUBLE PRECISION;
@@ -0,0 +1,14 @@
+-- The schema and models read macros as Float4; the columns were created as double precision,
+-- which fails to decode when loading ingredients. Store them as REAL to match.
+ALTER TABLE ingredients
+    ALTER COLUMN gram_protein_per_gram TYPE REAL,
+    ALTER COLUMN gram_carbs_per_gram TYPE REAL,
+    ALTER COLUMN gram_fat_per_gram TYPE REAL,
+    ALTER COLUMN gram_fiber_per_gram TYPE REAL,
+    ALTER COLUMN gram_trans_fat_per_gram TYPE REAL;
+
+-- Range filters on macros
+CREATE INDEX idx_ingredients_gram_protein ON ingredients(gram_protein_per_gram);
+CREATE INDEX idx_ingredients_gram_carbs ON ingredients(gram_carbs_per_gram);
+CREATE INDEX idx_ingredients_gram_fat ON ingredients(gram_fat_per_gram);
+CREATE INDEX idx_ingredients_gram_fiber ON ingredients(gram_fiber_per_gram);
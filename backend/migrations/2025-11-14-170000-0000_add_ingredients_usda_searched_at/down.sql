@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_ingredients_usda_searched_at;
+ALTER TABLE ingredients DROP COLUMN IF EXISTS usda_searched_at;
@@ -0,0 +1,4 @@
+-- When USDA was last searched for this ingredient, so backfills can skip recent attempts
+ALTER TABLE ingredients ADD COLUMN usda_searched_at TIMESTAMP;
+
+CREATE INDEX idx_ingredients_usda_searched_at ON ingredients(usda_searched_at);
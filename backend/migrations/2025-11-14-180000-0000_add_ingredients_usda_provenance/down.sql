@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_ingredients_fdc_id;
+ALTER TABLE ingredients DROP COLUMN IF EXISTS usda_food;
+ALTER TABLE ingredients DROP COLUMN IF EXISTS fdc_id;
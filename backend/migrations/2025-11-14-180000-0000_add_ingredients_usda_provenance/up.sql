@@ -0,0 +1,5 @@
+-- Which USDA FoodData Central food the macros came from, and its raw payload for auditing
+ALTER TABLE ingredients ADD COLUMN fdc_id INTEGER;
+ALTER TABLE ingredients ADD COLUMN usda_food JSONB;
+
+CREATE INDEX idx_ingredients_fdc_id ON ingredients(fdc_id);
@@ -0,0 +1,4 @@
+-- Which product source (see PRODUCT_SOURCES) supplied the data; everything stored so far came from OpenFoodFacts
+ALTER TABLE products ADD COLUMN data_source VARCHAR(64);
+
+UPDATE products SET data_source = 'openfoodfacts';
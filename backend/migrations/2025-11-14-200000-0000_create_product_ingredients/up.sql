@@ -0,0 +1,14 @@
+-- Which ingredients a product contains, in label order, and roughly how much of each.
+-- percent_source records where percent_estimate came from: OFF's declared 'percent',
+-- OFF's computed 'percent_estimate', or our own 'rank'-based fallback.
+CREATE TABLE product_ingredients (
+    product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    ingredient_id INTEGER NOT NULL REFERENCES ingredients(id) ON DELETE CASCADE,
+    rank INTEGER NOT NULL,
+    percent_estimate REAL,
+    percent_source VARCHAR(16),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (product_id, ingredient_id)
+);
+
+CREATE INDEX idx_product_ingredients_ingredient_id ON product_ingredients(ingredient_id);
@@ -0,0 +1,2 @@
+ALTER TABLE products DROP COLUMN IF EXISTS allergen_tags;
+ALTER TABLE products DROP COLUMN IF EXISTS trace_tags;
@@ -0,0 +1,18 @@
+-- Normalized allergen slugs ("peanuts") next to the raw `allergens` text, and the
+-- "may contain" traces OFF lists separately
+ALTER TABLE products ADD COLUMN allergen_tags JSONB;
+ALTER TABLE products ADD COLUMN trace_tags JSONB;
+
+UPDATE products
+SET allergen_tags = (
+    SELECT COALESCE(jsonb_agg(DISTINCT regexp_replace(lower(trim(tag)), '^[a-z]+:', '')), '[]'::jsonb)
+    FROM jsonb_array_elements_text(full_response->'allergens_tags') AS tag
+)
+WHERE jsonb_typeof(full_response->'allergens_tags') = 'array';
+
+UPDATE products
+SET trace_tags = (
+    SELECT COALESCE(jsonb_agg(DISTINCT regexp_replace(lower(trim(tag)), '^[a-z]+:', '')), '[]'::jsonb)
+    FROM jsonb_array_elements_text(full_response->'traces_tags') AS tag
+)
+WHERE jsonb_typeof(full_response->'traces_tags') = 'array';
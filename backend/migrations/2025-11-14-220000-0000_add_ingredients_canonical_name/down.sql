@@ -0,0 +1,3 @@
+-- Merged duplicates are not restored
+DROP INDEX IF EXISTS idx_ingredients_canonical_name;
+ALTER TABLE ingredients DROP COLUMN IF EXISTS canonical_name;
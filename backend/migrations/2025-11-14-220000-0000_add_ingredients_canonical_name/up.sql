@@ -0,0 +1,56 @@
+-- Dedup key for ingredients: "Sugar", "sugar " and "SUGAR\t" all become "sugar".
+-- Lookups must canonicalize their input with the same expression.
+ALTER TABLE ingredients ADD COLUMN canonical_name VARCHAR(500) NOT NULL
+    GENERATED ALWAYS AS (lower(btrim(regexp_replace(name, '\s+', ' ', 'g')))) STORED;
+
+-- One survivor per canonical name: prefer rows that have USDA data, then the oldest
+CREATE TEMP TABLE ingredient_duplicates AS
+SELECT id AS duplicate_id, survivor_id
+FROM (
+    SELECT
+        id,
+        first_value(id) OVER (
+            PARTITION BY canonical_name
+            ORDER BY (fdc_id IS NULL AND gram_protein_per_gram IS NULL), id
+        ) AS survivor_id
+    FROM ingredients
+) ranked
+WHERE id <> survivor_id;
+
+-- Point everything that referenced a duplicate at its survivor
+UPDATE ingredient_aliases a
+SET ingredient_id = d.survivor_id
+FROM ingredient_duplicates d
+WHERE a.ingredient_id = d.duplicate_id;
+
+WITH remapped AS (
+    DELETE FROM product_ingredients pi
+    USING ingredient_duplicates d
+    WHERE pi.ingredient_id = d.duplicate_id
+    RETURNING pi.product_id, d.survivor_id, pi.rank, pi.percent_estimate, pi.percent_source, pi.created_at
+)
+INSERT INTO product_ingredients (product_id, ingredient_id, rank, percent_estimate, percent_source, created_at)
+SELECT DISTINCT ON (product_id, survivor_id) product_id, survivor_id, rank, percent_estimate, percent_source, created_at
+FROM remapped
+ORDER BY product_id, survivor_id, rank
+ON CONFLICT (product_id, ingredient_id) DO NOTHING;
+
+UPDATE ingredients i
+SET sub_ingredients = ARRAY(
+        SELECT DISTINCT COALESCE(d.survivor_id, sub)
+        FROM unnest(i.sub_ingredients) AS sub
+        LEFT JOIN ingredient_duplicates d ON d.duplicate_id = sub
+    ),
+    parent_ingredients = ARRAY(
+        SELECT DISTINCT COALESCE(d.survivor_id, parent)
+        FROM unnest(i.parent_ingredients) AS parent
+        LEFT JOIN ingredient_duplicates d ON d.duplicate_id = parent
+    )
+WHERE i.sub_ingredients && ARRAY(SELECT duplicate_id FROM ingredient_duplicates)
+    OR i.parent_ingredients && ARRAY(SELECT duplicate_id FROM ingredient_duplicates);
+
+DELETE FROM ingredients WHERE id IN (SELECT duplicate_id FROM ingredient_duplicates);
+
+DROP TABLE ingredient_duplicates;
+
+CREATE UNIQUE INDEX idx_ingredients_canonical_name ON ingredients(canonical_name);
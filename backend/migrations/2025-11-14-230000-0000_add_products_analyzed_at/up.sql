@@ -0,0 +1,2 @@
+-- When AnalyzeIngredientsJob last completed for the product
+ALTER TABLE products ADD COLUMN analyzed_at TIMESTAMP;
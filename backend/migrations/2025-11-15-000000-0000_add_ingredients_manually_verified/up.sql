@@ -0,0 +1,2 @@
+-- Set when a curator edits an ingredient by hand; enrichment jobs leave such ingredients alone
+ALTER TABLE ingredients ADD COLUMN manually_verified BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_products_brand_tags;
+ALTER TABLE products DROP COLUMN IF EXISTS brand_tags;
@@ -0,0 +1,25 @@
+-- Normalized brand slugs ("thai-kitchen") for filtering and facets; `brands` keeps
+-- OFF's comma-separated display string
+ALTER TABLE products ADD COLUMN brand_tags JSONB;
+
+UPDATE products
+SET brand_tags = (
+    SELECT COALESCE(jsonb_agg(DISTINCT regexp_replace(lower(trim(tag)), '^[a-z]+:', '')), '[]'::jsonb)
+    FROM jsonb_array_elements_text(full_response->'brands_tags') AS tag
+    WHERE trim(tag) <> ''
+)
+WHERE jsonb_typeof(full_response->'brands_tags') = 'array';
+
+-- Products OFF sent without brands_tags: slugify the display string
+UPDATE products
+SET brand_tags = (
+    SELECT COALESCE(jsonb_agg(DISTINCT slug), '[]'::jsonb)
+    FROM (
+        SELECT btrim(regexp_replace(lower(entry), '[^[:alnum:]]+', '-', 'g'), '-') AS slug
+        FROM unnest(string_to_array(brands, ',')) AS entry
+    ) slugs
+    WHERE slug <> ''
+)
+WHERE brand_tags IS NULL AND brands IS NOT NULL;
+
+CREATE INDEX idx_products_brand_tags ON products USING GIN (brand_tags);
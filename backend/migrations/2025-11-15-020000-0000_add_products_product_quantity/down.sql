@@ -0,0 +1,2 @@
+ALTER TABLE products DROP COLUMN IF EXISTS product_quantity_unit;
+ALTER TABLE products DROP COLUMN IF EXISTS product_quantity;
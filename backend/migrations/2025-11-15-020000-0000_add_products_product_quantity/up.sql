@@ -0,0 +1,12 @@
+-- OFF's numeric package size next to the display `quantity` ("330 ml"), so scaling
+-- doesn't depend on parsing free text
+ALTER TABLE products ADD COLUMN product_quantity DOUBLE PRECISION;
+ALTER TABLE products ADD COLUMN product_quantity_unit VARCHAR(8);
+
+UPDATE products
+SET product_quantity = replace(btrim(full_response->>'product_quantity'), ',', '.')::double precision,
+    product_quantity_unit = COALESCE(NULLIF(lower(btrim(full_response->>'product_quantity_unit')), ''), 'g')
+WHERE btrim(full_response->>'product_quantity') ~ '^[0-9]+([.,][0-9]+)?$'
+  AND replace(btrim(full_response->>'product_quantity'), ',', '.')::double precision > 0
+  AND COALESCE(NULLIF(lower(btrim(full_response->>'product_quantity_unit')), ''), 'g')
+      IN ('mg', 'g', 'gr', 'kg', 'ml', 'cl', 'dl', 'l', 'oz', 'lb');
@@ -0,0 +1,11 @@
+-- Compressed payloads can't be turned back into JSONB in SQL, so refuse rather than lose them
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM products WHERE full_response IS NULL) THEN
+        RAISE EXCEPTION 'products has compressed full_response_gz rows; delete or re-fetch them before reverting';
+    END IF;
+END $$;
+
+ALTER TABLE products DROP CONSTRAINT IF EXISTS products_full_response_stored;
+ALTER TABLE products ALTER COLUMN full_response SET NOT NULL;
+ALTER TABLE products DROP COLUMN IF EXISTS full_response_gz;
@@ -0,0 +1,6 @@
+-- Gzip-compressed OFF payload, written instead of full_response when COMPRESS_FULL_RESPONSE is on.
+-- Existing rows keep their uncompressed JSONB; every row has exactly one of the two.
+ALTER TABLE products ADD COLUMN full_response_gz BYTEA;
+ALTER TABLE products ALTER COLUMN full_response DROP NOT NULL;
+ALTER TABLE products ADD CONSTRAINT products_full_response_stored
+    CHECK ((full_response IS NULL) <> (full_response_gz IS NULL));
@@ -0,0 +1,6 @@
+-- Set once CreateIngredientJob has enqueued an ingredient's sub-ingredients, so a retried
+-- job resumes there instead of enqueueing them a second time
+ALTER TABLE ingredients ADD COLUMN sub_ingredients_processed BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Jobs for existing ingredients have long since finished, one way or another
+UPDATE ingredients SET sub_ingredients_processed = TRUE;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_ingredients_needs_review;
+ALTER TABLE ingredients DROP COLUMN IF EXISTS needs_review;
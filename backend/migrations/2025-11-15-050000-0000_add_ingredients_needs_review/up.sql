@@ -0,0 +1,5 @@
+-- Set when USDA's best match for an ingredient was too weak to take its macros from,
+-- so a curator can pick the right food. Cleared by a confident match or a manual edit.
+ALTER TABLE ingredients ADD COLUMN needs_review BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX idx_ingredients_needs_review ON ingredients(id) WHERE needs_review;
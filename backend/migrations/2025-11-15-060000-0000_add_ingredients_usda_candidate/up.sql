@@ -0,0 +1,3 @@
+-- The USDA food rejected as too weak a match for a needs_review ingredient, kept so a
+-- curator can accept or reject it from the review queue
+ALTER TABLE ingredients ADD COLUMN usda_candidate JSONB;
@@ -0,0 +1,4 @@
+-- SHA-256 of the canonicalized ingredient list (see off::ingredients_hash) last linked, so
+-- refreshes and reprocessing skip products whose ingredient list didn't change. NULL
+-- until the ingredients are next processed.
+ALTER TABLE products ADD COLUMN ingredients_hash TEXT;
@@ -0,0 +1,12 @@
+-- OFF's photo URLs by kind ({"front": ..., "ingredients": ..., "nutrition": ...}), only
+-- the kinds the product has; image_url keeps the single front image
+ALTER TABLE products ADD COLUMN images JSONB;
+
+-- Gzip-compressed payloads can't be read here and are filled in on their next refresh
+UPDATE products
+SET images = NULLIF(jsonb_strip_nulls(jsonb_build_object(
+    'front', NULLIF(btrim(COALESCE(full_response->>'image_front_url', full_response->>'image_url')), ''),
+    'ingredients', NULLIF(btrim(full_response->>'image_ingredients_url'), ''),
+    'nutrition', NULLIF(btrim(full_response->>'image_nutrition_url'), '')
+)), '{}'::jsonb)
+WHERE full_response IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Ingredient list read off the ingredients-panel photo by OcrIngredientsJob, for products
+-- OFF has no ingredients_text for. Kept apart from ingredients_text, which stays as OFF
+-- sent it, so refreshes don't overwrite it.
+ALTER TABLE products ADD COLUMN ocr_ingredients_text TEXT;
@@ -0,0 +1,3 @@
+-- Whether the last USDA search (see usda_searched_at) came back without any usable match,
+-- as opposed to failing or finding one, so backfills can wait longer before asking again
+ALTER TABLE ingredients ADD COLUMN usda_no_match BOOLEAN NOT NULL DEFAULT FALSE;
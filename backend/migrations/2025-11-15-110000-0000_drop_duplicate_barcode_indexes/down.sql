@@ -0,0 +1,2 @@
+CREATE INDEX IF NOT EXISTS idx_products_barcode ON products(barcode);
+CREATE INDEX IF NOT EXISTS idx_products_nf_barcode ON products_non_food(barcode);
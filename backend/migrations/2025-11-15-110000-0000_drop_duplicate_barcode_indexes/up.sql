@@ -0,0 +1,6 @@
+-- Barcode lookups are served by the unique indexes behind the UNIQUE constraints
+-- (products_barcode_key, products_non_food_barcode_key), which also back
+-- ON CONFLICT (barcode). These plain indexes duplicated them, costing a second
+-- index write per insert for no faster reads.
+DROP INDEX IF EXISTS idx_products_barcode;
+DROP INDEX IF EXISTS idx_products_nf_barcode;
@@ -0,0 +1,10 @@
+-- One row per USDA backfill run, so an admin can see what a triggered (or scheduled) run did
+CREATE TABLE usda_backfill_runs (
+    id SERIAL PRIMARY KEY,
+    manual BOOLEAN NOT NULL,
+    candidates INTEGER NOT NULL,
+    updated INTEGER NOT NULL,
+    finished_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_usda_backfill_runs_finished_at ON usda_backfill_runs(finished_at);
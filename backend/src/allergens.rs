@@ -0,0 +1,121 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::diet;
+
+/// Normalized slugs for one OFF allergen field, preferring the `*_tags` array
+/// (`["en:peanuts"]`) over the comma-separated text (`"en:peanuts, en:milk"`).
+/// `None` when OFF has neither.
+pub fn from_off(product_data: &Value, tags_key: &str, text_key: &str) -> Option<Value> {
+    let slugs = match product_data.get(tags_key).filter(|tags| tags.is_array()) {
+        Some(tags) => diet::normalize_labels(tags),
+        None => {
+            let text = product_data.get(text_key).and_then(|v| v.as_str())?;
+            diet::normalize_labels(&Value::from(text.split(',').collect::<Vec<_>>()))
+        }
+    };
+
+    Some(serde_json::json!(slugs))
+}
+
+/// Slugs stored in an `allergen_tags`/`trace_tags` column
+pub fn stored_slugs(column: Option<&Value>) -> Vec<String> {
+    column
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|slug| slug.as_str().map(str::to_string))
+        .collect()
+}
+
+/// Allergens a caller wants to avoid (`?exclude_allergens=peanuts,milk`). Traces only
+/// count when `strict`, since "may contain" is a warning rather than an ingredient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllergenExclusion {
+    excluded: Vec<String>,
+    strict: bool,
+}
+
+/// Which excluded allergens a product has, split by how it has them
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct AllergenMatches {
+    pub allergens: Vec<String>,
+    pub traces: Vec<String>,
+}
+
+impl AllergenMatches {
+    pub fn is_empty(&self) -> bool {
+        self.allergens.is_empty() && self.traces.is_empty()
+    }
+}
+
+impl AllergenExclusion {
+    /// Parse a comma-separated list; entries may carry a language prefix ("en:milk")
+    pub fn parse(list: &str, strict: bool) -> Self {
+        AllergenExclusion {
+            excluded: diet::normalize_labels(&Value::from(list.split(',').collect::<Vec<_>>())),
+            strict,
+        }
+    }
+
+    /// Excluded allergens the product contains, plus the ones it may contain when strict
+    pub fn matches(&self, allergen_tags: &[String], trace_tags: &[String]) -> AllergenMatches {
+        let hits = |tags: &[String]| -> Vec<String> {
+            self.excluded.iter().filter(|slug| tags.contains(slug)).cloned().collect()
+        };
+
+        AllergenMatches {
+            allergens: hits(allergen_tags),
+            traces: if self.strict { hits(trace_tags) } else { Vec::new() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixture() -> Value {
+        json!({
+            "allergens": "en:peanuts, en:soybeans",
+            "allergens_tags": ["en:peanuts", "en:soybeans"],
+            "traces": "en:milk,en:nuts",
+            "traces_tags": ["en:milk", "en:nuts"]
+        })
+    }
+
+    #[test]
+    fn test_from_off_reads_allergens_and_traces_separately() {
+        assert_eq!(from_off(&fixture(), "allergens_tags", "allergens"), Some(json!(["peanuts", "soybeans"])));
+        assert_eq!(from_off(&fixture(), "traces_tags", "traces"), Some(json!(["milk", "nuts"])));
+    }
+
+    #[test]
+    fn test_from_off_falls_back_to_text() {
+        let product = json!({ "traces": "en:Milk, fr:fruits-a-coque, " });
+        assert_eq!(from_off(&product, "traces_tags", "traces"), Some(json!(["milk", "fruits-a-coque"])));
+
+        assert_eq!(from_off(&json!({ "traces": "" }), "traces_tags", "traces"), Some(json!([])));
+        assert_eq!(from_off(&json!({}), "traces_tags", "traces"), None);
+    }
+
+    #[test]
+    fn test_exclusion_only_counts_traces_when_strict() {
+        let product = crate::off::extract("0000000000001", &fixture());
+        let allergens = stored_slugs(product.allergen_tags.as_ref());
+        let traces = stored_slugs(product.trace_tags.as_ref());
+
+        let lenient = AllergenExclusion::parse("milk, en:Peanuts", false).matches(&allergens, &traces);
+        assert_eq!(lenient.allergens, vec!["peanuts"]);
+        assert!(lenient.traces.is_empty());
+
+        let strict = AllergenExclusion::parse("milk,peanuts", true).matches(&allergens, &traces);
+        assert_eq!(strict.allergens, vec!["peanuts"]);
+        assert_eq!(strict.traces, vec!["milk"]);
+
+        // Milk is only a trace, so a lenient milk-free filter keeps the product
+        assert!(AllergenExclusion::parse("milk", false).matches(&allergens, &traces).is_empty());
+        assert!(!AllergenExclusion::parse("milk", true).matches(&allergens, &traces).is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+//! Response envelopes shared by every JSON endpoint.
+//!
+//! Successful responses are `{"data": ...}` and failures are
+//! `{"error": {"code": "...", "message": "...", ...context}}`, whatever the endpoint, so
+//! clients can branch on the top-level key before looking at the payload, and on `code`
+//! rather than the message text.
+
+use actix_web::error::{InternalError, JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Body of a successful response
+#[derive(Serialize, Debug)]
+pub struct ApiOk<T> {
+    pub data: T,
+}
+
+impl<T: Serialize> ApiOk<T> {
+    pub fn new(data: T) -> Self {
+        ApiOk { data }
+    }
+}
+
+/// Body of a failed response
+#[derive(Serialize, Debug, Clone)]
+pub struct ApiError {
+    pub error: ErrorBody,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorBody {
+    pub message: String,
+    /// What the failure is about, such as the barcode or id that wasn't found
+    #[serde(flatten)]
+    pub context: Map<String, Value>,
+}
+
+impl ApiError {
+    pub fn new(message: impl Into<String>) -> Self {
+        ApiError {
+            error: ErrorBody {
+                message: message.into(),
+                context: Map::new(),
+            },
+        }
+    }
+
+    /// Add a field next to `message`, e.g. `.with("barcode", &barcode)`
+    pub fn with(mut self, key: &str, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.error.context.insert(key.to_string(), value);
+        self
+    }
+}
+
+/// `code` of an error response with `status` whose body doesn't name a more specific
+/// one, in the same vocabulary as [`crate::errors::AppError::code`]
+pub fn code_for_status(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable",
+        StatusCode::BAD_GATEWAY => "upstream_failed",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        StatusCode::GATEWAY_TIMEOUT => "upstream_timeout",
+        status if status.is_client_error() => "bad_request",
+        _ => "internal",
+    }
+}
+
+/// Respond with `status` and the error envelope, adding the `code` for that status unless
+/// `body` already has one
+pub fn error(status: StatusCode, mut body: ApiError) -> HttpResponse {
+    if !body.error.context.contains_key("code") {
+        body = body.with("code", code_for_status(status));
+    }
+    HttpResponse::build(status).json(body)
+}
+
+/// Answer malformed bodies, query strings and paths with the error envelope instead of
+/// Actix's plain-text message, keeping the status Actix picked (400, 413, ...)
+fn rejected_input<E>(err: E, _req: &HttpRequest) -> actix_web::Error
+where
+    E: ResponseError + 'static,
+{
+    let response = error(err.status_code(), ApiError::new(err.to_string()));
+    InternalError::from_response(err, response).into()
+}
+
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(rejected_input::<JsonPayloadError>)
+}
+
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(rejected_input::<QueryPayloadError>)
+}
+
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(rejected_input::<PathError>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{post, App};
+    use serde::Deserialize;
+
+    #[test]
+    fn test_ok_wraps_payload_in_data() {
+        let body = serde_json::to_value(ApiOk::new(vec![1, 2])).unwrap();
+        assert_eq!(body, serde_json::json!({ "data": [1, 2] }));
+    }
+
+    #[test]
+    fn test_error_carries_message_and_context() {
+        let body = serde_json::to_value(ApiError::new("Product not found").with("barcode", "0001")).unwrap();
+        assert_eq!(body, serde_json::json!({ "error": { "message": "Product not found", "barcode": "0001" } }));
+
+        let bare = serde_json::to_value(ApiError::new("Database query failed")).unwrap();
+        assert_eq!(bare, serde_json::json!({ "error": { "message": "Database query failed" } }));
+    }
+
+    #[actix_rt::test]
+    async fn test_error_response_names_a_code_for_its_status() {
+        for (status, code) in [
+            (StatusCode::NOT_FOUND, "not_found"),
+            (StatusCode::CONFLICT, "conflict"),
+            (StatusCode::UNPROCESSABLE_ENTITY, "unprocessable"),
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        ] {
+            let resp = error(status, ApiError::new("Alias already exists"));
+            assert_eq!(resp.status(), status);
+            let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body, serde_json::json!({ "error": { "code": code, "message": "Alias already exists" } }));
+        }
+
+        // A more specific code is kept
+        let resp = error(StatusCode::SERVICE_UNAVAILABLE, ApiError::new("Job queue is full").with("code", "queue_full"));
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["code"], "queue_full");
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Echo {
+        #[allow(dead_code)]
+        name: String,
+    }
+
+    #[post("/echo")]
+    async fn echo(_body: web::Json<Echo>) -> HttpResponse {
+        HttpResponse::Ok().json(ApiOk::new("ok"))
+    }
+
+    #[actix_rt::test]
+    async fn test_rejected_json_uses_error_envelope() {
+        let app = actix_web::test::init_service(App::new().app_data(json_config()).service(echo)).await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/echo")
+            .set_json(serde_json::json!({ "name": "salt", "extra": true }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let body: Value = actix_web::test::read_body_json(resp).await;
+        assert!(body["error"]["message"].as_str().unwrap().contains("extra"));
+        assert_eq!(body["error"]["code"], "bad_request");
+        assert_eq!(body.as_object().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::api::{self, ApiError};
+use crate::config::Config;
+
+/// Header admin-only endpoints read the key from
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Key required by admin-only endpoints (ADMIN_API_KEY). With no key configured those
+/// endpoints refuse every request rather than run unauthenticated.
+#[derive(Clone)]
+pub struct AdminApiKey {
+    key: Option<String>,
+}
+
+impl AdminApiKey {
+    pub fn new(key: Option<String>) -> Self {
+        AdminApiKey {
+            key: key.map(|k| k.trim().to_string()).filter(|k| !k.is_empty()),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        AdminApiKey::new(config.admin_api_key.clone())
+    }
+
+    /// Response to send instead of running the handler, or `None` when the request carries the key
+    pub fn rejection(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        let Some(expected) = &self.key else {
+            return Some(api::error(StatusCode::FORBIDDEN, ApiError::new("Admin API key is not configured")));
+        };
+
+        let provided = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+
+        if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            None
+        } else {
+            Some(api::error(StatusCode::UNAUTHORIZED, ApiError::new("Missing or invalid API key")))
+        }
+    }
+}
+
+/// Compare without returning early, so response timing doesn't reveal how much of the key matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_rejection_unless_key_matches() {
+        let key = AdminApiKey::new(Some("s3cret".to_string()));
+
+        assert!(key.rejection(&TestRequest::default().insert_header((API_KEY_HEADER, "s3cret")).to_http_request()).is_none());
+
+        let wrong = key.rejection(&TestRequest::default().insert_header((API_KEY_HEADER, "s3cre")).to_http_request());
+        assert_eq!(wrong.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let missing = key.rejection(&TestRequest::default().to_http_request());
+        assert_eq!(missing.unwrap().status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_unconfigured_key_refuses_everything() {
+        for configured in [None, Some("  ".to_string())] {
+            let key = AdminApiKey::new(configured);
+            let req = TestRequest::default().insert_header((API_KEY_HEADER, "")).to_http_request();
+            assert_eq!(key.rejection(&req).unwrap().status(), StatusCode::FORBIDDEN);
+        }
+    }
+}
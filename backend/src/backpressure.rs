@@ -0,0 +1,197 @@
+//! Turning new jobs away while the queue is backed up, so a burst of requests or the
+//! ingredient fan-out can't grow `fang_tasks` faster than the workers drain it.
+//!
+//! Only jobs that can wait are checked. Cleanup, failure alerts and the recurring jobs
+//! the workers schedule are always queued, since they are what keeps the queue healthy.
+
+use diesel::prelude::*;
+
+/// Which ceiling a job is held to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobClass {
+    /// Product fetches a user is waiting on
+    Fetch,
+    /// Enrichment nobody waits on: ingredient creation, analysis, OCR, USDA backfill and re-enrichment
+    Background,
+}
+
+impl JobClass {
+    /// Pending tasks of this class at which more are refused (JOB_QUEUE_MAX_PENDING_FETCH,
+    /// JOB_QUEUE_MAX_PENDING_BACKGROUND)
+    pub fn max_pending(self) -> i64 {
+        let config = crate::config::get();
+        match self {
+            JobClass::Fetch => config.job_queue_max_pending_fetch,
+            JobClass::Background => config.job_queue_max_pending_background,
+        }
+    }
+
+    /// Task types counted against this class's ceiling. Each type has its own workers (see
+    /// `queue::WORKER_POOLS`), so a backlog of one class doesn't hold up the other.
+    pub fn task_types(self) -> &'static [&'static str] {
+        match self {
+            JobClass::Fetch => &["fetch_product"],
+            JobClass::Background => &[
+                "create_ingredient",
+                "analyze_ingredients",
+                "ocr_ingredients",
+                "enrich_non_food",
+                "usda_backfill",
+                "usda_reenrich",
+            ],
+        }
+    }
+}
+
+/// A job turned away because the queue was full for its class
+#[derive(Debug, PartialEq)]
+pub struct QueueFull {
+    pub class: JobClass,
+    pub pending: i64,
+    pub max_pending: i64,
+}
+
+impl std::fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} jobs pending, {:?} jobs are refused at {}",
+            self.pending, self.class, self.max_pending
+        )
+    }
+}
+
+/// Tasks of `class` waiting for a worker: new ones and failed ones whose retry is due.
+/// Tasks scheduled for later (the next run of a recurring job, a retry still backing off)
+/// aren't waiting yet.
+pub fn pending_tasks(class: JobClass, conn: &mut PgConnection) -> QueryResult<i64> {
+    #[derive(QueryableByName)]
+    struct Pending {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        pending: i64,
+    }
+
+    diesel::sql_query(
+        "SELECT COUNT(*) AS pending FROM fang_tasks \
+         WHERE state IN ('new', 'retried') AND task_type = ANY($1) AND scheduled_at <= NOW()",
+    )
+    .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(class.task_types())
+    .get_result::<Pending>(conn)
+    .map(|row| row.pending)
+}
+
+/// Whether a `class` job may join `pending` waiting tasks of its class
+pub fn admit(class: JobClass, pending: i64, max_pending: i64) -> Result<(), QueueFull> {
+    if pending < max_pending {
+        Ok(())
+    } else {
+        Err(QueueFull { class, pending, max_pending })
+    }
+}
+
+/// Check the queue before enqueueing a `class` job (`max_pending` is normally
+/// [`JobClass::max_pending`]), logging a refusal. A queue that can't be counted lets
+/// the job through; enqueueing it fails anyway if the database is really gone.
+pub fn check(class: JobClass, max_pending: i64, conn: &mut PgConnection) -> Result<(), QueueFull> {
+    let pending = match pending_tasks(class, conn) {
+        Ok(pending) => pending,
+        Err(e) => {
+            log::error!("Failed to count pending jobs, not applying backpressure: {}", e);
+            return Ok(());
+        }
+    };
+
+    admit(class, pending, max_pending).inspect_err(|full| log::warn!("Backpressure: {}", full))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admit_below_the_ceiling_only() {
+        assert_eq!(admit(JobClass::Background, 0, 2), Ok(()));
+        assert_eq!(admit(JobClass::Background, 1, 2), Ok(()));
+        assert_eq!(
+            admit(JobClass::Background, 2, 2),
+            Err(QueueFull { class: JobClass::Background, pending: 2, max_pending: 2 })
+        );
+    }
+
+    #[test]
+    fn test_enqueue_refused_past_the_threshold() {
+        let Some(pool) = crate::db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let mut conn = pool.get().unwrap();
+
+        let before = pending_tasks(JobClass::Background, &mut conn).unwrap();
+        for (task_type, state) in [
+            ("create_ingredient", "new"),
+            ("analyze_ingredients", "retried"),
+            ("usda_reenrich", "new"),
+            ("create_ingredient", "in_progress"),
+            ("create_ingredient", "finished"),
+            ("create_ingredient", "failed"),
+            ("fetch_product", "new"),
+            ("send_notification", "new"),
+        ] {
+            insert_task(task_type, state, 0, &mut conn);
+        }
+        // The next run of a recurring job, and a retry still backing off
+        insert_task("usda_backfill", "new", 3600, &mut conn);
+        insert_task("create_ingredient", "retried", 60, &mut conn);
+
+        let pending = before + 3;
+        assert_eq!(pending_tasks(JobClass::Background, &mut conn).unwrap(), pending);
+
+        // Background work hits its ceiling; fetches are counted on their own
+        assert_eq!(
+            check(JobClass::Background, pending, &mut conn),
+            Err(QueueFull { class: JobClass::Background, pending, max_pending: pending })
+        );
+        let fetches = pending_tasks(JobClass::Fetch, &mut conn).unwrap();
+        assert_eq!(check(JobClass::Fetch, fetches + 1, &mut conn), Ok(()));
+    }
+
+    #[test]
+    fn test_queue_admits_again_once_drained_below_the_ceiling() {
+        use diesel::sql_types::Text;
+
+        let Some(pool) = crate::db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let mut conn = pool.get().unwrap();
+
+        let max_pending = pending_tasks(JobClass::Background, &mut conn).unwrap() + 2;
+        insert_task("create_ingredient", "new", 0, &mut conn);
+        insert_task("create_ingredient", "new", 0, &mut conn);
+        assert!(check(JobClass::Background, max_pending, &mut conn).is_err());
+
+        // A worker picks one up: it's running, no longer waiting
+        diesel::sql_query(
+            "UPDATE fang_tasks SET state = $1::fang_task_state \
+             WHERE id = (SELECT id FROM fang_tasks WHERE task_type = 'create_ingredient' AND state = 'new' ORDER BY created_at DESC LIMIT 1)",
+        )
+        .bind::<Text, _>("in_progress")
+        .execute(&mut conn)
+        .unwrap();
+        assert_eq!(check(JobClass::Background, max_pending, &mut conn), Ok(()));
+    }
+
+    fn insert_task(task_type: &str, state: &str, due_in_secs: i32, conn: &mut PgConnection) {
+        use diesel::sql_types::{Integer, Text};
+
+        diesel::sql_query(
+            "INSERT INTO fang_tasks (metadata, state, task_type, scheduled_at) \
+             VALUES ('{}', $1::fang_task_state, $2, NOW() + make_interval(secs => $3))",
+        )
+        .bind::<Text, _>(state)
+        .bind::<Text, _>(task_type)
+        .bind::<Integer, _>(due_in_secs)
+        .execute(conn)
+        .unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+//! Barcodes taken from request paths. Only plain ASCII digits get past the handler, so
+//! nothing else ends up in an upstream URL or a database query.
+
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+
+use crate::api::ApiError;
+use crate::errors::AppError;
+
+/// Longest barcode accepted: GTIN-14, the longest GS1 product number
+pub const MAX_BARCODE_LEN: usize = 14;
+
+/// Normalize a barcode from a request path: surrounding whitespace and the spaces or
+/// hyphens printed between digit groups ("0 12345 67890 5") are dropped. What is left
+/// must be 1 to `MAX_BARCODE_LEN` ASCII digits; otherwise the reason it was refused.
+pub fn normalize(raw: &str) -> Result<String, &'static str> {
+    let barcode: String = raw.trim().chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+
+    if barcode.is_empty() {
+        return Err("Barcode is empty");
+    }
+    if !barcode.chars().all(|c| c.is_ascii_digit()) {
+        return Err("Barcode must contain only digits");
+    }
+    if barcode.len() > MAX_BARCODE_LEN {
+        return Err("Barcode is too long");
+    }
+
+    Ok(barcode)
+}
+
+/// The `{barcode}` segment of a route, normalized. Extracting it answers a barcode that
+/// isn't plain digits with a 400 before the handler runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Barcode(pub String);
+
+impl Barcode {
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl FromRequest for Barcode {
+    type Error = AppError;
+    type Future = Ready<Result<Self, AppError>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let raw = req.match_info().get("barcode").unwrap_or("");
+        ready(normalize(raw).map(Barcode).map_err(invalid))
+    }
+}
+
+/// Refusal of a path barcode that isn't plain digits. The input isn't echoed back, since
+/// it can be arbitrarily long.
+fn invalid(reason: &str) -> AppError {
+    AppError::BadRequest(ApiError::new(reason).with("max_length", MAX_BARCODE_LEN))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use actix_web::ResponseError;
+
+    #[test]
+    fn test_normalize_keeps_digits_and_drops_separators() {
+        assert_eq!(normalize("0737628064502"), Ok("0737628064502".to_string()));
+        assert_eq!(normalize(" 0 12345 67890 5 "), Ok("012345678905".to_string()));
+        assert_eq!(normalize("4006381-333931"), Ok("4006381333931".to_string()));
+        assert_eq!(normalize("12345678901234"), Ok("12345678901234".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_rejects_anything_else() {
+        assert_eq!(normalize(""), Err("Barcode is empty"));
+        assert_eq!(normalize(" - "), Err("Barcode is empty"));
+        // Arabic-Indic and fullwidth digits are digits, just not ASCII ones
+        assert_eq!(normalize("١٢٣٤٥٦٧٨"), Err("Barcode must contain only digits"));
+        assert_eq!(normalize("１２３４５６７８"), Err("Barcode must contain only digits"));
+        assert_eq!(normalize("0737/628064502"), Err("Barcode must contain only digits"));
+        assert_eq!(normalize("../../admin"), Err("Barcode must contain only digits"));
+        assert_eq!(normalize("0737%2F628064502"), Err("Barcode must contain only digits"));
+        assert_eq!(normalize("123456789012345"), Err("Barcode is too long"));
+        assert_eq!(normalize(&"9".repeat(4096)), Err("Barcode is too long"));
+    }
+
+    #[actix_rt::test]
+    async fn test_extractor_normalizes_or_refuses_the_path_segment() {
+        let req = TestRequest::default().param("barcode", "0 12345 67890 5").to_http_request();
+        let barcode = Barcode::extract(&req).await.unwrap();
+        assert_eq!(barcode, Barcode("012345678905".to_string()));
+
+        let req = TestRequest::default().param("barcode", "../../admin").to_http_request();
+        let refused = Barcode::extract(&req).await.unwrap_err();
+        assert_eq!(refused.status_code(), actix_web::http::StatusCode::BAD_REQUEST);
+        assert_eq!(refused.code(), "bad_request");
+    }
+}
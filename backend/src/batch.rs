@@ -0,0 +1,158 @@
+//! Shared response contract for batch endpoints.
+//!
+//! Every item in a batch gets its own result, so one bad input doesn't fail the
+//! whole request:
+//!
+//! ```json
+//! {
+//!   "results": [
+//!     { "input": 1, "status": "ok", "data": { ... } },
+//!     { "input": 9, "status": "error", "error": "Ingredient not found" }
+//!   ],
+//!   "succeeded": 1,
+//!   "failed": 1
+//! }
+//! ```
+//!
+//! Results keep the order of the request (duplicates dropped). The response is
+//! 200 even when some items fail; 400 is reserved for a malformed request as a whole
+//! (e.g. empty or oversized).
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchItemStatus {
+    Ok,
+    Error,
+}
+
+/// Outcome for one input of a batch request
+#[derive(Serialize, Debug)]
+pub struct BatchItemResult<I, T> {
+    pub input: I,
+    pub status: BatchItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<I, T> BatchItemResult<I, T> {
+    pub fn ok(input: I, data: T) -> Self {
+        BatchItemResult {
+            input,
+            status: BatchItemStatus::Ok,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    pub fn error(input: I, error: impl Into<String>) -> Self {
+        BatchItemResult {
+            input,
+            status: BatchItemStatus::Error,
+            data: None,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct BatchResponse<I, T> {
+    pub results: Vec<BatchItemResult<I, T>>,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+impl<I, T> BatchResponse<I, T> {
+    pub fn new(results: Vec<BatchItemResult<I, T>>) -> Self {
+        let succeeded = results.iter().filter(|r| r.status == BatchItemStatus::Ok).count();
+        let failed = results.len() - succeeded;
+
+        BatchResponse {
+            results,
+            succeeded,
+            failed,
+        }
+    }
+}
+
+/// Match loaded rows back to the requested keys, in request order and without
+/// duplicates. Keys with no row become `missing_error` results.
+pub fn results_by_key<K, T>(
+    inputs: &[K],
+    rows: Vec<T>,
+    key_of: impl Fn(&T) -> K,
+    missing_error: &str,
+) -> BatchResponse<K, T>
+where
+    K: Eq + Hash + Copy,
+{
+    let mut by_key: HashMap<K, T> = rows.into_iter().map(|row| (key_of(&row), row)).collect();
+    let mut seen = HashSet::new();
+
+    let results = inputs
+        .iter()
+        .filter(|key| seen.insert(**key))
+        .map(|key| match by_key.remove(key) {
+            Some(row) => BatchItemResult::ok(*key, row),
+            None => BatchItemResult::error(*key, missing_error),
+        })
+        .collect();
+
+    BatchResponse::new(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mixed_batch_keeps_request_order() {
+        let rows = vec![(3, "c"), (1, "a")];
+        let response = results_by_key(&[1, 2, 3], rows, |row| row.0, "Not found");
+
+        let inputs: Vec<i32> = response.results.iter().map(|r| r.input).collect();
+        assert_eq!(inputs, vec![1, 2, 3]);
+        assert_eq!(response.succeeded, 2);
+        assert_eq!(response.failed, 1);
+        assert_eq!(response.results[1].status, BatchItemStatus::Error);
+        assert_eq!(response.results[1].error.as_deref(), Some("Not found"));
+    }
+
+    #[test]
+    fn test_duplicate_inputs_reported_once() {
+        let rows = vec![(5, "e")];
+        let response = results_by_key(&[5, 5, 7, 7], rows, |row| row.0, "Not found");
+
+        let inputs: Vec<i32> = response.results.iter().map(|r| r.input).collect();
+        assert_eq!(inputs, vec![5, 7]);
+        assert_eq!(response.succeeded, 1);
+        assert_eq!(response.failed, 1);
+    }
+
+    #[test]
+    fn test_envelope_serialization() {
+        let response = BatchResponse::new(vec![
+            BatchItemResult::ok("737628064502", json!({ "product_name": "Thai Peanut Noodle Kit" })),
+            BatchItemResult::error("not-a-barcode", "Invalid barcode"),
+        ]);
+
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({
+                "results": [
+                    { "input": "737628064502", "status": "ok", "data": { "product_name": "Thai Peanut Noodle Kit" } },
+                    { "input": "not-a-barcode", "status": "error", "error": "Invalid barcode" }
+                ],
+                "succeeded": 1,
+                "failed": 1
+            })
+        );
+    }
+}
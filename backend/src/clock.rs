@@ -0,0 +1,59 @@
+use chrono::NaiveDateTime;
+
+/// Source of "now" (UTC) for timestamps and TTL checks, so expiry can be tested by moving
+/// a mock clock instead of waiting
+pub trait Clock: Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// The real wall clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        chrono::Utc::now().naive_utc()
+    }
+}
+
+/// Clock that only moves when told to
+#[cfg(test)]
+pub struct MockClock {
+    now: std::sync::Mutex<NaiveDateTime>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn at(now: NaiveDateTime) -> Self {
+        MockClock {
+            now: std::sync::Mutex::new(now),
+        }
+    }
+
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_only_moves_when_advanced() {
+        let start = chrono::NaiveDate::from_ymd_opt(2025, 11, 14).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let clock = MockClock::at(start);
+
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(chrono::Duration::hours(25));
+        assert_eq!(clock.now(), start + chrono::Duration::hours(25));
+    }
+}
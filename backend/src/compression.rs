@@ -0,0 +1,80 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::Value;
+
+/// Gzip the serialized document
+pub fn gzip_json(document: &Value) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    // Writing into a Vec can't fail, nor can serializing a Value
+    serde_json::to_writer(&mut encoder, document).expect("serializing JSON into memory");
+    encoder.finish().expect("compressing into memory")
+}
+
+/// Inverse of [`gzip_json`]
+pub fn gunzip_json(bytes: &[u8]) -> std::io::Result<Value> {
+    let mut text = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut text)?;
+    Ok(serde_json::from_slice(&text)?)
+}
+
+/// A product's OFF payload from whichever column holds it: `full_response` for rows
+/// written uncompressed (including every row from before compression existed),
+/// `full_response_gz` otherwise
+pub fn stored_response(json: Option<Value>, gz: Option<&[u8]>) -> std::io::Result<Value> {
+    match (json, gz) {
+        (Some(document), _) => Ok(document),
+        (None, Some(bytes)) => gunzip_json(bytes),
+        (None, None) => Ok(Value::Null),
+    }
+}
+
+/// Columns to write a payload to: `(full_response, full_response_gz)`
+pub fn to_columns(document: &Value, compress: bool) -> (Option<Value>, Option<Vec<u8>>) {
+    if compress {
+        (None, Some(gzip_json(document)))
+    } else {
+        (Some(document.clone()), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let document = fixtures::off_product("multilingual");
+        let compressed = gzip_json(&document);
+
+        assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+        assert_eq!(gunzip_json(&compressed).unwrap(), document);
+    }
+
+    #[test]
+    fn test_stored_response_reads_either_column() {
+        let document = fixtures::off_product("full");
+
+        let (json, gz) = to_columns(&document, false);
+        assert!(gz.is_none());
+        assert_eq!(stored_response(json, None).unwrap(), document);
+
+        let (json, gz) = to_columns(&document, true);
+        assert!(json.is_none());
+        assert_eq!(stored_response(None, gz.as_deref()).unwrap(), document);
+
+        assert_eq!(stored_response(None, None).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_corrupt_payload_is_an_error() {
+        assert!(gunzip_json(b"not gzip").is_err());
+
+        let mut truncated = gzip_json(&fixtures::off_product("full"));
+        truncated.truncate(truncated.len() / 2);
+        assert!(gunzip_json(&truncated).is_err());
+    }
+}
@@ -1,13 +1,287 @@
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
-use std::env;
+use diesel::r2d2::{self, ConnectionManager, HandleEvent};
+use diesel::r2d2::event::{CheckoutEvent, TimeoutEvent};
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::{self, DEFAULT_POOL_SIZE};
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+pub type DbConnection = r2d2::PooledConnection<ConnectionManager<PgConnection>>;
 
-pub fn establish_connection_pool() -> DbPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+pub fn establish_connection_pool_with_size(max_size: u32) -> DbPool {
+    let config = config::get();
+    let manager = ConnectionManager::<PgConnection>::new(config.database_url());
+    // Connectivity is verified by startup::ensure_ready; building unchecked lets a
+    // degraded start come up and serve errors instead of panicking here
     r2d2::Pool::builder()
-        .build(manager)
-        .expect("Failed to create pool.")
+        .max_size(max_size)
+        .connection_timeout(config.db_pool_timeout)
+        .event_handler(Box::new(PoolMetricsHandler))
+        .build_unchecked(manager)
+}
+
+/// Pool size for the HTTP server: DB_POOL_SIZE if set, otherwise two connections per
+/// Actix worker (every `web::block` DB call holds one) and never below r2d2's default
+pub fn pool_size_for_workers(http_workers: usize) -> u32 {
+    config::get()
+        .db_pool_size
+        .unwrap_or_else(|| (http_workers as u32 * 2).max(DEFAULT_POOL_SIZE))
+}
+
+/// Process-wide counters for how long requests wait on `pool.get()`
+static CHECKOUTS: AtomicU64 = AtomicU64::new(0);
+static CHECKOUT_WAIT_MICROS: AtomicU64 = AtomicU64::new(0);
+static MAX_CHECKOUT_WAIT_MICROS: AtomicU64 = AtomicU64::new(0);
+static CHECKOUT_TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+struct PoolMetricsHandler;
+
+impl HandleEvent for PoolMetricsHandler {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        let micros = event.duration().as_micros() as u64;
+        CHECKOUTS.fetch_add(1, Ordering::Relaxed);
+        CHECKOUT_WAIT_MICROS.fetch_add(micros, Ordering::Relaxed);
+        MAX_CHECKOUT_WAIT_MICROS.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        CHECKOUT_TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+        log::warn!("Timed out after {:?} waiting for a database connection", event.timeout());
+    }
+}
+
+/// Pool size, checkout wait and gate statistics since the process started
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PoolStats {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle_connections: u32,
+    pub checkouts: u64,
+    pub timeouts: u64,
+    pub avg_wait_ms: f64,
+    pub max_wait_ms: f64,
+    /// Concurrency cap on request-path DB operations
+    pub gate: GateStats,
+}
+
+pub fn pool_stats(pool: &DbPool) -> PoolStats {
+    let gate = gate();
+    let state = pool.state();
+    let checkouts = CHECKOUTS.load(Ordering::Relaxed);
+    let total_wait_micros = CHECKOUT_WAIT_MICROS.load(Ordering::Relaxed);
+
+    PoolStats {
+        max_size: pool.max_size(),
+        connections: state.connections,
+        idle_connections: state.idle_connections,
+        checkouts,
+        timeouts: CHECKOUT_TIMEOUTS.load(Ordering::Relaxed),
+        avg_wait_ms: if checkouts == 0 { 0.0 } else { total_wait_micros as f64 / checkouts as f64 / 1000.0 },
+        max_wait_ms: MAX_CHECKOUT_WAIT_MICROS.load(Ordering::Relaxed) as f64 / 1000.0,
+        gate: gate.stats(),
+    }
+}
+
+/// Caps how many request-path DB operations run at once. Each one occupies a thread of
+/// Actix's blocking pool for its `web::block` call; past the cap, requests wait briefly
+/// for a slot and then get a 503 instead of queueing behind a slow database.
+pub struct DbGate {
+    semaphore: Arc<Semaphore>,
+    max_concurrent: usize,
+    timeout: Duration,
+    entered: AtomicU64,
+    rejected: AtomicU64,
+    wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+/// Why a request couldn't get a connection
+#[derive(Debug)]
+pub enum CheckoutError {
+    /// Every DB slot stayed busy for the whole gate timeout
+    Saturated { max_concurrent: usize, waited: Duration },
+    Pool(r2d2::PoolError),
+}
+
+impl fmt::Display for CheckoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckoutError::Saturated { max_concurrent, waited } => write!(
+                f,
+                "all {} database slots busy after waiting {:?}",
+                max_concurrent, waited
+            ),
+            CheckoutError::Pool(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Slot usage and wait statistics since the process started
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GateStats {
+    pub max_concurrent: usize,
+    pub in_flight: usize,
+    pub entered: u64,
+    pub rejected: u64,
+    pub avg_wait_ms: f64,
+    pub max_wait_ms: f64,
+}
+
+impl DbGate {
+    pub fn new(max_concurrent: usize, timeout: Duration) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        DbGate {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+            timeout,
+            entered: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            wait_micros: AtomicU64::new(0),
+            max_wait_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait up to the gate timeout for a slot. The slot is held until the permit is dropped.
+    pub async fn enter(&self) -> Result<OwnedSemaphorePermit, CheckoutError> {
+        let started = Instant::now();
+        let permit = tokio::time::timeout(self.timeout, self.semaphore.clone().acquire_owned()).await;
+        let waited = started.elapsed();
+
+        match permit {
+            Ok(Ok(permit)) => {
+                let micros = waited.as_micros() as u64;
+                self.entered.fetch_add(1, Ordering::Relaxed);
+                self.wait_micros.fetch_add(micros, Ordering::Relaxed);
+                self.max_wait_micros.fetch_max(micros, Ordering::Relaxed);
+                Ok(permit)
+            }
+            // The semaphore is never closed, so only the timeout gets here
+            _ => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                log::warn!("Rejected request: all {} database slots busy for {:?}", self.max_concurrent, waited);
+                Err(CheckoutError::Saturated { max_concurrent: self.max_concurrent, waited })
+            }
+        }
+    }
+
+    /// A slot plus a pooled connection. Keep the permit alive until the DB work is done.
+    pub async fn checkout(&self, pool: &DbPool) -> Result<(OwnedSemaphorePermit, DbConnection), CheckoutError> {
+        let permit = self.enter().await?;
+        let conn = pool.get().map_err(CheckoutError::Pool)?;
+        Ok((permit, conn))
+    }
+
+    pub fn stats(&self) -> GateStats {
+        let entered = self.entered.load(Ordering::Relaxed);
+        GateStats {
+            max_concurrent: self.max_concurrent,
+            in_flight: self.max_concurrent - self.semaphore.available_permits(),
+            entered,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            avg_wait_ms: if entered == 0 {
+                0.0
+            } else {
+                self.wait_micros.load(Ordering::Relaxed) as f64 / entered as f64 / 1000.0
+            },
+            max_wait_ms: self.max_wait_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+}
+
+static GATE: OnceLock<DbGate> = OnceLock::new();
+
+/// Size the process-wide gate: DB_MAX_CONCURRENT if set, otherwise the pool size, since
+/// every gated operation holds a connection anyway. Call once at startup.
+pub fn init_gate(pool_size: u32) {
+    let config = config::get();
+    let max_concurrent = config.db_max_concurrent.unwrap_or(pool_size as usize);
+    if GATE.set(DbGate::new(max_concurrent, config.db_gate_timeout)).is_err() {
+        log::warn!("Database gate already initialized, keeping the existing one");
+    }
+}
+
+/// The process-wide gate, sized for the default pool if `init_gate` wasn't called
+pub fn gate() -> &'static DbGate {
+    GATE.get_or_init(|| DbGate::new(DEFAULT_POOL_SIZE as usize, config::get().db_gate_timeout))
+}
+
+/// Check out a connection through the process-wide gate
+pub async fn checkout(pool: &DbPool) -> Result<(OwnedSemaphorePermit, DbConnection), CheckoutError> {
+    gate().checkout(pool).await
+}
+
+/// A one-connection pool on DATABASE_URL whose connection stays inside a test
+/// transaction, so nothing a test writes through it is committed. `None` without
+/// DATABASE_URL, for tests to skip.
+#[cfg(test)]
+pub fn test_pool() -> Option<DbPool> {
+    let url = std::env::var("DATABASE_URL").ok()?;
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(r2d2::TestCustomizer))
+        .build(ConnectionManager::<PgConnection>::new(url))
+        .expect("Failed to build pool");
+    Some(pool)
+}
+
+/// A connection to DATABASE_URL inside a test transaction, for tests that don't need a
+/// pool. `None` without DATABASE_URL, for tests to skip.
+#[cfg(test)]
+pub fn test_connection() -> Option<PgConnection> {
+    let mut conn = committing_test_connection()?;
+    conn.begin_test_transaction().expect("Failed to begin test transaction");
+    Some(conn)
+}
+
+/// A plain connection to DATABASE_URL, whose writes are committed: for checking and
+/// removing what the job queue and its workers committed, which a test transaction can't
+/// see or undo. `None` without DATABASE_URL.
+#[cfg(test)]
+pub fn committing_test_connection() -> Option<PgConnection> {
+    let url = std::env::var("DATABASE_URL").ok()?;
+    Some(PgConnection::establish(&url).expect("Failed to connect to DATABASE_URL"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unreachable_pool() -> DbPool {
+        // Never connected: a saturated gate must answer before touching the pool
+        r2d2::Pool::builder()
+            .max_size(1)
+            .build_unchecked(ConnectionManager::<PgConnection>::new("postgres://127.0.0.1:1/none"))
+    }
+
+    #[actix_rt::test]
+    async fn test_saturated_gate_rejects_promptly() {
+        let gate = DbGate::new(2, Duration::from_millis(50));
+        let _busy = (gate.enter().await.unwrap(), gate.enter().await.unwrap());
+        assert_eq!(gate.stats().in_flight, 2);
+
+        let started = Instant::now();
+        let result = gate.checkout(&unreachable_pool()).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(matches!(result, Err(CheckoutError::Saturated { max_concurrent: 2, .. })));
+
+        let stats = gate.stats();
+        assert_eq!((stats.entered, stats.rejected), (2, 1));
+    }
+
+    #[actix_rt::test]
+    async fn test_slot_is_released_with_permit() {
+        let gate = DbGate::new(1, Duration::from_millis(50));
+        let permit = gate.enter().await.unwrap();
+        assert!(gate.enter().await.is_err());
+
+        drop(permit);
+        assert!(gate.enter().await.is_ok());
+        assert_eq!(gate.stats().in_flight, 0);
+    }
 }
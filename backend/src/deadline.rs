@@ -0,0 +1,110 @@
+//! Bounding and detaching the upstream work a request starts.
+//!
+//! The server drops a handler's future as soon as its client closes the connection
+//! (`h1_allow_half_closed(false)`), which cancels whatever that future is awaiting. Work run
+//! through [`within`] is also cut off at the request deadline. Work run through [`detached`]
+//! gets its own task, so it finishes even when the request that started it is abandoned.
+
+use std::future::Future;
+
+use tokio::time::{Duration, Instant};
+
+/// The request ran out of time before the upstream work finished
+#[derive(Debug, PartialEq)]
+pub struct DeadlineExceeded;
+
+/// Deadline for a request starting now that may take `allowed` (REQUEST_DEADLINE_SECS)
+pub fn start(allowed: Duration) -> Instant {
+    Instant::now() + allowed
+}
+
+/// Run `work` until `deadline`, dropping it (and any HTTP call in flight) if time runs out
+pub async fn within<F: Future>(deadline: Instant, work: F) -> Result<F::Output, DeadlineExceeded> {
+    tokio::time::timeout_at(deadline, work).await.map_err(|_| DeadlineExceeded)
+}
+
+/// Run `work` on its own task so it completes even if the awaiting request is dropped
+pub async fn detached<F>(work: F) -> Result<F::Output, tokio::task::JoinError>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    actix_web::rt::spawn(work).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Stand-in for an upstream call that records whether it finished or was cancelled
+    async fn upstream_call(delay: Duration, finished: Arc<AtomicBool>) {
+        tokio::time::sleep(delay).await;
+        finished.store(true, Ordering::SeqCst);
+    }
+
+    #[actix_rt::test]
+    async fn test_within_returns_fast_work() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        assert_eq!(within(deadline, upstream_call(Duration::from_millis(1), finished.clone())).await, Ok(()));
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[actix_rt::test]
+    async fn test_within_abandons_work_past_deadline() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let deadline = Instant::now() + Duration::from_millis(10);
+
+        let result = within(deadline, upstream_call(Duration::from_secs(5), finished.clone())).await;
+
+        assert_eq!(result, Err(DeadlineExceeded));
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+
+    /// A `get_product`-shaped handler: fetch within the deadline, then store on its own task
+    async fn handler(fetch_delay: Duration, fetched: Arc<AtomicBool>, stored: Arc<AtomicBool>) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        if within(deadline, upstream_call(fetch_delay, fetched)).await.is_ok() {
+            let _ = detached(upstream_call(Duration::from_millis(20), stored)).await;
+        }
+    }
+
+    /// Simulate the client hanging up after `after`: Actix drops the handler future
+    async fn disconnect_after(after: Duration, handler: impl Future<Output = ()>) {
+        assert!(tokio::time::timeout(after, handler).await.is_err(), "handler finished before the disconnect");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_client_disconnect_cancels_upstream_fetch() {
+        let fetched = Arc::new(AtomicBool::new(false));
+        let stored = Arc::new(AtomicBool::new(false));
+
+        disconnect_after(
+            Duration::from_millis(5),
+            handler(Duration::from_secs(5), fetched.clone(), stored.clone()),
+        )
+        .await;
+
+        assert!(!fetched.load(Ordering::SeqCst));
+        assert!(!stored.load(Ordering::SeqCst));
+    }
+
+    #[actix_rt::test]
+    async fn test_client_disconnect_lets_started_store_finish() {
+        let fetched = Arc::new(AtomicBool::new(false));
+        let stored = Arc::new(AtomicBool::new(false));
+
+        disconnect_after(
+            Duration::from_millis(5),
+            handler(Duration::from_millis(1), fetched.clone(), stored.clone()),
+        )
+        .await;
+
+        assert!(fetched.load(Ordering::SeqCst));
+        assert!(stored.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,126 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Dietary/certification flags for a product. `None` means we can't tell.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct DietFlags {
+    pub vegan: Option<bool>,
+    pub vegetarian: Option<bool>,
+    pub gluten_free: Option<bool>,
+    pub organic: Option<bool>,
+}
+
+/// Normalize OFF `labels_tags` (e.g. "en:gluten-free") to lowercase slugs without the
+/// language prefix, dropping duplicates while keeping OFF's order
+pub fn normalize_labels(labels_tags: &Value) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+
+    for tag in labels_tags.as_array().into_iter().flatten().filter_map(|t| t.as_str()) {
+        let tag = tag.trim().to_lowercase();
+        let slug = match tag.split_once(':') {
+            Some((_, slug)) => slug.to_string(),
+            None => tag,
+        };
+
+        if !slug.is_empty() && !labels.contains(&slug) {
+            labels.push(slug);
+        }
+    }
+
+    labels
+}
+
+/// Classify a product's diet, letting certification labels override what OFF
+/// inferred from the ingredient list (`ingredients_analysis_tags`)
+pub fn classify(labels: &[String], ingredients_analysis_tags: &Value) -> DietFlags {
+    let mut flags = infer_from_ingredients(ingredients_analysis_tags);
+    let has = |names: &[&str]| labels.iter().any(|l| names.contains(&l.as_str()));
+
+    if has(&["vegan"]) {
+        flags.vegan = Some(true);
+        flags.vegetarian = Some(true);
+    }
+    if has(&["vegetarian"]) {
+        flags.vegetarian = Some(true);
+    }
+    if has(&["no-gluten", "gluten-free"]) {
+        flags.gluten_free = Some(true);
+    }
+    if has(&["organic", "eu-organic", "usda-organic"]) {
+        flags.organic = Some(true);
+    }
+
+    flags
+}
+
+fn infer_from_ingredients(ingredients_analysis_tags: &Value) -> DietFlags {
+    let mut flags = DietFlags::default();
+
+    for tag in normalize_labels(ingredients_analysis_tags) {
+        match tag.as_str() {
+            "vegan" => flags.vegan = Some(true),
+            "non-vegan" => flags.vegan = Some(false),
+            "vegetarian" => flags.vegetarian = Some(true),
+            "non-vegetarian" => flags.vegetarian = Some(false),
+            _ => {}
+        }
+    }
+
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn labelled_product() -> Value {
+        json!({
+            "labels_tags": ["en:no-gluten", "en:vegetarian", "en:vegan", "fr:ab-agriculture-biologique", "EN:Organic", "en:vegan"],
+            "ingredients_analysis_tags": ["en:palm-oil-free", "en:maybe-vegan", "en:maybe-vegetarian"]
+        })
+    }
+
+    #[test]
+    fn test_normalize_labels_strips_prefix_and_duplicates() {
+        let labels = normalize_labels(&labelled_product()["labels_tags"]);
+        assert_eq!(labels, vec!["no-gluten", "vegetarian", "vegan", "ab-agriculture-biologique", "organic"]);
+    }
+
+    #[test]
+    fn test_normalize_labels_missing_field() {
+        assert!(normalize_labels(&Value::Null).is_empty());
+        assert!(normalize_labels(&json!("en:vegan")).is_empty());
+    }
+
+    #[test]
+    fn test_classify_from_labels() {
+        let product = labelled_product();
+        let labels = normalize_labels(&product["labels_tags"]);
+        let flags = classify(&labels, &product["ingredients_analysis_tags"]);
+
+        assert_eq!(
+            flags,
+            DietFlags {
+                vegan: Some(true),
+                vegetarian: Some(true),
+                gluten_free: Some(true),
+                organic: Some(true),
+            }
+        );
+    }
+
+    #[test]
+    fn test_labels_take_precedence_over_inference() {
+        let analysis = json!(["en:non-vegan", "en:non-vegetarian"]);
+
+        let inferred = classify(&[], &analysis);
+        assert_eq!(inferred.vegan, Some(false));
+        assert_eq!(inferred.vegetarian, Some(false));
+
+        let labelled = classify(&["vegan".to_string()], &analysis);
+        assert_eq!(labelled.vegan, Some(true));
+        assert_eq!(labelled.vegetarian, Some(true));
+        assert_eq!(labelled.gluten_free, None);
+    }
+}
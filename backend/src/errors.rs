@@ -0,0 +1,210 @@
+//! Failures a handler can return with `?`. Each maps to its status code and the error
+//! envelope from [`crate::api`], with a `code` next to `message` that clients can branch
+//! on without matching message text: `{"error": {"code": "not_found", "message": "..."}}`.
+
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use std::fmt;
+
+use crate::api::ApiError;
+use crate::db::CheckoutError;
+
+/// Seconds clients are asked to wait before retrying when no DB connection was free
+pub const DB_RETRY_AFTER_SECS: u64 = 1;
+
+#[derive(Debug)]
+pub enum AppError {
+    /// No pooled connection (or DB slot) freed up in time. Under a burst that's transient,
+    /// so clients get a 503 with `Retry-After` rather than a 500.
+    DbPool(CheckoutError),
+    /// A query failed
+    DbQuery(diesel::result::Error),
+    /// A blocking or detached task died before answering
+    Internal(String),
+    /// The thing asked for doesn't exist; the body says what was looked up
+    NotFound(ApiError),
+    /// An upstream source (OpenFoodFacts, USDA) failed. Not our fault, so a 502.
+    UpstreamApi(ApiError),
+    /// An upstream source didn't answer before the request deadline
+    UpstreamTimeout(ApiError),
+    /// The request itself is wrong
+    BadRequest(ApiError),
+}
+
+impl AppError {
+    /// The `code` field of the error body
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::DbPool(_) => "db_unavailable",
+            AppError::DbQuery(_) => "db_query_failed",
+            AppError::Internal(_) => "internal",
+            AppError::NotFound(_) => "not_found",
+            AppError::UpstreamApi(_) => "upstream_failed",
+            AppError::UpstreamTimeout(_) => "upstream_timeout",
+            AppError::BadRequest(_) => "bad_request",
+        }
+    }
+
+    fn body(&self) -> ApiError {
+        let body = match self {
+            AppError::DbPool(_) => return db_unavailable_body(),
+            AppError::DbQuery(_) => ApiError::new("Database query failed"),
+            AppError::Internal(_) => ApiError::new("Internal server error"),
+            AppError::NotFound(body)
+            | AppError::UpstreamApi(body)
+            | AppError::UpstreamTimeout(body)
+            | AppError::BadRequest(body) => body.clone(),
+        };
+        body.with("code", self.code())
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::DbPool(e) => write!(f, "Failed to get DB connection: {}", e),
+            AppError::DbQuery(e) => write!(f, "Database query error: {}", e),
+            AppError::Internal(e) => write!(f, "Internal error: {}", e),
+            AppError::NotFound(body)
+            | AppError::UpstreamApi(body)
+            | AppError::UpstreamTimeout(body)
+            | AppError::BadRequest(body) => write!(f, "{}", body.error.message),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::DbPool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::DbQuery(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::UpstreamApi(_) => StatusCode::BAD_GATEWAY,
+            AppError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Our own failures are logged here; the caller's mistakes and upstream misses aren't
+        if let AppError::DbPool(_) | AppError::DbQuery(_) | AppError::Internal(_) = self {
+            log::error!("{}", self);
+        }
+
+        match self {
+            AppError::DbPool(_) => db_unavailable(),
+            _ => HttpResponse::build(self.status_code()).json(self.body()),
+        }
+    }
+}
+
+fn db_unavailable_body() -> ApiError {
+    ApiError::new("Database connection failed")
+        .with("code", "db_unavailable")
+        .with("retry_after_secs", DB_RETRY_AFTER_SECS)
+}
+
+/// The 503 for a checkout failure the caller has already logged, for handlers that don't
+/// return [`AppError`]
+pub fn db_unavailable() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((RETRY_AFTER, DB_RETRY_AFTER_SECS.to_string()))
+        .json(db_unavailable_body())
+}
+
+impl From<CheckoutError> for AppError {
+    fn from(e: CheckoutError) -> Self {
+        AppError::DbPool(e)
+    }
+}
+
+impl From<diesel::result::Error> for AppError {
+    fn from(e: diesel::result::Error) -> Self {
+        AppError::DbQuery(e)
+    }
+}
+
+impl From<actix_web::error::BlockingError> for AppError {
+    fn from(e: actix_web::error::BlockingError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn respond(error: AppError) -> (StatusCode, Option<String>, serde_json::Value) {
+        let resp = error.error_response();
+        let status = resp.status();
+        let retry_after = resp.headers().get(RETRY_AFTER).map(|v| v.to_str().unwrap().to_string());
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, retry_after, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn test_each_variant_maps_to_status_and_body() {
+        let saturated = CheckoutError::Saturated { max_concurrent: 2, waited: Duration::from_millis(100) };
+        let (status, retry_after, body) = respond(AppError::DbPool(saturated)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(retry_after.as_deref(), Some("1"));
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": { "code": "db_unavailable", "message": "Database connection failed", "retry_after_secs": 1 }
+            })
+        );
+
+        let cases = [
+            (
+                AppError::DbQuery(diesel::result::Error::NotFound),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                serde_json::json!({ "code": "db_query_failed", "message": "Database query failed" }),
+            ),
+            (
+                AppError::Internal("task panicked".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                serde_json::json!({ "code": "internal", "message": "Internal server error" }),
+            ),
+            (
+                AppError::NotFound(ApiError::new("Product not found").with("barcode", "0001")),
+                StatusCode::NOT_FOUND,
+                serde_json::json!({ "code": "not_found", "message": "Product not found", "barcode": "0001" }),
+            ),
+            (
+                AppError::UpstreamApi(ApiError::new("Failed to query product sources")),
+                StatusCode::BAD_GATEWAY,
+                serde_json::json!({ "code": "upstream_failed", "message": "Failed to query product sources" }),
+            ),
+            (
+                AppError::UpstreamTimeout(ApiError::new("Product sources did not respond in time")),
+                StatusCode::GATEWAY_TIMEOUT,
+                serde_json::json!({ "code": "upstream_timeout", "message": "Product sources did not respond in time" }),
+            ),
+            (
+                AppError::BadRequest(ApiError::new("Unknown include 'reviews'")),
+                StatusCode::BAD_REQUEST,
+                serde_json::json!({ "code": "bad_request", "message": "Unknown include 'reviews'" }),
+            ),
+        ];
+        for (error, expected_status, expected_error) in cases {
+            let (status, retry_after, body) = respond(error).await;
+            assert_eq!(status, expected_status);
+            assert_eq!(retry_after, None);
+            assert_eq!(body, serde_json::json!({ "error": expected_error }));
+        }
+    }
+
+    #[test]
+    fn test_question_mark_converts_db_and_blocking_failures() {
+        fn query() -> Result<(), AppError> {
+            Err(diesel::result::Error::NotFound)?
+        }
+        assert!(matches!(query(), Err(AppError::DbQuery(_))));
+
+        let pool_error = CheckoutError::Saturated { max_concurrent: 1, waited: Duration::ZERO };
+        assert!(matches!(AppError::from(pool_error), AppError::DbPool(_)));
+    }
+}
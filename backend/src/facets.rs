@@ -0,0 +1,256 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use diesel::prelude::*;
+use serde::Serialize;
+
+/// Most values returned per facet
+pub const MAX_FACET_VALUES: i64 = 100;
+
+/// One facet value and how many products have it
+#[derive(QueryableByName, Serialize, Debug, Clone, PartialEq)]
+pub struct FacetCount {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub value: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub count: i64,
+}
+
+/// The most frequent values of one facet, at most [`MAX_FACET_VALUES`] of them
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Facet {
+    pub values: Vec<FacetCount>,
+    /// Distinct values in all, including those past the cut
+    pub total: i64,
+    /// Some values were cut
+    pub has_more: bool,
+}
+
+/// A [`FacetCount`] with the number of distinct values, counted before the `LIMIT`
+#[derive(QueryableByName)]
+struct FacetRow {
+    #[diesel(embed)]
+    count: FacetCount,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    total: i64,
+}
+
+impl Facet {
+    fn from_rows(rows: Vec<FacetRow>) -> Facet {
+        let total = rows.first().map_or(0, |row| row.total);
+        let values: Vec<FacetCount> = rows.into_iter().map(|row| row.count).collect();
+        let has_more = total > values.len() as i64;
+        Facet { values, total, has_more }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ProductFacets {
+    pub brands: Facet,
+    pub categories: Facet,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct NonFoodFacets {
+    pub brands: Facet,
+    pub categories: Facet,
+    pub manufacturers: Facet,
+}
+
+/// Food product brands are counted by normalized slug (`brand_tags`), so "Thai Kitchen"
+/// and "thai kitchen" are one brand. Categories are OFF's comma-separated text, each
+/// entry counted separately.
+pub fn product_facets(conn: &mut PgConnection) -> Result<ProductFacets, diesel::result::Error> {
+    Ok(ProductFacets {
+        brands: array_column_counts("products", "brand_tags", conn)?,
+        categories: split_column_counts("products", "categories", conn)?,
+    })
+}
+
+pub fn non_food_facets(conn: &mut PgConnection) -> Result<NonFoodFacets, diesel::result::Error> {
+    Ok(NonFoodFacets {
+        brands: column_counts("products_non_food", "brand", conn)?,
+        categories: column_counts("products_non_food", "category", conn)?,
+        manufacturers: column_counts("products_non_food", "manufacturer", conn)?,
+    })
+}
+
+/// Top values of a comma-separated column, most frequent first
+fn split_column_counts(
+    table: &str,
+    column: &str,
+    conn: &mut PgConnection,
+) -> Result<Facet, diesel::result::Error> {
+    diesel::sql_query(format!(
+        "SELECT trim(entry) AS value, count(DISTINCT t.id) AS count, count(*) OVER () AS total
+         FROM {table} t, unnest(string_to_array(t.{column}, ',')) AS entry
+         WHERE trim(entry) <> ''
+         GROUP BY trim(entry)
+         ORDER BY count DESC, value
+         LIMIT $1"
+    ))
+    .bind::<diesel::sql_types::BigInt, _>(MAX_FACET_VALUES)
+    .load(conn)
+    .map(Facet::from_rows)
+}
+
+/// Top values of a JSONB array column, most frequent first
+fn array_column_counts(
+    table: &str,
+    column: &str,
+    conn: &mut PgConnection,
+) -> Result<Facet, diesel::result::Error> {
+    diesel::sql_query(format!(
+        "SELECT entry AS value, count(DISTINCT t.id) AS count, count(*) OVER () AS total
+         FROM {table} t, jsonb_array_elements_text(t.{column}) AS entry
+         WHERE jsonb_typeof(t.{column}) = 'array' AND entry <> ''
+         GROUP BY entry
+         ORDER BY count DESC, value
+         LIMIT $1"
+    ))
+    .bind::<diesel::sql_types::BigInt, _>(MAX_FACET_VALUES)
+    .load(conn)
+    .map(Facet::from_rows)
+}
+
+/// Top values of a single-valued column, most frequent first
+fn column_counts(table: &str, column: &str, conn: &mut PgConnection) -> Result<Facet, diesel::result::Error> {
+    diesel::sql_query(format!(
+        "SELECT trim({column}) AS value, count(*) AS count, count(*) OVER () AS total
+         FROM {table}
+         WHERE trim({column}) <> ''
+         GROUP BY trim({column})
+         ORDER BY count DESC, value
+         LIMIT $1"
+    ))
+    .bind::<diesel::sql_types::BigInt, _>(MAX_FACET_VALUES)
+    .load(conn)
+    .map(Facet::from_rows)
+}
+
+/// Last computed facets, reused until they are older than the TTL
+pub struct FacetCache<T> {
+    entry: Mutex<Option<(Instant, T)>>,
+}
+
+impl<T: Clone> FacetCache<T> {
+    pub const fn new() -> Self {
+        FacetCache { entry: Mutex::new(None) }
+    }
+
+    /// The cached value if it was stored less than `ttl` before `now`
+    pub fn fresh(&self, now: Instant, ttl: Duration) -> Option<T> {
+        let entry = self.entry.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        entry
+            .as_ref()
+            .filter(|(stored_at, _)| now.duration_since(*stored_at) < ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn store(&self, now: Instant, value: T) {
+        *self.entry.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some((now, value));
+    }
+}
+
+impl<T: Clone> Default for FacetCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static PRODUCT_FACETS: FacetCache<ProductFacets> = FacetCache::new();
+pub static NON_FOOD_FACETS: FacetCache<NonFoodFacets> = FacetCache::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_connection;
+
+    #[test]
+    fn test_cache_expires_after_ttl() {
+        let cache = FacetCache::new();
+        let start = Instant::now();
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(cache.fresh(start, ttl), None);
+
+        cache.store(start, vec![1, 2, 3]);
+        assert_eq!(cache.fresh(start + Duration::from_secs(59), ttl), Some(vec![1, 2, 3]));
+        assert_eq!(cache.fresh(start + Duration::from_secs(60), ttl), None);
+
+        // A zero TTL disables caching
+        assert_eq!(cache.fresh(start, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_facet_counts_over_seeded_products() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        for (barcode, off_product) in [
+            (
+                "facet-test-1",
+                serde_json::json!({
+                    "brands": "Facet Brand A, Facet Brand B",
+                    "brands_tags": ["facet-brand-a", "facet-brand-b"],
+                    "categories": "Facet Snacks,Facet Sweets"
+                }),
+            ),
+            ("facet-test-2", serde_json::json!({ "brands": "facet brand a", "categories": "Facet Snacks" })),
+            ("facet-test-3", serde_json::json!({ "brands": " , Facet Brand A", "categories": "Facet Snacks" })),
+        ] {
+            diesel::insert_into(crate::schema::products::table)
+                .values(&crate::off::extract(barcode, &off_product))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let facets = product_facets(&mut conn).unwrap();
+        let count_of = |counts: &[FacetCount], value: &str| counts.iter().find(|c| c.value == value).map(|c| c.count);
+
+        // Spelling and case differences collapse onto one slug
+        assert_eq!(count_of(&facets.brands.values, "facet-brand-a"), Some(3));
+        assert_eq!(count_of(&facets.brands.values, "facet-brand-b"), Some(1));
+        assert_eq!(count_of(&facets.categories.values, "Facet Snacks"), Some(3));
+        assert_eq!(count_of(&facets.categories.values, "Facet Sweets"), Some(1));
+        assert!(facets.brands.values.iter().all(|c| !c.value.is_empty()));
+        assert!(facets.brands.values.windows(2).all(|pair| pair[0].count >= pair[1].count));
+
+        // The total counts every distinct value, the cut ones included
+        let total_brands: i64 = diesel::sql_query(
+            "SELECT count(DISTINCT entry) AS count FROM products, jsonb_array_elements_text(brand_tags) AS entry \
+             WHERE jsonb_typeof(brand_tags) = 'array' AND entry <> ''",
+        )
+        .get_result::<Total>(&mut conn)
+        .unwrap()
+        .count;
+        assert_eq!(facets.brands.total, total_brands);
+        assert_eq!(facets.brands.values.len() as i64, total_brands.min(MAX_FACET_VALUES));
+        assert_eq!(facets.brands.has_more, total_brands > MAX_FACET_VALUES);
+    }
+
+    #[derive(QueryableByName)]
+    struct Total {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+
+    #[test]
+    fn test_facet_says_when_values_were_cut() {
+        let row = |value: &str, total: i64| FacetRow {
+            count: FacetCount { value: value.to_string(), count: 1 },
+            total,
+        };
+
+        let cut = Facet::from_rows(vec![row("a", 150), row("b", 150)]);
+        assert_eq!(cut.values.len(), 2);
+        assert_eq!(cut.total, 150);
+        assert!(cut.has_more);
+
+        let whole = Facet::from_rows(vec![row("a", 2), row("b", 2)]);
+        assert!(!whole.has_more);
+        assert_eq!(Facet::from_rows(Vec::new()), Facet::default());
+    }
+}
@@ -0,0 +1,48 @@
+//! Recorded OpenFoodFacts and USDA FoodData Central responses for tests, kept as JSON
+//! under `tests/fixtures` so extraction and job logic run against realistic payloads.
+//!
+//! OFF (`tests/fixtures/off`, API v2 product responses): `full` (every field we read),
+//! `minimal` (name only), `not_found`, `multilingual` (French product with `_fr`/`_en` fields),
+//! `nameless_ingredients` (`ingredients` entries named only by `text_en`, or not at all),
+//! `string_numbers` (an older product sending `nova_group` and `rev` as strings).
+//! USDA (`tests/fixtures/usda`, `/foods/search` responses): `foundation`, `branded`, `empty`,
+//! `trans_fat` (branded shortening listing total trans fat), `branded_first` (a "salt" search
+//! that ranks branded chips above the Foundation food).
+
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+fn load(source: &str, name: &str) -> Value {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", source, &format!("{}.json", name)]
+        .iter()
+        .collect();
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("fixture {}: {}", path.display(), e));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("fixture {} is not valid JSON: {}", path.display(), e))
+}
+
+/// The whole OFF response, envelope included
+pub fn off_response(name: &str) -> Value {
+    load("off", name)
+}
+
+/// The `product` object of an OFF response, as `off::extract` receives it
+pub fn off_product(name: &str) -> Value {
+    off_response(name)
+        .get("product")
+        .cloned()
+        .unwrap_or_else(|| panic!("OFF fixture {} has no product", name))
+}
+
+/// The whole USDA search response
+pub fn usda_search(name: &str) -> Value {
+    load("usda", name)
+}
+
+/// The first food of a USDA search response
+pub fn usda_food(name: &str) -> Value {
+    usda_search(name)["foods"]
+        .get(0)
+        .cloned()
+        .unwrap_or_else(|| panic!("USDA fixture {} has no foods", name))
+}
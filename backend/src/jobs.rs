@@ -1,7 +1,27 @@
 use async_trait::async_trait;
 use fang::asynk::async_queue::AsyncQueueable;
 use fang::{AsyncRunnable, Deserialize, FangError, Scheduled, Serialize};
-use serde_json::Value;
+use std::sync::OnceLock;
+
+/// Connections for job runs: two for each of the [`WORKERS`](crate::queue::WORKERS)
+/// `workers::start_worker_pool` starts, since a run may briefly hold a second one
+const JOB_POOL_SIZE: u32 = 2 * crate::queue::WORKERS;
+
+static JOB_POOL: OnceLock<crate::db::DbPool> = OnceLock::new();
+
+/// The pool every job run checks its connections out of, built on first use. A pool per
+/// run would open fresh Postgres connections for each job and drop them again.
+pub(crate) fn job_pool() -> &'static crate::db::DbPool {
+    JOB_POOL.get_or_init(|| crate::db::establish_connection_pool_with_size(JOB_POOL_SIZE))
+}
+
+/// Longest wait between retries, so a job with a high ENRICHMENT_MAX_RETRIES still comes back
+const MAX_BACKOFF_SECS: u32 = 6 * 60 * 60;
+
+/// Seconds before retry `attempt`: 60s, 120s, 240s and so on, capped at [`MAX_BACKOFF_SECS`]
+fn exponential_backoff(attempt: u32) -> u32 {
+    2_u32.saturating_pow(attempt).saturating_mul(60).min(MAX_BACKOFF_SECS)
+}
 
 /// Job to fetch and cache a product from OpenFoodFacts
 #[derive(Serialize, Deserialize)]
@@ -10,41 +30,132 @@ pub struct FetchProductJob {
     pub barcode: String,
 }
 
+impl FetchProductJob {
+    /// Ask OpenFoodFacts (at `base_url`) for the product. `None` when OFF doesn't have it.
+    async fn fetch(&self, base_url: &str) -> Result<Option<serde_json::Value>, String> {
+        let url = format!("{}/api/v2/product/{}", base_url, self.barcode);
+
+        let response = crate::http_client::get(crate::http_client::Upstream::OpenFoodFacts, &url)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to fetch product {}: {}", self.barcode, e);
+                format!("Fetch error: {}", e)
+            })?;
+        let data = crate::http_client::json::<crate::models::OpenFoodFactsResponse>(response)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to parse response for {}: {}", self.barcode, e);
+                format!("Parse error: {}", e)
+            })?;
+
+        log::info!("Successfully fetched product {}", self.barcode);
+        Ok(data.product.filter(|_| data.status == 1))
+    }
+
+    /// The row OFF's `product` object maps to, extracted exactly as product lookups do
+    fn new_product(&self, product_data: &serde_json::Value) -> crate::models::NewProduct {
+        crate::models::NewProduct {
+            data_source: Some("openfoodfacts".to_string()),
+            ..crate::off::extract(&self.barcode, product_data)
+        }
+    }
+
+    /// Record the lookup and store what OFF answered (`product_data`, fetched from
+    /// `started_at`). Returns the inserted or refreshed product, `None` when OFF doesn't have
+    /// it, its revision is unchanged or another refresh got there first.
+    fn store(
+        &self,
+        product_data: Option<&serde_json::Value>,
+        started_at: chrono::NaiveDateTime,
+        conn: &mut diesel::PgConnection,
+    ) -> Result<Option<crate::models::Product>, diesel::result::Error> {
+        use diesel::prelude::*;
+        use crate::models::{Product, ProductLookup, Refreshed};
+        use crate::schema::products;
+
+        ProductLookup::record(&self.barcode, product_data.is_some(), &crate::clock::SystemClock, conn)?;
+
+        let Some(product_data) = product_data else {
+            log::info!("Product {} not found on OpenFoodFacts", self.barcode);
+            return Ok(None);
+        };
+
+        let stored = products::table
+            .filter(products::barcode.eq(&self.barcode))
+            .first::<Product>(conn)
+            .optional()?;
+        let new_product = self.new_product(product_data);
+
+        let Some(stored) = stored else {
+            let (product, inserted) = Product::insert_or_get(&new_product, conn)?;
+            if !inserted {
+                log::info!("Product {} was stored elsewhere while fetching, skipping insert", self.barcode);
+                return Ok(None);
+            }
+            log::info!("Product {} stored at rev {:?}", self.barcode, new_product.off_rev);
+            return Ok(Some(product));
+        };
+
+        match stored.apply_refresh(&new_product, started_at, chrono::Utc::now().naive_utc(), conn)? {
+            Refreshed::Updated(product) => {
+                log::info!("Product {} stored at rev {:?}", self.barcode, new_product.off_rev);
+                Ok(Some(*product))
+            }
+            // Nothing changed upstream - only recorded that we checked
+            Refreshed::Unchanged => {
+                log::info!("Product {} unchanged at rev {:?}, skipping update", self.barcode, new_product.off_rev);
+                Ok(None)
+            }
+            Refreshed::ClaimedElsewhere => {
+                log::info!("Product {} was refreshed elsewhere while fetching, skipping update", self.barcode);
+                Ok(None)
+            }
+        }
+    }
+}
+
 #[typetag::serde]
 #[async_trait]
 impl AsyncRunnable for FetchProductJob {
-    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
         log::info!("Processing FetchProductJob for barcode: {}", self.barcode);
+        let started_at = chrono::Utc::now().naive_utc();
+
+        // An HTML outage page fails here too, so the job is retried with backoff
+        let product_data = self
+            .fetch(&crate::http_client::Upstream::OpenFoodFacts.base_url())
+            .await
+            .map_err(|description| FangError { description })?;
+
+        let pool = job_pool();
+        let mut conn = pool.get().map_err(|e| FangError {
+            description: format!("Database connection error: {}", e),
+        })?;
+        let saved = self
+            .store(product_data.as_ref(), started_at, &mut conn)
+            .map_err(|e| FangError {
+                description: format!("Database error: {}", e),
+            })?;
+        drop(conn);
+        let Some(saved) = saved else {
+            return Ok(());
+        };
 
-        // Fetch from OpenFoodFacts API
-        let client = reqwest::Client::new();
-        let url = format!(
-            "https://world.openfoodfacts.org/api/v2/product/{}",
-            self.barcode
-        );
-
-        match client.get(&url).send().await {
-            Ok(response) => match response.json::<Value>().await {
-                Ok(_data) => {
-                    log::info!("Successfully fetched product {}", self.barcode);
-                    // Here you would normally save to database
-                    // For now just log success
-                    Ok(())
-                }
-                Err(e) => {
-                    log::error!("Failed to parse response for {}: {}", self.barcode, e);
-                    Err(FangError {
-                        description: format!("Parse error: {}", e),
-                    })
-                }
-            },
-            Err(e) => {
-                log::error!("Failed to fetch product {}: {}", self.barcode, e);
-                Err(FangError {
-                    description: format!("Fetch error: {}", e),
-                })
+        // A new revision often leaves the ingredient list as it was
+        crate::product_ingredients::process_if_changed(&saved.ingredient_data(), saved.id, pool, queue).await;
+
+        // No ingredients from OFF, but a photo of them to read
+        let class = crate::backpressure::JobClass::Background;
+        if crate::config::get().ocr_enabled
+            && saved.ocr_image_url().is_some()
+            && pool.get().is_ok_and(|mut conn| crate::backpressure::check(class, class.max_pending(), &mut conn).is_ok())
+        {
+            match queue.insert_task(&OcrIngredientsJob { product_id: saved.id }).await {
+                Ok(_) => log::info!("Enqueued OCR of ingredients photo for {}", self.barcode),
+                Err(e) => log::error!("Failed to enqueue OCR job for {}: {:?}", self.barcode, e),
             }
         }
+        Ok(())
     }
 
     fn uniq(&self) -> bool {
@@ -56,12 +167,11 @@ impl AsyncRunnable for FetchProductJob {
     }
 
     fn max_retries(&self) -> i32 {
-        3
+        crate::config::get().enrichment_max_retries
     }
 
     fn backoff(&self, attempt: u32) -> u32 {
-        // Exponential backoff: 60s, 120s, 240s
-        60 * (2_u32.pow(attempt))
+        exponential_backoff(attempt)
     }
 }
 
@@ -84,6 +194,14 @@ impl AsyncRunnable for AnalyzeIngredientsJob {
         // Simulate analysis work
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
+        let pool = job_pool();
+        let mut conn = pool.get().map_err(|e| FangError {
+            description: format!("Database connection error: {}", e),
+        })?;
+        crate::models::Product::mark_analyzed(self.product_id, &mut conn).map_err(|e| FangError {
+            description: format!("Database error: {}", e),
+        })?;
+
         log::info!("Completed ingredient analysis for {}", self.product_id);
         Ok(())
     }
@@ -101,6 +219,162 @@ impl AsyncRunnable for AnalyzeIngredientsJob {
     }
 }
 
+/// Job to read a product's ingredients off its ingredients photo when OFF has no
+/// `ingredients_text` for it, then process them like OFF's own list
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct OcrIngredientsJob {
+    pub product_id: i32,
+}
+
+impl OcrIngredientsJob {
+    /// Recognize, store and process the product's ingredient list. Returns the stored
+    /// text, or `None` when there was nothing to read (product gone, ingredients already
+    /// known to OFF, no photo, or no text found on it).
+    pub async fn read_ingredients(
+        &self,
+        backend: &dyn crate::ocr::OcrBackend,
+        pool: &crate::db::DbPool,
+        queue: &mut dyn AsyncQueueable,
+    ) -> Result<Option<String>, String> {
+        use diesel::prelude::*;
+        use crate::models::Product;
+        use crate::schema::products;
+
+        let db_error = |e: diesel::result::Error| format!("Database error: {}", e);
+
+        let mut conn = pool.get().map_err(|e| format!("Database connection error: {}", e))?;
+        let product = products::table
+            .find(self.product_id)
+            .first::<Product>(&mut conn)
+            .optional()
+            .map_err(db_error)?;
+        drop(conn);
+
+        let Some(image_url) = product.as_ref().and_then(|product| product.ocr_image_url()) else {
+            log::info!("Product {} has no ingredients photo to read", self.product_id);
+            return Ok(None);
+        };
+
+        let image = crate::ocr::download_image(image_url).await?;
+        let recognized = backend.recognize(&image).await?;
+        let Some(text) = crate::ocr::ingredients_text(&recognized) else {
+            log::warn!("No text recognized on the ingredients photo of product {}", self.product_id);
+            return Ok(None);
+        };
+
+        let mut conn = pool.get().map_err(|e| format!("Database connection error: {}", e))?;
+        let product = Product::store_ocr_ingredients(self.product_id, &text, &mut conn).map_err(db_error)?;
+        drop(conn);
+
+        crate::product_ingredients::process_if_changed(&product.ingredient_data(), product.id, pool, queue).await;
+        Ok(Some(text))
+    }
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for OcrIngredientsJob {
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        log::info!("Processing OcrIngredientsJob for product_id: {}", self.product_id);
+
+        // Jobs queued before OCR was switched off have nothing to call
+        if !crate::config::get().ocr_enabled {
+            log::info!("OCR is disabled, skipping product {}", self.product_id);
+            return Ok(());
+        }
+        let Some(backend) = crate::ocr::HttpOcrBackend::from_config(crate::config::get()) else {
+            log::warn!("OCR_SERVICE_URL is not set, skipping product {}", self.product_id);
+            return Ok(());
+        };
+
+        let pool = job_pool();
+        let text = self
+            .read_ingredients(&backend, pool, queue)
+            .await
+            .map_err(|description| FangError { description })?;
+
+        if let Some(text) = text {
+            log::info!("Read {} characters of ingredients for product {}", text.len(), self.product_id);
+        }
+        Ok(())
+    }
+
+    fn uniq(&self) -> bool {
+        true
+    }
+
+    fn task_type(&self) -> String {
+        "ocr_ingredients".to_string()
+    }
+
+    fn max_retries(&self) -> i32 {
+        crate::config::get().enrichment_max_retries
+    }
+}
+
+/// Job to re-run enrichment for a non-food product, triggered on demand by curators
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct EnrichNonFoodJob {
+    pub product_id: i32,
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for EnrichNonFoodJob {
+    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        use diesel::prelude::*;
+        use crate::models::ProductNonFood;
+        use crate::schema::products_non_food;
+
+        log::info!("Processing EnrichNonFoodJob for product_id: {}", self.product_id);
+
+        let pool = job_pool();
+        let mut conn = pool.get().map_err(|e| FangError {
+            description: format!("Database connection error: {}", e),
+        })?;
+
+        let product = products_non_food::table
+            .find(self.product_id)
+            .first::<ProductNonFood>(&mut conn)
+            .optional()
+            .map_err(|e| FangError {
+                description: format!("Database error: {}", e),
+            })?;
+
+        let Some(product) = product else {
+            log::warn!("Non-food product {} no longer exists, nothing to enrich", self.product_id);
+            return Ok(());
+        };
+
+        // No external UPC database is wired up yet; once one is, merge its data here
+        log::info!(
+            "No external UPC source configured for '{}' (barcode {:?})",
+            product.name, product.barcode
+        );
+
+        ProductNonFood::mark_verified(product.id, &mut conn).map_err(|e| FangError {
+            description: format!("Database error: {}", e),
+        })?;
+
+        log::info!("Completed enrichment for non-food product {}", self.product_id);
+        Ok(())
+    }
+
+    fn uniq(&self) -> bool {
+        true
+    }
+
+    fn task_type(&self) -> String {
+        "enrich_non_food".to_string()
+    }
+
+    fn max_retries(&self) -> i32 {
+        crate::config::get().enrichment_max_retries
+    }
+}
+
 /// Job to send notifications (email, push, etc.)
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "fang::serde")]
@@ -144,16 +418,28 @@ impl AsyncRunnable for SendNotificationJob {
     }
 }
 
-/// Recurring job to clean up old data
+/// Job to clean up old data. Runs daily and on demand.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct CleanupJob {
+    /// The scheduled instance re-arms itself; on-demand runs don't
+    pub recurring: bool,
+}
+
+/// Recurring job that alerts the ops channel when enrichment jobs keep exhausting their retries
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "fang::serde")]
-pub struct CleanupJob {}
+pub struct FailureAlertJob {}
 
 /// Job to create a new ingredient
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "fang::serde")]
 pub struct CreateIngredientJob {
     pub name: String,
+    /// The ingredient whose ingredient statement listed this one, when enqueued as its
+    /// sub-ingredient
+    #[serde(default)]
+    pub parent_id: Option<i32>,
 }
 
 #[typetag::serde]
@@ -179,7 +465,7 @@ impl AsyncRunnable for CleanupJob {
 
     fn cron(&self) -> Option<Scheduled> {
         // Run every day at 2 AM
-        Some(Scheduled::CronPattern("0 2 * * *".to_string()))
+        self.recurring.then(|| Scheduled::CronPattern("0 2 * * *".to_string()))
     }
 
     fn max_retries(&self) -> i32 {
@@ -187,268 +473,996 @@ impl AsyncRunnable for CleanupJob {
     }
 }
 
-#[typetag::serde]
-#[async_trait]
-impl AsyncRunnable for CreateIngredientJob {
-    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
-        log::info!("Creating ingredient: {}", self.name);
+/// Task types whose failures mean USDA/OpenFoodFacts enrichment is degraded
+const ENRICHMENT_TASK_TYPES: [&str; 3] = ["create_ingredient", "enrich_non_food", "fetch_product"];
 
-        // Fetch nutritional data from USDA FoodData Central
-        let usda_data = self.fetch_usda_data().await;
+/// Notifications addressed to this user id go to the ops channel
+const OPS_ALERT_USER_ID: i32 = 0;
+const OPS_ALERT_NOTIFICATION_TYPE: &str = "ops_alert";
 
-        // Get database URL
-        let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+#[derive(diesel::QueryableByName, Debug)]
+struct TaskFailureCount {
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    task_type: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    failures: i64,
+}
 
-        // Establish database connection
-        use diesel::r2d2::{self, ConnectionManager};
-        use diesel::{PgConnection, RunQueryDsl};
-        use crate::models::NewIngredient;
-        use crate::schema::ingredients;
+#[derive(diesel::QueryableByName)]
+struct RecentAlertCount {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    alerts: i64,
+}
 
-        let manager = ConnectionManager::<PgConnection>::new(database_url);
-        let pool = r2d2::Pool::builder()
-            .max_size(3)
-            .build(manager)
-            .expect("Failed to create pool");
-
-        let mut conn = pool.get().expect("Failed to get connection from pool");
-
-        // Create new ingredient with nutritional data if available
-        let new_ingredient = if let Some(ref data) = usda_data {
-            log::info!("Found USDA data for ingredient: {}", self.name);
-            NewIngredient {
-                name: self.name.clone(),
-                branded: false,
-                gram_protein_per_gram: data.protein,
-                gram_carbs_per_gram: data.carbs,
-                gram_fat_per_gram: data.fat,
-                gram_fiber_per_gram: data.fiber,
-            }
-        } else {
-            log::info!("No USDA data found, creating ingredient with name only: {}", self.name);
-            NewIngredient {
-                name: self.name.clone(),
-                branded: false,
-                gram_protein_per_gram: None,
-                gram_carbs_per_gram: None,
-                gram_fat_per_gram: None,
-                gram_fiber_per_gram: None,
-            }
-        };
+/// Longest error message returned by the failure listing; the rest is cut off
+pub const MAX_ERROR_MESSAGE_LEN: usize = 500;
 
-        let result = diesel::insert_into(ingredients::table)
-            .values(&new_ingredient)
-            .get_result::<crate::models::Ingredient>(&mut conn);
+/// A task that failed for good (`failed`, kept by the workers' `RemoveFinished` retention,
+/// see [`crate::queue::worker_pool`]) or is waiting for another attempt (`retried`)
+#[derive(diesel::QueryableByName, Serialize, Debug)]
+#[serde(crate = "fang::serde")]
+pub struct FailedTask {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub id: String,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub task_type: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub state: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub error_message: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub retries: i32,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
 
-        match result {
-            Ok(created_ingredient) => {
-                log::info!("Successfully created ingredient: {} (ID: {})", self.name, created_ingredient.id);
+/// Failed and retried tasks last updated after `since`, most recent first, with error
+/// messages redacted and truncated for display
+pub fn recent_failures(
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    limit: i64,
+    conn: &mut diesel::PgConnection,
+) -> Result<Vec<FailedTask>, diesel::result::Error> {
+    use diesel::prelude::*;
+    use diesel::sql_types::{BigInt, Nullable, Timestamptz};
+
+    let mut tasks = diesel::sql_query(
+        "SELECT id::text AS id, task_type, state::text AS state, error_message, retries, created_at, updated_at \
+         FROM fang_tasks \
+         WHERE state IN ('failed', 'retried') AND ($1::timestamptz IS NULL OR updated_at >= $1) \
+         ORDER BY updated_at DESC, id \
+         LIMIT $2",
+    )
+    .bind::<Nullable<Timestamptz>, _>(since)
+    .bind::<BigInt, _>(limit)
+    .load::<FailedTask>(conn)?;
+
+    for task in &mut tasks {
+        task.error_message = task.error_message.as_deref().map(display_error);
+    }
+    Ok(tasks)
+}
 
-                // Check for sub-ingredients and enqueue them
-                if let Some(ref data) = usda_data {
-                    self.process_sub_ingredients(data, created_ingredient.id).await;
-                }
+/// Tasks of one type by state. `pending` counts new tasks and failed ones waiting for their
+/// retry, including those not due yet; `failed` only those that ran out of retries.
+#[derive(Serialize, Debug, Default, PartialEq)]
+#[serde(crate = "fang::serde")]
+pub struct JobStats {
+    pub pending: i64,
+    pub running: i64,
+    pub failed: i64,
+    pub finished: i64,
+}
 
-                Ok(())
-            }
-            Err(e) => {
-                log::error!("Failed to create ingredient '{}': {}", self.name, e);
-                Err(FangError {
-                    description: format!("Database error: {}", e),
-                })
-            }
+/// Task counts in `fang_tasks` by task type
+pub fn job_stats(conn: &mut diesel::PgConnection) -> Result<std::collections::BTreeMap<String, JobStats>, diesel::result::Error> {
+    use diesel::prelude::*;
+
+    #[derive(diesel::QueryableByName)]
+    struct StateCount {
+        #[diesel(sql_type = diesel::sql_types::Varchar)]
+        task_type: String,
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        state: String,
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        tasks: i64,
+    }
+
+    let counts = diesel::sql_query(
+        "SELECT task_type, state::text AS state, COUNT(*) AS tasks FROM fang_tasks GROUP BY task_type, state",
+    )
+    .load::<StateCount>(conn)?;
+
+    let mut stats = std::collections::BTreeMap::<String, JobStats>::new();
+    for count in counts {
+        let by_state = stats.entry(count.task_type).or_default();
+        match count.state.as_str() {
+            "new" | "retried" => by_state.pending += count.tasks,
+            "in_progress" => by_state.running += count.tasks,
+            "failed" => by_state.failed += count.tasks,
+            "finished" => by_state.finished += count.tasks,
+            other => log::warn!("Unknown fang task state '{}'", other),
+        }
+    }
+    Ok(stats)
+}
+
+/// Error text safe to show: API keys in URLs are masked (reqwest errors include the
+/// request URL, and USDA takes its key as a query parameter) and long text is cut off
+fn display_error(message: &str) -> String {
+    let mut redacted = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(pos) = rest.find("api_key=") {
+        let (before, after) = rest.split_at(pos + "api_key=".len());
+        redacted.push_str(before);
+        redacted.push_str("REDACTED");
+        let key_len = after
+            .find(|c: char| c == '&' || c == ')' || c == '"' || c.is_whitespace())
+            .unwrap_or(after.len());
+        rest = &after[key_len..];
+    }
+    redacted.push_str(rest);
+
+    match redacted.char_indices().nth(MAX_ERROR_MESSAGE_LEN) {
+        Some((cut, _)) => format!("{}... ({} chars truncated)", &redacted[..cut], redacted[cut..].chars().count()),
+        None => redacted,
+    }
+}
+
+/// Pick the task types that crossed the failure threshold, unless an alert already went out
+/// in the current window (one outage should produce one alert, not one per check)
+fn task_types_to_alert(
+    failures: &[TaskFailureCount],
+    threshold: i64,
+    recently_alerted: bool,
+) -> Vec<String> {
+    if recently_alerted {
+        return Vec::new();
+    }
+
+    failures
+        .iter()
+        .filter(|f| f.failures >= threshold)
+        .map(|f| format!("{} ({} failed)", f.task_type, f.failures))
+        .collect()
+}
+
+impl FailureAlertJob {
+    /// Alert the ops channel if any of `task_types` exhausted its retries at least
+    /// JOB_FAILURE_ALERT_THRESHOLD times within the window
+    async fn check(task_types: &[&str], queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        use diesel::prelude::*;
+        use diesel::sql_types::{Array, Integer, Text};
+
+        let window_minutes = crate::config::get().job_failure_alert_window_minutes;
+
+        let pool = job_pool();
+        let mut conn = pool.get().map_err(|e| FangError {
+            description: format!("Database connection error: {}", e),
+        })?;
+
+        let failures = diesel::sql_query(
+            "SELECT task_type, COUNT(*) AS failures FROM fang_tasks \
+             WHERE state = 'failed' AND task_type = ANY($1) \
+             AND updated_at > NOW() - make_interval(mins => $2) \
+             GROUP BY task_type",
+        )
+        .bind::<Array<Text>, _>(task_types)
+        .bind::<Integer, _>(window_minutes)
+        .load::<TaskFailureCount>(&mut conn)
+        .map_err(|e| FangError {
+            description: format!("Database error: {}", e),
+        })?;
+
+        let recent_alerts = diesel::sql_query(
+            "SELECT COUNT(*) AS alerts FROM fang_tasks \
+             WHERE task_type = 'send_notification' \
+             AND metadata->>'notification_type' = $1 \
+             AND created_at > NOW() - make_interval(mins => $2)",
+        )
+        .bind::<Text, _>(OPS_ALERT_NOTIFICATION_TYPE)
+        .bind::<Integer, _>(window_minutes)
+        .get_result::<RecentAlertCount>(&mut conn)
+        .map_err(|e| FangError {
+            description: format!("Database error: {}", e),
+        })?;
+
+        let alerting = task_types_to_alert(&failures, crate::config::get().job_failure_alert_threshold, recent_alerts.alerts > 0);
+
+        if alerting.is_empty() {
+            log::info!("No enrichment failure alerts to send");
+            return Ok(());
         }
+
+        let job = SendNotificationJob {
+            user_id: OPS_ALERT_USER_ID,
+            notification_type: OPS_ALERT_NOTIFICATION_TYPE.to_string(),
+            message: format!(
+                "Enrichment jobs exhausted their retries in the last {} minutes: {}",
+                window_minutes,
+                alerting.join(", ")
+            ),
+        };
+
+        log::warn!("{}", job.message);
+
+        queue.insert_task(&job).await.map_err(|e| FangError {
+            description: format!("Failed to enqueue ops alert: {:?}", e),
+        })?;
+
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for FailureAlertJob {
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        Self::check(&ENRICHMENT_TASK_TYPES, queue).await
     }
 
     fn uniq(&self) -> bool {
-        true // Prevent duplicate creation jobs for the same ingredient
+        true
     }
 
     fn task_type(&self) -> String {
-        "create_ingredient".to_string()
+        "failure_alert".to_string()
+    }
+
+    fn cron(&self) -> Option<Scheduled> {
+        // Check every 10 minutes
+        Some(Scheduled::CronPattern("0 */10 * * * *".to_string()))
     }
 
     fn max_retries(&self) -> i32 {
-        3
+        1
     }
 }
 
-#[derive(Debug, Clone)]
-struct USDANutritionData {
-    protein: Option<f32>,
-    carbs: Option<f32>,
-    fat: Option<f32>,
-    fiber: Option<f32>,
-    food_data: serde_json::Value, // Store full food data for sub-ingredient extraction
-}
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for CreateIngredientJob {
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        log::info!("Creating ingredient: {}", self.name);
 
-impl CreateIngredientJob {
-    /// Fetch nutritional data from USDA FoodData Central API
-    async fn fetch_usda_data(&self) -> Option<USDANutritionData> {
-        // Get API key from environment (optional - has demo key fallback)
-        let api_key = std::env::var("USDA_API_KEY")
-            .unwrap_or_else(|_| "DEMO_KEY".to_string());
+        // Jobs queued before names were length-checked would fail the insert on every retry
+        if !crate::models::ingredient_name_fits(&self.name, crate::config::get().max_ingredient_name_len) {
+            return Ok(());
+        }
 
-        let client = reqwest::Client::new();
-        let url = format!(
-            "https://api.nal.usda.gov/fdc/v1/foods/search?api_key={}&query={}",
-            api_key,
-            urlencoding::encode(&self.name)
-        );
+        let mut conn = job_pool().get().map_err(|e| FangError {
+            description: format!("Database connection error: {}", e),
+        })?;
 
-        log::info!("Searching USDA FoodData Central for: {}", self.name);
+        let db_error = |e: diesel::result::Error| FangError {
+            description: format!("Database error: {}", e),
+        };
 
-        match client.get(&url).send().await {
-            Ok(response) => {
-                match response.json::<serde_json::Value>().await {
-                    Ok(data) => {
-                        // Check if we got any foods back
-                        let foods = data.get("foods").and_then(|f| f.as_array());
-
-                        if let Some(foods_array) = foods {
-                            if let Some(first_food) = foods_array.first() {
-                                log::info!("Found USDA match for '{}': {}",
-                                    self.name,
-                                    first_food.get("description")
-                                        .and_then(|d| d.as_str())
-                                        .unwrap_or("unknown")
-                                );
-
-                                return self.extract_nutrition_data(first_food);
+        // Already exists under this name or a registered alias: either another job made it,
+        // or this is a retry of a run that inserted it and failed before finishing
+        let ingredient = match self.existing(&mut conn).map_err(db_error)? {
+            Some(existing) if existing.sub_ingredients_processed => {
+                log::info!("Ingredient '{}' already exists (ID: {}), skipping creation", self.name, existing.id);
+                return self.link_to_parent(existing.id, &mut conn).map_err(db_error);
+            }
+            Some(existing) => {
+                log::info!("Ingredient '{}' exists (ID: {}) but its sub-ingredients weren't processed, resuming", self.name, existing.id);
+                existing
+            }
+            None => {
+                // Fetch nutritional data from USDA FoodData Central. When it's throttling us,
+                // fail so fang retries the job later rather than storing the ingredient bare.
+                let search = match self.fetch_usda_data(&crate::http_client::Upstream::Usda.base_url()).await {
+                    Err(throttled @ UsdaSearchError::Throttled { .. }) => {
+                        return Err(FangError { description: throttled.to_string() });
+                    }
+                    search => search,
+                };
+                let usda_data = search.as_ref().ok().and_then(Option::as_ref);
+
+                match self.create(usda_data, crate::config::get().min_usda_match_confidence, &mut conn) {
+                    Ok(Some(created_ingredient)) => {
+                        // A failed search leaves usda_searched_at unset, so the backfill retries it first
+                        if search.is_ok() {
+                            let searched_now = chrono::Utc::now().naive_utc();
+                            if let Err(e) = crate::models::Ingredient::record_usda_search(
+                                created_ingredient.id,
+                                usda_data.is_none(),
+                                searched_now,
+                                &mut conn,
+                            ) {
+                                log::error!("Failed to record the USDA search for '{}': {}", self.name, e);
                             }
                         }
-
-                        log::info!("No USDA results found for: {}", self.name);
-                        None
+                        created_ingredient
                     }
-                    Err(e) => {
-                        log::error!("Failed to parse USDA response for '{}': {}", self.name, e);
-                        None
+                    // Created concurrently, but it still belongs under this job's parent
+                    Ok(None) => {
+                        if let Some(existing) = self.existing(&mut conn).map_err(db_error)? {
+                            self.link_to_parent(existing.id, &mut conn).map_err(db_error)?;
+                        }
+                        return Ok(());
                     }
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to fetch USDA data for '{}': {}", self.name, e);
-                None
-            }
-        }
-    }
-
-    /// Extract nutrition data from USDA food item
-    fn extract_nutrition_data(&self, food: &serde_json::Value) -> Option<USDANutritionData> {
-        let nutrients = food.get("foodNutrients").and_then(|n| n.as_array())?;
-
-        let mut protein = None;
-        let mut carbs = None;
-        let mut fat = None;
-        let mut fiber = None;
-
-        // USDA nutrient IDs (from FoodData Central)
-        // 1003 = Protein, 1005 = Carbs, 1004 = Fat, 1079 = Fiber
-        for nutrient in nutrients {
-            if let Some(nutrient_id) = nutrient.get("nutrientId").and_then(|id| id.as_i64()) {
-                if let Some(value) = nutrient.get("value").and_then(|v| v.as_f64()) {
-                    // Convert from per 100g to per 1g
-                    let value_per_gram = (value / 100.0) as f32;
-
-                    match nutrient_id {
-                        1003 => protein = Some(value_per_gram), // Protein
-                        1005 => carbs = Some(value_per_gram),   // Carbs
-                        1004 => fat = Some(value_per_gram),     // Fat
-                        1079 => fiber = Some(value_per_gram),   // Fiber
-                        _ => {}
+                    Err(e) => {
+                        log::error!("Failed to create ingredient '{}': {}", self.name, e);
+                        return Err(db_error(e));
                     }
                 }
             }
-        }
-
-        log::info!(
-            "Extracted nutrition for '{}': protein={:?}g, carbs={:?}g, fat={:?}g, fiber={:?}g per gram",
-            self.name, protein, carbs, fat, fiber
-        );
+        };
 
-        Some(USDANutritionData {
-            protein,
-            carbs,
-            fat,
-            fiber,
-            food_data: food.clone(), // Store full food data for sub-ingredient parsing
-        })
+        self.link_to_parent(ingredient.id, &mut conn).map_err(db_error)?;
+        self.process_sub_ingredients(&ingredient, queue, &mut conn).await
     }
 
-    /// Process sub-ingredients: check if ingredient has components and enqueue jobs
-    async fn process_sub_ingredients(&self, usda_data: &USDANutritionData, _parent_id: i32) {
-        log::info!("Checking for sub-ingredients in '{}'", self.name);
-
-        // Try to extract ingredients from the food data
-        // USDA Branded foods sometimes have an "ingredients" field
-        let ingredients_text = usda_data.food_data
-            .get("ingredients")
-            .or_else(|| usda_data.food_data.get("ingredientStatement"))
-            .and_then(|i| i.as_str());
+    fn uniq(&self) -> bool {
+        true // Prevent duplicate creation jobs for the same ingredient
+    }
 
-        if let Some(ingredients) = ingredients_text {
-            log::info!("Found ingredient list for '{}': {}", self.name, ingredients);
 
-            // Parse ingredients (comma-separated, handle parentheses)
-            let sub_ingredients = self.parse_ingredient_list(ingredients);
+    fn task_type(&self) -> String {
+        "create_ingredient".to_string()
+    }
 
-            if sub_ingredients.is_empty() {
-                log::info!("'{}' is a basic ingredient (no sub-ingredients)", self.name);
-                return;
-            }
+    fn max_retries(&self) -> i32 {
+        crate::config::get().enrichment_max_retries
+    }
 
-            log::info!("'{}' has {} sub-ingredients", self.name, sub_ingredients.len());
+    fn backoff(&self, attempt: u32) -> u32 {
+        // Minutes apart, since a throttled USDA key takes that long to recover
+        exponential_backoff(attempt)
+    }
+}
 
-            // Enqueue jobs for each sub-ingredient
-            let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+/// Job that re-queries USDA for ingredients that were created without macros
+/// (e.g. while USDA was rate limiting us). Runs hourly and on demand.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct UsdaBackfillJob {
+    /// The scheduled instance re-arms itself; on-demand runs don't
+    pub recurring: bool,
+}
 
-            let mut queue = fang::asynk::async_queue::AsyncQueue::builder()
-                .uri(database_url)
-                .max_pool_size(3_u32)
-                .build();
+impl UsdaBackfillJob {
+    /// Ingredients with no macros at all that haven't been searched since `searched_before`,
+    /// or since `no_match_before` when USDA's last answer was that it has no match.
+    /// Curated ingredients are left alone even when a curator left their macros empty.
+    fn candidates(
+        searched_before: chrono::NaiveDateTime,
+        no_match_before: chrono::NaiveDateTime,
+        limit: i64,
+        conn: &mut diesel::PgConnection,
+    ) -> Result<Vec<(i32, String)>, diesel::result::Error> {
+        use diesel::prelude::*;
+        use crate::schema::ingredients::dsl::*;
 
-            match queue.connect(fang::NoTls).await {
-                Ok(_) => {
-                    for sub_ingredient_name in sub_ingredients {
-                        log::info!("Enqueueing sub-ingredient '{}' for parent '{}'", sub_ingredient_name, self.name);
+        ingredients
+            .filter(manually_verified.eq(false))
+            .filter(gram_protein_per_gram.is_null())
+            .filter(gram_carbs_per_gram.is_null())
+            .filter(gram_fat_per_gram.is_null())
+            .filter(gram_fiber_per_gram.is_null())
+            .filter(
+                usda_searched_at
+                    .is_null()
+                    .or(usda_no_match.eq(false).and(usda_searched_at.lt(searched_before)))
+                    .or(usda_no_match.eq(true).and(usda_searched_at.lt(no_match_before))),
+            )
+            .order((usda_searched_at.asc().nulls_first(), id.asc()))
+            .select((id, name))
+            .limit(limit)
+            .load::<(i32, String)>(conn)
+    }
 
-                        let job = CreateIngredientJob {
-                            name: sub_ingredient_name.clone(),
-                        };
+    /// Search USDA (at `usda_base_url`) for each `(id, name)` in turn, `delay` apart to stay
+    /// within its rate limit, and store what it finds. Stops at the first throttled search.
+    /// Returns how many ingredients got macros; `label` prefixes the log lines.
+    async fn search_and_store(
+        label: &str,
+        candidates: &[(i32, String)],
+        usda_base_url: &str,
+        delay: std::time::Duration,
+        conn: &mut diesel::PgConnection,
+    ) -> usize {
+        let min_confidence = crate::config::get().min_usda_match_confidence;
+        let mut updated = 0;
+        for (index, (ingredient_id, ingredient_name)) in candidates.iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(delay).await;
+            }
 
-                        match queue.insert_task(&job).await {
-                            Ok(_) => {
-                                log::info!("Enqueued CreateIngredientJob for sub-ingredient: {}", sub_ingredient_name);
-                            }
-                            Err(e) => {
-                                log::error!("Failed to enqueue sub-ingredient '{}': {:?}", sub_ingredient_name, e);
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    log::error!("Failed to connect to job queue for sub-ingredients: {:?}", e);
+            let lookup = CreateIngredientJob { name: ingredient_name.clone(), parent_id: None };
+            let search = match lookup.fetch_usda_data(usda_base_url).await {
+                // The rest of the batch would be refused too. Nothing is written, so these
+                // ingredients stay first in line for the next run.
+                Err(throttled @ UsdaSearchError::Throttled { .. }) => {
+                    log::warn!(
+                        "{}: {}, stopping after {} of {} ingredients",
+                        label, throttled, index, candidates.len()
+                    );
+                    break;
                 }
+                search => search,
+            };
+            let usda_data = search.as_ref().ok().and_then(Option::as_ref).filter(|data| data.has_macros());
+            let no_match = search.is_ok() && usda_data.is_none();
+            let searched_now = chrono::Utc::now().naive_utc();
+
+            match Self::store_result(*ingredient_id, usda_data, no_match, min_confidence, searched_now, conn) {
+                Ok(BackfillOutcome::Updated) => updated += 1,
+                Ok(BackfillOutcome::NoMacros) => log::info!("{}: still no macros for '{}'", label, ingredient_name),
+                Ok(BackfillOutcome::WeakMatch) => log::info!("{}: only a weak match for '{}', flagged for review", label, ingredient_name),
+                Ok(BackfillOutcome::ManuallyVerified) => log::info!(
+                    "{}: '{}' was manually verified meanwhile, leaving it untouched",
+                    label, ingredient_name
+                ),
+                Err(e) => log::error!("{}: failed to update '{}': {}", label, ingredient_name, e),
             }
-        } else {
-            log::info!("'{}' is a basic ingredient (no ingredient statement found)", self.name);
         }
+        updated
     }
 
-    /// Parse ingredient list from text (handles commas, parentheses, etc.)
-    fn parse_ingredient_list(&self, ingredients_text: &str) -> Vec<String> {
-        let mut ingredients = Vec::new();
+    /// Write one search result. Macros are only written to ingredients nobody has curated,
+    /// since a curator may have verified the ingredient after it was picked as a candidate,
+    /// and only from a match scoring at least `min_confidence`; a weaker one flags the
+    /// ingredient `needs_review` instead. Without data, `no_match` says whether USDA
+    /// answered that it has none rather than failing.
+    fn store_result(
+        ingredient_id: i32,
+        usda_data: Option<&USDANutritionData>,
+        no_match: bool,
+        min_confidence: f64,
+        searched_now: chrono::NaiveDateTime,
+        conn: &mut diesel::PgConnection,
+    ) -> Result<BackfillOutcome, diesel::result::Error> {
+        use diesel::prelude::*;
+        use crate::schema::ingredients::dsl::*;
+
+        let Some(data) = usda_data else {
+            diesel::update(ingredients.find(ingredient_id))
+                .set((usda_searched_at.eq(searched_now), usda_no_match.eq(no_match)))
+                .execute(conn)?;
+            return Ok(BackfillOutcome::NoMacros);
+        };
 
-        // Simple parsing: split by comma, clean up
-        // TODO: Handle parentheses properly for sub-sub-ingredients
-        for part in ingredients_text.split(',') {
-            let clean = part
-                .trim()
-                .trim_end_matches('.')
-                .to_string();
+        if data.confidence < min_confidence {
+            diesel::update(ingredients.find(ingredient_id).filter(manually_verified.eq(false)))
+                .set((
+                    needs_review.eq(true),
+                    usda_candidate.eq(Some(&data.food_data)),
+                    usda_searched_at.eq(searched_now),
+                    usda_no_match.eq(false),
+                ))
+                .execute(conn)?;
+            return Ok(BackfillOutcome::WeakMatch);
+        }
 
-            // Remove percentage notations like "2%" or "(Contains 2% or less of...)"
+        let written = diesel::update(ingredients.find(ingredient_id).filter(manually_verified.eq(false)))
+            .set((
+                gram_protein_per_gram.eq(data.protein),
+                gram_carbs_per_gram.eq(data.carbs),
+                gram_fat_per_gram.eq(data.fat),
+                gram_fiber_per_gram.eq(data.fiber),
+                gram_trans_fat_per_gram.eq(data.trans_fat),
+                fdc_id.eq(data.fdc_id()),
+                usda_food.eq(Some(&data.food_data)),
+                needs_review.eq(false),
+                usda_candidate.eq(None::<serde_json::Value>),
+                usda_searched_at.eq(searched_now),
+                usda_no_match.eq(false),
+                updated_at.eq(searched_now),
+            ))
+            .execute(conn)?;
+
+        Ok(if written == 1 { BackfillOutcome::Updated } else { BackfillOutcome::ManuallyVerified })
+    }
+
+    /// Search USDA (at `usda_base_url`, `delay` apart) for one batch of candidates and
+    /// record how the run went
+    async fn backfill(
+        &self,
+        usda_base_url: &str,
+        delay: std::time::Duration,
+        conn: &mut diesel::PgConnection,
+    ) -> Result<crate::models::UsdaBackfillRun, diesel::result::Error> {
+        use crate::models::{NewUsdaBackfillRun, UsdaBackfillRun};
+
+        let config = crate::config::get();
+        let now = chrono::Utc::now().naive_utc();
+        let candidates = Self::candidates(
+            now - chrono::Duration::hours(config.usda_backfill_retry_hours),
+            now - chrono::Duration::hours(config.usda_no_match_ttl_hours),
+            config.usda_backfill_batch_size,
+            conn,
+        )?;
+
+        log::info!("USDA backfill: {} ingredients without macros to retry", candidates.len());
+
+        let updated = Self::search_and_store("USDA backfill", &candidates, usda_base_url, delay, conn).await;
+
+        log::info!("USDA backfill updated {} of {} ingredients", updated, candidates.len());
+        UsdaBackfillRun::record(
+            &NewUsdaBackfillRun {
+                manual: !self.recurring,
+                candidates: candidates.len() as i32,
+                updated: updated as i32,
+            },
+            conn,
+        )
+    }
+}
+
+/// What the backfill did with one candidate
+#[derive(Debug, PartialEq)]
+enum BackfillOutcome {
+    Updated,
+    NoMacros,
+    /// USDA's best match scored below MIN_USDA_MATCH_CONFIDENCE
+    WeakMatch,
+    ManuallyVerified,
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for UsdaBackfillJob {
+    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        let pool = job_pool();
+        let mut conn = pool.get().map_err(|e| FangError {
+            description: format!("Database connection error: {}", e),
+        })?;
+
+        self.backfill(
+            &crate::http_client::Upstream::Usda.base_url(),
+            crate::config::get().usda_backfill_delay,
+            &mut conn,
+        )
+        .await
+        .map_err(|e| FangError {
+            description: format!("Database error: {}", e),
+        })?;
+        Ok(())
+    }
+
+    fn uniq(&self) -> bool {
+        true
+    }
+
+    fn task_type(&self) -> String {
+        "usda_backfill".to_string()
+    }
+
+    fn cron(&self) -> Option<Scheduled> {
+        // Hourly, one batch at a time, to stay within the USDA rate limit
+        self.recurring.then(|| Scheduled::CronPattern("0 30 * * * *".to_string()))
+    }
+
+    fn max_retries(&self) -> i32 {
+        1
+    }
+}
+
+/// Job that searches USDA again for chosen ingredients, e.g. every one enriched before
+/// matching improved. Queued by `POST /api/admin/ingredients/reenrich`, and paced like
+/// the backfill. Ingredients curated since they were picked are skipped.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct UsdaReenrichJob {
+    pub ingredient_ids: Vec<i32>,
+}
+
+impl UsdaReenrichJob {
+    /// Names of the job's ingredients that still exist and nobody has curated, lowest id first
+    fn targets(&self, conn: &mut diesel::PgConnection) -> Result<Vec<(i32, String)>, diesel::result::Error> {
+        use diesel::prelude::*;
+        use crate::schema::ingredients::dsl::*;
+
+        ingredients
+            .filter(id.eq_any(&self.ingredient_ids))
+            .filter(manually_verified.eq(false))
+            .order(id.asc())
+            .select((id, name))
+            .load(conn)
+    }
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for UsdaReenrichJob {
+    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        let pool = job_pool();
+        let mut conn = pool.get().map_err(|e| FangError {
+            description: format!("Database connection error: {}", e),
+        })?;
+
+        let targets = self.targets(&mut conn).map_err(|e| FangError {
+            description: format!("Database error: {}", e),
+        })?;
+        log::info!("USDA re-enrichment: {} of {} queued ingredients to search", targets.len(), self.ingredient_ids.len());
+
+        let delay = crate::config::get().usda_backfill_delay;
+        let updated = UsdaBackfillJob::search_and_store(
+            "USDA re-enrichment",
+            &targets,
+            &crate::http_client::Upstream::Usda.base_url(),
+            delay,
+            &mut conn,
+        )
+        .await;
+
+        log::info!("USDA re-enrichment updated {} of {} ingredients", updated, targets.len());
+        Ok(())
+    }
+
+    fn uniq(&self) -> bool {
+        true
+    }
+
+    fn task_type(&self) -> String {
+        "usda_reenrich".to_string()
+    }
+
+    fn max_retries(&self) -> i32 {
+        1
+    }
+}
+
+#[derive(Debug, Clone)]
+struct USDANutritionData {
+    protein: Option<f32>,
+    carbs: Option<f32>,
+    fat: Option<f32>,
+    fiber: Option<f32>,
+    trans_fat: Option<f32>,
+    food_data: serde_json::Value, // Store full food data for sub-ingredient extraction
+    /// How well the food's description matches the ingredient name, 0 to 1
+    confidence: f64,
+}
+
+impl USDANutritionData {
+    fn has_macros(&self) -> bool {
+        self.protein.is_some() || self.carbs.is_some() || self.fat.is_some() || self.fiber.is_some()
+    }
+
+    fn fdc_id(&self) -> Option<i32> {
+        self.food_data
+            .get("fdcId")
+            .and_then(|id| id.as_i64())
+            .and_then(|id| i32::try_from(id).ok())
+    }
+}
+
+/// Why a USDA search got no answer
+#[derive(Debug, PartialEq)]
+enum UsdaSearchError {
+    /// 429 (the DEMO_KEY's hourly limit) or 503, still there after the client's own retries.
+    /// `retry_after` is what the response's `Retry-After` asked for.
+    Throttled { status: u16, retry_after: Option<std::time::Duration> },
+    /// No usable answer for any other reason
+    Failed(String),
+}
+
+impl std::fmt::Display for UsdaSearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsdaSearchError::Throttled { status, retry_after: Some(wait) } => {
+                write!(f, "USDA answered {}, retry after {}s", status, wait.as_secs())
+            }
+            UsdaSearchError::Throttled { status, retry_after: None } => write!(f, "USDA answered {}", status),
+            UsdaSearchError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Fetch one food from USDA FoodData Central by its fdc_id. Ok(None) if USDA doesn't know it.
+pub async fn fetch_usda_food(fdc_id: i32) -> Result<Option<serde_json::Value>, reqwest::Error> {
+    let url = format!(
+        "{}/food/{}?api_key={}",
+        crate::http_client::Upstream::Usda.base_url(),
+        fdc_id, crate::config::get().usda_api_key
+    );
+
+    let response = crate::http_client::get(crate::http_client::Upstream::Usda, &url).await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    response.error_for_status()?.json::<serde_json::Value>().await.map(Some)
+}
+
+/// The food in a USDA `/foods/search` response that best fits `name`, if any; see
+/// [`crate::usda_match::best_match`]
+fn best_search_result<'a>(name: &str, data: &'a serde_json::Value) -> Option<&'a serde_json::Value> {
+    data.get("foods")
+        .and_then(|f| f.as_array())
+        .and_then(|foods| crate::usda_match::best_match(name, foods))
+}
+
+/// Most products a newly created ingredient is linked to in one go
+const INGREDIENT_LINK_BATCH_SIZE: i64 = 500;
+
+impl CreateIngredientJob {
+    /// Insert the ingredient (with USDA macros when found) and link it to products stored
+    /// while it was pending. A match scoring below `min_confidence` is not trusted: the
+    /// ingredient is created without macros and flagged `needs_review` instead.
+    /// `None` when an equivalent ingredient already exists.
+    fn create(
+        &self,
+        usda_data: Option<&USDANutritionData>,
+        min_confidence: f64,
+        conn: &mut diesel::PgConnection,
+    ) -> Result<Option<crate::models::Ingredient>, diesel::result::Error> {
+        use crate::models::{Ingredient, NewIngredient};
+
+        let new_ingredient = match usda_data {
+            Some(data) if data.confidence >= min_confidence => {
+                log::info!("Found USDA data for ingredient: {} (confidence {:.2})", self.name, data.confidence);
+                NewIngredient {
+                    name: self.name.clone(),
+                    branded: false,
+                    gram_protein_per_gram: data.protein,
+                    gram_carbs_per_gram: data.carbs,
+                    gram_fat_per_gram: data.fat,
+                    gram_fiber_per_gram: data.fiber,
+                    gram_trans_fat_per_gram: data.trans_fat,
+                    fdc_id: data.fdc_id(),
+                    usda_food: Some(data.food_data.clone()),
+                    needs_review: false,
+                    usda_candidate: None,
+                }
+            }
+            weak => {
+                if let Some(data) = weak {
+                    log::warn!(
+                        "USDA match {:?} for '{}' scored {:.2} (below {:.2}), creating it without macros for review",
+                        data.food_data.get("description").and_then(|d| d.as_str()).unwrap_or("unknown"),
+                        self.name,
+                        data.confidence,
+                        min_confidence
+                    );
+                } else {
+                    log::info!("No USDA data found, creating ingredient with name only: {}", self.name);
+                }
+                NewIngredient {
+                    name: self.name.clone(),
+                    branded: false,
+                    gram_protein_per_gram: None,
+                    gram_carbs_per_gram: None,
+                    gram_fat_per_gram: None,
+                    gram_fiber_per_gram: None,
+                    gram_trans_fat_per_gram: None,
+                    fdc_id: None,
+                    usda_food: None,
+                    needs_review: weak.is_some(),
+                    usda_candidate: weak.map(|data| data.food_data.clone()),
+                }
+            }
+        };
+
+        let Some(created_ingredient) = Ingredient::insert_deduplicated(&new_ingredient, conn)? else {
+            log::info!("Ingredient '{}' was created concurrently under the same canonical name", self.name);
+            return Ok(None);
+        };
+        log::info!("Successfully created ingredient: {} (ID: {})", self.name, created_ingredient.id);
+
+        // Products processed while this ingredient was pending couldn't link it then
+        let linked = created_ingredient.link_listing_products(INGREDIENT_LINK_BATCH_SIZE, conn)?;
+        if linked > 0 {
+            log::info!("Linked ingredient '{}' to {} waiting products", self.name, linked);
+        }
+
+        Ok(Some(created_ingredient))
+    }
+
+    /// Fetch nutritional data from the USDA FoodData Central API at `base_url`. `Ok(None)`
+    /// when USDA answered without a usable match, `Err` when it couldn't be asked.
+    async fn fetch_usda_data(&self, base_url: &str) -> Result<Option<USDANutritionData>, UsdaSearchError> {
+        // USDA_API_KEY is optional, USDA's demo key is used without one
+        let url = format!(
+            "{}/foods/search?api_key={}&query={}",
+            base_url,
+            crate::config::get().usda_api_key,
+            urlencoding::encode(&self.name)
+        );
+
+        log::info!("Searching USDA FoodData Central for: {}", self.name);
+
+        match crate::http_client::get(crate::http_client::Upstream::Usda, &url).await {
+            Ok(response) if matches!(response.status().as_u16(), 429 | 503) => {
+                let error = UsdaSearchError::Throttled {
+                    status: response.status().as_u16(),
+                    retry_after: crate::http_client::retry_after(&response),
+                };
+                log::warn!("USDA search for '{}' failed: {}", self.name, error);
+                Err(error)
+            }
+            Ok(response) if !response.status().is_success() => {
+                log::error!("USDA search for '{}' answered {}", self.name, response.status());
+                Err(UsdaSearchError::Failed(format!("status {}", response.status())))
+            }
+            Ok(response) => {
+                match response.json::<serde_json::Value>().await {
+                    Ok(data) => {
+                        if let Some(best_food) = best_search_result(&self.name, &data) {
+                            log::info!("Found USDA match for '{}': {}",
+                                self.name,
+                                best_food.get("description")
+                                    .and_then(|d| d.as_str())
+                                    .unwrap_or("unknown")
+                            );
+
+                            return Ok(self.extract_nutrition_data(best_food));
+                        }
+
+                        log::info!("No USDA results found for: {}", self.name);
+                        Ok(None)
+                    }
+                    Err(e) => {
+                        log::error!("Failed to parse USDA response for '{}': {}", self.name, e);
+                        Err(UsdaSearchError::Failed(format!("unparseable response: {}", e)))
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to fetch USDA data for '{}': {}", self.name, e);
+                Err(UsdaSearchError::Failed(e.to_string()))
+            }
+        }
+    }
+
+    /// Extract nutrition data from USDA food item
+    fn extract_nutrition_data(&self, food: &serde_json::Value) -> Option<USDANutritionData> {
+        let crate::nutrition::IngredientMacros { protein, carbs, fat, fiber } = crate::usda_match::per_gram_macros(food)?;
+        let trans_fat = crate::usda_match::per_gram_trans_fat(food);
+
+        log::info!(
+            "Extracted nutrition for '{}': protein={:?}g, carbs={:?}g, fat={:?}g, fiber={:?}g, trans fat={:?}g per gram",
+            self.name, protein, carbs, fat, fiber, trans_fat
+        );
+
+        Some(USDANutritionData {
+            protein,
+            carbs,
+            fat,
+            fiber,
+            trans_fat,
+            food_data: food.clone(), // Store full food data for sub-ingredient parsing
+            confidence: crate::usda_match::confidence(&self.name, food),
+        })
+    }
+
+    /// The ingredient this job is for, if it already exists under its name or an alias
+    fn existing(&self, conn: &mut diesel::PgConnection) -> Result<Option<crate::models::Ingredient>, diesel::result::Error> {
+        use crate::schema::ingredients;
+        use diesel::prelude::*;
+
+        let Some(existing_id) = crate::models::Ingredient::find_in_db(&self.name, conn)? else {
+            return Ok(None);
+        };
+        ingredients::table.find(existing_id).first(conn).optional()
+    }
+
+    /// Record the ingredient under the one that listed it, if this job was enqueued for a parent
+    fn link_to_parent(&self, ingredient_id: i32, conn: &mut diesel::PgConnection) -> Result<(), diesel::result::Error> {
+        match self.parent_id {
+            Some(parent_id) => crate::models::Ingredient::link_sub_ingredient(parent_id, ingredient_id, conn),
+            None => Ok(()),
+        }
+    }
+
+    /// Sub-ingredients still to enqueue for the ingredient, from the ingredient statement
+    /// of its stored USDA food (branded foods have one). Empty once they've been processed.
+    fn pending_sub_ingredients(&self, ingredient: &crate::models::Ingredient) -> Vec<String> {
+        if ingredient.sub_ingredients_processed {
+            return Vec::new();
+        }
+
+        // USDA Branded foods sometimes have an "ingredients" field
+        let ingredients_text = ingredient
+            .usda_food
+            .as_ref()
+            .and_then(|food| food.get("ingredients").or_else(|| food.get("ingredientStatement")))
+            .and_then(|i| i.as_str());
+
+        match ingredients_text {
+            Some(ingredients) => {
+                log::info!("Found ingredient list for '{}': {}", self.name, ingredients);
+                self.parse_ingredient_list(ingredients)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Flag the ingredient and enqueue a job per sub-ingredient, in one transaction. The
+    /// flag is claimed first, so a duplicate or retried job waits on it and then finds
+    /// nothing to do; a failed enqueue fails the job and rolls the claim back for a retry.
+    async fn process_sub_ingredients(
+        &self,
+        ingredient: &crate::models::Ingredient,
+        queue: &mut dyn AsyncQueueable,
+        conn: &mut diesel::PgConnection,
+    ) -> Result<(), FangError> {
+        use diesel::connection::{AnsiTransactionManager, TransactionManager};
+
+        let sub_ingredients = self.pending_sub_ingredients(ingredient);
+
+        // Failing hands the sub-ingredients to a later retry, once the queue has drained
+        let class = crate::backpressure::JobClass::Background;
+        if !sub_ingredients.is_empty()
+            && let Err(full) = crate::backpressure::check(class, class.max_pending(), conn)
+        {
+            return Err(FangError {
+                description: format!("Not enqueueing sub-ingredients of '{}': {}", self.name, full),
+            });
+        }
+
+        let db_error = |e: diesel::result::Error| FangError {
+            description: format!("Database error: {}", e),
+        };
+        AnsiTransactionManager::begin_transaction(conn).map_err(db_error)?;
+        match self.enqueue_sub_ingredients(ingredient.id, &sub_ingredients, queue, conn).await {
+            Ok(()) => AnsiTransactionManager::commit_transaction(conn).map_err(db_error),
+            Err(e) => {
+                if let Err(rollback) = AnsiTransactionManager::rollback_transaction(conn) {
+                    log::error!("Failed to roll back the sub-ingredients claim of '{}': {}", self.name, rollback);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Claim the ingredient's sub-ingredients and enqueue them, unless another run already
+    /// claimed them. Runs inside [`Self::process_sub_ingredients`]'s transaction.
+    async fn enqueue_sub_ingredients(
+        &self,
+        ingredient_id: i32,
+        sub_ingredients: &[String],
+        queue: &mut dyn AsyncQueueable,
+        conn: &mut diesel::PgConnection,
+    ) -> Result<(), FangError> {
+        let claimed = crate::models::Ingredient::claim_sub_ingredients(ingredient_id, conn).map_err(|e| FangError {
+            description: format!("Database error: {}", e),
+        })?;
+        if !claimed {
+            log::info!("Sub-ingredients of '{}' were already enqueued, skipping", self.name);
+            return Ok(());
+        }
+
+        if sub_ingredients.is_empty() {
+            log::info!("'{}' is a basic ingredient (no sub-ingredients)", self.name);
+        } else {
+            log::info!("'{}' has {} sub-ingredients", self.name, sub_ingredients.len());
+        }
+
+        for sub_ingredient_name in sub_ingredients {
+            log::info!("Enqueueing sub-ingredient '{}' for parent '{}'", sub_ingredient_name, self.name);
+
+            let job = CreateIngredientJob {
+                name: sub_ingredient_name.clone(),
+                parent_id: Some(ingredient_id),
+            };
+            queue.insert_task(&job).await.map_err(|e| FangError {
+                description: format!("Failed to enqueue sub-ingredient '{}': {:?}", sub_ingredient_name, e),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Parse ingredient list from text (handles commas, parentheses, etc.)
+    fn parse_ingredient_list(&self, ingredients_text: &str) -> Vec<String> {
+        let mut ingredients = Vec::new();
+        let max_name_len = crate::config::get().max_ingredient_name_len;
+
+        // Simple parsing: split by comma, clean up
+        // TODO: Handle parentheses properly for sub-sub-ingredients
+        for part in ingredients_text.split(',') {
+            let clean = part
+                .trim()
+                .trim_end_matches('.')
+                .to_string();
+
+            // Remove percentage notations like "2%" or "(Contains 2% or less of...)"
             let clean = clean
                 .split('(')
                 .next()
@@ -456,7 +1470,7 @@ impl CreateIngredientJob {
                 .trim()
                 .to_string();
 
-            if !clean.is_empty() && clean.len() > 1 {
+            if !clean.is_empty() && clean.len() > 1 && crate::models::ingredient_name_fits(&clean, max_name_len) {
                 ingredients.push(clean);
             }
         }
@@ -464,3 +1478,1168 @@ impl CreateIngredientJob {
         ingredients
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_connection;
+    use crate::config::{DEFAULT_USDA_BACKFILL_RETRY_HOURS, DEFAULT_USDA_NO_MATCH_TTL_HOURS};
+    use crate::fixtures;
+
+    fn failure(task_type: &str, failures: i64) -> TaskFailureCount {
+        TaskFailureCount {
+            task_type: task_type.to_string(),
+            failures,
+        }
+    }
+
+    #[test]
+    fn test_alert_when_failures_reach_threshold() {
+        let failures = vec![failure("create_ingredient", 7), failure("fetch_product", 2)];
+        let alerting = task_types_to_alert(&failures, 5, false);
+
+        assert_eq!(alerting, vec!["create_ingredient (7 failed)".to_string()]);
+    }
+
+    #[test]
+    fn test_no_alert_below_threshold() {
+        let failures = vec![failure("create_ingredient", 4)];
+        assert!(task_types_to_alert(&failures, 5, false).is_empty());
+    }
+
+    #[test]
+    fn test_recent_alert_suppresses_duplicates() {
+        let failures = vec![failure("create_ingredient", 50)];
+        assert!(task_types_to_alert(&failures, 5, true).is_empty());
+    }
+
+    #[test]
+    fn test_every_task_type_has_workers() {
+        let jobs: Vec<Box<dyn AsyncRunnable>> = vec![
+            Box::new(FetchProductJob { barcode: String::new() }),
+            Box::new(AnalyzeIngredientsJob { product_id: 0 }),
+            Box::new(OcrIngredientsJob { product_id: 0 }),
+            Box::new(EnrichNonFoodJob { product_id: 0 }),
+            Box::new(SendNotificationJob { user_id: 0, notification_type: String::new(), message: String::new() }),
+            Box::new(CleanupJob { recurring: false }),
+            Box::new(FailureAlertJob {}),
+            Box::new(CreateIngredientJob { name: String::new(), parent_id: None }),
+            Box::new(UsdaBackfillJob { recurring: false }),
+            Box::new(UsdaReenrichJob { ingredient_ids: Vec::new() }),
+        ];
+
+        for job in jobs {
+            let task_type = job.task_type();
+            assert!(
+                crate::queue::WORKER_POOLS.iter().any(|(pooled, workers)| *pooled == task_type && *workers > 0),
+                "no workers fetch {} tasks",
+                task_type
+            );
+        }
+    }
+
+    /// Fails every attempt with `error`, so a worker runs it to exhaustion (or leaves it
+    /// `retried` while the backoff lasts)
+    #[derive(Serialize, Deserialize)]
+    #[serde(crate = "fang::serde")]
+    struct AlwaysFailsTestJob {
+        task_type: String,
+        error: String,
+        max_retries: i32,
+        backoff: u32,
+    }
+
+    impl AlwaysFailsTestJob {
+        fn new(task_type: &str, error: &str) -> Self {
+            AlwaysFailsTestJob { task_type: task_type.to_string(), error: error.to_string(), max_retries: 1, backoff: 0 }
+        }
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for AlwaysFailsTestJob {
+        async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+            Err(FangError {
+                description: self.error.clone(),
+            })
+        }
+
+        fn task_type(&self) -> String {
+            self.task_type.clone()
+        }
+
+        fn max_retries(&self) -> i32 {
+            self.max_retries
+        }
+
+        fn backoff(&self, _attempt: u32) -> u32 {
+            self.backoff
+        }
+    }
+
+    /// Wait (up to ten seconds) for `expected` tasks of `task_type` to be in `state`, and
+    /// return how many are
+    async fn wait_for_tasks(task_type: &str, state: &str, expected: i64, conn: &mut diesel::PgConnection) -> i64 {
+        use diesel::prelude::*;
+        use diesel::sql_types::{BigInt, Text};
+
+        #[derive(QueryableByName)]
+        struct Count {
+            #[diesel(sql_type = BigInt)]
+            count: i64,
+        }
+
+        let mut found = 0;
+        for _ in 0..100 {
+            found = diesel::sql_query("SELECT COUNT(*) AS count FROM fang_tasks WHERE task_type = $1 AND state::text = $2")
+                .bind::<Text, _>(task_type)
+                .bind::<Text, _>(state)
+                .get_result::<Count>(conn)
+                .unwrap()
+                .count;
+            if found == expected {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        found
+    }
+
+    #[actix_rt::test]
+    async fn test_exhausted_tasks_are_kept_failed_and_alerted() {
+        use diesel::prelude::*;
+        use diesel::sql_types::{BigInt, Text};
+
+        #[derive(QueryableByName)]
+        struct Count {
+            #[diesel(sql_type = BigInt)]
+            count: i64,
+        }
+
+        // The worker commits through its own connections, so this test cleans up after itself
+        let Some(mut conn) = crate::db::committing_test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let cleanup = |conn: &mut PgConnection| {
+            diesel::sql_query(
+                "DELETE FROM fang_tasks WHERE task_type = 'failure_alert_test' \
+                 OR (task_type = 'send_notification' AND metadata->>'message' LIKE '%failure_alert_test%')",
+            )
+            .execute(conn)
+            .unwrap();
+        };
+        cleanup(&mut conn);
+
+        let mut queue = crate::queue::connect_queue(crate::config::get().database_url(), 2).await.expect("queue connects");
+        let threshold = crate::config::get().job_failure_alert_threshold;
+        for _ in 0..threshold {
+            queue.insert_task(&AlwaysFailsTestJob::new("failure_alert_test", "always fails")).await.unwrap();
+        }
+        crate::queue::worker_pool(queue.clone(), "failure_alert_test", 1).start().await;
+
+        assert_eq!(
+            wait_for_tasks("failure_alert_test", "failed", threshold, &mut conn).await,
+            threshold,
+            "every task is kept as failed once its retries run out"
+        );
+
+        FailureAlertJob::check(&["failure_alert_test"], &mut queue).await.unwrap();
+
+        let alerts = diesel::sql_query(
+            "SELECT COUNT(*) AS count FROM fang_tasks WHERE task_type = 'send_notification' \
+             AND metadata->>'notification_type' = $1 AND metadata->>'message' LIKE $2",
+        )
+        .bind::<Text, _>(OPS_ALERT_NOTIFICATION_TYPE)
+        .bind::<Text, _>(format!("%failure_alert_test ({} failed)%", threshold))
+        .get_result::<Count>(&mut conn)
+        .unwrap();
+        cleanup(&mut conn);
+        assert_eq!(alerts.count, 1);
+    }
+
+    #[test]
+    fn test_usda_data_without_macros() {
+        let data = USDANutritionData {
+            protein: None,
+            carbs: None,
+            fat: None,
+            fiber: None,
+            trans_fat: None,
+            food_data: serde_json::Value::Null,
+            confidence: 1.0,
+        };
+        assert!(!data.has_macros());
+        assert!(USDANutritionData { fat: Some(0.0), ..data }.has_macros());
+    }
+
+    #[test]
+    fn test_usda_fdc_id_from_food() {
+        let food = |food_data| USDANutritionData {
+            protein: None,
+            carbs: None,
+            fat: None,
+            fiber: None,
+            trans_fat: None,
+            food_data,
+            confidence: 1.0,
+        };
+
+        assert_eq!(food(serde_json::json!({ "fdcId": 2346404, "description": "Salt, table" })).fdc_id(), Some(2346404));
+        assert_eq!(food(serde_json::json!({ "description": "Salt, table" })).fdc_id(), None);
+        assert_eq!(food(serde_json::json!({ "fdcId": "2346404" })).fdc_id(), None);
+    }
+
+    #[test]
+    fn test_extract_foundation_food_nutrition() {
+        let job = CreateIngredientJob { name: "salt".to_string(), parent_id: None };
+        let search = fixtures::usda_search("foundation");
+        let data = job.extract_nutrition_data(best_search_result("salt", &search).unwrap()).unwrap();
+
+        // Zero macros are still macros: salt has none, and that is known
+        assert_eq!((data.protein, data.carbs, data.fat, data.fiber), (Some(0.0), Some(0.0), Some(0.0), None));
+        assert!(data.has_macros());
+        assert_eq!(data.fdc_id(), Some(2346404));
+    }
+
+    #[test]
+    fn test_extract_branded_food_nutrition_and_ingredients() {
+        let job = CreateIngredientJob { name: "peanut butter".to_string(), parent_id: None };
+        let data = job.extract_nutrition_data(&fixtures::usda_food("branded")).unwrap();
+
+        assert_eq!(data.protein, Some(0.219));
+        assert_eq!(data.carbs, Some(0.25));
+        assert_eq!(data.fat, Some(0.5));
+        assert_eq!(data.fiber, Some(0.062));
+        assert_eq!(data.fdc_id(), Some(2099245));
+
+        let statement = data.food_data["ingredients"].as_str().unwrap();
+        assert_eq!(
+            job.parse_ingredient_list(statement),
+            vec!["ROASTED PEANUTS", "SUGAR", "2% OR LESS OF: MOLASSES", "FULLY HYDROGENATED VEGETABLE OILS", "SALT"]
+        );
+    }
+
+    #[test]
+    fn test_extract_trans_fat_when_usda_lists_it() {
+        let job = CreateIngredientJob { name: "vegetable shortening".to_string(), parent_id: None };
+        let data = job.extract_nutrition_data(&fixtures::usda_food("trans_fat")).unwrap();
+        assert!((data.trans_fat.unwrap() - 0.0417).abs() < 1e-6);
+        assert_eq!(data.fat, Some(1.0));
+
+        // Not listed is unknown, not zero
+        let data = job.extract_nutrition_data(&fixtures::usda_food("branded")).unwrap();
+        assert_eq!(data.trans_fat, None);
+
+        // Trans fat alone doesn't make an ingredient's macros known
+        let only_trans_fat = serde_json::json!({
+            "foodNutrients": [{ "nutrientId": 1257, "value": 1.2 }]
+        });
+        let data = job.extract_nutrition_data(&only_trans_fat).unwrap();
+        assert!(data.trans_fat.is_some());
+        assert!(!data.has_macros());
+    }
+
+    #[test]
+    fn test_created_ingredient_stores_trans_fat() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let job = CreateIngredientJob { name: "Trans Fat Test Vegetable Shortening".to_string(), parent_id: None };
+        let usda_data = job.extract_nutrition_data(&fixtures::usda_food("trans_fat")).unwrap();
+        let created = job.create(Some(&usda_data), 0.0, &mut conn).unwrap().expect("ingredient is new");
+
+        assert!((created.gram_trans_fat_per_gram.unwrap() - 0.0417).abs() < 1e-6);
+        let json = serde_json::to_value(&created).unwrap();
+        assert!(json["gram_trans_fat_per_gram"].is_number(), "{}", json);
+    }
+
+    #[test]
+    fn test_ingredient_statement_drops_oversized_tokens() {
+        let job = CreateIngredientJob { name: "mystery bar".to_string(), parent_id: None };
+        let statement = format!("Sugar, {}, Salt", "X".repeat(5000));
+
+        assert_eq!(job.parse_ingredient_list(&statement), vec!["Sugar", "Salt"]);
+    }
+
+    #[test]
+    fn test_job_runs_share_one_pool() {
+        // The pool is built from the config, which needs DATABASE_URL
+        if std::env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        }
+
+        assert!(std::ptr::eq(job_pool(), job_pool()));
+        assert_eq!(job_pool().max_size(), JOB_POOL_SIZE);
+    }
+
+    #[test]
+    fn test_create_ingredient_jobs_queued_without_parent_still_load() {
+        // Metadata of a job queued before parent_id existed
+        let job: CreateIngredientJob = serde_json::from_value(serde_json::json!({ "name": "salt" })).unwrap();
+        assert_eq!(job.parent_id, None);
+
+        let child = CreateIngredientJob { name: "salt".to_string(), parent_id: Some(7) };
+        let stored = serde_json::to_value(&child).unwrap();
+        assert_eq!(serde_json::from_value::<CreateIngredientJob>(stored).unwrap().parent_id, Some(7));
+    }
+
+    #[test]
+    fn test_extract_uses_best_match_not_first_result() {
+        let job = CreateIngredientJob { name: "salt".to_string(), parent_id: None };
+        let search = fixtures::usda_search("branded_first");
+        let data = job.extract_nutrition_data(best_search_result(&job.name, &search).unwrap()).unwrap();
+
+        assert_eq!(data.fdc_id(), Some(2346404));
+        assert_eq!((data.protein, data.fat), (Some(0.0), Some(0.0)));
+    }
+
+    #[test]
+    fn test_empty_usda_search_has_no_match() {
+        assert!(best_search_result("salt", &fixtures::usda_search("empty")).is_none());
+        assert!(best_search_result("salt", &serde_json::json!({ "error": "API_KEY_INVALID" })).is_none());
+    }
+
+    #[test]
+    fn test_backfill_only_is_scheduled_when_recurring() {
+        assert!(UsdaBackfillJob { recurring: true }.cron().is_some());
+        assert!(UsdaBackfillJob { recurring: false }.cron().is_none());
+    }
+
+    #[test]
+    fn test_backfill_skips_manually_patched_ingredient() {
+        use crate::models::{Ingredient, IngredientPatch, NewIngredient};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let curated = NewIngredient::named("Patch Test Curated").seed(&mut conn);
+        let untouched = NewIngredient::named("Patch Test Untouched").seed(&mut conn);
+
+        // The curator only fixes contaminants, leaving macros empty
+        let patch = IngredientPatch {
+            heavy_metals: Some(Some(serde_json::json!({ "lead": "trace" }))),
+            ..Default::default()
+        };
+        let patched = Ingredient::apply_manual_patch(curated, &patch, &mut conn).unwrap().unwrap();
+        assert!(patched.manually_verified);
+        assert_eq!(patched.heavy_metals, Some(serde_json::json!({ "lead": "trace" })));
+        assert_eq!(patched.gram_protein_per_gram, None);
+
+        let cutoff = chrono::Utc::now().naive_utc();
+        let ids: Vec<i32> = UsdaBackfillJob::candidates(cutoff, cutoff, 10_000, &mut conn)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+
+        assert!(!ids.contains(&curated));
+        assert!(ids.contains(&untouched));
+    }
+
+    #[test]
+    fn test_backfill_result_not_written_to_verified_ingredient() {
+        use diesel::prelude::*;
+        use crate::models::{Ingredient, IngredientPatch, NewIngredient};
+        use crate::schema::ingredients;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let unverified = NewIngredient::named("Backfill Guard Test Oats").seed(&mut conn);
+        let verified = NewIngredient::named("Backfill Guard Test Rye").seed(&mut conn);
+
+        // Verified after both were picked as candidates, while USDA was being queried
+        let patch = IngredientPatch { gram_protein_per_gram: Some(Some(0.1)), ..Default::default() };
+        Ingredient::apply_manual_patch(verified, &patch, &mut conn).unwrap().unwrap();
+
+        let usda = USDANutritionData {
+            protein: Some(0.169),
+            carbs: Some(0.663),
+            fat: Some(0.069),
+            fiber: Some(0.106),
+            trans_fat: Some(0.002),
+            food_data: serde_json::json!({ "fdcId": 173904 }),
+            confidence: 0.9,
+        };
+        let now = chrono::Utc::now().naive_utc();
+
+        assert_eq!(
+            UsdaBackfillJob::store_result(unverified, Some(&usda), false, 0.6, now, &mut conn).unwrap(),
+            BackfillOutcome::Updated
+        );
+        assert_eq!(
+            UsdaBackfillJob::store_result(verified, Some(&usda), false, 0.6, now, &mut conn).unwrap(),
+            BackfillOutcome::ManuallyVerified
+        );
+
+        // The same food as a weak match only flags the ingredient
+        let weak = NewIngredient::named("Backfill Guard Test Millet").seed(&mut conn);
+        let weak_usda = USDANutritionData { confidence: 0.4, ..usda };
+        assert_eq!(
+            UsdaBackfillJob::store_result(weak, Some(&weak_usda), false, 0.6, now, &mut conn).unwrap(),
+            BackfillOutcome::WeakMatch
+        );
+        let flagged = ingredients::table.find(weak).first::<Ingredient>(&mut conn).unwrap();
+        assert!(flagged.needs_review);
+        assert_eq!(flagged.gram_protein_per_gram, None);
+
+        let stored = |ingredient_id: i32, conn: &mut PgConnection| {
+            ingredients::table
+                .find(ingredient_id)
+                .select((ingredients::gram_protein_per_gram, ingredients::gram_trans_fat_per_gram, ingredients::fdc_id))
+                .first::<(Option<f32>, Option<f32>, Option<i32>)>(conn)
+                .unwrap()
+        };
+        assert_eq!(stored(unverified, &mut conn), (Some(0.169), Some(0.002), Some(173904)));
+        assert_eq!(stored(verified, &mut conn), (Some(0.1), None, None));
+    }
+
+    #[test]
+    fn test_created_ingredient_links_waiting_products() {
+        use diesel::prelude::*;
+        use crate::schema::{product_ingredients, products};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let store = |barcode: &str, off_product: serde_json::Value, conn: &mut PgConnection| -> i32 {
+            diesel::insert_into(products::table)
+                .values(&crate::off::extract(barcode, &off_product))
+                .returning(products::id)
+                .get_result::<i32>(conn)
+                .unwrap()
+        };
+        // Stored while "Link Test Spelt" had no ingredient row yet, so it has no link
+        let waiting = store(
+            "link-test-1",
+            serde_json::json!({
+                "ingredients_text": "Water, Link Test Spelt, salt",
+                "ingredients": [
+                    { "text": "Water", "percent_estimate": 50 },
+                    { "text": "Link Test  Spelt", "percent_estimate": 40 },
+                    { "text": "salt" }
+                ]
+            }),
+            &mut conn,
+        );
+        // Only mentions the name inside another ingredient
+        let blend = store(
+            "link-test-2",
+            serde_json::json!({ "ingredients_text": "Link Test Spelt blend, yeast" }),
+            &mut conn,
+        );
+
+        let job = CreateIngredientJob { name: "link test spelt".to_string(), parent_id: None };
+        let created = job.create(None, 0.0, &mut conn).unwrap().expect("ingredient is new");
+
+        let links = product_ingredients::table
+            .filter(product_ingredients::ingredient_id.eq(created.id))
+            .select((product_ingredients::product_id, product_ingredients::rank, product_ingredients::percent_estimate))
+            .load::<(i32, i32, Option<f32>)>(&mut conn)
+            .unwrap();
+        assert_eq!(links, vec![(waiting, 2, Some(40.0))]);
+        assert!(!links.iter().any(|(product_id, _, _)| *product_id == blend));
+
+        // A second creation attempt finds the ingredient and links nothing new
+        assert!(job.create(None, 0.0, &mut conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_retry_after_insert_resumes_sub_ingredients_once() {
+        use crate::models::Ingredient;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let job = CreateIngredientJob { name: "Retry Test Peanut Butter".to_string(), parent_id: None };
+        let usda_data = job.extract_nutrition_data(&fixtures::usda_food("branded")).unwrap();
+
+        // First attempt: the insert commits, then the job fails before enqueueing anything
+        let created = job.create(Some(&usda_data), 0.0, &mut conn).unwrap().expect("ingredient is new");
+        assert!(!created.sub_ingredients_processed);
+
+        // The retry finds the ingredient and still has every sub-ingredient to enqueue
+        let existing = job.existing(&mut conn).unwrap().expect("retry finds the ingredient");
+        assert_eq!(existing.id, created.id);
+        assert!(!existing.sub_ingredients_processed);
+        assert_eq!(job.pending_sub_ingredients(&existing).len(), 5);
+
+        // Once they are claimed, a further retry has nothing left to do
+        assert!(Ingredient::claim_sub_ingredients(existing.id, &mut conn).unwrap());
+        assert!(!Ingredient::claim_sub_ingredients(existing.id, &mut conn).unwrap());
+        let existing = job.existing(&mut conn).unwrap().unwrap();
+        assert!(existing.sub_ingredients_processed);
+        assert!(job.pending_sub_ingredients(&existing).is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_run_enqueues_sub_ingredients_once_it_claims_them() {
+        use crate::models::{Ingredient, NewIngredient};
+        use crate::schema::ingredients;
+        use diesel::prelude::*;
+        use diesel::sql_types::{BigInt, Integer};
+
+        #[derive(QueryableByName)]
+        struct Count {
+            #[diesel(sql_type = BigInt)]
+            count: i64,
+        }
+
+        // The job and the queue commit through their own connections, so this test cleans up after itself
+        let Some(mut conn) = crate::db::committing_test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let name = "Claim Test Peanut Butter";
+        diesel::delete(ingredients::table.filter(ingredients::name.eq(name))).execute(&mut conn).unwrap();
+        // Stored by an earlier run that failed before enqueueing its sub-ingredients
+        let id = NewIngredient { usda_food: Some(fixtures::usda_food("branded")), ..NewIngredient::named(name) }.seed(&mut conn);
+
+        let enqueued = |conn: &mut PgConnection| {
+            diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM fang_tasks \
+                 WHERE task_type = 'create_ingredient' AND (metadata->>'parent_id')::int = $1",
+            )
+            .bind::<Integer, _>(id)
+            .get_result::<Count>(conn)
+            .unwrap()
+            .count
+        };
+        let claimed = |conn: &mut PgConnection| {
+            ingredients::table.find(id).select(ingredients::sub_ingredients_processed).first::<bool>(conn).unwrap()
+        };
+        let job = CreateIngredientJob { name: name.to_string(), parent_id: None };
+
+        // A failed enqueue rolls the claim back, so a retry still has everything to do
+        let mut unreachable = crate::queue::disconnected_queue("postgres://unused/spoils", 1);
+        assert!(job.run(&mut unreachable).await.is_err());
+        assert!(!claimed(&mut conn));
+
+        // The retry claims and enqueues them; a duplicate run finds them claimed
+        let mut queue = crate::queue::connect_queue(crate::config::get().database_url(), 2).await.expect("queue connects");
+        job.run(&mut queue).await.unwrap();
+        job.run(&mut queue).await.unwrap();
+        let (enqueued_count, flagged) = (enqueued(&mut conn), claimed(&mut conn));
+
+        diesel::sql_query(
+            "DELETE FROM fang_tasks WHERE task_type = 'create_ingredient' AND (metadata->>'parent_id')::int = $1",
+        )
+        .bind::<Integer, _>(id)
+        .execute(&mut conn)
+        .unwrap();
+        diesel::delete(ingredients::table.find(id)).execute(&mut conn).unwrap();
+        assert_eq!(enqueued_count, 5);
+        assert!(flagged);
+        assert!(Ingredient::find_in_db(name, &mut conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sub_ingredient_creation_links_parent_and_child() {
+        use crate::models::Ingredient;
+        use crate::schema::ingredients;
+        use diesel::prelude::*;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let parent_job = CreateIngredientJob { name: "Hierarchy Test Peanut Butter".to_string(), parent_id: None };
+        let usda_data = parent_job.extract_nutrition_data(&fixtures::usda_food("branded")).unwrap();
+        let parent = parent_job.create(Some(&usda_data), 0.0, &mut conn).unwrap().expect("ingredient is new");
+
+        // What the parent's run enqueues for each sub-ingredient, run to completion
+        let child_name = format!("Hierarchy Test {}", parent_job.pending_sub_ingredients(&parent)[0]);
+        let child_job = CreateIngredientJob { name: child_name, parent_id: Some(parent.id) };
+        let child = child_job.create(None, 0.0, &mut conn).unwrap().expect("ingredient is new");
+        child_job.link_to_parent(child.id, &mut conn).unwrap();
+        // A retry of the child links nothing twice
+        child_job.link_to_parent(child.id, &mut conn).unwrap();
+
+        let hierarchy = |id: i32, conn: &mut PgConnection| {
+            ingredients::table
+                .find(id)
+                .select((ingredients::sub_ingredients, ingredients::parent_ingredients))
+                .first::<(Vec<i32>, Vec<i32>)>(conn)
+                .unwrap()
+        };
+        assert_eq!(hierarchy(parent.id, &mut conn), (vec![child.id], vec![]));
+        assert_eq!(hierarchy(child.id, &mut conn), (vec![], vec![parent.id]));
+
+        // An ingredient listing itself isn't its own parent
+        Ingredient::link_sub_ingredient(child.id, child.id, &mut conn).unwrap();
+        assert_eq!(hierarchy(child.id, &mut conn), (vec![], vec![parent.id]));
+    }
+
+    #[test]
+    fn test_usda_match_below_threshold_is_left_for_review() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        // "Creamy Peanut Butter" shares half the words of these names
+        let confident = CreateIngredientJob { name: "Threshold Test Peanut Butter".to_string(), parent_id: None };
+        let usda_data = confident.extract_nutrition_data(&fixtures::usda_food("branded")).unwrap();
+        assert!(usda_data.confidence > 0.5 && usda_data.confidence < 0.6, "{}", usda_data.confidence);
+
+        let above = confident.create(Some(&usda_data), 0.5, &mut conn).unwrap().unwrap();
+        assert_eq!(above.fdc_id, Some(2099245));
+        assert!(above.gram_protein_per_gram.is_some());
+        assert!(!above.needs_review);
+
+        let weak = CreateIngredientJob { name: "Review Test Peanut Butter".to_string(), parent_id: None };
+        let usda_data = weak.extract_nutrition_data(&fixtures::usda_food("branded")).unwrap();
+        let below = weak.create(Some(&usda_data), 0.6, &mut conn).unwrap().unwrap();
+        assert_eq!(below.fdc_id, None);
+        assert_eq!(below.gram_protein_per_gram, None);
+        assert_eq!(below.usda_food, None);
+        assert!(below.needs_review);
+        assert_eq!(below.usda_candidate, Some(fixtures::usda_food("branded")));
+        // Nothing from the weak match's ingredient statement gets queued either
+        assert!(weak.pending_sub_ingredients(&below).is_empty());
+
+        // Curated macros settle the review; other corrections don't
+        use crate::models::{Ingredient, IngredientPatch};
+        let contaminants = Ingredient::record_contaminants(below.id, "pesticides", serde_json::json!([]), false, &mut conn).unwrap().unwrap();
+        assert!(contaminants.needs_review);
+        let patch = IngredientPatch { gram_protein_per_gram: Some(Some(0.25)), ..Default::default() };
+        assert!(!Ingredient::apply_manual_patch(below.id, &patch, &mut conn).unwrap().unwrap().needs_review);
+
+        // No match at all is not a review case, just an ingredient USDA doesn't know
+        let unknown = CreateIngredientJob { name: "Review Test Spirulina".to_string(), parent_id: None };
+        assert!(!unknown.create(None, 0.6, &mut conn).unwrap().unwrap().needs_review);
+    }
+
+    #[actix_rt::test]
+    async fn test_create_ingredient_from_mocked_usda_search() {
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/foods/search"))
+            .and(query_param("query", "Mock Test Peanut Butter"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(fixtures::usda_search("branded")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let job = CreateIngredientJob { name: "Mock Test Peanut Butter".to_string(), parent_id: None };
+        let usda_data = job.fetch_usda_data(&server.uri()).await.expect("mock search answers").expect("mock search has a match");
+        let created = job.create(Some(&usda_data), 0.0, &mut conn).unwrap().expect("ingredient is new");
+
+        assert_eq!(created.fdc_id, Some(2099245));
+        assert!((created.gram_protein_per_gram.unwrap() - 0.219).abs() < 1e-5);
+        assert!((created.gram_fat_per_gram.unwrap() - 0.5).abs() < 1e-5);
+        assert_eq!(job.pending_sub_ingredients(&created).len(), 5);
+    }
+
+    #[actix_rt::test]
+    async fn test_usda_search_waits_out_rate_limit() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        // The USDA client's policy comes from the config, which needs DATABASE_URL
+        if std::env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        }
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/foods/search"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/foods/search"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(fixtures::usda_search("branded")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let job = CreateIngredientJob { name: "Rate Limit Test Peanut Butter".to_string(), parent_id: None };
+        let usda_data = job.fetch_usda_data(&server.uri()).await.expect("answers once the limit lifts");
+        assert_eq!(usda_data.expect("mock search has a match").fdc_id(), Some(2099245));
+    }
+
+    #[actix_rt::test]
+    async fn test_usda_rate_limit_is_an_error_not_a_missing_match() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        if std::env::var("DATABASE_URL").is_err() {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        }
+
+        // Longer than the client waits in place, so the job has to come back later
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/foods/search"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let job = CreateIngredientJob { name: "Rate Limit Test Oats".to_string(), parent_id: None };
+        let error = job.fetch_usda_data(&server.uri()).await.unwrap_err();
+        assert_eq!(
+            error,
+            UsdaSearchError::Throttled { status: 429, retry_after: Some(std::time::Duration::from_secs(3600)) }
+        );
+        assert_eq!(error.to_string(), "USDA answered 429, retry after 3600s");
+    }
+
+    #[actix_rt::test]
+    async fn test_throttled_backfill_stops_without_recording_searches() {
+        use diesel::prelude::*;
+        use crate::schema::ingredients;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let candidates: Vec<(i32, String)> = ["Throttle Test Oats", "Throttle Test Rye"]
+            .into_iter()
+            .map(|name| {
+                let ingredient_id = diesel::insert_into(ingredients::table)
+                    .values(ingredients::name.eq(name))
+                    .returning(ingredients::id)
+                    .get_result::<i32>(&mut conn)
+                    .unwrap();
+                (ingredient_id, name.to_string())
+            })
+            .collect();
+
+        // Only the first is searched; the second would be refused as well
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/foods/search"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let updated = UsdaBackfillJob::search_and_store(
+            "throttle test",
+            &candidates,
+            &server.uri(),
+            std::time::Duration::ZERO,
+            &mut conn,
+        )
+        .await;
+        assert_eq!(updated, 0);
+
+        // Not recorded as searched (or as no match), so the next run picks them up first
+        let searched: Vec<(Option<chrono::NaiveDateTime>, bool)> = ingredients::table
+            .filter(ingredients::id.eq_any(candidates.iter().map(|(id, _)| *id)))
+            .select((ingredients::usda_searched_at, ingredients::usda_no_match))
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(searched, vec![(None, false), (None, false)]);
+    }
+
+    #[actix_rt::test]
+    async fn test_backfill_records_how_many_ingredients_it_updated() {
+        use diesel::prelude::*;
+        use crate::models::{Ingredient, UsdaBackfillRun};
+        use crate::schema::ingredients;
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let ingredient_id = diesel::insert_into(ingredients::table)
+            .values(ingredients::name.eq("Creamy Peanut Butter"))
+            .returning(ingredients::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        // Any other ingredient still missing macros finds nothing
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/foods/search"))
+            .and(query_param("query", "Creamy Peanut Butter"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(fixtures::usda_search("branded")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/foods/search"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "foods": [] })))
+            .mount(&server)
+            .await;
+
+        let job = UsdaBackfillJob { recurring: false };
+        let run = job.backfill(&server.uri(), std::time::Duration::ZERO, &mut conn).await.unwrap();
+
+        assert!(run.manual);
+        assert!(run.candidates >= 1);
+        assert_eq!(run.updated, 1);
+        assert_eq!(UsdaBackfillRun::recent(1, &mut conn).unwrap(), vec![run]);
+        let stored: Ingredient = ingredients::table.find(ingredient_id).first(&mut conn).unwrap();
+        assert_eq!(stored.fdc_id, Some(2099245));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        assert_eq!(exponential_backoff(0), 60);
+        assert_eq!(exponential_backoff(1), 120);
+        assert_eq!(exponential_backoff(2), 240);
+        assert_eq!(exponential_backoff(20), MAX_BACKOFF_SECS);
+        assert_eq!(exponential_backoff(u32::MAX), MAX_BACKOFF_SECS);
+    }
+
+    #[actix_rt::test]
+    async fn test_fetch_product_job_stores_fixture_product() {
+        use diesel::prelude::*;
+        use crate::models::Product;
+        use crate::schema::products;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let barcode = "0737628064502";
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/api/v2/product/{}", barcode)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(fixtures::off_response("full")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/0000000000000"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(fixtures::off_response("not_found")))
+            .mount(&server)
+            .await;
+
+        let job = FetchProductJob { barcode: barcode.to_string() };
+        let product_data = job.fetch(&server.uri()).await.unwrap().expect("fixture product is found");
+        let new_product = job.new_product(&product_data);
+        assert_eq!(new_product.barcode, barcode);
+        assert_eq!(
+            new_product.product_name.as_deref(),
+            Some("Thai peanut noodle kit includes stir-fry rice noodles & thai peanut seasoning")
+        );
+        assert_eq!(new_product.brands.as_deref(), Some("Simply Asia, Thai Kitchen"));
+        assert_eq!(new_product.categories.as_deref(), Some("Cereals and potatoes, Noodles"));
+        assert_eq!(new_product.quantity.as_deref(), Some("155 g"));
+        assert_eq!(new_product.nutriscore_grade.as_deref(), Some("d"));
+        assert_eq!(new_product.nova_group, Some(4));
+        assert_eq!(new_product.data_source.as_deref(), Some("openfoodfacts"));
+
+        let missing = FetchProductJob { barcode: "0000000000000".to_string() };
+        assert_eq!(missing.fetch(&server.uri()).await.unwrap(), None);
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        diesel::delete(products::table.filter(products::barcode.eq(barcode))).execute(&mut conn).unwrap();
+
+        let started_at = chrono::Utc::now().naive_utc();
+        let saved = job.store(Some(&product_data), started_at, &mut conn).unwrap().expect("new product is inserted");
+        let stored = products::table.filter(products::barcode.eq(barcode)).first::<Product>(&mut conn).unwrap();
+        assert_eq!(stored.id, saved.id);
+        assert_eq!(stored.product_name, new_product.product_name);
+        assert_eq!(stored.nova_group, Some(4));
+
+        // The same revision again is only marked verified
+        assert!(job.store(Some(&product_data), started_at, &mut conn).unwrap().is_none());
+        assert_eq!(products::table.filter(products::barcode.eq(barcode)).count().get_result::<i64>(&mut conn).unwrap(), 1);
+    }
+
+    /// OCR backend answering every photo with the same text, remembering what it was sent
+    struct MockOcr {
+        text: &'static str,
+        images: std::sync::Mutex<Vec<Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl crate::ocr::OcrBackend for MockOcr {
+        async fn recognize(&self, image: &[u8]) -> Result<String, String> {
+            self.images.lock().unwrap().push(image.to_vec());
+            Ok(self.text.to_string())
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_ocr_job_reads_and_links_ingredients_from_photo() {
+        use diesel::prelude::*;
+        use crate::models::{NewIngredient, Product};
+        use crate::schema::{ingredients, product_ingredients, products};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let Some(pool) = crate::db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/images/ingredients.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"jpeg bytes".to_vec()))
+            .mount(&server)
+            .await;
+        let photo = format!("{}/images/ingredients.jpg", server.uri());
+
+        let product_id = |barcode: &str, product_data: serde_json::Value| -> i32 {
+            diesel::insert_into(products::table)
+                .values(&crate::off::extract(barcode, &product_data))
+                .returning(products::id)
+                .get_result::<i32>(&mut pool.get().unwrap())
+                .unwrap()
+        };
+        for name in ["Ocr Test Oats", "Ocr Test Honey"] {
+            diesel::insert_into(ingredients::table)
+                .values(&NewIngredient {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .execute(&mut pool.get().unwrap())
+                .unwrap();
+        }
+        let photo_only = product_id("ocr-test-1", serde_json::json!({ "image_ingredients_url": photo }));
+        let has_text = product_id(
+            "ocr-test-2",
+            serde_json::json!({ "ingredients_text": "Ocr Test Oats", "image_ingredients_url": photo }),
+        );
+
+        let backend = MockOcr {
+            text: "INGREDIENTS: Ocr Test Oats,\n Ocr Test Honey.",
+            images: std::sync::Mutex::new(Vec::new()),
+        };
+
+        // Both ingredients are known, so nothing is enqueued through the unconnected queue
+        let mut queue = crate::queue::disconnected_queue("postgres://unused/spoils", 1);
+        let job = OcrIngredientsJob { product_id: photo_only };
+        let text = job.read_ingredients(&backend, &pool, &mut queue).await.unwrap();
+        assert_eq!(text.as_deref(), Some("Ocr Test Oats, Ocr Test Honey"));
+        assert_eq!(*backend.images.lock().unwrap(), vec![b"jpeg bytes".to_vec()]);
+
+        let product = products::table.find(photo_only).first::<Product>(&mut pool.get().unwrap()).unwrap();
+        assert_eq!(product.ocr_ingredients_text.as_deref(), Some("Ocr Test Oats, Ocr Test Honey"));
+        assert_eq!(product.ingredients_text, None);
+        let linked: Vec<String> = product_ingredients::table
+            .inner_join(ingredients::table)
+            .filter(product_ingredients::product_id.eq(photo_only))
+            .order(product_ingredients::rank)
+            .select(ingredients::name)
+            .load(&mut pool.get().unwrap())
+            .unwrap();
+        assert_eq!(linked, ["Ocr Test Oats", "Ocr Test Honey"]);
+
+        // OFF's own list wins, so its photo isn't sent for OCR
+        let job = OcrIngredientsJob { product_id: has_text };
+        assert_eq!(job.read_ingredients(&backend, &pool, &mut queue).await.unwrap(), None);
+        assert_eq!(backend.images.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_error_messages_are_redacted_and_truncated() {
+        let reqwest_error = "error sending request for url (https://api.nal.usda.gov/fdc/v1/foods/search?api_key=abc123XYZ&query=salt): timed out";
+        assert_eq!(
+            display_error(reqwest_error),
+            "error sending request for url (https://api.nal.usda.gov/fdc/v1/foods/search?api_key=REDACTED&query=salt): timed out"
+        );
+        assert_eq!(display_error("a?api_key=k1 b?api_key=k2"), "a?api_key=REDACTED b?api_key=REDACTED");
+
+        let long = "é".repeat(MAX_ERROR_MESSAGE_LEN + 20);
+        let shown = display_error(&long);
+        assert!(shown.starts_with(&"é".repeat(MAX_ERROR_MESSAGE_LEN)));
+        assert!(shown.ends_with("... (20 chars truncated)"));
+        assert_eq!(display_error("Database error"), "Database error");
+    }
+
+    #[actix_rt::test]
+    async fn test_recent_failures_lists_failed_and_retried_tasks() {
+        use diesel::prelude::*;
+
+        // The worker commits through its own connections, so this test cleans up after itself
+        let Some(mut conn) = crate::db::committing_test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let cleanup = |conn: &mut PgConnection| {
+            diesel::sql_query("DELETE FROM fang_tasks WHERE task_type = 'failures_test'").execute(conn).unwrap();
+        };
+        cleanup(&mut conn);
+
+        // One task out of retries, one failed once and waiting an hour for its retry
+        let mut queue = crate::queue::connect_queue(crate::config::get().database_url(), 2).await.expect("queue connects");
+        let exhausted = AlwaysFailsTestJob { max_retries: 0, ..AlwaysFailsTestJob::new("failures_test", "OFF returned HTML") };
+        let waiting = AlwaysFailsTestJob { backoff: 3600, ..AlwaysFailsTestJob::new("failures_test", "USDA timed out") };
+        queue.insert_task(&exhausted).await.unwrap();
+        queue.insert_task(&waiting).await.unwrap();
+        crate::queue::worker_pool(queue.clone(), "failures_test", 1).start().await;
+
+        let failed = wait_for_tasks("failures_test", "failed", 1, &mut conn).await;
+        let retried = wait_for_tasks("failures_test", "retried", 1, &mut conn).await;
+
+        let ours = |tasks: Vec<FailedTask>| -> Vec<FailedTask> {
+            tasks.into_iter().filter(|t| t.task_type == "failures_test").collect()
+        };
+        let mut all = ours(recent_failures(None, 10_000, &mut conn).unwrap());
+        all.sort_by_key(|t| t.state.clone());
+        let latest = all.iter().map(|t| t.updated_at).max();
+        let since_latest = latest.map(|at| ours(recent_failures(Some(at), 10_000, &mut conn).unwrap()).len());
+        let limited = recent_failures(None, 1, &mut conn).unwrap().len();
+        cleanup(&mut conn);
+
+        assert_eq!((failed, retried), (1, 1));
+        assert_eq!(
+            all.iter().map(|t| (t.state.as_str(), t.error_message.as_deref(), t.retries)).collect::<Vec<_>>(),
+            vec![("failed", Some("OFF returned HTML"), 0), ("retried", Some("USDA timed out"), 1)]
+        );
+        assert_eq!(since_latest, Some(1), "only tasks updated since `since` are listed");
+        assert_eq!(limited, 1);
+    }
+
+    #[test]
+    fn test_job_stats_count_states_by_task_type() {
+        use diesel::prelude::*;
+        use diesel::sql_types::Text;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        for (task_type, state) in [
+            ("stats_test_a", "new"),
+            ("stats_test_a", "retried"),
+            ("stats_test_a", "in_progress"),
+            ("stats_test_a", "finished"),
+            ("stats_test_a", "finished"),
+            ("stats_test_b", "failed"),
+        ] {
+            diesel::sql_query("INSERT INTO fang_tasks (metadata, state, task_type) VALUES ('{}', $1::fang_task_state, $2)")
+                .bind::<Text, _>(state)
+                .bind::<Text, _>(task_type)
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let stats = job_stats(&mut conn).unwrap();
+        assert_eq!(stats["stats_test_a"], JobStats { pending: 2, running: 1, failed: 0, finished: 2 });
+        assert_eq!(stats["stats_test_b"], JobStats { pending: 0, running: 0, failed: 1, finished: 0 });
+    }
+
+    #[test]
+    fn test_backfill_candidates_skip_recent_attempts() {
+        use diesel::prelude::*;
+        use crate::models::NewIngredient;
+        use crate::schema::ingredients;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let never_searched = NewIngredient::named("Backfill Test Never Searched").seed(&mut conn);
+        let searched_long_ago = NewIngredient::named("Backfill Test Long Ago").seed(&mut conn);
+        let searched_recently = NewIngredient::named("Backfill Test Recent").seed(&mut conn);
+        let has_macros = NewIngredient {
+            gram_protein_per_gram: Some(0.1),
+            ..NewIngredient::named("Backfill Test Has Macros")
+        }
+        .seed(&mut conn);
+
+        let now = chrono::Utc::now().naive_utc();
+        for (ingredient_id, searched_at) in [
+            (searched_long_ago, now - chrono::Duration::days(3)),
+            (searched_recently, now - chrono::Duration::minutes(5)),
+        ] {
+            diesel::update(ingredients::table.find(ingredient_id))
+                .set(ingredients::usda_searched_at.eq(searched_at))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let cutoff = now - chrono::Duration::hours(DEFAULT_USDA_BACKFILL_RETRY_HOURS);
+        let ids: Vec<i32> = UsdaBackfillJob::candidates(cutoff, cutoff, 10_000, &mut conn)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+
+        assert!(ids.contains(&never_searched));
+        assert!(ids.contains(&searched_long_ago));
+        assert!(!ids.contains(&searched_recently));
+        assert!(!ids.contains(&has_macros));
+    }
+
+    #[test]
+    fn test_backfill_skips_no_match_within_ttl() {
+        use diesel::prelude::*;
+        use crate::models::{Ingredient, NewIngredient};
+        use crate::schema::ingredients;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let no_match_recently = NewIngredient::named("Backfill Test No Match Recent").seed(&mut conn);
+        let no_match_long_ago = NewIngredient::named("Backfill Test No Match Long Ago").seed(&mut conn);
+        let failed_recently = NewIngredient::named("Backfill Test Failed Recent").seed(&mut conn);
+
+        // Two days is past the retry wait but within the no-match TTL
+        let now = chrono::Utc::now().naive_utc();
+        let two_days_ago = now - chrono::Duration::days(2);
+        UsdaBackfillJob::store_result(no_match_recently, None, true, 0.6, two_days_ago, &mut conn).unwrap();
+        UsdaBackfillJob::store_result(no_match_long_ago, None, true, 0.6, now - chrono::Duration::days(8), &mut conn).unwrap();
+        UsdaBackfillJob::store_result(failed_recently, None, false, 0.6, two_days_ago, &mut conn).unwrap();
+
+        let stored: Ingredient = ingredients::table.find(no_match_recently).first(&mut conn).unwrap();
+        assert!(stored.usda_no_match);
+        assert!(stored.usda_searched_at.is_some());
+
+        let candidates = |conn: &mut PgConnection| -> Vec<i32> {
+            UsdaBackfillJob::candidates(
+                now - chrono::Duration::hours(DEFAULT_USDA_BACKFILL_RETRY_HOURS),
+                now - chrono::Duration::hours(DEFAULT_USDA_NO_MATCH_TTL_HOURS),
+                10_000,
+                conn,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+        };
+        let ids = candidates(&mut conn);
+        assert!(!ids.contains(&no_match_recently), "a second search within the TTL is skipped");
+        assert!(ids.contains(&no_match_long_ago));
+        assert!(ids.contains(&failed_recently));
+
+        // A failed search since then isn't a no-match, so only the retry wait applies
+        UsdaBackfillJob::store_result(no_match_recently, None, false, 0.6, two_days_ago, &mut conn).unwrap();
+        assert!(candidates(&mut conn).contains(&no_match_recently));
+    }
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Default depth below which nested objects are compared as whole values
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+/// Structural difference between two JSON documents, keyed by JSON pointer paths
+#[derive(Serialize, Debug, Default)]
+pub struct JsonDiff {
+    pub added: Vec<DiffEntry>,
+    pub removed: Vec<DiffEntry>,
+    pub changed: Vec<DiffChange>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffEntry {
+    pub path: String,
+    pub value: Value,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffChange {
+    pub path: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+impl JsonDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Diff two JSON values. Objects are walked key by key up to `max_depth`;
+/// arrays, scalars and anything deeper are compared as whole values.
+pub fn diff(from: &Value, to: &Value, max_depth: usize) -> JsonDiff {
+    let mut result = JsonDiff::default();
+    diff_at(from, to, "", 0, max_depth, &mut result);
+    result
+}
+
+fn diff_at(from: &Value, to: &Value, path: &str, depth: usize, max_depth: usize, out: &mut JsonDiff) {
+    if from == to {
+        return;
+    }
+
+    match (from, to) {
+        (Value::Object(a), Value::Object(b)) if depth < max_depth => {
+            for (key, a_value) in a {
+                let child_path = format!("{}/{}", path, escape_pointer_token(key));
+                match b.get(key) {
+                    Some(b_value) => diff_at(a_value, b_value, &child_path, depth + 1, max_depth, out),
+                    None => out.removed.push(DiffEntry {
+                        path: child_path,
+                        value: a_value.clone(),
+                    }),
+                }
+            }
+
+            for (key, b_value) in b {
+                if !a.contains_key(key) {
+                    out.added.push(DiffEntry {
+                        path: format!("{}/{}", path, escape_pointer_token(key)),
+                        value: b_value.clone(),
+                    });
+                }
+            }
+        }
+        _ => out.changed.push(DiffChange {
+            path: path.to_string(),
+            from: from.clone(),
+            to: to.clone(),
+        }),
+    }
+}
+
+/// Escape a key for use in a JSON pointer (RFC 6901)
+fn escape_pointer_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_identical_documents_have_no_diff() {
+        let doc = json!({ "product_name": "Nutella", "nutriments": { "sugars_100g": 56.3 } });
+        assert!(diff(&doc, &doc, DEFAULT_MAX_DEPTH).is_empty());
+    }
+
+    #[test]
+    fn test_added_removed_and_changed_keys() {
+        let from = json!({
+            "product_name": "Nutella",
+            "nutriscore_grade": "e",
+            "nutriments": { "sugars_100g": 56.3, "salt_100g": 0.107 }
+        });
+        let to = json!({
+            "product_name": "Nutella",
+            "nutriscore_grade": "d",
+            "labels": "Vegetarian",
+            "nutriments": { "sugars_100g": 50.1 }
+        });
+
+        let result = diff(&from, &to, DEFAULT_MAX_DEPTH);
+
+        assert_eq!(result.added.len(), 1);
+        assert_eq!(result.added[0].path, "/labels");
+
+        assert_eq!(result.removed.len(), 1);
+        assert_eq!(result.removed[0].path, "/nutriments/salt_100g");
+
+        let changed: Vec<&str> = result.changed.iter().map(|c| c.path.as_str()).collect();
+        assert!(changed.contains(&"/nutriscore_grade"));
+        assert!(changed.contains(&"/nutriments/sugars_100g"));
+    }
+
+    #[test]
+    fn test_depth_cap_compares_nested_objects_whole() {
+        let from = json!({ "nutriments": { "sugars_100g": 56.3 } });
+        let to = json!({ "nutriments": { "sugars_100g": 50.1 } });
+
+        let result = diff(&from, &to, 1);
+
+        assert_eq!(result.changed.len(), 1);
+        assert_eq!(result.changed[0].path, "/nutriments");
+        assert_eq!(result.changed[0].to, json!({ "sugars_100g": 50.1 }));
+    }
+
+    #[test]
+    fn test_pointer_tokens_are_escaped() {
+        let from = json!({});
+        let to = json!({ "a/b": 1, "c~d": 2 });
+
+        let mut paths: Vec<String> = diff(&from, &to, DEFAULT_MAX_DEPTH)
+            .added
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        paths.sort();
+
+        assert_eq!(paths, vec!["/a~1b", "/c~0d"]);
+    }
+}
@@ -0,0 +1,135 @@
+use serde_json::Value;
+
+/// Longest path accepted, in bytes
+pub const MAX_PATH_LEN: usize = 512;
+/// Deepest path accepted, in segments
+pub const MAX_DEPTH: usize = 32;
+/// Largest array index accepted. OFF arrays (ingredients, images) stay far below this,
+/// and capping it keeps absurd indices from being parsed at all.
+pub const MAX_ARRAY_INDEX: usize = 9_999;
+
+/// A validated JSON-pointer-style path (`nutriments/sodium_100g`, leading `/` optional).
+/// Segments use RFC 6901 escapes: `~1` for `/` and `~0` for `~`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldPath {
+    segments: Vec<String>,
+}
+
+impl FieldPath {
+    pub fn parse(path: &str) -> Result<Self, String> {
+        if path.len() > MAX_PATH_LEN {
+            return Err(format!("path is longer than {} bytes", MAX_PATH_LEN));
+        }
+
+        let trimmed = path.strip_prefix('/').unwrap_or(path);
+        if trimmed.is_empty() {
+            return Err("path must name at least one field".to_string());
+        }
+
+        let segments = trimmed.split('/').map(unescape_segment).collect::<Result<Vec<_>, _>>()?;
+        if segments.len() > MAX_DEPTH {
+            return Err(format!("path is deeper than {} segments", MAX_DEPTH));
+        }
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err("path has an empty segment".to_string());
+        }
+
+        Ok(FieldPath { segments })
+    }
+
+    /// The value at this path, or `None` when any segment is missing. A segment indexes
+    /// an array only when it is a plain decimal number no larger than `MAX_ARRAY_INDEX`.
+    pub fn resolve<'a>(&self, document: &'a Value) -> Option<&'a Value> {
+        self.segments.iter().try_fold(document, |value, segment| match value {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => array_index(segment).and_then(|index| items.get(index)),
+            _ => None,
+        })
+    }
+}
+
+fn unescape_segment(segment: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(segment.len());
+    let mut chars = segment.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => out.push('~'),
+            Some('1') => out.push('/'),
+            _ => return Err(format!("invalid escape in segment '{}' (use ~0 or ~1)", segment)),
+        }
+    }
+    Ok(out)
+}
+
+fn array_index(segment: &str) -> Option<usize> {
+    let canonical = segment == "0" || (!segment.starts_with('0') && segment.bytes().all(|b| b.is_ascii_digit()));
+    // Checking the length first means even a thousand-digit segment is never parsed
+    if !canonical || segment.len() > MAX_ARRAY_INDEX.to_string().len() {
+        return None;
+    }
+    segment.parse().ok().filter(|index| *index <= MAX_ARRAY_INDEX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixture() -> Value {
+        json!({
+            "product_name": "Nutella",
+            "nutriments": { "sodium_100g": 0.0428, "energy-kcal_100g": 539 },
+            "ingredients": [
+                { "id": "en:sugar", "percent_estimate": 56.3 },
+                { "id": "en:palm-oil", "ingredients": [{ "id": "en:palm-fat" }] }
+            ],
+            "a/b": { "~tilde": true }
+        })
+    }
+
+    fn resolve(path: &str) -> Option<Value> {
+        FieldPath::parse(path).unwrap().resolve(&fixture()).cloned()
+    }
+
+    #[test]
+    fn test_present_and_nested_paths() {
+        assert_eq!(resolve("nutriments/sodium_100g"), Some(json!(0.0428)));
+        assert_eq!(resolve("/product_name"), Some(json!("Nutella")));
+        assert_eq!(resolve("ingredients/1/ingredients/0/id"), Some(json!("en:palm-fat")));
+        assert_eq!(resolve("nutriments"), Some(json!({ "sodium_100g": 0.0428, "energy-kcal_100g": 539 })));
+        assert_eq!(resolve("a~1b/~0tilde"), Some(json!(true)));
+    }
+
+    #[test]
+    fn test_absent_paths() {
+        assert_eq!(resolve("nutriments/salt_100g"), None);
+        assert_eq!(resolve("product_name/0"), None);
+        assert_eq!(resolve("ingredients/2"), None);
+        assert_eq!(resolve("ingredients/01"), None);
+        assert_eq!(resolve("ingredients/-1"), None);
+        assert_eq!(resolve("ingredients/first"), None);
+    }
+
+    #[test]
+    fn test_huge_array_indices_are_absent() {
+        assert_eq!(resolve("ingredients/10000"), None);
+        assert_eq!(resolve("ingredients/18446744073709551616"), None);
+        assert_eq!(resolve(&format!("ingredients/{}", "9".repeat(400))), None);
+    }
+
+    #[test]
+    fn test_invalid_paths_are_rejected() {
+        assert!(FieldPath::parse("").is_err());
+        assert!(FieldPath::parse("/").is_err());
+        assert!(FieldPath::parse("nutriments//sodium_100g").is_err());
+        assert!(FieldPath::parse("nutriments/").is_err());
+        assert!(FieldPath::parse("a~2b").is_err());
+        assert!(FieldPath::parse("a~").is_err());
+        assert!(FieldPath::parse(&format!("{}a", "a/".repeat(MAX_DEPTH))).is_err());
+        assert!(FieldPath::parse(&"x".repeat(MAX_PATH_LEN + 1)).is_err());
+    }
+}
@@ -1,16 +1,49 @@
 // Re-export modules for testing
+pub mod allergens;
+pub mod api;
+pub mod auth;
+pub mod backpressure;
+pub mod barcode;
+pub mod batch;
+pub mod clock;
+pub mod compression;
+pub mod config;
 pub mod db;
+pub mod deadline;
+pub mod diet;
+pub mod errors;
+pub mod facets;
+#[cfg(test)]
+pub mod fixtures;
+pub mod http_client;
 pub mod jobs;
+pub mod json_diff;
+pub mod json_pointer;
+pub mod metrics;
 pub mod models;
+pub mod nutrition;
+pub mod ocr;
+pub mod off;
+pub mod pagination;
+pub mod product_ingredients;
+pub mod quantity;
+pub mod queue;
+pub mod safety;
 pub mod schema;
+pub mod slow_log;
+pub mod sources;
+pub mod startup;
+pub mod usda_match;
 
 // Re-export endpoint functions for integration tests
-pub use crate::handlers::{health, hello};
+pub use crate::handlers::{health, hello, ping};
 
 mod handlers {
     use actix_web::{get, HttpResponse, Responder};
     use serde::Serialize;
 
+    use crate::api::ApiOk;
+
     #[derive(Serialize)]
     pub struct HealthResponse {
         pub status: String,
@@ -19,16 +52,27 @@ mod handlers {
 
     #[get("/health")]
     pub async fn health() -> impl Responder {
-        HttpResponse::Ok().json(HealthResponse {
+        HttpResponse::Ok().json(ApiOk::new(HealthResponse {
             status: "ok".to_string(),
             message: "Spoils API is running".to_string(),
-        })
+        }))
+    }
+
+    /// Bare liveness check for high-frequency uptime monitors
+    #[get("/api/ping")]
+    pub async fn ping() -> HttpResponse {
+        HttpResponse::Ok().content_type("text/plain").body("pong")
+    }
+
+    #[derive(Serialize)]
+    pub struct HelloResponse {
+        pub message: String,
     }
 
     #[get("/api/hello")]
     pub async fn hello() -> impl Responder {
-        HttpResponse::Ok().json(serde_json::json!({
-            "message": "Hello from Spoils API!"
+        HttpResponse::Ok().json(ApiOk::new(HelloResponse {
+            message: "Hello from Spoils API!".to_string(),
         }))
     }
 }
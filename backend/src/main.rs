@@ -1,20 +1,66 @@
+mod auth;
+mod backpressure;
+mod barcode;
+mod allergens;
+mod api;
+mod batch;
+mod clock;
+mod compression;
+mod config;
 mod db;
+mod deadline;
+mod diet;
+mod errors;
+mod facets;
+#[cfg(test)]
+mod fixtures;
+mod http_client;
 mod jobs;
+mod json_diff;
+mod json_pointer;
+mod metrics;
 mod models;
+mod nutrition;
+mod ocr;
+mod off;
+mod pagination;
+mod product_ingredients;
+mod quantity;
+mod queue;
+mod safety;
 mod schema;
+mod slow_log;
+mod sources;
+mod startup;
+mod usda_match;
 mod workers;
 
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use actix_web::http::StatusCode;
+use actix_web::{get, patch, post, put, web, App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError};
 use actix_cors::Cors;
 use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
 use serde::{Deserialize, Serialize};
-use fang::asynk::async_queue::{AsyncQueue, AsyncQueueable};
-use fang::NoTls;
+use fang::asynk::async_queue::AsyncQueueable;
 
+use crate::api::{ApiError, ApiOk};
+use crate::barcode::Barcode;
+use crate::backpressure::JobClass;
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::auth::AdminApiKey;
 use crate::db::DbPool;
-use crate::jobs::{FetchProductJob, AnalyzeIngredientsJob};
-use crate::models::{NewProduct, OpenFoodFactsResponse, Product, Ingredient, ProductNonFood, NewProductNonFood};
-use crate::schema::{products, products_non_food};
+use crate::errors::{db_unavailable, AppError};
+use crate::metrics::FetchOutcome;
+use crate::pagination::PageRequest;
+use crate::jobs::{FetchProductJob, AnalyzeIngredientsJob, CleanupJob, EnrichNonFoodJob, OcrIngredientsJob, UsdaBackfillJob, UsdaReenrichJob};
+use crate::models::{NewProduct, Product, ProductHistory, ProductLookup, Ingredient, IngredientAlias, IngredientMacroFilter, IngredientPatch, MacroRange, PatchError, MacroSort, ProductListFilter, ProductNonFood, ProductNonFoodPatch, NewProductNonFood, UsdaBackfillRun};
+use crate::sources::{ChainLookup, SourceChain};
+use crate::queue::SharedQueue;
+use crate::schema::{ingredients, product_history, product_lookups, products, products_non_food};
 
 #[derive(Serialize)]
 struct HealthResponse {
@@ -22,53 +68,271 @@ struct HealthResponse {
     message: String,
 }
 
+#[derive(Serialize)]
+struct HelloResponse {
+    message: String,
+}
+
 #[get("/health")]
 async fn health() -> impl Responder {
-    HttpResponse::Ok().json(HealthResponse {
+    HttpResponse::Ok().json(ApiOk::new(HealthResponse {
         status: "ok".to_string(),
         message: "Spoils API is running".to_string(),
-    })
+    }))
+}
+
+/// Bare liveness check for high-frequency uptime monitors: a static body, no app state,
+/// and left out of the access log
+#[get("/api/ping")]
+async fn ping() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain").body("pong")
 }
 
 #[get("/api/hello")]
 async fn hello() -> impl Responder {
-    HttpResponse::Ok().json(serde_json::json!({
-        "message": "Hello from Spoils API!"
+    HttpResponse::Ok().json(ApiOk::new(HelloResponse {
+        message: "Hello from Spoils API!".to_string(),
     }))
 }
 
-#[get("/api/products/{barcode}")]
-async fn get_product(
-    barcode: web::Path<String>,
+/// Products returned when `?limit=` is not given
+const DEFAULT_PRODUCT_LIST_LIMIT: i64 = 50;
+/// Most products one request may ask for
+const MAX_PRODUCT_LIST_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+struct ProductListQuery {
+    brand: Option<String>,
+    nutriscore: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// Validated product list request: filter, limit and offset, with grades compared as stored
+/// under `unknown_grades`. Unscored grades can only be asked for when they are stored as
+/// "unknown"; with UNKNOWN_GRADES=null they look the same as no grade at all.
+fn parse_product_list_query(
+    query: &ProductListQuery,
+    unknown_grades: off::UnknownGrades,
+) -> Result<(ProductListFilter, i64, i64), String> {
+    let brand = query.brand.as_deref().map(str::trim).filter(|brand| !brand.is_empty());
+    let nutriscore = match query.nutriscore.as_deref() {
+        None => None,
+        Some(grade) => match off::normalize_grade(grade, unknown_grades) {
+            Some(grade) => Some(grade),
+            None if unknown_grades == off::UnknownGrades::Sentinel => {
+                return Err(format!("Unknown nutriscore '{}', expected a, b, c, d, e or unknown", grade));
+            }
+            None => return Err(format!("Unknown nutriscore '{}', expected a, b, c, d or e", grade)),
+        },
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PRODUCT_LIST_LIMIT);
+    if !(1..=MAX_PRODUCT_LIST_LIMIT).contains(&limit) {
+        return Err(format!("limit must be between 1 and {}", MAX_PRODUCT_LIST_LIMIT));
+    }
+    let offset = query.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err("offset must not be negative".to_string());
+    }
+
+    Ok((ProductListFilter { brand: brand.map(str::to_string), nutriscore }, limit, offset))
+}
+
+#[derive(Serialize)]
+struct ProductList {
+    products: Vec<Product>,
+    count: usize,
+    limit: i64,
+    offset: i64,
+}
+
+/// Stored food products, newest first, e.g. `?brand=thai&nutriscore=b&limit=20&offset=40`
+#[get("/api/products")]
+async fn list_products(
+    query: web::Query<ProductListQuery>,
     pool: web::Data<DbPool>,
 ) -> impl Responder {
-    let barcode = barcode.into_inner();
+    let (filter, limit, offset) = match parse_product_list_query(&query, config::get().unknown_grades) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            return api::error(StatusCode::BAD_REQUEST, ApiError::new(message));
+        }
+    };
 
-    // Check database first
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
         Err(e) => {
             log::error!("Failed to get DB connection: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Database connection failed"
-            }));
+            return db_unavailable();
         }
     };
 
-    // Try to find product in database
+    let found = web::block(move || Product::list(&filter, limit, offset, &mut conn)).await;
+
+    match found {
+        Ok(Ok(products)) => HttpResponse::Ok().json(ApiOk::new(ProductList {
+            count: products.len(),
+            products,
+            limit,
+            offset,
+        })),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// Brand and category counts for filter UIs (registered ahead of `/api/products/{barcode}`)
+#[get("/api/products/facets")]
+async fn product_facets(pool: web::Data<DbPool>) -> impl Responder {
+    cached_facets(&facets::PRODUCT_FACETS, facets::product_facets, pool).await
+}
+
+/// Serve facets from `cache` while fresh, otherwise recompute them with `compute`
+async fn cached_facets<T>(
+    cache: &'static facets::FacetCache<T>,
+    compute: fn(&mut PgConnection) -> Result<T, diesel::result::Error>,
+    pool: web::Data<DbPool>,
+) -> HttpResponse
+where
+    T: Clone + Serialize + Send + 'static,
+{
+    let ttl = config::get().facets_cache_ttl;
+    if let Some(cached) = cache.fresh(std::time::Instant::now(), ttl) {
+        return HttpResponse::Ok().json(ApiOk::new(cached));
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    match web::block(move || compute(&mut conn)).await {
+        Ok(Ok(computed)) => {
+            cache.store(std::time::Instant::now(), computed.clone());
+            HttpResponse::Ok().json(ApiOk::new(computed))
+        }
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ProductQuery {
+    /// Comma-separated sections to embed, see [`ProductIncludes`]
+    include: Option<String>,
+    /// Re-fetch a stored product from the product sources even if it isn't stale yet
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// Sections `?include=` embeds in a product response. Both are left out by default to
+/// keep the payload small.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ProductIncludes {
+    /// The linked ingredient objects, in label order
+    ingredients: bool,
+    /// Nutrition facts in the default basis, as from `/nutrition`
+    nutrition: bool,
+}
+
+impl ProductIncludes {
+    fn parse(include: Option<&str>) -> Result<Self, String> {
+        let mut includes = ProductIncludes::default();
+        for section in include.unwrap_or("").split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match section {
+                "ingredients" => includes.ingredients = true,
+                "nutrition" => includes.nutrition = true,
+                other => return Err(format!("Unknown include '{}', expected ingredients or nutrition", other)),
+            }
+        }
+        Ok(includes)
+    }
+}
+
+/// A product with the sections asked for through `?include=` next to its own fields
+#[derive(Serialize)]
+struct ProductWithIncludes {
+    #[serde(flatten)]
+    product: Product,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ingredients: Option<Vec<Ingredient>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nutrition: Option<nutrition::NutritionFacts>,
+}
+
+impl ProductWithIncludes {
+    /// Compose `product` with its already loaded `ingredients` and, when included, its nutrition
+    fn new(product: Product, includes: ProductIncludes, ingredients: Option<Vec<Ingredient>>) -> Self {
+        let nutrition = includes.nutrition.then(|| {
+            nutrition::from_off_product(&product.full_response, config::get().default_nutrition_basis, product.package_grams())
+        });
+
+        ProductWithIncludes { product, ingredients, nutrition }
+    }
+
+    /// Load the included sections for `product`
+    fn load(product: Product, includes: ProductIncludes, conn: &mut PgConnection) -> QueryResult<Self> {
+        let ingredients = match includes.ingredients {
+            true => Some(Ingredient::linked_to_product(product.id, conn)?),
+            false => None,
+        };
+        Ok(ProductWithIncludes::new(product, includes, ingredients))
+    }
+}
+
+/// A product, fetched from the product sources on a cache miss, or when the stored copy is
+/// older than PRODUCT_TTL_DAYS or `?refresh=true` asks for it. `?include=ingredients,nutrition`
+/// embeds its linked ingredients and nutrition facts.
+#[get("/api/products/{barcode}")]
+async fn get_product(
+    barcode: Barcode,
+    query: web::Query<ProductQuery>,
+    pool: web::Data<DbPool>,
+    clock: web::Data<dyn Clock>,
+    source_chain: web::Data<SourceChain>,
+    config: web::Data<Config>,
+    queue: web::Data<SharedQueue>,
+) -> Result<HttpResponse, AppError> {
+    let barcode = barcode.into_inner();
+    let includes = ProductIncludes::parse(query.include.as_deref())
+        .map_err(|message| AppError::BadRequest(ApiError::new(message)))?;
+    let deadline = deadline::start(config.request_deadline);
+
+    // Check database first
+    let (permit, mut conn) = db::checkout(&pool).await?;
+
+    // Try to find product in database; a stale copy is refreshed instead of served
     let barcode_clone = barcode.clone();
+    let (refresh, product_ttl, now) = (query.refresh, config.product_ttl, clock.now());
     let existing_product = web::block(move || {
-        products::table
+        let Some(product) = products::table
             .filter(products::barcode.eq(&barcode_clone))
             .first::<Product>(&mut conn)
-            .optional()
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        if refresh || product_ttl.is_some_and(|ttl| product.is_stale(now, ttl)) {
+            return Ok(Some(CachedProduct::Stale(product)));
+        }
+        ProductWithIncludes::load(product, includes, &mut conn).map(|product| Some(CachedProduct::Fresh(product)))
     })
     .await;
+    // Don't hold a DB slot while waiting on upstream sources
+    drop(permit);
 
     match existing_product {
-        Ok(Ok(Some(product))) => {
+        Ok(Ok(Some(CachedProduct::Fresh(product)))) => {
             log::info!("Product {} found in database", barcode);
-            return HttpResponse::Ok().json(product);
+            metrics::record_cache_hit();
+            return Ok(HttpResponse::Ok().json(ApiOk::new(product)));
+        }
+        Ok(Ok(Some(CachedProduct::Stale(product)))) => {
+            log::info!("Product {} found in database, refreshing from the product sources", barcode);
+            return Ok(refresh_product(product, includes, deadline, pool, queue, source_chain, clock).await);
         }
         Ok(Ok(None)) => {
             log::info!("Product {} not found in database, querying OpenFoodFacts", barcode);
@@ -81,94 +345,189 @@ async fn get_product(
         }
     }
 
-    // Query OpenFoodFacts API
-    let client = reqwest::Client::new();
-    let url = format!("https://world.openfoodfacts.org/api/v2/product/{}", barcode);
+    // Skip OpenFoodFacts if it recently told us this barcode doesn't exist
+    if let Ok((_permit, mut conn)) = db::checkout(&pool).await {
+        let barcode_clone = barcode.clone();
+        let lookup = web::block(move || ProductLookup::find(&barcode_clone, &mut conn)).await;
 
-    let off_response = match client.get(&url).send().await {
-        Ok(response) => response,
-        Err(e) => {
-            log::error!("Failed to query OpenFoodFacts: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to query OpenFoodFacts API"
-            }));
+        if let Ok(Ok(Some(lookup))) = lookup
+            && lookup.is_recent_miss(clock.get_ref(), config.negative_lookup_ttl)
+        {
+            log::info!("Product {} recently not found on OpenFoodFacts, skipping lookup", barcode);
+            metrics::record_negative_cache_hit();
+            return Err(product_not_found(&barcode, LookupSource::NegativeCache));
         }
-    };
+    }
 
-    let off_data: OpenFoodFactsResponse = match off_response.json().await {
-        Ok(data) => data,
-        Err(e) => {
-            log::error!("Failed to parse OpenFoodFacts response: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to parse OpenFoodFacts response"
-            }));
+    // Walk the product sources (PRODUCT_SOURCES). Abandoned if the deadline passes or the
+    // client disconnects (Actix then drops this future), so impatient clients don't cost
+    // a full upstream round trip.
+    let lookup = match deadline::within(deadline, source_chain.lookup(&barcode)).await {
+        Ok(lookup) => lookup,
+        Err(deadline::DeadlineExceeded) => {
+            log::warn!("Product source lookup for {} abandoned at the request deadline", barcode);
+            metrics::record_off_fetch(FetchOutcome::Timeout);
+            return Err(AppError::UpstreamTimeout(
+                ApiError::new("Product sources did not respond in time").with("barcode", &barcode),
+            ));
         }
     };
+    metrics::record_off_fetch(match lookup {
+        ChainLookup::Found { .. } => FetchOutcome::Found,
+        ChainLookup::NotFound => FetchOutcome::NotFound,
+        ChainLookup::Failed => FetchOutcome::Failed,
+    });
 
-    // Check if product was found
-    if off_data.status != 1 || off_data.product.is_none() {
-        return HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Product not found"
-        }));
-    }
-
-    let product_data = off_data.product.unwrap();
-
-    // Extract key fields
-    let product_name = product_data.get("product_name")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
-
-    let brands = product_data.get("brands")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+    // The answer is worth keeping even if the client has gone, so store it on its own task
+    deadline::detached(store_lookup_result(barcode, lookup, includes, pool, queue, clock))
+        .await
+        .map_err(|e| AppError::Internal(format!("storing product lookup result failed: {}", e)))?
+}
 
-    let categories = product_data.get("categories")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+/// A stored product as `get_product` found it
+enum CachedProduct {
+    /// Served as is
+    Fresh(ProductWithIncludes),
+    /// Past PRODUCT_TTL_DAYS, or a refresh was asked for
+    Stale(Product),
+}
 
-    let quantity = product_data.get("quantity")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+/// Re-fetch a stored product from the product sources and write a new revision over it.
+/// Concurrent refreshes of one barcode share the lookup, and only one of them writes. If
+/// the sources fail or no longer have it, the stored copy is served.
+async fn refresh_product(
+    product: Product,
+    includes: ProductIncludes,
+    deadline: tokio::time::Instant,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+    source_chain: web::Data<SourceChain>,
+    clock: web::Data<dyn Clock>,
+) -> HttpResponse {
+    let started_at = clock.now();
+    let lookup = match deadline::within(deadline, source_chain.lookup(&product.barcode)).await {
+        Ok(lookup) => lookup,
+        Err(deadline::DeadlineExceeded) => {
+            log::warn!("Refresh of product {} abandoned at the request deadline", product.barcode);
+            metrics::record_off_fetch(FetchOutcome::Timeout);
+            return serve_stored_product(product, includes, pool).await;
+        }
+    };
+    metrics::record_off_fetch(match lookup {
+        ChainLookup::Found { .. } => FetchOutcome::Found,
+        ChainLookup::NotFound => FetchOutcome::NotFound,
+        ChainLookup::Failed => FetchOutcome::Failed,
+    });
 
-    let image_url = product_data.get("image_url")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+    let (source, product_data) = match lookup {
+        ChainLookup::Found { source, product } => (source, product),
+        ChainLookup::NotFound | ChainLookup::Failed => {
+            log::warn!("Could not refresh product {}, serving the stored copy", product.barcode);
+            return serve_stored_product(product, includes, pool).await;
+        }
+    };
+    let new_product = NewProduct {
+        data_source: Some(source.to_string()),
+        ..off::extract(&product.barcode, &product_data)
+    };
 
-    let nutriscore_grade = product_data.get("nutriscore_grade")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+    // The new revision is worth keeping even if the client has gone
+    let store = async move {
+        let mut conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(e) => {
+                log::error!("Failed to get DB connection for refresh: {}", e);
+                return serve_stored_product(product, includes, pool).await;
+            }
+        };
+        let product_id = product.id;
+        let now = clock.now();
+        let refreshed = web::block(move || {
+            let refreshed = product.apply_refresh(&new_product, started_at, now, &mut conn)?;
+            let updated = matches!(refreshed, models::Refreshed::Updated(_));
+            let current = match refreshed {
+                models::Refreshed::Updated(product) => *product,
+                models::Refreshed::Unchanged | models::Refreshed::ClaimedElsewhere => {
+                    products::table.find(product_id).first::<Product>(&mut conn)?
+                }
+            };
+            Ok::<_, diesel::result::Error>((updated, current))
+        })
+        .await;
 
-    let nova_group = product_data.get("nova_group")
-        .and_then(|v| v.as_i64())
-        .map(|i| i as i32);
+        match refreshed {
+            Ok(Ok((updated, current))) => {
+                if updated {
+                    log::info!("Product {} refreshed at rev {:?}", current.barcode, current.off_rev);
+                    product_ingredients::process_if_changed(&current.ingredient_data(), current.id, &pool, &mut queue.get()).await;
+                }
+                serve_stored_product(current, includes, pool).await
+            }
+            Ok(Err(e)) => {
+                log::error!("Failed to refresh product {}: {}", product_id, e);
+                api::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ApiError::new("Database query failed").with("code", "db_query_failed"),
+                )
+            }
+            Err(e) => AppError::from(e).error_response(),
+        }
+    };
+    match deadline::detached(store).await {
+        Ok(response) => response,
+        Err(e) => {
+            log::error!("Storing product refresh failed: {}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Internal server error"))
+        }
+    }
+}
 
-    let ecoscore_grade = product_data.get("ecoscore_grade")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+/// Respond with a stored product and the sections `includes` asks for
+async fn serve_stored_product(product: Product, includes: ProductIncludes, pool: web::Data<DbPool>) -> HttpResponse {
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
 
-    let ingredients_text = product_data.get("ingredients_text")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+    match web::block(move || ProductWithIncludes::load(product, includes, &mut conn)).await {
+        Ok(Ok(product)) => HttpResponse::Ok().json(ApiOk::new(product)),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
 
-    let allergens = product_data.get("allergens")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+/// Record the lookup and cache a found product with its history and ingredients
+async fn store_lookup_result(
+    barcode: String,
+    lookup: ChainLookup,
+    includes: ProductIncludes,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, AppError> {
+    let (source, product_data) = match lookup {
+        ChainLookup::Found { source, product } => {
+            record_product_lookup(&barcode, true, &pool, clock).await;
+            (source, product)
+        }
+        ChainLookup::NotFound => {
+            record_product_lookup(&barcode, false, &pool, clock).await;
+            log::info!("Product {} not found in any product source", barcode);
+            return Err(product_not_found(&barcode, LookupSource::Off));
+        }
+        // Upstream outages (including HTML error pages) aren't our fault, so say so
+        ChainLookup::Failed => {
+            return Err(AppError::UpstreamApi(ApiError::new("Failed to query product sources")));
+        }
+    };
 
     // Store in database
     let new_product = NewProduct {
-        barcode: barcode.clone(),
-        product_name,
-        brands,
-        categories,
-        quantity,
-        image_url,
-        nutriscore_grade,
-        nova_group,
-        ecoscore_grade,
-        ingredients_text,
-        allergens,
-        full_response: product_data.clone(),
+        data_source: Some(source.to_string()),
+        ..off::extract(&barcode, &product_data)
     };
 
     let mut conn = match pool.get() {
@@ -176,131 +535,177 @@ async fn get_product(
         Err(e) => {
             log::error!("Failed to get DB connection for insert: {}", e);
             // Still return the product data even if we can't store it
-            return HttpResponse::Ok().json(product_data);
+            return Ok(HttpResponse::Ok().json(ApiOk::new(product_data)));
         }
     };
 
-    let inserted_product = web::block(move || {
-        diesel::insert_into(products::table)
-            .values(&new_product)
-            .get_result::<Product>(&mut conn)
-    })
-    .await;
+    // A concurrent request for the same new barcode may have stored it first
+    let inserted_product = web::block(move || Product::insert_or_get(&new_product, &mut conn)).await;
 
     match inserted_product {
-        Ok(Ok(product)) => {
-            log::info!("Product {} stored in database", barcode);
+        Ok(Ok((product, inserted))) => {
+            if inserted {
+                log::info!("Product {} stored in database", barcode);
+                // Process ingredients - extract and enqueue for creation if needed
+                product_ingredients::process_if_changed(&product_data, product.id, &pool, &mut queue.get()).await;
+            } else {
+                log::info!("Product {} was stored by a concurrent request", barcode);
+            }
 
-            // Process ingredients - extract and enqueue for creation if needed
-            process_product_ingredients(&product_data, &pool);
+            if !includes.ingredients {
+                return Ok(HttpResponse::Ok().json(ApiOk::new(ProductWithIncludes::new(product, includes, None))));
+            }
 
-            HttpResponse::Ok().json(product)
+            // The ingredients just linked; an empty list if they can't be loaded
+            let product_id = product.id;
+            let linked = match pool.get() {
+                Ok(mut conn) => web::block(move || Ingredient::linked_to_product(product_id, &mut conn))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|linked| linked.map_err(|e| e.to_string())),
+                Err(e) => Err(e.to_string()),
+            };
+            let linked = linked.unwrap_or_else(|e| {
+                log::error!("Failed to load linked ingredients of product {}: {}", product_id, e);
+                Vec::new()
+            });
+            Ok(HttpResponse::Ok().json(ApiOk::new(ProductWithIncludes::new(product, includes, Some(linked)))))
         }
         Ok(Err(e)) => {
             log::error!("Failed to insert product: {}", e);
             // Still return the product data even if we can't store it
-            HttpResponse::Ok().json(product_data)
+            Ok(HttpResponse::Ok().json(ApiOk::new(product_data)))
         }
         Err(e) => {
             log::error!("Blocking error on insert: {}", e);
-            HttpResponse::Ok().json(product_data)
+            Ok(HttpResponse::Ok().json(ApiOk::new(product_data)))
         }
     }
 }
 
-/// Process ingredients from product data and enqueue for creation if needed
-fn process_product_ingredients(product_data: &serde_json::Value, pool: &web::Data<DbPool>) {
-    // Try to get ingredients array from OpenFoodFacts data
-    let ingredients_array = product_data
-        .get("ingredients")
-        .and_then(|v| v.as_array());
+/// Where a product lookup gave up, reported in 404 bodies for debuggability
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum LookupSource {
+    /// The product sources (OpenFoodFacts unless PRODUCT_SOURCES says otherwise) were
+    /// queried and none has the product
+    Off,
+    /// Only our own database was consulted
+    Cache,
+    /// OpenFoodFacts recently reported a miss, so it wasn't asked again
+    NegativeCache,
+}
 
-    if let Some(ingredients) = ingredients_array {
-        log::info!("Processing {} ingredients from product", ingredients.len());
+fn product_not_found(barcode: &str, source: LookupSource) -> AppError {
+    AppError::NotFound(ApiError::new("Product not found").with("barcode", barcode).with("source", source))
+}
 
-        // Get a database connection
-        let mut conn = match pool.get() {
-            Ok(conn) => conn,
-            Err(e) => {
-                log::error!("Failed to get DB connection for ingredient processing: {}", e);
-                return;
-            }
-        };
+/// Seconds clients are asked to wait before retrying when the job queue is full
+const QUEUE_FULL_RETRY_AFTER_SECS: u64 = 30;
 
-        // Process each ingredient
-        for ingredient in ingredients {
-            // Extract ingredient name (can be "text", "id", or other fields)
-            let ingredient_name = ingredient
-                .get("text")
-                .or_else(|| ingredient.get("id"))
-                .and_then(|v| v.as_str());
-
-            if let Some(name) = ingredient_name {
-                // Clean up the ingredient name
-                let clean_name = name.trim();
-
-                if !clean_name.is_empty() {
-                    log::info!("Processing ingredient: {}", clean_name);
-
-                    // Find or enqueue for creation
-                    match Ingredient::find_or_enqueue_for_creation(clean_name, &mut conn) {
-                        Ok(Some(id)) => {
-                            log::info!("Ingredient '{}' found with ID: {}", clean_name, id);
-                        }
-                        Ok(None) => {
-                            log::info!("Ingredient '{}' enqueued for creation", clean_name);
-                        }
-                        Err(e) => {
-                            log::error!("Error processing ingredient '{}': {}", clean_name, e);
-                        }
-                    }
-                }
-            }
+/// 503 when the job queue is too backed up for another `class` job (see `backpressure`).
+/// Without a free connection to count the queue with, the job is let through.
+async fn queue_backpressure(class: JobClass, pool: &DbPool) -> Option<HttpResponse> {
+    let (_permit, mut conn) = match db::checkout(pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection for the queue check: {}", e);
+            return None;
         }
-    } else {
-        // Fallback: try to parse ingredients_text (comma-separated string)
-        if let Some(ingredients_text) = product_data
-            .get("ingredients_text")
-            .and_then(|v| v.as_str())
-        {
-            log::info!("Processing ingredients from text: {}", ingredients_text);
+    };
 
-            let mut conn = match pool.get() {
-                Ok(conn) => conn,
-                Err(e) => {
-                    log::error!("Failed to get DB connection for ingredient processing: {}", e);
-                    return;
-                }
-            };
+    let max_pending = class.max_pending();
+    match web::block(move || backpressure::check(class, max_pending, &mut conn)).await {
+        Ok(Err(full)) => Some(queue_full(&full)),
+        Ok(Ok(())) => None,
+        Err(e) => {
+            log::error!("Blocking error: {}", e);
+            None
+        }
+    }
+}
 
-            // Split by commas and process each ingredient
-            for ingredient_name in ingredients_text.split(',') {
-                let clean_name = ingredient_name.trim();
-
-                if !clean_name.is_empty() {
-                    log::info!("Processing ingredient: {}", clean_name);
-
-                    match Ingredient::find_or_enqueue_for_creation(clean_name, &mut conn) {
-                        Ok(Some(id)) => {
-                            log::info!("Ingredient '{}' found with ID: {}", clean_name, id);
-                        }
-                        Ok(None) => {
-                            log::info!("Ingredient '{}' enqueued for creation", clean_name);
-                        }
-                        Err(e) => {
-                            log::error!("Error processing ingredient '{}': {}", clean_name, e);
-                        }
-                    }
-                }
-            }
-        } else {
-            log::info!("No ingredients data found in product");
+/// The 503 for a job the queue turned away
+fn queue_full(full: &backpressure::QueueFull) -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((actix_web::http::header::RETRY_AFTER, QUEUE_FULL_RETRY_AFTER_SECS.to_string()))
+        .json(
+            ApiError::new("Job queue is full")
+                .with("code", "queue_full")
+                .with("pending", full.pending)
+                .with("max_pending", full.max_pending)
+                .with("retry_after_secs", QUEUE_FULL_RETRY_AFTER_SECS),
+        )
+}
+
+/// Connection pool size and how long requests have waited for a connection
+#[get("/api/admin/db-pool")]
+async fn db_pool_stats(req: HttpRequest, api_key: web::Data<AdminApiKey>, pool: web::Data<DbPool>) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    HttpResponse::Ok().json(ApiOk::new(db::pool_stats(&pool)))
+}
+
+/// Product lookup counters in Prometheus text format
+#[get("/metrics")]
+async fn product_metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render(&metrics::product_lookup_counts()))
+}
+
+/// Remember whether OpenFoodFacts knew about a barcode so repeat misses can be short-circuited
+async fn record_product_lookup(barcode: &str, was_found: bool, pool: &web::Data<DbPool>, clock: web::Data<dyn Clock>) {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            log::error!("Failed to get DB connection for lookup record: {}", e);
+            return;
         }
+    };
+
+    let barcode = barcode.to_string();
+    match web::block(move || ProductLookup::record(&barcode, was_found, clock.get_ref(), &mut conn)).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => log::error!("Failed to record product lookup: {}", e),
+        Err(e) => log::error!("Blocking error recording product lookup: {}", e),
     }
 }
 
+/// Number of distinct ingredients `process_product_ingredients` looks up for a product.
+/// OFF entries without any name can't be told apart, so each of them counts once.
+fn listed_ingredient_count(product_data: &serde_json::Value) -> usize {
+    let names: Vec<Option<Cow<str>>> = match product_data.get("ingredients").and_then(|v| v.as_array()) {
+        Some(ingredients) => ingredients.iter().map(off::ingredient_name).collect(),
+        None => product_data
+            .get("ingredients_text")
+            .and_then(|v| v.as_str())
+            .map(|text| text.split(',').map(|name| Some(Cow::Borrowed(name))).collect())
+            .unwrap_or_default(),
+    };
+
+    let mut unnamed = 0;
+    let mut distinct: Vec<String> = names
+        .into_iter()
+        .take(config::get().max_ingredients_per_product)
+        .filter_map(|name| {
+            if name.is_none() {
+                unnamed += 1;
+            }
+            name
+        })
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    distinct.sort();
+    distinct.dedup();
+    distinct.len() + unnamed
+}
+
 /// Process ingredients from non-food products (supplements, beauty, etc.)
-fn process_non_food_ingredients(product: &ProductNonFood, pool: &web::Data<DbPool>) {
+async fn process_non_food_ingredients(product: &ProductNonFood, pool: &web::Data<DbPool>, queue: &web::Data<SharedQueue>) {
     log::info!("Extracting ingredients from non-food product: {}", product.name);
 
     // Try to extract ingredients from description
@@ -323,6 +728,7 @@ fn process_non_food_ingredients(product: &ProductNonFood, pool: &web::Data<DbPoo
         };
 
         // Collect ingredient names
+        let max_name_len = config::get().max_ingredient_name_len;
         let ingredient_names: Vec<String> = ingredients
             .split(',')
             .map(|name| name.trim().trim_end_matches('.').trim_end_matches(';').to_string())
@@ -330,7 +736,8 @@ fn process_non_food_ingredients(product: &ProductNonFood, pool: &web::Data<DbPoo
                 !name.is_empty() &&
                 name.len() >= 2 &&
                 !name.eq_ignore_ascii_case("and") &&
-                !name.eq_ignore_ascii_case("or")
+                !name.eq_ignore_ascii_case("or") &&
+                models::ingredient_name_fits(name, max_name_len)
             })
             .collect();
 
@@ -339,95 +746,43 @@ fn process_non_food_ingredients(product: &ProductNonFood, pool: &web::Data<DbPoo
             return;
         }
 
-        log::info!("Processing {} ingredients", ingredient_names.len());
-
-        // Spawn async task to enqueue all ingredients sequentially with single queue connection
-        tokio::spawn(async move {
-            use fang::asynk::async_queue::{AsyncQueue, AsyncQueueable};
-            use fang::NoTls;
-            use crate::jobs::CreateIngredientJob;
-
-            let database_url = match std::env::var("DATABASE_URL") {
-                Ok(url) => url,
-                Err(_) => {
-                    log::error!("DATABASE_URL not set");
-                    return;
-                }
-            };
-
-            let mut queue = AsyncQueue::builder()
-                .uri(database_url)
-                .max_pool_size(2_u32)
-                .build();
-
-            // Connect once and reuse the connection
-            let connect_result = tokio::time::timeout(
-                std::time::Duration::from_secs(10),
-                queue.connect(NoTls)
-            ).await;
-
-            match connect_result {
-                Ok(Ok(_)) => {
-                    log::info!("Connected to job queue for ingredient processing");
-
-                    // Process ingredients sequentially to avoid overwhelming the connection pool
-                    for ingredient_name in ingredient_names {
-                        let job = CreateIngredientJob {
-                            name: ingredient_name.clone(),
-                        };
-
-                        match queue.insert_task(&job).await {
-                            Ok(_) => {
-                                log::info!("Successfully enqueued CreateIngredientJob for '{}'", ingredient_name);
-                            }
-                            Err(e) => {
-                                log::error!("Failed to enqueue job for '{}': {:?}", ingredient_name, e);
-                            }
-                        }
-
-                        // Small delay between insertions to avoid rate limiting
-                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    }
-
-                    log::info!("Finished enqueueing all ingredient jobs");
-                }
-                Ok(Err(e)) => {
-                    log::error!("Failed to connect to job queue: {:?}", e);
-                }
-                Err(_) => {
-                    log::error!("Timeout connecting to job queue");
-                }
-            }
-        });
-
-        // Mark ingredients as found or enqueued in the sync code
-        for ingredient_name in ingredients.split(',') {
-            let clean_name = ingredient_name
-                .trim()
-                .trim_end_matches('.')
-                .trim_end_matches(';');
+        let ingredient_names = product_ingredients::cap_ingredients(ingredient_names, config::get().max_ingredients_per_product);
 
-            if clean_name.is_empty() ||
-               clean_name.len() < 2 ||
-               clean_name.eq_ignore_ascii_case("and") ||
-               clean_name.eq_ignore_ascii_case("or") {
-                continue;
-            }
+        log::info!("Processing {} ingredients", ingredient_names.len());
 
+        let mut missing = Vec::new();
+        for clean_name in ingredient_names {
             log::info!("Processing ingredient: {}", clean_name);
 
-            match Ingredient::find_in_db(clean_name, &mut conn) {
+            match Ingredient::find_in_db(&clean_name, &mut conn) {
                 Ok(Some(id)) => {
                     log::info!("Ingredient '{}' found with ID: {}", clean_name, id);
                 }
-                Ok(None) => {
-                    log::info!("Ingredient '{}' enqueued for creation", clean_name);
-                }
+                Ok(None) => missing.push(clean_name),
                 Err(e) => {
+                    // The creation job looks the name up again, so it can still be queued
                     log::error!("Error checking ingredient '{}': {}", clean_name, e);
+                    missing.push(clean_name);
                 }
             }
         }
+
+        let missing_count = missing.len();
+        let missing = match Ingredient::admit_for_creation(missing, config::get().auto_create_ingredients, &mut conn) {
+            Ok(missing) => missing,
+            Err(full) => {
+                log::warn!("Job queue full, not enqueueing {} ingredients of {}: {}", missing_count, product.name, full);
+                return;
+            }
+        };
+        // Not held while the jobs are inserted through the queue's own connections
+        drop(conn);
+
+        // Awaited rather than spawned, so the jobs are queued by the time the product is returned
+        match Ingredient::enqueue_creation(&missing, &mut queue.get()).await {
+            Ok(()) => log::info!("Enqueued {} ingredient jobs for {}", missing.len(), product.name),
+            Err(e) => log::error!("Failed to enqueue ingredient jobs for {}: {}", product.name, e),
+        }
     } else {
         log::info!("No ingredients found in product description");
     }
@@ -458,10 +813,10 @@ fn extract_ingredients_from_text(text: &str) -> Option<String> {
             // Look for common ending patterns
             if let Some(idx) = remaining_text.find(". ") {
                 // Check if next character is uppercase (likely new sentence)
-                if let Some(next_char) = remaining_text.chars().nth(idx + 2) {
-                    if next_char.is_uppercase() {
-                        end_idx = idx;
-                    }
+                if let Some(next_char) = remaining_text.chars().nth(idx + 2)
+                    && next_char.is_uppercase()
+                {
+                    end_idx = idx;
                 }
             }
 
@@ -475,431 +830,3890 @@ fn extract_ingredients_from_text(text: &str) -> Option<String> {
     None
 }
 
-// ============= Non-Food Products Endpoints =============
+#[derive(Deserialize)]
+struct HistoryDiffQuery {
+    from: i32,
+    to: i32,
+}
 
-#[get("/api/products-non-food/{barcode}")]
-async fn get_product_non_food(
-    barcode: web::Path<String>,
+/// Which history entry one side of a diff is
+#[derive(Serialize)]
+struct SnapshotRef {
+    id: i32,
+    off_rev: Option<i32>,
+    captured_at: chrono::NaiveDateTime,
+}
+
+impl SnapshotRef {
+    fn of(snapshot: &ProductHistory) -> Self {
+        SnapshotRef {
+            id: snapshot.id,
+            off_rev: snapshot.off_rev,
+            captured_at: snapshot.captured_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HistoryDiff {
+    barcode: String,
+    from: SnapshotRef,
+    to: SnapshotRef,
+    unchanged: bool,
+    diff: json_diff::JsonDiff,
+}
+
+#[get("/api/products/{barcode}/history/diff")]
+async fn product_history_diff(
+    barcode: Barcode,
+    query: web::Query<HistoryDiffQuery>,
     pool: web::Data<DbPool>,
 ) -> impl Responder {
     let barcode = barcode.into_inner();
+    let HistoryDiffQuery { from, to } = query.into_inner();
 
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
         Err(e) => {
             log::error!("Failed to get DB connection: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Database connection failed"
-            }));
+            return db_unavailable();
         }
     };
 
-    // Try to find product in database
     let barcode_clone = barcode.clone();
-    let existing_product = web::block(move || {
-        products_non_food::table
-            .filter(products_non_food::barcode.eq(&barcode_clone))
-            .first::<ProductNonFood>(&mut conn)
-            .optional()
+    let snapshots = web::block(move || {
+        product_history::table
+            .filter(product_history::barcode.eq(&barcode_clone))
+            .filter(product_history::id.eq_any([from, to]))
+            .load::<ProductHistory>(&mut conn)
     })
     .await;
 
-    match existing_product {
-        Ok(Ok(Some(product))) => {
-            log::info!("Non-food product {} found in database", barcode);
-            HttpResponse::Ok().json(product)
-        }
-        Ok(Ok(None)) => {
-            log::info!("Non-food product {} not found in database", barcode);
-            HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Product not found",
-                "barcode": barcode
-            }))
-        }
-        Ok(Err(e)) => {
-            log::error!("Database query error: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Database query failed"
-            }))
-        }
-        Err(e) => {
-            log::error!("Blocking error: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
+    let snapshots = match snapshots {
+        Ok(Ok(snapshots)) => snapshots,
+        Ok(Err(e)) => return AppError::DbQuery(e).error_response(),
+        Err(e) => return AppError::from(e).error_response(),
+    };
+
+    let from_snapshot = snapshots.iter().find(|s| s.id == from);
+    let to_snapshot = snapshots.iter().find(|s| s.id == to);
+
+    match (from_snapshot, to_snapshot) {
+        (Some(from_snapshot), Some(to_snapshot)) => {
+            let diff = json_diff::diff(
+                &from_snapshot.full_response,
+                &to_snapshot.full_response,
+                json_diff::DEFAULT_MAX_DEPTH,
+            );
+
+            HttpResponse::Ok().json(ApiOk::new(HistoryDiff {
+                unchanged: diff.is_empty(),
+                from: SnapshotRef::of(from_snapshot),
+                to: SnapshotRef::of(to_snapshot),
+                barcode,
+                diff,
             }))
         }
+        _ => api::error(StatusCode::NOT_FOUND, ApiError::new("History entry not found").with("barcode", &barcode)),
     }
 }
 
+/// How far a scanned product has got through caching, ingredient resolution and analysis
+#[derive(Serialize, Debug, PartialEq)]
+struct ProductStatus {
+    barcode: String,
+    cached: bool,
+    ingredient_count: usize,
+    pending_ingredients: usize,
+    analyzed: bool,
+}
+
 #[derive(Deserialize)]
-struct CreateProductNonFoodRequest {
-    barcode: Option<String>,
-    name: String,
-    brand: Option<String>,
-    category: Option<String>,
-    description: Option<String>,
-    data_source: Option<String>,
+struct ReprocessQuery {
+    /// Redo the work even when the ingredient list is the one processed last time
+    #[serde(default)]
+    force: bool,
 }
 
-#[post("/api/products-non-food")]
-async fn create_product_non_food(
-    body: web::Json<CreateProductNonFoodRequest>,
+#[derive(Serialize)]
+struct IngredientReprocessing {
+    barcode: String,
+    /// False when the ingredient list is the one processed last time
+    reprocessed: bool,
+}
+
+/// Re-link a stored product's ingredients from its stored OFF data (or the list read off
+/// its ingredients photo). Skipped when its `ingredients_text` hasn't changed since the
+/// ingredients were last processed, unless `?force=true`.
+#[post("/api/products/{barcode}/reprocess-ingredients")]
+async fn reprocess_product_ingredients(
+    barcode: Barcode,
+    query: web::Query<ReprocessQuery>,
     pool: web::Data<DbPool>,
-) -> impl Responder {
-    let new_product = NewProductNonFood {
-        barcode: body.barcode.clone(),
-        name: body.name.clone(),
-        brand: body.brand.clone(),
-        category: body.category.clone(),
-        description: body.description.clone(),
-        full_response: None,
-        data_source: body.data_source.clone(),
+    queue: web::Data<SharedQueue>,
+) -> Result<HttpResponse, AppError> {
+    let barcode = barcode.into_inner();
+    let force = query.force;
+    let (_permit, mut conn) = db::checkout(&pool).await?;
+
+    let barcode_clone = barcode.clone();
+    let (product, mut conn) = web::block(move || {
+        products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()
+            .map(|product| (product, conn))
+    })
+    .await??;
+    let Some(product) = product else {
+        return Err(product_not_found(&barcode, LookupSource::Cache));
     };
 
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
+    let mut queue = queue.get();
+    match product_ingredients::process(&product.ingredient_data(), product.id, force, &mut conn, &mut queue).await {
+        Err(product_ingredients::ProcessingError::Db(e)) => Err(AppError::DbQuery(e)),
+        // Ingredients need creating and the queue can't take them; nothing was recorded
+        Err(product_ingredients::ProcessingError::QueueFull(full)) => Ok(queue_full(&full)),
+        Err(product_ingredients::ProcessingError::Enqueue(e)) => {
+            log::error!("Failed to enqueue ingredient creation for product {}: {:?}", barcode, e);
+            Ok(api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job")))
+        }
+        Ok(reprocessed) => {
+            if !reprocessed {
+                log::info!("Ingredients of product {} unchanged, skipping reprocessing", barcode);
+            }
+            Ok(HttpResponse::Ok().json(ApiOk::new(IngredientReprocessing { barcode, reprocessed })))
+        }
+    }
+}
+
+/// Queue reading a stored product's ingredients off its ingredients photo, for products
+/// OFF has no ingredient list for. Needs OCR_ENABLED and an OCR service. Admin-only,
+/// since every job downloads a photo and pays for a call to the OCR service.
+#[post("/api/products/{barcode}/ocr-ingredients")]
+async fn ocr_product_ingredients(
+    req: HttpRequest,
+    barcode: Barcode,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+    let barcode = barcode.into_inner();
+
+    if !config.ocr_enabled {
+        return api::error(StatusCode::SERVICE_UNAVAILABLE, ApiError::new("OCR is not enabled"));
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
         Err(e) => {
             log::error!("Failed to get DB connection: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Database connection failed"
-            }));
+            return db_unavailable();
         }
     };
 
-    let inserted_product = web::block(move || {
-        diesel::insert_into(products_non_food::table)
-            .values(&new_product)
-            .get_result::<ProductNonFood>(&mut conn)
+    let barcode_clone = barcode.clone();
+    let product = web::block(move || {
+        products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()
     })
     .await;
 
-    match inserted_product {
-        Ok(Ok(product)) => {
-            log::info!("Non-food product '{}' created with ID: {}", product.name, product.id);
+    let product = match product {
+        Ok(Ok(Some(product))) => product,
+        Ok(Ok(None)) => return product_not_found(&barcode, LookupSource::Cache).error_response(),
+        Ok(Err(e)) => return AppError::DbQuery(e).error_response(),
+        Err(e) => return AppError::from(e).error_response(),
+    };
 
-            // Process ingredients for supplements and beauty products
-            if let Some(ref category) = product.category {
-                let category_lower = category.to_lowercase();
-                if category_lower.contains("supplement") ||
-                   category_lower.contains("beauty") ||
-                   category_lower.contains("cosmetic") ||
-                   category_lower.contains("skincare") ||
-                   category_lower.contains("vitamin") {
-                    log::info!("Processing ingredients for {} product: {}", category, product.name);
-                    process_non_food_ingredients(&product, &pool);
-                }
-            }
+    if product.off_lists_ingredients() {
+        return api::error(
+            StatusCode::CONFLICT,
+            ApiError::new("Product already has an ingredient list").with("barcode", &barcode),
+        );
+    }
+    if product.ocr_image_url().is_none() {
+        return api::error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::new("Product has no ingredients photo").with("barcode", &barcode),
+        );
+    }
+    if let Some(full) = queue_backpressure(JobClass::Background, &pool).await {
+        return full;
+    }
 
-            HttpResponse::Created().json(product)
-        }
-        Ok(Err(e)) => {
-            log::error!("Failed to create non-food product: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to create product",
-                "details": format!("{}", e)
+    let mut queue = queue.get();
+    match queue.insert_task(&OcrIngredientsJob { product_id: product.id }).await {
+        Ok(_) => {
+            log::info!("Enqueued OCR job for product: {}", barcode);
+            HttpResponse::Ok().json(ApiOk::new(JobEnqueued {
+                barcode: Some(barcode),
+                product_id: Some(product.id),
+                ..JobEnqueued::new("OCR job enqueued successfully")
             }))
         }
         Err(e) => {
-            log::error!("Blocking error: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            }))
+            log::error!("Failed to enqueue OCR job: {:?}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job"))
         }
     }
 }
 
-#[get("/api/products-non-food")]
-async fn list_products_non_food(
-    pool: web::Data<DbPool>,
-) -> impl Responder {
-    let mut conn = match pool.get() {
-        Ok(conn) => conn,
+/// Cheap progress check for a scanned product, so clients can poll until everything is ready
+#[get("/api/products/{barcode}/status")]
+async fn product_status(barcode: Barcode, pool: web::Data<DbPool>) -> impl Responder {
+    let barcode = barcode.into_inner();
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
         Err(e) => {
             log::error!("Failed to get DB connection: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Database connection failed"
-            }));
+            return db_unavailable();
         }
     };
 
-    let products = web::block(move || {
-        products_non_food::table
-            .order(products_non_food::created_at.desc())
-            .limit(100)
-            .load::<ProductNonFood>(&mut conn)
+    let barcode_clone = barcode.clone();
+    let status = web::block(move || {
+        let product = products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()?;
+
+        let Some(product) = product else {
+            // A lookup record without a product means it was requested but isn't stored (yet)
+            let requested = ProductLookup::find(&barcode_clone, &mut conn)?.is_some();
+            return Ok::<_, diesel::result::Error>(requested.then_some(ProductStatus {
+                barcode: barcode_clone,
+                cached: false,
+                ingredient_count: 0,
+                pending_ingredients: 0,
+                analyzed: false,
+            }));
+        };
+
+        let linked = schema::product_ingredients::table
+            .filter(schema::product_ingredients::product_id.eq(product.id))
+            .count()
+            .get_result::<i64>(&mut conn)?;
+        let listed = listed_ingredient_count(&product.full_response);
+
+        Ok(Some(ProductStatus {
+            barcode: barcode_clone,
+            cached: true,
+            ingredient_count: listed,
+            pending_ingredients: listed.saturating_sub(linked as usize),
+            analyzed: product.analyzed_at.is_some(),
+        }))
     })
     .await;
 
-    match products {
-        Ok(Ok(products_list)) => {
-            log::info!("Retrieved {} non-food products", products_list.len());
-            HttpResponse::Ok().json(serde_json::json!({
-                "products": products_list,
-                "count": products_list.len()
-            }))
-        }
-        Ok(Err(e)) => {
-            log::error!("Database query error: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Database query failed"
-            }))
-        }
-        Err(e) => {
-            log::error!("Blocking error: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            }))
-        }
+    match status {
+        Ok(Ok(Some(status))) => HttpResponse::Ok().json(ApiOk::new(status)),
+        Ok(Ok(None)) => product_not_found(&barcode, LookupSource::Cache).error_response(),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
     }
 }
 
-// Job enqueueing endpoints
 #[derive(Deserialize)]
-struct EnqueueProductJobRequest {
+struct AllergenQuery {
+    exclude_allergens: Option<String>,
+    strict: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct ProductAllergens {
     barcode: String,
+    allergens: Vec<String>,
+    traces: Vec<String>,
+    excluded: bool,
+    matches: allergens::AllergenMatches,
 }
 
-#[post("/api/jobs/fetch-product")]
-async fn enqueue_fetch_product(
-    body: web::Json<EnqueueProductJobRequest>,
+/// A stored product's allergens and traces, and whether it should be avoided by someone
+/// excluding `?exclude_allergens=` (traces count too with `?strict=true`)
+#[get("/api/products/{barcode}/allergens")]
+async fn product_allergens(
+    barcode: Barcode,
+    query: web::Query<AllergenQuery>,
+    pool: web::Data<DbPool>,
 ) -> impl Responder {
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let barcode = barcode.into_inner();
+    let exclusion = allergens::AllergenExclusion::parse(
+        query.exclude_allergens.as_deref().unwrap_or(""),
+        query.strict.unwrap_or(false),
+    );
 
-    let mut queue = AsyncQueue::builder()
-        .uri(database_url)
-        .max_pool_size(3_u32)
-        .build();
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
 
-    match queue.connect(NoTls).await {
-        Ok(_) => {
-            let job = FetchProductJob {
-                barcode: body.barcode.clone(),
-            };
+    let barcode_clone = barcode.clone();
+    let product = web::block(move || {
+        products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()
+    })
+    .await;
 
-            match queue.insert_task(&job).await {
-                Ok(_) => {
-                    log::info!("Enqueued fetch product job for barcode: {}", body.barcode);
-                    HttpResponse::Ok().json(serde_json::json!({
-                        "message": "Job enqueued successfully",
-                        "barcode": body.barcode
-                    }))
-                }
-                Err(e) => {
-                    log::error!("Failed to enqueue job: {:?}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Failed to enqueue job"
-                    }))
-                }
-            }
-        }
-        Err(e) => {
-            log::error!("Failed to connect to job queue: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to connect to job queue"
+    match product {
+        Ok(Ok(Some(product))) => {
+            let allergen_tags = allergens::stored_slugs(product.allergen_tags.as_ref());
+            let trace_tags = allergens::stored_slugs(product.trace_tags.as_ref());
+            let matches = exclusion.matches(&allergen_tags, &trace_tags);
+
+            HttpResponse::Ok().json(ApiOk::new(ProductAllergens {
+                barcode,
+                allergens: allergen_tags,
+                traces: trace_tags,
+                excluded: !matches.is_empty(),
+                matches,
             }))
         }
+        Ok(Ok(None)) => product_not_found(&barcode, LookupSource::Cache).error_response(),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
     }
 }
 
-#[derive(Deserialize)]
-struct EnqueueAnalysisJobRequest {
-    product_id: i32,
+#[derive(Serialize)]
+struct ProductSafety {
+    barcode: String,
+    #[serde(flatten)]
+    report: safety::SafetyReport,
 }
 
-#[post("/api/jobs/analyze-ingredients")]
-async fn enqueue_analyze_ingredients(
-    body: web::Json<EnqueueAnalysisJobRequest>,
-) -> impl Responder {
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-
-    let mut queue = AsyncQueue::builder()
-        .uri(database_url)
-        .max_pool_size(3_u32)
-        .build();
-
-    match queue.connect(NoTls).await {
-        Ok(_) => {
-            let job = AnalyzeIngredientsJob {
-                product_id: body.product_id,
-            };
+/// Contaminants flagged across a stored product's linked ingredients, with how much of
+/// its ingredient list the rollup covers
+#[get("/api/products/{barcode}/safety")]
+async fn product_safety(barcode: Barcode, pool: web::Data<DbPool>) -> impl Responder {
+    let barcode = barcode.into_inner();
 
-            match queue.insert_task(&job).await {
-                Ok(_) => {
-                    log::info!("Enqueued ingredient analysis job for product: {}", body.product_id);
-                    HttpResponse::Ok().json(serde_json::json!({
-                        "message": "Analysis job enqueued successfully",
-                        "product_id": body.product_id
-                    }))
-                }
-                Err(e) => {
-                    log::error!("Failed to enqueue analysis job: {:?}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "error": "Failed to enqueue job"
-                    }))
-                }
-            }
-        }
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
         Err(e) => {
-            log::error!("Failed to connect to job queue: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to connect to job queue"
-            }))
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
         }
+    };
+
+    let barcode_clone = barcode.clone();
+    let report = web::block(move || {
+        let product = products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()?;
+        let Some(product) = product else {
+            return Ok::<_, diesel::result::Error>(None);
+        };
+
+        let ingredients = Ingredient::linked_to_product(product.id, &mut conn)?;
+        let contaminants: Vec<_> = ingredients.iter().map(safety::IngredientContaminants::from_ingredient).collect();
+        Ok(Some(safety::summarize(&contaminants, listed_ingredient_count(&product.full_response))))
+    })
+    .await;
+
+    match report {
+        Ok(Ok(Some(report))) => HttpResponse::Ok().json(ApiOk::new(ProductSafety { barcode, report })),
+        Ok(Ok(None)) => product_not_found(&barcode, LookupSource::Cache).error_response(),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
     }
 }
 
-#[get("/api/jobs/status")]
-async fn job_status() -> impl Responder {
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+#[derive(Deserialize)]
+struct FieldQuery {
+    path: String,
+}
 
-    let mut queue = AsyncQueue::builder()
-        .uri(database_url)
-        .max_pool_size(3_u32)
-        .build();
+#[derive(Serialize)]
+struct ProductField<'a> {
+    barcode: String,
+    path: &'a str,
+    value: &'a serde_json::Value,
+}
 
-    match queue.connect(NoTls).await {
-        Ok(_) => {
-            // Query job statistics
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": "Job queue is operational",
-                "status": "running"
-            }))
+/// One value from a stored product's `full_response`, addressed by a JSON-pointer-style
+/// `?path=nutriments/sodium_100g`, so callers don't have to download the whole document
+#[get("/api/products/{barcode}/field")]
+async fn product_field(
+    barcode: Barcode,
+    query: web::Query<FieldQuery>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    let barcode = barcode.into_inner();
+    let path = match json_pointer::FieldPath::parse(&query.path) {
+        Ok(path) => path,
+        Err(e) => {
+            return api::error(StatusCode::BAD_REQUEST, ApiError::new(format!("Invalid path: {}", e)));
         }
+    };
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
         Err(e) => {
-            log::error!("Failed to connect to job queue: {:?}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to connect to job queue"
-            }))
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
         }
+    };
+
+    let barcode_clone = barcode.clone();
+    let full_response = web::block(move || {
+        let stored = products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .select((products::full_response, products::full_response_gz))
+            .first::<(Option<serde_json::Value>, Option<Vec<u8>>)>(&mut conn)
+            .optional()?;
+
+        stored
+            .map(|(json, gz)| compression::stored_response(json, gz.as_deref()))
+            .transpose()
+            .map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))
+    })
+    .await;
+
+    match full_response {
+        Ok(Ok(Some(document))) => match path.resolve(&document) {
+            Some(value) => HttpResponse::Ok().json(ApiOk::new(ProductField {
+                barcode,
+                path: &query.path,
+                value,
+            })),
+            None => api::error(
+                StatusCode::NOT_FOUND,
+                ApiError::new("Field not found")
+                    .with("barcode", &barcode)
+                    .with("path", &query.path),
+            ),
+        },
+        Ok(Ok(None)) => product_not_found(&barcode, LookupSource::Cache).error_response(),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
     }
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    dotenvy::dotenv().ok();
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+#[derive(Deserialize)]
+struct NutritionQuery {
+    basis: Option<String>,
+}
 
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .expect("PORT must be a valid number");
+#[derive(Serialize)]
+struct ProductNutrition {
+    barcode: String,
+    nutrition: nutrition::NutritionFacts,
+}
 
-    log::info!("Starting Spoils API server on port {}", port);
+/// Nutrition facts for a stored product, per 100g or per serving
+#[get("/api/products/{barcode}/nutrition")]
+async fn product_nutrition(
+    barcode: Barcode,
+    query: web::Query<NutritionQuery>,
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    let barcode = barcode.into_inner();
 
-    // Initialize database connection pool
-    let pool = db::establish_connection_pool();
-    log::info!("Database connection pool established");
+    let requested = match query.basis.as_deref() {
+        None => config.default_nutrition_basis,
+        Some(basis) => match nutrition::NutritionBasis::parse(basis) {
+            Some(basis) => basis,
+            None => {
+                return api::error(StatusCode::BAD_REQUEST, ApiError::new("basis must be '100g', 'serving' or 'package'"));
+            }
+        },
+    };
 
-    // Start background worker pool in a separate task
-    tokio::spawn(async move {
-        log::info!("Starting background job worker pool...");
-        workers::start_worker_pool().await;
-    });
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
 
-    log::info!("Worker pool started in background");
+    let barcode_clone = barcode.clone();
+    let product = web::block(move || {
+        products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()
+    })
+    .await;
 
-    HttpServer::new(move || {
-        let cors = Cors::permissive(); // Configure this properly for production
+    match product {
+        Ok(Ok(Some(product))) => {
+            let facts = nutrition::from_off_product(&product.full_response, requested, product.package_grams());
+            HttpResponse::Ok().json(ApiOk::new(ProductNutrition { barcode, nutrition: facts }))
+        }
+        Ok(Ok(None)) => product_not_found(&barcode, LookupSource::Cache).error_response(),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
 
-        App::new()
-            .app_data(web::Data::new(pool.clone()))
-            .wrap(cors)
-            .wrap(actix_web::middleware::Logger::default())
-            .service(health)
-            .service(hello)
-            .service(get_product)
-            .service(get_product_non_food)
-            .service(create_product_non_food)
-            .service(list_products_non_food)
-            .service(enqueue_fetch_product)
-            .service(enqueue_analyze_ingredients)
-            .service(job_status)
-    })
-    .bind(("0.0.0.0", port))?
-    .run()
-    .await
+#[derive(Serialize)]
+struct ProductNutritionEstimate {
+    barcode: String,
+    #[serde(flatten)]
+    estimate: nutrition::IngredientMacroEstimate,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Per-100g macros summed from the product's linked ingredients, each weighted by its
+/// estimated share of the product, rather than the label's own figures. Ingredients without
+/// macro data are listed under `missing_nutrition`.
+#[get("/api/products/{barcode}/nutrition/estimate")]
+async fn product_nutrition_estimate(
+    barcode: Barcode,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, AppError> {
+    let barcode = barcode.into_inner();
+    let (_permit, mut conn) = db::checkout(&pool).await?;
 
-    #[test]
-    fn test_extract_ingredients_with_ingredients_marker() {
-        let text = "Premium supplement. Ingredients: Vitamin C, Zinc, Magnesium. Take daily.";
+    let barcode_clone = barcode.clone();
+    let portions = web::block(move || {
+        let product = products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()?;
+        product
+            .map(|product| Ingredient::portions_of_product(product.id, &mut conn))
+            .transpose()
+    })
+    .await??;
+
+    let Some(portions) = portions else {
+        return Err(product_not_found(&barcode, LookupSource::Cache));
+    };
+    let estimate = nutrition::estimate_from_ingredients(&portions);
+    Ok(HttpResponse::Ok().json(ApiOk::new(ProductNutritionEstimate { barcode, estimate })))
+}
+
+#[derive(Serialize)]
+struct AllergenSlugs {
+    allergens: Vec<String>,
+    traces: Vec<String>,
+}
+
+/// A stored product with everything computed from it, so a client needs one call
+/// instead of one per endpoint. A section that couldn't be computed is null, with the
+/// reason under `errors` keyed by section name.
+#[derive(Serialize)]
+struct ProductFull {
+    product: Product,
+    diet: Option<diet::DietFlags>,
+    /// Contaminant rollup over the linked ingredients, as from `/safety`
+    safety: Option<safety::SafetyReport>,
+    allergens: Option<AllergenSlugs>,
+    /// Nutrition facts in the default basis, as from `/nutrition`
+    nutrition: Option<nutrition::NutritionFacts>,
+    errors: std::collections::BTreeMap<&'static str, String>,
+}
+
+impl ProductFull {
+    /// Compose the sections for `product`. `linked` is its linked ingredients, or why they
+    /// couldn't be loaded; only the safety section depends on them.
+    fn compose(product: Product, linked: Result<Vec<Ingredient>, String>) -> Self {
+        let mut errors = std::collections::BTreeMap::new();
+
+        let diet = full_section("diet", &mut errors, || {
+            let labels = product.labels.as_ref().map(diet::normalize_labels).unwrap_or_default();
+            Ok(diet::classify(
+                &labels,
+                product.full_response.get("ingredients_analysis_tags").unwrap_or(&serde_json::Value::Null),
+            ))
+        });
+        let safety = full_section("safety", &mut errors, || {
+            let ingredients = linked?;
+            let contaminants: Vec<_> = ingredients.iter().map(safety::IngredientContaminants::from_ingredient).collect();
+            Ok(safety::summarize(&contaminants, listed_ingredient_count(&product.full_response)))
+        });
+        let allergens = full_section("allergens", &mut errors, || {
+            Ok(AllergenSlugs {
+                allergens: allergens::stored_slugs(product.allergen_tags.as_ref()),
+                traces: allergens::stored_slugs(product.trace_tags.as_ref()),
+            })
+        });
+        let nutrition = full_section("nutrition", &mut errors, || {
+            Ok(nutrition::from_off_product(&product.full_response, config::get().default_nutrition_basis, product.package_grams()))
+        });
+
+        ProductFull { product, diet, safety, allergens, nutrition, errors }
+    }
+}
+
+/// Run one section's helper, recording its error (or panic) under `name` instead of
+/// letting it take the rest of the response down
+fn full_section<T>(
+    name: &'static str,
+    errors: &mut std::collections::BTreeMap<&'static str, String>,
+    compute: impl FnOnce() -> Result<T, String>,
+) -> Option<T> {
+    let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(compute))
+        .unwrap_or_else(|_| Err("Failed to compute".to_string()));
+
+    match outcome {
+        Ok(section) => Some(section),
+        Err(e) => {
+            log::error!("Product section {} failed: {}", name, e);
+            errors.insert(name, e);
+            None
+        }
+    }
+}
+
+/// A stored product together with its diet, safety rollup, allergens and nutrition
+#[get("/api/products/{barcode}/full")]
+async fn product_full(barcode: Barcode, pool: web::Data<DbPool>) -> impl Responder {
+    let barcode = barcode.into_inner();
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let barcode_clone = barcode.clone();
+    let full = web::block(move || {
+        let product = products::table
+            .filter(products::barcode.eq(&barcode_clone))
+            .first::<Product>(&mut conn)
+            .optional()?;
+        let Some(product) = product else {
+            return Ok::<_, diesel::result::Error>(None);
+        };
+
+        let linked = Ingredient::linked_to_product(product.id, &mut conn)
+            .map_err(|e| format!("Failed to load linked ingredients: {}", e));
+        Ok(Some(ProductFull::compose(product, linked)))
+    })
+    .await;
+
+    match full {
+        Ok(Ok(Some(full))) => HttpResponse::Ok().json(ApiOk::new(full)),
+        Ok(Ok(None)) => product_not_found(&barcode, LookupSource::Cache).error_response(),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+// ============= Ingredients Endpoints =============
+
+#[derive(Deserialize, Default)]
+struct IngredientListQuery {
+    name: Option<String>,
+    protein_min: Option<f32>,
+    protein_max: Option<f32>,
+    carbs_min: Option<f32>,
+    carbs_max: Option<f32>,
+    fat_min: Option<f32>,
+    fat_max: Option<f32>,
+    fiber_min: Option<f32>,
+    fiber_max: Option<f32>,
+    include_unknown: Option<bool>,
+    sort: Option<String>,
+    order: Option<String>,
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+/// Validated ingredient list request: macro filter plus the requested page
+fn parse_ingredient_list_query(query: &IngredientListQuery) -> Result<(IngredientMacroFilter, PageRequest), String> {
+    let range = |macro_name: &str, min: Option<f32>, max: Option<f32>| -> Result<MacroRange, String> {
+        for (bound, value) in [("min", min), ("max", max)] {
+            if let Some(value) = value
+                && !(0.0..=1.0).contains(&value)
+            {
+                return Err(format!("{}_{} must be between 0 and 1 (grams per gram)", macro_name, bound));
+            }
+        }
+        if let (Some(min), Some(max)) = (min, max)
+            && min > max
+        {
+            return Err(format!("{}_min must not exceed {}_max", macro_name, macro_name));
+        }
+        Ok(MacroRange { min, max })
+    };
+
+    let sort = match query.sort.as_deref() {
+        None | Some("id") => MacroSort::Id,
+        Some("name") => MacroSort::Name,
+        Some("protein") => MacroSort::Protein,
+        Some("carbs") => MacroSort::Carbs,
+        Some("fat") => MacroSort::Fat,
+        Some("fiber") => MacroSort::Fiber,
+        Some(other) => return Err(format!("Unknown sort '{}', expected id, name, protein, carbs, fat or fiber", other)),
+    };
+
+    let descending = match query.order.as_deref() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(other) => return Err(format!("Unknown order '{}', expected asc or desc", other)),
+    };
+
+    let page = PageRequest::from_query(query.page, query.per_page)?;
+
+    let name = match query.name.as_deref().map(str::trim) {
+        Some("") => return Err("name must not be empty".to_string()),
+        name => name.map(str::to_string),
+    };
+
+    let filter = IngredientMacroFilter {
+        name,
+        protein: range("protein", query.protein_min, query.protein_max)?,
+        carbs: range("carbs", query.carbs_min, query.carbs_max)?,
+        fat: range("fat", query.fat_min, query.fat_max)?,
+        fiber: range("fiber", query.fiber_min, query.fiber_max)?,
+        include_unknown: query.include_unknown.unwrap_or(false),
+        sort,
+        descending,
+    };
+
+    Ok((filter, page))
+}
+
+/// List ingredients filtered by per-gram macro thresholds, e.g. `?protein_min=0.25&sort=protein&order=desc`,
+/// or look one up by name (`?name=Sugar`, case-insensitive)
+#[get("/api/ingredients")]
+async fn list_ingredients(
+    query: web::Query<IngredientListQuery>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    let (filter, page) = match parse_ingredient_list_query(&query) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            return api::error(StatusCode::BAD_REQUEST, ApiError::new(message));
+        }
+    };
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let found = web::block(move || Ingredient::search_by_macros(&filter, page, &mut conn)).await;
+
+    match found {
+        Ok(Ok(ingredients_page)) => HttpResponse::Ok().json(ApiOk::new(ingredients_page)),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// Maximum number of barcodes accepted by the product batch endpoint
+const MAX_PRODUCT_BATCH_SIZE: usize = 50;
+
+#[derive(Deserialize)]
+struct ProductBatchRequest {
+    barcodes: Vec<String>,
+}
+
+#[derive(Serialize, Default)]
+struct ProductBatch {
+    /// Every requested barcode, as sent, to its stored product; null when it isn't stored
+    products: BTreeMap<String, Option<serde_json::Value>>,
+    /// Barcodes a `FetchProductJob` was queued for; `/status` says when they're stored
+    fetching: Vec<String>,
+    /// Barcodes that aren't valid, with the reason
+    invalid: BTreeMap<String, &'static str>,
+}
+
+/// Resolve a cart's worth of barcodes at once. Stored products are returned as they are;
+/// for the rest a `FetchProductJob` is queued instead of waiting on the product sources,
+/// unless they recently reported the barcode unknown or the queue is full. Unlike the
+/// `batch` contract this answers with a map, since clients look products up by barcode.
+#[post("/api/products/batch")]
+async fn get_products_batch(
+    body: web::Json<ProductBatchRequest>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+    clock: web::Data<dyn Clock>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, AppError> {
+    let requested = body.into_inner().barcodes;
+    if requested.is_empty() || requested.len() > MAX_PRODUCT_BATCH_SIZE {
+        return Err(AppError::BadRequest(ApiError::new(format!(
+            "Provide between 1 and {} barcodes",
+            MAX_PRODUCT_BATCH_SIZE
+        ))));
+    }
+
+    let mut batch = ProductBatch::default();
+    // (barcode as sent, normalized)
+    let mut valid = Vec::new();
+    for input in requested {
+        match barcode::normalize(&input) {
+            Ok(normalized) => valid.push((input, normalized)),
+            Err(reason) => {
+                batch.invalid.insert(input.clone(), reason);
+                batch.products.insert(input, None);
+            }
+        }
+    }
+
+    let barcodes: Vec<String> = valid.iter().map(|(_, barcode)| barcode.clone()).collect();
+    let (permit, mut conn) = db::checkout(&pool).await?;
+    let (stored, lookups) = web::block(move || {
+        let stored = products::table.filter(products::barcode.eq_any(&barcodes)).load::<Product>(&mut conn)?;
+        let lookups = product_lookups::table
+            .filter(product_lookups::barcode.eq_any(&barcodes))
+            .load::<ProductLookup>(&mut conn)?;
+        Ok::<_, diesel::result::Error>((stored, lookups))
+    })
+    .await??;
+    drop(permit);
+
+    let stored = stored
+        .into_iter()
+        .map(|product| Ok((product.barcode.clone(), serde_json::to_value(&product)?)))
+        .collect::<Result<HashMap<_, _>, serde_json::Error>>()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let recent_misses: Vec<String> = lookups
+        .into_iter()
+        .filter(|lookup| lookup.is_recent_miss(clock.get_ref(), config.negative_lookup_ttl))
+        .map(|lookup| lookup.barcode)
+        .collect();
+
+    let mut missing: Vec<&String> = Vec::new();
+    for (_, barcode) in &valid {
+        if !stored.contains_key(barcode) && !recent_misses.contains(barcode) && !missing.contains(&barcode) {
+            missing.push(barcode);
+        }
+    }
+
+    let mut queued = Vec::new();
+    if !missing.is_empty() {
+        if queue_backpressure(JobClass::Fetch, &pool).await.is_some() {
+            log::warn!("Job queue is full, not fetching {} batch barcodes", missing.len());
+        } else {
+            let mut queue = queue.get();
+            for barcode in missing {
+                match queue.insert_task(&FetchProductJob { barcode: barcode.clone() }).await {
+                    Ok(_) => queued.push(barcode.clone()),
+                    Err(e) => log::error!("Failed to enqueue fetch of {}: {:?}", barcode, e),
+                }
+            }
+        }
+    }
+
+    for (input, barcode) in valid {
+        if queued.contains(&barcode) && !batch.fetching.contains(&input) {
+            batch.fetching.push(input.clone());
+        }
+        batch.products.insert(input, stored.get(&barcode).cloned());
+    }
+
+    Ok(HttpResponse::Ok().json(ApiOk::new(batch)))
+}
+
+/// Maximum number of ids accepted by the ingredient batch endpoint
+const MAX_INGREDIENT_BATCH_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+struct IngredientBatchRequest {
+    ids: Vec<i32>,
+}
+
+/// Fetch many ingredients by id; see `batch` for the per-item result contract
+#[post("/api/ingredients/batch")]
+async fn get_ingredients_batch(
+    body: web::Json<IngredientBatchRequest>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    let ids = body.into_inner().ids;
+
+    if ids.is_empty() || ids.len() > MAX_INGREDIENT_BATCH_SIZE {
+        return api::error(
+            StatusCode::BAD_REQUEST,
+            ApiError::new(format!("Provide between 1 and {} ingredient ids", MAX_INGREDIENT_BATCH_SIZE)),
+        );
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let ids_clone = ids.clone();
+    let found = web::block(move || {
+        ingredients::table
+            .filter(ingredients::id.eq_any(&ids_clone))
+            .load::<Ingredient>(&mut conn)
+    })
+    .await;
+
+    match found {
+        Ok(Ok(rows)) => {
+            HttpResponse::Ok().json(ApiOk::new(batch::results_by_key(&ids, rows, |i| i.id, "Ingredient not found")))
+        }
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// An ingredient awaiting review, with the USDA food that was too weak a match to use
+#[derive(Serialize)]
+struct ReviewQueueItem {
+    #[serde(flatten)]
+    ingredient: Ingredient,
+    candidates: Vec<usda_match::UsdaCandidate>,
+}
+
+impl ReviewQueueItem {
+    fn new(ingredient: Ingredient) -> Self {
+        let candidates = ingredient
+            .usda_candidate
+            .iter()
+            .map(|food| usda_match::UsdaCandidate::new(&ingredient.name, food))
+            .collect();
+        ReviewQueueItem { ingredient, candidates }
+    }
+}
+
+/// Curators' worklist: ingredients flagged `needs_review` because USDA's best match was
+/// weak, oldest first. `PATCH /api/ingredients/{id}` with `accept_usda_candidate: true`,
+/// macros, or `needs_review: false` takes an ingredient off the list.
+#[get("/api/ingredients/review-queue")]
+async fn ingredient_review_queue(
+    query: web::Query<PageQuery>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    let page = match PageRequest::from_query(query.page, query.per_page) {
+        Ok(page) => page,
+        Err(message) => {
+            return api::error(StatusCode::BAD_REQUEST, ApiError::new(message));
+        }
+    };
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let queue = web::block(move || Ingredient::review_queue(page, &mut conn)).await;
+
+    match queue {
+        Ok(Ok(queue_page)) => HttpResponse::Ok().json(ApiOk::new(queue_page.map(ReviewQueueItem::new))),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// An ingredient with its full nutrient and contaminant profile (registered after the
+/// `/api/ingredients/...` routes with fixed segments)
+#[get("/api/ingredients/{id}")]
+async fn get_ingredient(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    let ingredient_id = id.into_inner();
+    let (_permit, mut conn) = db::checkout(&pool).await?;
+
+    let ingredient =
+        web::block(move || ingredients::table.find(ingredient_id).first::<Ingredient>(&mut conn).optional()).await??;
+
+    match ingredient {
+        Some(ingredient) => Ok(HttpResponse::Ok().json(ApiOk::new(ingredient))),
+        None => Err(AppError::NotFound(ApiError::new("Ingredient not found").with("id", ingredient_id))),
+    }
+}
+
+/// Levels of sub-ingredients an ingredient tree resolves by default, and at most
+const DEFAULT_INGREDIENT_TREE_DEPTH: u32 = 3;
+const MAX_INGREDIENT_TREE_DEPTH: u32 = 10;
+
+#[derive(Deserialize, Default)]
+struct IngredientTreeQuery {
+    depth: Option<u32>,
+}
+
+impl IngredientTreeQuery {
+    /// `?depth=`, clamped to MAX_INGREDIENT_TREE_DEPTH
+    fn depth(&self) -> u32 {
+        self.depth.unwrap_or(DEFAULT_INGREDIENT_TREE_DEPTH).min(MAX_INGREDIENT_TREE_DEPTH)
+    }
+}
+
+/// An ingredient with its sub-ingredients nested `?depth=` levels down (default 3, at most
+/// 10), each as `{id, name, children}`. A sub-ingredient that leads back to one of its
+/// ancestors is left out, so cycles in the data end there.
+#[get("/api/ingredients/{id}/tree")]
+async fn ingredient_tree(
+    id: web::Path<i32>,
+    query: web::Query<IngredientTreeQuery>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, AppError> {
+    let ingredient_id = id.into_inner();
+    let depth = query.depth();
+    let (_permit, mut conn) = db::checkout(&pool).await?;
+
+    match web::block(move || Ingredient::tree(ingredient_id, depth, &mut conn)).await?? {
+        Some(tree) => Ok(HttpResponse::Ok().json(ApiOk::new(tree))),
+        None => Err(AppError::NotFound(ApiError::new("Ingredient not found").with("id", ingredient_id))),
+    }
+}
+
+/// Curator override of an ingredient's nutrition and contaminant data. Marks the
+/// ingredient `manually_verified` so enrichment jobs stop touching it.
+#[patch("/api/ingredients/{id}")]
+async fn patch_ingredient(
+    req: HttpRequest,
+    id: web::Path<i32>,
+    body: web::Json<IngredientPatch>,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let ingredient_id = id.into_inner();
+    let patch = body.into_inner();
+
+    if let Err(message) = patch.validate() {
+        return api::error(StatusCode::BAD_REQUEST, ApiError::new(message));
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let updated = web::block(move || Ingredient::apply_manual_patch(ingredient_id, &patch, &mut conn)).await;
+
+    match updated {
+        Ok(Ok(Some(ingredient))) => {
+            log::info!("Ingredient {} manually updated", ingredient_id);
+            HttpResponse::Ok().json(ApiOk::new(ingredient))
+        }
+        Ok(Ok(None)) => api::error(StatusCode::NOT_FOUND, ApiError::new("Ingredient not found").with("id", ingredient_id)),
+        Ok(Err(e @ PatchError::NoUsdaCandidate)) => {
+            api::error(StatusCode::BAD_REQUEST, ApiError::new(e.to_string()).with("id", ingredient_id))
+        }
+        Ok(Err(PatchError::Db(e))) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// Lab findings a curator records for one contaminant category
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContaminantEntry {
+    /// One of `safety::CONTAMINANT_FIELDS`, e.g. `heavy_metals`
+    category: String,
+    /// Object (`{"lead": "0.2 ppm"}`) or array (`["glyphosate"]`) of findings
+    findings: serde_json::Value,
+    /// Overwrite the category instead of merging into it
+    #[serde(default)]
+    replace: bool,
+}
+
+/// Record lab findings in one of an ingredient's contaminant columns and mark it
+/// `manually_verified`. Only the contaminant categories can be written here.
+#[post("/api/ingredients/{id}/contaminants")]
+async fn record_ingredient_contaminants(
+    req: HttpRequest,
+    id: web::Path<i32>,
+    body: web::Json<ContaminantEntry>,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let ingredient_id = id.into_inner();
+    let entry = body.into_inner();
+
+    if safety::contaminant_index(&entry.category).is_none() {
+        return api::error(
+            StatusCode::BAD_REQUEST,
+            ApiError::new(format!("Unknown contaminant category {:?}", entry.category))
+                .with("categories", safety::CONTAMINANT_FIELDS),
+        );
+    }
+    if !(entry.findings.is_object() || entry.findings.is_array()) {
+        return api::error(StatusCode::BAD_REQUEST, ApiError::new("findings must be a JSON object or array"));
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let category = entry.category.clone();
+    let updated = web::block(move || {
+        Ingredient::record_contaminants(ingredient_id, &entry.category, entry.findings, entry.replace, &mut conn)
+    })
+    .await;
+
+    match updated {
+        Ok(Ok(Some(ingredient))) => {
+            log::info!("Ingredient {} {} findings recorded manually", ingredient_id, category);
+            HttpResponse::Ok().json(ApiOk::new(ingredient))
+        }
+        Ok(Ok(None)) => api::error(StatusCode::NOT_FOUND, ApiError::new("Ingredient not found").with("id", ingredient_id)),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+#[derive(Serialize)]
+struct UsdaRawFood {
+    id: i32,
+    fdc_id: Option<i32>,
+    cached: bool,
+    food: serde_json::Value,
+}
+
+/// Raw USDA food an ingredient's macros were taken from: the stored copy when we have
+/// one (`cached: true`), otherwise re-fetched live by `fdc_id`
+#[get("/api/ingredients/{id}/usda-raw")]
+async fn ingredient_usda_raw(
+    id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    let ingredient_id = id.into_inner();
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let ingredient = web::block(move || {
+        ingredients::table
+            .find(ingredient_id)
+            .first::<Ingredient>(&mut conn)
+            .optional()
+    })
+    .await;
+
+    let (fdc_id, usda_food) = match ingredient {
+        Ok(Ok(Some(ingredient))) => (ingredient.fdc_id, ingredient.usda_food),
+        Ok(Ok(None)) => {
+            return api::error(StatusCode::NOT_FOUND, ApiError::new("Ingredient not found").with("id", ingredient_id));
+        }
+        Ok(Err(e)) => return AppError::DbQuery(e).error_response(),
+        Err(e) => return AppError::from(e).error_response(),
+    };
+
+    match (fdc_id, usda_food) {
+        (_, Some(food)) => HttpResponse::Ok().json(ApiOk::new(UsdaRawFood {
+            id: ingredient_id,
+            fdc_id,
+            cached: true,
+            food,
+        })),
+        (Some(fdc_id), None) => match jobs::fetch_usda_food(fdc_id).await {
+            Ok(Some(food)) => HttpResponse::Ok().json(ApiOk::new(UsdaRawFood {
+                id: ingredient_id,
+                fdc_id: Some(fdc_id),
+                cached: false,
+                food,
+            })),
+            Ok(None) => api::error(
+                StatusCode::NOT_FOUND,
+                ApiError::new("USDA no longer has this food")
+                    .with("id", ingredient_id)
+                    .with("fdc_id", fdc_id),
+            ),
+            Err(e) => {
+                log::error!("Failed to fetch USDA food {}: {}", fdc_id, e);
+                api::error(StatusCode::BAD_GATEWAY, ApiError::new("USDA request failed"))
+            }
+        },
+        (None, None) => api::error(
+            StatusCode::NOT_FOUND,
+            ApiError::new("No USDA match recorded for this ingredient")
+                .with("id", ingredient_id),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateIngredientAliasRequest {
+    alias: String,
+    ingredient_id: i32,
+}
+
+/// Register a synonym that resolves to an existing canonical ingredient
+#[post("/api/admin/ingredient-aliases")]
+async fn create_ingredient_alias(
+    req: HttpRequest,
+    body: web::Json<CreateIngredientAliasRequest>,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let request = body.into_inner();
+
+    if IngredientAlias::normalize(&request.alias).is_empty() {
+        return api::error(StatusCode::BAD_REQUEST, ApiError::new("Alias must not be empty"));
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let ingredient_id = request.ingredient_id;
+    let inserted = web::block(move || IngredientAlias::create(&request.alias, ingredient_id, &mut conn)).await;
+
+    match inserted {
+        Ok(Ok(alias)) => {
+            log::info!("Alias '{}' now resolves to ingredient {}", alias.alias, alias.ingredient_id);
+            HttpResponse::Created().json(ApiOk::new(alias))
+        }
+        Ok(Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _))) => {
+            api::error(StatusCode::NOT_FOUND, ApiError::new("Ingredient not found").with("ingredient_id", ingredient_id))
+        }
+        Ok(Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))) => {
+            api::error(StatusCode::CONFLICT, ApiError::new("Alias already exists"))
+        }
+        Ok(Err(e)) => {
+            log::error!("Failed to create ingredient alias: {}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to create alias"))
+        }
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// Default and maximum number of ingredients one vacuum request handles
+const DEFAULT_VACUUM_LIMIT: i64 = 500;
+const MAX_VACUUM_LIMIT: i64 = 5000;
+
+#[derive(Deserialize)]
+struct VacuumIngredientsQuery {
+    dry_run: Option<bool>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct VacuumReport {
+    dry_run: bool,
+    count: usize,
+    ingredients: Vec<models::OrphanIngredient>,
+}
+
+/// Report ingredients nothing refers to any more, deleting them when `?dry_run=false`
+#[post("/api/admin/ingredients/vacuum")]
+async fn vacuum_orphan_ingredients(
+    req: HttpRequest,
+    query: web::Query<VacuumIngredientsQuery>,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+    clock: web::Data<dyn Clock>,
+    config: web::Data<Config>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let dry_run = query.dry_run.unwrap_or(true);
+    let limit = query.limit.unwrap_or(DEFAULT_VACUUM_LIMIT);
+
+    if !(1..=MAX_VACUUM_LIMIT).contains(&limit) {
+        return api::error(
+            StatusCode::BAD_REQUEST,
+            ApiError::new(format!("limit must be between 1 and {}", MAX_VACUUM_LIMIT)),
+        );
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let created_before = clock.now() - config.orphan_ingredient_min_age;
+    let result = web::block(move || Ingredient::vacuum_orphans(created_before, limit, dry_run, &mut conn)).await;
+
+    match result {
+        Ok(Ok(orphans)) => {
+            if dry_run {
+                log::info!("Ingredient vacuum dry run found {} orphan(s)", orphans.len());
+            } else {
+                log::info!("Ingredient vacuum deleted {} orphan(s)", orphans.len());
+            }
+            HttpResponse::Ok().json(ApiOk::new(VacuumReport {
+                dry_run,
+                count: orphans.len(),
+                ingredients: orphans,
+            }))
+        }
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+// ============= Non-Food Products Endpoints =============
+
+/// Brand, category and manufacturer counts (registered ahead of `/api/products-non-food/{barcode}`)
+#[get("/api/products-non-food/facets")]
+async fn product_non_food_facets(pool: web::Data<DbPool>) -> impl Responder {
+    cached_facets(&facets::NON_FOOD_FACETS, facets::non_food_facets, pool).await
+}
+
+#[get("/api/products-non-food/{barcode}")]
+async fn get_product_non_food(
+    barcode: Barcode,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, AppError> {
+    let barcode = barcode.into_inner();
+    let (_permit, mut conn) = db::checkout(&pool).await?;
+
+    // Try to find product in database
+    let barcode_clone = barcode.clone();
+    let existing_product = web::block(move || {
+        products_non_food::table
+            .filter(products_non_food::barcode.eq(&barcode_clone))
+            .first::<ProductNonFood>(&mut conn)
+            .optional()
+    })
+    .await??;
+
+    match existing_product {
+        Some(product) => {
+            log::info!("Non-food product {} found in database", barcode);
+            Ok(HttpResponse::Ok().json(ApiOk::new(product)))
+        }
+        None => {
+            log::info!("Non-food product {} not found in database", barcode);
+            Err(product_not_found(&barcode, LookupSource::Cache))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateProductNonFoodRequest {
+    barcode: Option<String>,
+    name: String,
+    brand: Option<String>,
+    category: Option<String>,
+    description: Option<String>,
+    data_source: Option<String>,
+}
+
+#[post("/api/products-non-food")]
+async fn create_product_non_food(
+    body: web::Json<CreateProductNonFoodRequest>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    let new_product = NewProductNonFood {
+        barcode: body.barcode.clone(),
+        name: body.name.clone(),
+        brand: body.brand.clone(),
+        category: body.category.clone(),
+        description: body.description.clone(),
+        full_response: None,
+        data_source: body.data_source.clone(),
+    };
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let inserted_product = web::block(move || {
+        diesel::insert_into(products_non_food::table)
+            .values(&new_product)
+            .get_result::<ProductNonFood>(&mut conn)
+    })
+    .await;
+
+    match inserted_product {
+        Ok(Ok(product)) => {
+            log::info!("Non-food product '{}' created with ID: {}", product.name, product.id);
+
+            // Process ingredients for supplements and beauty products
+            if product.has_ingredient_category() {
+                log::info!("Processing ingredients for {} product: {}", product.category.as_deref().unwrap_or_default(), product.name);
+                process_non_food_ingredients(&product, &pool, &queue).await;
+            }
+
+            HttpResponse::Created().json(ApiOk::new(product))
+        }
+        Ok(Err(e)) => {
+            log::error!("Failed to create non-food product: {}", e);
+            api::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ApiError::new("Failed to create product")
+                    .with("details", e.to_string()),
+            )
+        }
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// Correct or fill in a non-food product's details. Omitted fields are left as they are;
+/// a new description or category for a supplement or beauty product is mined for
+/// ingredients again.
+#[put("/api/products-non-food/{id}")]
+async fn update_product_non_food(
+    id: web::Path<i32>,
+    body: web::Json<ProductNonFoodPatch>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    let product_id = id.into_inner();
+    let patch = body.into_inner();
+
+    let (permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let updated = web::block(move || ProductNonFood::apply_patch(product_id, &patch, &mut conn)).await;
+    drop(permit);
+
+    match updated {
+        Ok(Ok(Some((before, product)))) => {
+            log::info!("Non-food product {} updated", product_id);
+
+            let ingredients_changed = before.description != product.description || before.category != product.category;
+            if ingredients_changed && product.has_ingredient_category() {
+                log::info!("Reprocessing ingredients for updated product: {}", product.name);
+                process_non_food_ingredients(&product, &pool, &queue).await;
+            }
+
+            HttpResponse::Ok().json(ApiOk::new(product))
+        }
+        Ok(Ok(None)) => api::error(StatusCode::NOT_FOUND, ApiError::new("Product not found").with("id", product_id)),
+        Ok(Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))) => {
+            api::error(StatusCode::CONFLICT, ApiError::new("Another product has this barcode").with("id", product_id))
+        }
+        Ok(Err(e)) => {
+            log::error!("Failed to update non-food product: {}", e);
+            api::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ApiError::new("Database query failed").with("code", "db_query_failed"),
+            )
+        }
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+#[post("/api/products-non-food/{id}/refresh")]
+async fn refresh_product_non_food(
+    id: web::Path<i32>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    let product_id = id.into_inner();
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let exists = web::block(move || {
+        diesel::select(diesel::dsl::exists(products_non_food::table.find(product_id)))
+            .get_result::<bool>(&mut conn)
+    })
+    .await;
+
+    match exists {
+        Ok(Ok(true)) => {}
+        Ok(Ok(false)) => {
+            return api::error(StatusCode::NOT_FOUND, ApiError::new("Product not found").with("id", product_id));
+        }
+        Ok(Err(e)) => return AppError::DbQuery(e).error_response(),
+        Err(e) => return AppError::from(e).error_response(),
+    }
+
+    if let Some(full) = queue_backpressure(JobClass::Background, &pool).await {
+        return full;
+    }
+
+    let job = EnrichNonFoodJob { product_id };
+
+    let mut queue = queue.get();
+    match queue.insert_task(&job).await {
+        Ok(_) => {
+            log::info!("Enqueued enrichment job for non-food product: {}", product_id);
+            HttpResponse::Ok().json(ApiOk::new(JobEnqueued {
+                id: Some(product_id),
+                ..JobEnqueued::new("Enrichment job enqueued successfully")
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to enqueue enrichment job: {:?}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[get("/api/products-non-food")]
+async fn list_products_non_food(
+    query: web::Query<PageQuery>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    let page = match PageRequest::from_query(query.page, query.per_page) {
+        Ok(page) => page,
+        Err(message) => {
+            return api::error(StatusCode::BAD_REQUEST, ApiError::new(message));
+        }
+    };
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let products = web::block(move || {
+        pagination::paginate(
+            page,
+            &mut conn,
+            |conn| products_non_food::table.count().get_result::<i64>(conn),
+            |conn, limit, offset| {
+                products_non_food::table
+                    .order((products_non_food::created_at.desc(), products_non_food::id.desc()))
+                    .limit(limit)
+                    .offset(offset)
+                    .load::<ProductNonFood>(conn)
+            },
+        )
+    })
+    .await;
+
+    match products {
+        Ok(Ok(products_page)) => {
+            log::info!("Retrieved {} non-food products", products_page.items.len());
+            HttpResponse::Ok().json(ApiOk::new(products_page))
+        }
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+// Job enqueueing endpoints
+
+/// Acknowledgement that a job was queued, naming what it was queued for
+#[derive(Serialize)]
+struct JobEnqueued {
+    message: &'static str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    barcode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    product_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_id: Option<String>,
+}
+
+impl JobEnqueued {
+    fn new(message: &'static str) -> Self {
+        JobEnqueued {
+            message,
+            status: "enqueued",
+            id: None,
+            barcode: None,
+            product_id: None,
+            task_id: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EnqueueProductJobRequest {
+    barcode: String,
+}
+
+#[post("/api/jobs/fetch-product")]
+async fn enqueue_fetch_product(
+    body: web::Json<EnqueueProductJobRequest>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    if let Some(full) = queue_backpressure(JobClass::Fetch, &pool).await {
+        return full;
+    }
+
+    let job = FetchProductJob {
+        barcode: body.barcode.clone(),
+    };
+
+    let mut queue = queue.get();
+    match queue.insert_task(&job).await {
+        Ok(_) => {
+            log::info!("Enqueued fetch product job for barcode: {}", body.barcode);
+            HttpResponse::Ok().json(ApiOk::new(JobEnqueued {
+                barcode: Some(body.barcode.clone()),
+                ..JobEnqueued::new("Job enqueued successfully")
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to enqueue job: {:?}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EnqueueAnalysisJobRequest {
+    product_id: i32,
+}
+
+#[post("/api/jobs/analyze-ingredients")]
+async fn enqueue_analyze_ingredients(
+    body: web::Json<EnqueueAnalysisJobRequest>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    if let Some(full) = queue_backpressure(JobClass::Background, &pool).await {
+        return full;
+    }
+
+    let job = AnalyzeIngredientsJob {
+        product_id: body.product_id,
+    };
+
+    let mut queue = queue.get();
+    match queue.insert_task(&job).await {
+        Ok(_) => {
+            log::info!("Enqueued ingredient analysis job for product: {}", body.product_id);
+            HttpResponse::Ok().json(ApiOk::new(JobEnqueued {
+                product_id: Some(body.product_id),
+                ..JobEnqueued::new("Analysis job enqueued successfully")
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to enqueue analysis job: {:?}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job"))
+        }
+    }
+}
+
+/// Run the USDA macro backfill now instead of waiting for its hourly slot. How many
+/// ingredients it updated is listed by `GET /api/admin/usda-backfill` once it finishes.
+#[post("/api/admin/usda-backfill")]
+async fn enqueue_usda_backfill(
+    req: HttpRequest,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    if let Some(full) = queue_backpressure(JobClass::Background, &pool).await {
+        return full;
+    }
+
+    let mut queue = queue.get();
+    match queue.insert_task(&UsdaBackfillJob { recurring: false }).await {
+        Ok(_) => {
+            log::info!("Enqueued USDA backfill job");
+            HttpResponse::Ok().json(ApiOk::new(JobEnqueued::new("USDA backfill job enqueued successfully")))
+        }
+        Err(e) => {
+            log::error!("Failed to enqueue USDA backfill job: {:?}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job"))
+        }
+    }
+}
+
+/// How many past runs `GET /api/admin/usda-backfill` lists
+const USDA_BACKFILL_RUNS_LISTED: i64 = 20;
+
+#[derive(Serialize)]
+struct UsdaBackfillRuns {
+    count: usize,
+    runs: Vec<UsdaBackfillRun>,
+}
+
+/// The latest USDA backfill runs, most recent first, with how many ingredients each one
+/// searched and how many got macros. A triggered run shows up here once it finishes.
+#[get("/api/admin/usda-backfill")]
+async fn usda_backfill_runs(
+    req: HttpRequest,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let result = web::block(move || UsdaBackfillRun::recent(USDA_BACKFILL_RUNS_LISTED, &mut conn)).await;
+
+    match result {
+        Ok(Ok(runs)) => HttpResponse::Ok().json(ApiOk::new(UsdaBackfillRuns { count: runs.len(), runs })),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// Default and maximum number of ingredients one re-enrichment request queues. They are
+/// searched one at a time, USDA_BACKFILL_DELAY_MS apart, so the cap bounds how long the job runs.
+const DEFAULT_REENRICH_LIMIT: i64 = 100;
+const MAX_REENRICH_LIMIT: i64 = 1000;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReenrichRequest {
+    #[serde(default)]
+    without_fdc_id: bool,
+    #[serde(default)]
+    without_macros: bool,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ReenrichQueued {
+    queued: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_id: Option<String>,
+}
+
+/// Search USDA again for the uncurated ingredients matching a filter, e.g. those enriched
+/// before matching improved. One paced job handles up to `limit` of them.
+#[post("/api/admin/ingredients/reenrich")]
+async fn enqueue_ingredient_reenrichment(
+    req: HttpRequest,
+    body: web::Json<ReenrichRequest>,
+    api_key: web::Data<AdminApiKey>,
+    pool: web::Data<DbPool>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let filter = models::ReenrichFilter {
+        without_fdc_id: body.without_fdc_id,
+        without_macros: body.without_macros,
+    };
+    if filter.is_empty() {
+        return api::error(StatusCode::BAD_REQUEST, ApiError::new("Set without_fdc_id and/or without_macros"));
+    }
+    let limit = body.limit.unwrap_or(DEFAULT_REENRICH_LIMIT);
+    if !(1..=MAX_REENRICH_LIMIT).contains(&limit) {
+        return api::error(
+            StatusCode::BAD_REQUEST,
+            ApiError::new(format!("limit must be between 1 and {}", MAX_REENRICH_LIMIT)),
+        );
+    }
+
+    if let Some(full) = queue_backpressure(JobClass::Background, &pool).await {
+        return full;
+    }
+
+    let (permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+    // Ingredients USDA recently had no match for would only get the same answer
+    let no_match_before = chrono::Utc::now().naive_utc() - chrono::Duration::hours(config::get().usda_no_match_ttl_hours);
+    let ingredient_ids = match web::block(move || Ingredient::reenrich_candidates(filter, no_match_before, limit, &mut conn)).await {
+        Ok(Ok(ids)) => ids,
+        Ok(Err(e)) => return AppError::DbQuery(e).error_response(),
+        Err(e) => return AppError::from(e).error_response(),
+    };
+    drop(permit);
+
+    if ingredient_ids.is_empty() {
+        return HttpResponse::Ok().json(ApiOk::new(ReenrichQueued { queued: 0, task_id: None }));
+    }
+
+    let queued = ingredient_ids.len();
+    let mut queue = queue.get();
+    match queue.insert_task(&UsdaReenrichJob { ingredient_ids }).await {
+        Ok(task) => {
+            log::info!("Enqueued USDA re-enrichment of {} ingredients ({:?})", queued, filter);
+            HttpResponse::Ok().json(ApiOk::new(ReenrichQueued {
+                queued,
+                task_id: Some(task.id.to_string()),
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to enqueue USDA re-enrichment job: {:?}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job"))
+        }
+    }
+}
+
+/// Run the cleanup job now instead of waiting for its 2 AM slot
+#[post("/api/jobs/cleanup")]
+async fn enqueue_cleanup(
+    req: HttpRequest,
+    api_key: web::Data<AdminApiKey>,
+    queue: web::Data<SharedQueue>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let mut queue = queue.get();
+    match queue.insert_task(&CleanupJob { recurring: false }).await {
+        Ok(task) => {
+            log::info!("Enqueued cleanup job {}", task.id);
+            HttpResponse::Ok().json(ApiOk::new(JobEnqueued {
+                task_id: Some(task.id.to_string()),
+                ..JobEnqueued::new("Cleanup job enqueued successfully")
+            }))
+        }
+        Err(e) => {
+            log::error!("Failed to enqueue cleanup job: {:?}", e);
+            api::error(StatusCode::INTERNAL_SERVER_ERROR, ApiError::new("Failed to enqueue job"))
+        }
+    }
+}
+
+/// Most failures returned when `?limit=` is not given
+const DEFAULT_FAILURE_LIMIT: i64 = 50;
+/// Most failures one request may ask for
+const MAX_FAILURE_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+struct FailureQuery {
+    since: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct JobFailures {
+    count: usize,
+    failures: Vec<jobs::FailedTask>,
+}
+
+/// Recently failed and retried jobs with their (redacted) error messages, newest first
+#[get("/api/jobs/failures")]
+async fn job_failures(
+    req: HttpRequest,
+    api_key: web::Data<AdminApiKey>,
+    query: web::Query<FailureQuery>,
+    pool: web::Data<DbPool>,
+) -> impl Responder {
+    if let Some(rejection) = api_key.rejection(&req) {
+        return rejection;
+    }
+
+    let since = match query.since.as_deref().map(chrono::DateTime::parse_from_rfc3339) {
+        None => None,
+        Some(Ok(since)) => Some(since.with_timezone(&chrono::Utc)),
+        Some(Err(_)) => {
+            return api::error(
+                StatusCode::BAD_REQUEST,
+                ApiError::new("since must be an RFC 3339 timestamp, e.g. 2025-11-14T08:00:00Z"),
+            );
+        }
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_FAILURE_LIMIT);
+    if !(1..=MAX_FAILURE_LIMIT).contains(&limit) {
+        return api::error(
+            StatusCode::BAD_REQUEST,
+            ApiError::new(format!("limit must be between 1 and {}", MAX_FAILURE_LIMIT)),
+        );
+    }
+
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    let result = web::block(move || jobs::recent_failures(since, limit, &mut conn)).await;
+
+    match result {
+        Ok(Ok(failures)) => HttpResponse::Ok().json(ApiOk::new(JobFailures {
+            count: failures.len(),
+            failures,
+        })),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+/// How many tasks of each type are pending, running, failed and finished
+#[get("/api/jobs/status")]
+async fn job_status(pool: web::Data<DbPool>) -> impl Responder {
+    let (_permit, mut conn) = match db::checkout(&pool).await {
+        Ok(checkout) => checkout,
+        Err(e) => {
+            log::error!("Failed to get DB connection: {}", e);
+            return db_unavailable();
+        }
+    };
+
+    match web::block(move || jobs::job_stats(&mut conn)).await {
+        Ok(Ok(stats)) => HttpResponse::Ok().json(ApiOk::new(stats)),
+        Ok(Err(e)) => AppError::DbQuery(e).error_response(),
+        Err(e) => AppError::from(e).error_response(),
+    }
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenvy::dotenv().ok();
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    // Fail fast on bad config before binding the port
+    let (config, problems) = Config::from_env();
+    startup::ensure_ready(&config, problems);
+    let config = web::Data::from(config::init(config));
+
+    let (port, http_workers, blocking_threads) = (config.port, config.http_workers, config.blocking_threads);
+
+    log::info!("Starting Spoils API server on port {} with {} workers", port, http_workers);
+
+    // Initialize database connection pool, sized for the blocking DB work of every worker
+    let pool_size = db::pool_size_for_workers(http_workers);
+    let pool = db::establish_connection_pool_with_size(pool_size);
+    log::info!("Database connection pool established (max {} connections)", pool_size);
+    db::init_gate(pool_size);
+    log::info!("At most {} concurrent request DB operations", db::gate().stats().max_concurrent);
+
+    // One job queue for the whole process: handlers enqueue through it, workers poll it
+    let job_queue = match workers::connect_queue(config.database_url()).await {
+        Ok(queue) => {
+            // Start background worker pool in a separate task
+            let worker_queue = queue.clone();
+            tokio::spawn(async move {
+                log::info!("Starting background job worker pool...");
+                workers::start_worker_pool(worker_queue).await;
+            });
+            log::info!("Worker pool started in background");
+            SharedQueue::new(queue)
+        }
+        Err(e) if config.allow_degraded_start => {
+            log::warn!("Job queue unavailable (continuing, ALLOW_DEGRADED_START is set): {:?}", e);
+            // Handlers answer 500 for enqueues until the retries connect it and start the workers
+            let queue = SharedQueue::new(workers::disconnected_queue(config.database_url()));
+            tokio::spawn(workers::reconnect(config.database_url().to_string(), queue.clone()));
+            queue
+        }
+        Err(e) => {
+            log::error!("Failed to connect to database for job queue: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let job_queue = web::Data::new(job_queue);
+
+    let source_chain = web::Data::new(SourceChain::from_config(&config));
+    log::info!("Product sources: {}", source_chain.names().join(" -> "));
+
+    let admin_api_key = web::Data::new(AdminApiKey::from_config(&config));
+    let clock: web::Data<dyn Clock> = web::Data::from(std::sync::Arc::new(SystemClock) as std::sync::Arc<dyn Clock>);
+
+    let server = HttpServer::new(move || {
+        let cors = Cors::permissive(); // Configure this properly for production
+
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(job_queue.clone())
+            .app_data(clock.clone())
+            .app_data(admin_api_key.clone())
+            .app_data(source_chain.clone())
+            .app_data(config.clone())
+            .app_data(api::json_config())
+            .app_data(api::query_config())
+            .app_data(api::path_config())
+            .wrap(cors)
+            .wrap(actix_web::middleware::Logger::default().exclude("/api/ping"))
+            .wrap(actix_web::middleware::from_fn(slow_log::requests))
+            .service(health)
+            .service(ping)
+            .service(hello)
+            .service(product_facets)
+            .service(list_products)
+            .service(get_products_batch)
+            .service(get_product)
+            .service(product_history_diff)
+            .service(product_nutrition)
+            .service(product_nutrition_estimate)
+            .service(product_allergens)
+            .service(product_safety)
+            .service(product_full)
+            .service(product_field)
+            .service(product_status)
+            .service(reprocess_product_ingredients)
+            .service(ocr_product_ingredients)
+            .service(list_ingredients)
+            .service(ingredient_review_queue)
+            .service(get_ingredients_batch)
+            .service(ingredient_usda_raw)
+            .service(get_ingredient)
+            .service(ingredient_tree)
+            .service(patch_ingredient)
+            .service(record_ingredient_contaminants)
+            .service(create_ingredient_alias)
+            .service(vacuum_orphan_ingredients)
+            .service(db_pool_stats)
+            .service(product_metrics)
+            .service(product_non_food_facets)
+            .service(get_product_non_food)
+            .service(create_product_non_food)
+            .service(update_product_non_food)
+            .service(refresh_product_non_food)
+            .service(list_products_non_food)
+            .service(enqueue_fetch_product)
+            .service(enqueue_analyze_ingredients)
+            .service(enqueue_usda_backfill)
+            .service(usda_backfill_runs)
+            .service(enqueue_ingredient_reenrichment)
+            .service(enqueue_cleanup)
+            .service(job_failures)
+            .service(job_status)
+    })
+    .workers(http_workers);
+
+    // Actix's default is 512 blocking threads split across the workers
+    let server = match blocking_threads {
+        Some(threads) => server.worker_max_blocking_threads(threads),
+        None => server,
+    };
+
+    server
+    // Treat a client closing its side as gone, so its handler (and any OFF call) is dropped
+    .h1_allow_half_closed(false)
+    .bind(("0.0.0.0", port))?
+    .run()
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::models::{NewIngredient, NewProductIngredient};
+
+    #[test]
+    fn test_extract_ingredients_with_ingredients_marker() {
+        let text = "Premium supplement. Ingredients: Vitamin C, Zinc, Magnesium. Take daily.";
+        let result = extract_ingredients_from_text(text);
+
+        assert!(result.is_some());
+        let ingredients = result.unwrap();
+        assert!(ingredients.contains("Vitamin C"));
+        assert!(ingredients.contains("Zinc"));
+        assert!(ingredients.contains("Magnesium"));
+        assert!(!ingredients.contains("Take daily")); // Should stop at period before capital
+    }
+
+    #[test]
+    fn test_extract_ingredients_with_contains_marker() {
+        let text = "Natural formula. Contains: Water, Glycerin, Hyaluronic Acid.";
+        let result = extract_ingredients_from_text(text);
+
+        assert!(result.is_some());
+        let ingredients = result.unwrap();
+        assert!(ingredients.contains("Water"));
+        assert!(ingredients.contains("Glycerin"));
+        assert!(ingredients.contains("Hyaluronic Acid"));
+    }
+
+    #[test]
+    fn test_extract_ingredients_with_active_ingredients() {
+        let text = "Active Ingredients: Retinol, Niacinamide, Peptides. For external use only.";
+        let result = extract_ingredients_from_text(text);
+
+        assert!(result.is_some());
+        let ingredients = result.unwrap();
+        assert!(ingredients.contains("Retinol"));
+        assert!(ingredients.contains("Niacinamide"));
+    }
+
+    #[test]
+    fn test_extract_ingredients_no_marker() {
+        let text = "This is a product with no ingredient list in it.";
+        let result = extract_ingredients_from_text(text);
+
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_extract_ingredients_multiple_sentences() {
+        let text = "Product description. Ingredients: Salt, Pepper, Garlic. Directions: Use as needed. Storage: Keep cool.";
+        let result = extract_ingredients_from_text(text);
+
+        assert!(result.is_some());
+        let ingredients = result.unwrap();
+        assert!(ingredients.contains("Salt"));
+        assert!(ingredients.contains("Garlic"));
+        // Should stop before "Directions" (capital letter after period)
+        assert!(!ingredients.contains("Directions"));
+    }
+
+    #[test]
+    fn test_extract_ingredients_case_insensitive() {
+        let text = "INGREDIENTS: WATER, SUGAR, SALT";
+        let result = extract_ingredients_from_text(text);
+
+        assert!(result.is_some());
+        let ingredients = result.unwrap();
+        assert!(ingredients.contains("WATER"));
+        assert!(ingredients.contains("SUGAR"));
+    }
+
+    async fn not_found_body(source: LookupSource) -> serde_json::Value {
+        let resp = product_not_found("0000000000000", source).error_response();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_not_found_reports_source_per_path() {
+        for (source, expected) in [
+            (LookupSource::Off, "off"),
+            (LookupSource::Cache, "cache"),
+            (LookupSource::NegativeCache, "negative-cache"),
+        ] {
+            let body = not_found_body(source).await;
+            assert_eq!(body["error"]["source"], expected);
+            assert_eq!(body["error"]["barcode"], "0000000000000");
+            assert_eq!(body["error"]["message"], "Product not found");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_responses_use_data_and_error_envelopes() {
+        // Never connected to: every request here is answered before a connection is needed
+        let pool: DbPool = diesel::r2d2::Pool::builder()
+            .build_unchecked(diesel::r2d2::ConnectionManager::new("postgres://unused/spoils"));
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .app_data(web::Data::new(AdminApiKey::new(Some("envelope-test-key".to_string()))))
+                .app_data(web::Data::new(config::get().clone()))
+                .app_data(api::json_config())
+                .app_data(api::query_config())
+                .app_data(api::path_config())
+                .service(hello)
+                .service(product_field)
+                .service(patch_ingredient)
+                .service(enqueue_cleanup),
+        )
+        .await;
+
+        let call = |req: actix_web::test::TestRequest| async {
+            let resp = actix_web::test::call_service(&app, req.to_request()).await;
+            let status = resp.status();
+            let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+            let keys: Vec<String> = body.as_object().unwrap().keys().cloned().collect();
+            (status, keys, body)
+        };
+
+        let (status, keys, body) = call(actix_web::test::TestRequest::get().uri("/api/hello")).await;
+        assert!(status.is_success());
+        assert_eq!(keys, vec!["data"]);
+        assert_eq!(body["data"]["message"], "Hello from Spoils API!");
+
+        for req in [
+            // Admin key missing
+            actix_web::test::TestRequest::post().uri("/api/jobs/cleanup"),
+            // Required query parameter missing
+            actix_web::test::TestRequest::get().uri("/api/products/0000000000000/field"),
+            // Handler-level validation
+            actix_web::test::TestRequest::get().uri("/api/products/0000000000000/field?path=a//b"),
+            // Path that doesn't parse
+            actix_web::test::TestRequest::patch().uri("/api/ingredients/not-a-number"),
+            // A patch that changes nothing
+            actix_web::test::TestRequest::patch()
+                .uri("/api/ingredients/1")
+                .insert_header((auth::API_KEY_HEADER, "envelope-test-key"))
+                .set_json(serde_json::json!({})),
+        ] {
+            let (status, keys, body) = call(req).await;
+            assert!(status.is_client_error(), "{}", status);
+            assert_eq!(keys, vec!["error"]);
+            assert!(body["error"]["message"].is_string());
+        }
+
+        let resp = db_unavailable();
+        assert_eq!(resp.headers().get(actix_web::http::header::RETRY_AFTER).unwrap(), "1");
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": { "code": "db_unavailable", "message": "Database connection failed", "retry_after_secs": 1 }
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_admin_endpoints_refuse_requests_without_the_key() {
+        // Never connected to: the key is checked before a connection is needed
+        let pool: DbPool = diesel::r2d2::Pool::builder()
+            .build_unchecked(diesel::r2d2::ConnectionManager::new("postgres://unused/spoils"));
+        let clock: web::Data<dyn Clock> = web::Data::from(std::sync::Arc::new(SystemClock) as std::sync::Arc<dyn Clock>);
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .app_data(web::Data::new(AdminApiKey::new(Some("admin-test-key".to_string()))))
+                .app_data(web::Data::new(config::get().clone()))
+                .app_data(clock)
+                .service(db_pool_stats)
+                .service(create_ingredient_alias)
+                .service(vacuum_orphan_ingredients)
+                .service(enqueue_usda_backfill)
+                .service(usda_backfill_runs)
+                .service(patch_ingredient)
+                .service(record_ingredient_contaminants)
+                .service(ocr_product_ingredients),
+        )
+        .await;
+
+        for (req, key) in [
+            (actix_web::test::TestRequest::get().uri("/api/admin/db-pool"), None),
+            (
+                actix_web::test::TestRequest::post()
+                    .uri("/api/admin/ingredient-aliases")
+                    .set_json(serde_json::json!({ "alias": "sucre", "ingredient_id": 1 })),
+                Some("wrong-key"),
+            ),
+            (actix_web::test::TestRequest::post().uri("/api/admin/ingredients/vacuum?dry_run=false"), None),
+            (actix_web::test::TestRequest::post().uri("/api/admin/usda-backfill"), Some("wrong-key")),
+            (actix_web::test::TestRequest::get().uri("/api/admin/usda-backfill"), None),
+            (
+                actix_web::test::TestRequest::patch()
+                    .uri("/api/ingredients/1")
+                    .set_json(serde_json::json!({ "gram_protein_per_gram": 0.9 })),
+                None,
+            ),
+            (
+                actix_web::test::TestRequest::post()
+                    .uri("/api/ingredients/1/contaminants")
+                    .set_json(serde_json::json!({ "category": "heavy_metals", "findings": {} })),
+                Some("wrong-key"),
+            ),
+            (actix_web::test::TestRequest::post().uri("/api/products/0737628064502/ocr-ingredients"), None),
+        ] {
+            let req = match key {
+                Some(key) => req.insert_header((auth::API_KEY_HEADER, key)),
+                None => req,
+            };
+            let resp = actix_web::test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_product_lookups_reject_malformed_barcodes() {
+        // Never connected to, and no product sources: malformed barcodes must be refused first
+        let pool: DbPool = diesel::r2d2::Pool::builder()
+            .build_unchecked(diesel::r2d2::ConnectionManager::new("postgres://unused/spoils"));
+        let clock: web::Data<dyn Clock> = web::Data::from(std::sync::Arc::new(SystemClock) as std::sync::Arc<dyn Clock>);
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .app_data(clock)
+                .app_data(web::Data::new(SourceChain::new(Vec::new())))
+                .app_data(web::Data::new(config::get().clone()))
+                .app_data(web::Data::new(AdminApiKey::new(None)))
+                .service(get_product)
+                .service(product_history_diff)
+                .service(reprocess_product_ingredients)
+                .service(ocr_product_ingredients)
+                .service(product_status)
+                .service(product_allergens)
+                .service(product_safety)
+                .service(product_field)
+                .service(product_nutrition_estimate)
+                .service(product_nutrition)
+                .service(product_full)
+                .service(get_product_non_food),
+        )
+        .await;
+
+        // Every route taking a barcode, with whatever else it needs to get that far
+        let routes = [
+            ("GET", "/api/products/{}"),
+            ("GET", "/api/products/{}/history/diff?from=1&to=2"),
+            ("POST", "/api/products/{}/reprocess-ingredients"),
+            ("POST", "/api/products/{}/ocr-ingredients"),
+            ("GET", "/api/products/{}/status"),
+            ("GET", "/api/products/{}/allergens"),
+            ("GET", "/api/products/{}/safety"),
+            ("GET", "/api/products/{}/field?path=nutriments"),
+            ("GET", "/api/products/{}/nutrition"),
+            ("GET", "/api/products/{}/nutrition/estimate"),
+            ("GET", "/api/products/{}/full"),
+            ("GET", "/api/products-non-food/{}"),
+        ];
+        let overlong = "7".repeat(2000);
+        for barcode in [
+            // Arabic-Indic digits, and a letter outside ASCII
+            "%D9%A1%D9%A2%D9%A3%D9%A4%D9%A5%D9%A6%D9%A7%D9%A8",
+            "073762806450%C3%A9",
+            // Encoded slashes and traversal
+            "0737%2F628064502",
+            "..%2F..%2Fapi%2Fadmin",
+            "123456789012345",
+            overlong.as_str(),
+        ] {
+            for (method, route) in routes {
+                let uri = route.replace("{}", barcode);
+                let req = match method {
+                    "POST" => actix_web::test::TestRequest::post(),
+                    _ => actix_web::test::TestRequest::get(),
+                };
+                let resp = actix_web::test::call_service(&app, req.uri(&uri).to_request()).await;
+                assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST, "{} {}", method, uri);
+
+                let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+                assert!(body["error"]["message"].as_str().unwrap().starts_with("Barcode"));
+                assert_eq!(body["error"]["max_length"], 14);
+            }
+        }
+    }
+
+    #[test]
+    fn test_extract_ingredients_with_other_ingredients_marker() {
+        let text = "Supplement facts. Other Ingredients: Cellulose, Silica. Made in USA.";
         let result = extract_ingredients_from_text(text);
 
-        assert!(result.is_some());
-        let ingredients = result.unwrap();
-        assert!(ingredients.contains("Vitamin C"));
-        assert!(ingredients.contains("Zinc"));
-        assert!(ingredients.contains("Magnesium"));
-        assert!(!ingredients.contains("Take daily")); // Should stop at period before capital
+        assert!(result.is_some());
+        let ingredients = result.unwrap();
+        assert!(ingredients.contains("Cellulose"));
+        assert!(ingredients.contains("Silica"));
+    }
+
+    #[test]
+    fn test_ingredient_list_query_defaults() {
+        let (filter, page) = parse_ingredient_list_query(&IngredientListQuery::default()).unwrap();
+
+        assert_eq!(filter, IngredientMacroFilter::default());
+        assert_eq!(page, PageRequest::default());
+    }
+
+    #[test]
+    fn test_ingredient_list_query_builds_ranges_and_sort() {
+        let query = IngredientListQuery {
+            protein_min: Some(0.25),
+            fat_max: Some(0.0),
+            include_unknown: Some(true),
+            sort: Some("protein".to_string()),
+            order: Some("desc".to_string()),
+            page: Some(3),
+            ..Default::default()
+        };
+
+        let (filter, page) = parse_ingredient_list_query(&query).unwrap();
+        assert_eq!(filter.protein, MacroRange { min: Some(0.25), max: None });
+        assert_eq!(filter.fat, MacroRange { min: None, max: Some(0.0) });
+        assert!(filter.include_unknown);
+        assert_eq!(filter.sort, MacroSort::Protein);
+        assert!(filter.descending);
+        assert_eq!(page.page, 3);
+    }
+
+    #[test]
+    fn test_ingredient_list_query_rejects_bad_ranges() {
+        let cases = [
+            IngredientListQuery { protein_min: Some(1.5), ..Default::default() },
+            IngredientListQuery { fat_max: Some(-0.1), ..Default::default() },
+            IngredientListQuery { carbs_min: Some(0.5), carbs_max: Some(0.2), ..Default::default() },
+            IngredientListQuery { sort: Some("sugar".to_string()), ..Default::default() },
+            IngredientListQuery { page: Some(0), ..Default::default() },
+            IngredientListQuery { per_page: Some(500), ..Default::default() },
+            IngredientListQuery { name: Some("  ".to_string()), ..Default::default() },
+        ];
+
+        for query in cases {
+            assert!(parse_ingredient_list_query(&query).is_err());
+        }
+    }
+
+    #[test]
+    fn test_ingredient_tree_depth_defaults_and_clamps() {
+        assert_eq!(IngredientTreeQuery::default().depth(), 3);
+        assert_eq!(IngredientTreeQuery { depth: Some(0) }.depth(), 0);
+        assert_eq!(IngredientTreeQuery { depth: Some(5) }.depth(), 5);
+        assert_eq!(IngredientTreeQuery { depth: Some(500) }.depth(), MAX_INGREDIENT_TREE_DEPTH);
+    }
+
+    #[actix_rt::test]
+    async fn test_ingredient_tree_endpoint_nests_children() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let (granola, honey) = {
+            let mut conn = pool.get().unwrap();
+            let granola = NewIngredient::named("Tree Endpoint Granola").seed(&mut conn);
+            let honey = NewIngredient::named("Tree Endpoint Honey").seed(&mut conn);
+            Ingredient::link_sub_ingredient(granola, honey, &mut conn).unwrap();
+            (granola, honey)
+        };
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(api::query_config())
+                .service(ingredient_tree),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::get().uri(&format!("/api/ingredients/{}/tree", granola)).to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            body["data"],
+            serde_json::json!({
+                "id": granola,
+                "name": "Tree Endpoint Granola",
+                "children": [{ "id": honey, "name": "Tree Endpoint Honey", "children": [] }]
+            })
+        );
+
+        let req = actix_web::test::TestRequest::get().uri(&format!("/api/ingredients/{}/tree?depth=0", granola)).to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["children"], serde_json::json!([]));
+
+        let req = actix_web::test::TestRequest::get().uri(&format!("/api/ingredients/{}/tree?depth=-1", granola)).to_request();
+        assert_eq!(actix_web::test::call_service(&app, req).await.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let req = actix_web::test::TestRequest::get().uri(&format!("/api/ingredients/{}/tree", honey + 1_000_000)).to_request();
+        assert_eq!(actix_web::test::call_service(&app, req).await.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_ingredient_by_id_and_name() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let ingredient_id = {
+            let mut conn = pool.get().unwrap();
+            diesel::insert_into(ingredients::table)
+                .values((
+                    ingredients::name.eq("Lookup Test Spirulina"),
+                    ingredients::gram_protein_per_gram.eq(0.57),
+                    ingredients::heavy_metals.eq(serde_json::json!({ "lead": "0.3 ppm" })),
+                ))
+                .returning(ingredients::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap()
+        };
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(api::path_config())
+                .service(list_ingredients)
+                .service(ingredient_review_queue)
+                .service(get_ingredient),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::get().uri(&format!("/api/ingredients/{}", ingredient_id)).to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["name"], "Lookup Test Spirulina");
+        assert_eq!(body["data"]["heavy_metals"]["lead"], "0.3 ppm");
+        assert!((body["data"]["gram_protein_per_gram"].as_f64().unwrap() - 0.57).abs() < 1e-6);
+
+        let req = actix_web::test::TestRequest::get().uri(&format!("/api/ingredients/{}", ingredient_id + 1_000_000)).to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+        assert_eq!(body["error"]["code"], "not_found");
+        assert_eq!(body["error"]["id"], ingredient_id + 1_000_000);
+
+        // Fixed segments still reach their own handlers
+        let req = actix_web::test::TestRequest::get().uri("/api/ingredients/review-queue").to_request();
+        assert!(actix_web::test::call_service(&app, req).await.status().is_success());
+
+        // Case and extra whitespace don't matter; the name is bound, not spliced into SQL
+        for (name, expected) in [
+            ("lookup%20test%20SPIRULINA", 1),
+            ("%20Lookup%20%20Test%20Spirulina%20", 1),
+            ("Lookup%20Test%20Spirulina%27%20OR%20%271%27%3D%271", 0),
+        ] {
+            let req = actix_web::test::TestRequest::get().uri(&format!("/api/ingredients?name={}", name)).to_request();
+            let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+            let items = body["data"]["items"].as_array().unwrap();
+            assert_eq!(items.len(), expected, "{}: {}", name, body);
+            if expected == 1 {
+                assert_eq!(items[0]["id"], ingredient_id);
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_saturated_pool_returns_503_with_retry_after() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let pool: DbPool = diesel::r2d2::Pool::builder()
+            .max_size(1)
+            .connection_timeout(std::time::Duration::from_millis(50))
+            .build(diesel::r2d2::ConnectionManager::<PgConnection>::new(url))
+            .expect("Failed to build pool");
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(ingredient_usda_raw),
+        )
+        .await;
+
+        // Hold the only connection so the handler's checkout times out
+        let held = pool.get().unwrap();
+        let req = actix_web::test::TestRequest::get().uri("/api/ingredients/1/usda-raw").to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers().get(actix_web::http::header::RETRY_AFTER).unwrap(), "1");
+
+        // Once the connection is back, the same request goes through
+        drop(held);
+        let req = actix_web::test::TestRequest::get().uri("/api/ingredients/1/usda-raw").to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_ne!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_rt::test]
+    async fn test_cleanup_endpoint_enqueues_task() {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        // Started degraded: the queue connects only after the app is up
+        let queue = SharedQueue::new(workers::disconnected_queue(&url));
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(queue.clone()))
+                .app_data(web::Data::new(AdminApiKey::new(Some("cleanup-test-key".to_string()))))
+                .service(enqueue_cleanup),
+        )
+        .await;
+        let keyed = || {
+            actix_web::test::TestRequest::post()
+                .uri("/api/jobs/cleanup")
+                .insert_header((auth::API_KEY_HEADER, "cleanup-test-key"))
+                .to_request()
+        };
+
+        let req = actix_web::test::TestRequest::post().uri("/api/jobs/cleanup").to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        let resp = actix_web::test::call_service(&app, keyed()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Once reconnected, the running app enqueues through the new queue
+        queue.replace(workers::connect_queue(&url).await.expect("Failed to connect job queue"));
+        let req = keyed();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["status"], "enqueued");
+        let task_id = body["data"]["task_id"].as_str().expect("task_id in response").to_string();
+
+        #[derive(QueryableByName)]
+        struct QueuedTask {
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            task_type: String,
+            #[diesel(sql_type = diesel::sql_types::Jsonb)]
+            metadata: serde_json::Value,
+        }
+
+        let mut conn = db::committing_test_connection().expect("DATABASE_URL is set");
+        let queued: QueuedTask = diesel::sql_query(
+            "SELECT task_type::text AS task_type, metadata FROM fang_tasks WHERE id::text = $1",
+        )
+        .bind::<diesel::sql_types::Text, _>(&task_id)
+        .get_result(&mut conn)
+        .expect("enqueued task is in fang_tasks");
+
+        // The task is committed by the queue, so remove it again
+        diesel::sql_query("DELETE FROM fang_tasks WHERE id::text = $1")
+            .bind::<diesel::sql_types::Text, _>(&task_id)
+            .execute(&mut conn)
+            .unwrap();
+
+        assert_eq!(queued.task_type, "cleanup");
+        assert_eq!(queued.metadata["recurring"], false);
+    }
+
+    #[actix_rt::test]
+    async fn test_disconnected_queue_fails_enqueues() {
+        // Handlers never connect a queue of their own, so one that isn't connected stays down
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .app_data(web::Data::new(AdminApiKey::new(Some("shared-queue-test-key".to_string()))))
+                .service(enqueue_cleanup),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/jobs/cleanup")
+            .insert_header((auth::API_KEY_HEADER, "shared-queue-test-key"))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+        let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+        assert_eq!(body["error"]["message"], "Failed to enqueue job");
+    }
+
+    #[actix_rt::test]
+    async fn test_reenrich_endpoint_queues_matching_ingredients() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let url = std::env::var("DATABASE_URL").expect("test_pool found DATABASE_URL");
+
+        let (bare, matched) = {
+            let mut conn = pool.get().unwrap();
+            (
+                NewIngredient::named("Reenrich Endpoint Test Bare").seed(&mut conn),
+                NewIngredient { fdc_id: Some(173904), ..NewIngredient::named("Reenrich Endpoint Test Matched") }.seed(&mut conn),
+            )
+        };
+
+        let queue = workers::connect_queue(&url).await.expect("Failed to connect job queue");
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(queue)))
+                .app_data(web::Data::new(AdminApiKey::new(Some("reenrich-test-key".to_string()))))
+                .service(enqueue_ingredient_reenrichment),
+        )
+        .await;
+        let post = |body: serde_json::Value| {
+            actix_web::test::TestRequest::post()
+                .uri("/api/admin/ingredients/reenrich")
+                .insert_header((auth::API_KEY_HEADER, "reenrich-test-key"))
+                .set_json(body)
+        };
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/admin/ingredients/reenrich")
+            .set_json(serde_json::json!({ "without_fdc_id": true }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        for body in [
+            serde_json::json!({}),
+            serde_json::json!({ "without_fdc_id": true, "limit": 0 }),
+            serde_json::json!({ "without_fdc_id": true, "data_source": "usda" }),
+        ] {
+            let resp = actix_web::test::call_service(&app, post(body.clone()).to_request()).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST, "{}", body);
+        }
+
+        let req = post(serde_json::json!({ "without_fdc_id": true, "without_macros": true, "limit": MAX_REENRICH_LIMIT })).to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        let queued = body["data"]["queued"].as_u64().expect("queued in response");
+        let task_id = body["data"]["task_id"].as_str().expect("task_id in response").to_string();
+
+        #[derive(QueryableByName)]
+        struct QueuedTask {
+            #[diesel(sql_type = diesel::sql_types::Jsonb)]
+            metadata: serde_json::Value,
+        }
+
+        let mut conn = db::committing_test_connection().expect("DATABASE_URL is set");
+        let task: QueuedTask = diesel::sql_query("SELECT metadata FROM fang_tasks WHERE id::text = $1")
+            .bind::<diesel::sql_types::Text, _>(&task_id)
+            .get_result(&mut conn)
+            .expect("enqueued task is in fang_tasks");
+
+        // The task is committed by the queue, so remove it again
+        diesel::sql_query("DELETE FROM fang_tasks WHERE id::text = $1")
+            .bind::<diesel::sql_types::Text, _>(&task_id)
+            .execute(&mut conn)
+            .unwrap();
+
+        let ids: Vec<i64> = task.metadata["ingredient_ids"].as_array().unwrap().iter().map(|id| id.as_i64().unwrap()).collect();
+        assert_eq!(ids.len() as u64, queued);
+        assert!(ids.contains(&i64::from(bare)));
+        assert!(!ids.contains(&i64::from(matched)));
+    }
+
+    #[actix_rt::test]
+    async fn test_create_supplement_enqueues_its_described_ingredients() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let url = std::env::var("DATABASE_URL").expect("test_pool found DATABASE_URL");
+        if !config::get().auto_create_ingredients {
+            eprintln!("AUTO_CREATE_INGREDIENTS is off, skipping");
+            return;
+        }
+
+        let queue = workers::connect_queue(&url).await.expect("Failed to connect job queue");
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(queue)))
+                .service(create_product_non_food),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/products-non-food")
+            .set_json(serde_json::json!({
+                "barcode": "90000000001",
+                "name": "Daily Test Multivitamin",
+                "category": "Dietary Supplements",
+                "description": "Ingredients: Enqueue Test Ascorbate, Enqueue Test Zinc Gluconate, Enqueue Test Magnesium Citrate"
+            }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CREATED);
+        let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+        assert_eq!(body["data"]["name"], "Daily Test Multivitamin");
+
+        // Only supplement and beauty categories have their description mined
+        let req = actix_web::test::TestRequest::post()
+            .uri("/api/products-non-food")
+            .set_json(serde_json::json!({
+                "name": "Test Kitchen Sponge",
+                "category": "Household",
+                "description": "Contains: Enqueue Test Cellulose"
+            }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CREATED);
+
+        // The queue commits its tasks outside the test transaction, so the test removes the
+        // ones it caused, and only those: the names are unique to this test
+        let names = [
+            "Enqueue Test Ascorbate",
+            "Enqueue Test Zinc Gluconate",
+            "Enqueue Test Magnesium Citrate",
+            "Enqueue Test Cellulose",
+        ];
+        #[derive(QueryableByName)]
+        struct QueuedTask {
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            name: String,
+        }
+        let mut conn = db::committing_test_connection().expect("DATABASE_URL is set");
+        let queued: Vec<QueuedTask> = diesel::sql_query(
+            "DELETE FROM fang_tasks WHERE task_type = 'create_ingredient' AND metadata->>'name' = ANY($1) \
+             RETURNING metadata->>'name' AS name",
+        )
+        .bind::<diesel::sql_types::Array<diesel::sql_types::Text>, _>(&names[..])
+        .load(&mut conn)
+        .unwrap();
+
+        // The jobs are queued by the time the product is returned
+        let mut queued: Vec<String> = queued.into_iter().map(|task| task.name).collect();
+        queued.sort();
+        assert_eq!(
+            queued,
+            ["Enqueue Test Ascorbate", "Enqueue Test Magnesium Citrate", "Enqueue Test Zinc Gluconate"]
+        );
     }
 
-    #[test]
-    fn test_extract_ingredients_with_contains_marker() {
-        let text = "Natural formula. Contains: Water, Glycerin, Hyaluronic Acid.";
-        let result = extract_ingredients_from_text(text);
+    #[actix_rt::test]
+    async fn test_update_non_food_product_patches_given_fields() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let url = std::env::var("DATABASE_URL").expect("test_pool found DATABASE_URL");
 
-        assert!(result.is_some());
-        let ingredients = result.unwrap();
-        assert!(ingredients.contains("Water"));
-        assert!(ingredients.contains("Glycerin"));
-        assert!(ingredients.contains("Hyaluronic Acid"));
+        let product: ProductNonFood = diesel::insert_into(products_non_food::table)
+            .values(&NewProductNonFood {
+                barcode: Some("90000000002".to_string()),
+                name: "Test Dish Brush".to_string(),
+                brand: None,
+                category: Some("Household".to_string()),
+                description: Some("A brush".to_string()),
+                full_response: None,
+                data_source: Some("manual".to_string()),
+            })
+            .get_result(&mut pool.get().unwrap())
+            .unwrap();
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue(&url))))
+                .service(update_product_non_food),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::put()
+            .uri(&format!("/api/products-non-food/{}", product.id))
+            .set_json(serde_json::json!({ "brand": "Scrubco", "description": "Beech wood brush with sisal bristles" }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+        assert_eq!(body["data"]["brand"], "Scrubco");
+        assert_eq!(body["data"]["description"], "Beech wood brush with sisal bristles");
+        // Fields left out of the body keep their values
+        assert_eq!(body["data"]["name"], "Test Dish Brush");
+        assert_eq!(body["data"]["category"], "Household");
+        assert_eq!(body["data"]["data_source"], "manual");
+
+        let stored: ProductNonFood = products_non_food::table.find(product.id).first(&mut pool.get().unwrap()).unwrap();
+        assert_eq!(stored.brand.as_deref(), Some("Scrubco"));
+        assert_eq!(stored.description.as_deref(), Some("Beech wood brush with sisal bristles"));
+
+        let req = actix_web::test::TestRequest::put()
+            .uri(&format!("/api/products-non-food/{}", product.id + 1_000_000))
+            .set_json(serde_json::json!({ "brand": "Scrubco" }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let req = actix_web::test::TestRequest::put()
+            .uri(&format!("/api/products-non-food/{}", product.id))
+            .set_json(serde_json::json!({ "colour": "red" }))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
     }
 
-    #[test]
-    fn test_extract_ingredients_with_active_ingredients() {
-        let text = "Active Ingredients: Retinol, Niacinamide, Peptides. For external use only.";
-        let result = extract_ingredients_from_text(text);
+    #[actix_rt::test]
+    async fn test_list_products_filters_and_pages() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
 
-        assert!(result.is_some());
-        let ingredients = result.unwrap();
-        assert!(ingredients.contains("Retinol"));
-        assert!(ingredients.contains("Niacinamide"));
+        for (barcode, brands, grade) in [
+            ("list-test-1", "Listtest Foods", "b"),
+            ("list-test-2", "Other, LISTTEST Foods", "c"),
+            ("list-test-3", "Listtest Foods", "b"),
+            ("list-test-4", "List_test 100%", "a"),
+        ] {
+            diesel::insert_into(products::table)
+                .values(&off::extract(barcode, &serde_json::json!({ "brands": brands, "nutriscore_grade": grade })))
+                .execute(&mut pool.get().unwrap())
+                .unwrap();
+        }
+        // Found by its OFF brand tag alone, the display string naming something else
+        diesel::insert_into(products::table)
+            .values(&off::extract(
+                "list-test-5",
+                &serde_json::json!({ "brands": "Parent Co", "brands_tags": ["tagged-listtest-kitchen"], "nutriscore_grade": "d" }),
+            ))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+
+        let app = actix_web::test::init_service(
+            App::new().app_data(web::Data::new(pool)).service(list_products),
+        )
+        .await;
+        let list = |uri: &str| actix_web::test::TestRequest::get().uri(uri).to_request();
+        let barcodes = |body: &serde_json::Value| -> Vec<String> {
+            body["data"]["products"].as_array().unwrap().iter().map(|p| p["barcode"].as_str().unwrap().to_string()).collect()
+        };
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=listtest")).await;
+        assert_eq!(barcodes(&body), ["list-test-5", "list-test-3", "list-test-2", "list-test-1"]);
+        assert_eq!(body["data"]["count"], 4);
+        assert_eq!(body["data"]["limit"], DEFAULT_PRODUCT_LIST_LIMIT);
+        assert_eq!(body["data"]["offset"], 0);
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=listtest&limit=2")).await;
+        assert_eq!(barcodes(&body), ["list-test-5", "list-test-3"]);
+        assert_eq!(body["data"]["count"], 2);
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=listtest&limit=2&offset=3")).await;
+        assert_eq!(barcodes(&body), ["list-test-1"]);
+        assert_eq!((body["data"]["limit"].as_i64(), body["data"]["offset"].as_i64()), (Some(2), Some(3)));
+
+        // Past the end is empty, not an error
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=listtest&offset=4")).await;
+        assert_eq!(body["data"]["products"], serde_json::json!([]));
+        assert_eq!(body["data"]["count"], 0);
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=LISTTEST&nutriscore=B")).await;
+        assert_eq!(barcodes(&body), ["list-test-3", "list-test-1"]);
+
+        // Brand tags match in slug form
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=Listtest%20Kitchen")).await;
+        assert_eq!(barcodes(&body), ["list-test-5"]);
+
+        // LIKE wildcards in the brand are matched literally
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=t_test%20100%25")).await;
+        assert_eq!(barcodes(&body), ["list-test-4"]);
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=no%20such%20listtest%20brand")).await;
+        assert_eq!(body["data"]["products"], serde_json::json!([]));
+        assert_eq!(body["data"]["count"], 0);
+
+        let max = format!("/api/products?limit={}", MAX_PRODUCT_LIST_LIMIT + 1);
+        for uri in ["/api/products?nutriscore=z", "/api/products?limit=0", max.as_str(), "/api/products?offset=-1"] {
+            let resp = actix_web::test::call_service(&app, list(uri)).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST, "{}", uri);
+        }
     }
 
-    #[test]
-    fn test_extract_ingredients_no_marker() {
-        let text = "This is a product with no ingredient list in it.";
-        let result = extract_ingredients_from_text(text);
+    #[actix_rt::test]
+    async fn test_list_products_filters_mixed_grade_rows() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
 
-        assert!(result.is_none());
+        // Scored, unscored (in both of OFF's spellings) and never graded
+        for (barcode, grade) in [
+            ("grade-test-1", Some("A")),
+            ("grade-test-2", Some("unknown")),
+            ("grade-test-3", Some("not-applicable")),
+            ("grade-test-4", None),
+        ] {
+            let mut product = serde_json::json!({ "brands": "Gradetest Foods" });
+            if let Some(grade) = grade {
+                product["nutriscore_grade"] = serde_json::json!(grade);
+            }
+            diesel::insert_into(products::table)
+                .values(&off::extract(barcode, &product))
+                .execute(&mut pool.get().unwrap())
+                .unwrap();
+        }
+
+        let app = actix_web::test::init_service(
+            App::new().app_data(web::Data::new(pool)).service(list_products),
+        )
+        .await;
+        let barcodes = |body: serde_json::Value| -> Vec<String> {
+            body["data"]["products"].as_array().unwrap().iter().map(|p| p["barcode"].as_str().unwrap().to_string()).collect()
+        };
+        let list = |uri: &str| actix_web::test::TestRequest::get().uri(uri).to_request();
+
+        assert_eq!(config::get().unknown_grades, off::UnknownGrades::Sentinel, "the default stores what the grade migration did");
+        let body = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=gradetest&nutriscore=unknown")).await;
+        assert_eq!(barcodes(body), ["grade-test-3", "grade-test-2"]);
+        let body = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=gradetest&nutriscore=a")).await;
+        assert_eq!(barcodes(body), ["grade-test-1"]);
+        let body = actix_web::test::call_and_read_body_json(&app, list("/api/products?brand=gradetest")).await;
+        assert_eq!(barcodes(body).len(), 4);
     }
 
     #[test]
-    fn test_extract_ingredients_multiple_sentences() {
-        let text = "Product description. Ingredients: Salt, Pepper, Garlic. Directions: Use as needed. Storage: Keep cool.";
-        let result = extract_ingredients_from_text(text);
+    fn test_unscored_grade_filter_follows_unknown_grades() {
+        let query = ProductListQuery { brand: None, nutriscore: Some("Unknown".to_string()), limit: None, offset: None };
 
-        assert!(result.is_some());
-        let ingredients = result.unwrap();
-        assert!(ingredients.contains("Salt"));
-        assert!(ingredients.contains("Garlic"));
-        // Should stop before "Directions" (capital letter after period)
-        assert!(!ingredients.contains("Directions"));
+        let (filter, _, _) = parse_product_list_query(&query, off::UnknownGrades::Sentinel).unwrap();
+        assert_eq!(filter.nutriscore.as_deref(), Some("unknown"));
+        // Stored as NULL, an unscored product can't be told from one never graded
+        assert_eq!(
+            parse_product_list_query(&query, off::UnknownGrades::Null).unwrap_err(),
+            "Unknown nutriscore 'Unknown', expected a, b, c, d or e"
+        );
     }
 
     #[test]
-    fn test_extract_ingredients_case_insensitive() {
-        let text = "INGREDIENTS: WATER, SUGAR, SALT";
-        let result = extract_ingredients_from_text(text);
+    fn test_listed_ingredient_count_ignores_blanks_and_repeats() {
+        let from_array = serde_json::json!({
+            "ingredients": [{ "text": "Water" }, { "text": "water " }, { "id": "en:salt" }, { "percent": 5 }]
+        });
+        assert_eq!(listed_ingredient_count(&from_array), 3);
 
-        assert!(result.is_some());
-        let ingredients = result.unwrap();
-        assert!(ingredients.contains("WATER"));
-        assert!(ingredients.contains("SUGAR"));
+        let from_text = serde_json::json!({ "ingredients_text": "Sugar, , Cocoa, sugar" });
+        assert_eq!(listed_ingredient_count(&from_text), 2);
+
+        assert_eq!(listed_ingredient_count(&serde_json::json!({})), 0);
+
+        // Entries with no name still count, each on its own
+        let nameless = crate::fixtures::off_product("nameless_ingredients");
+        assert_eq!(listed_ingredient_count(&nameless), 5);
+    }
+
+    #[actix_rt::test]
+    async fn test_product_status_reports_progress() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        {
+            let mut conn = pool.get().unwrap();
+            let product_data = serde_json::json!({
+                "ingredients": [{ "text": "Status Test Oats" }, { "text": "Status Test Honey" }]
+            });
+            let product_id = diesel::insert_into(products::table)
+                .values(&off::extract("9101000001", &product_data))
+                .returning(products::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+            let oats = diesel::insert_into(ingredients::table)
+                .values(ingredients::name.eq("Status Test Oats"))
+                .returning(ingredients::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+            NewProductIngredient {
+                product_id,
+                ingredient_id: oats,
+                rank: 1,
+                percent_estimate: None,
+                percent_source: None,
+            }
+            .link(&mut conn)
+            .unwrap();
+            ProductLookup::record("9101000002", false, &SystemClock, &mut conn).unwrap();
+        }
+
+        let app = actix_web::test::init_service(
+            App::new().app_data(web::Data::new(pool.clone())).service(product_status),
+        )
+        .await;
+        let get = |barcode: &str| actix_web::test::TestRequest::get().uri(&format!("/api/products/{}/status", barcode)).to_request();
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, get("9101000001")).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "data": {
+                    "barcode": "9101000001",
+                    "cached": true,
+                    "ingredient_count": 2,
+                    "pending_ingredients": 1,
+                    "analyzed": false
+                }
+            })
+        );
+
+        {
+            let mut conn = pool.get().unwrap();
+            diesel::update(products::table.filter(products::barcode.eq("9101000001")))
+                .set(products::analyzed_at.eq(diesel::dsl::now))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, get("9101000001")).await;
+        assert_eq!(body["data"]["analyzed"], true);
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, get("9101000002")).await;
+        assert_eq!(body["data"]["cached"], false);
+
+        let resp = actix_web::test::call_service(&app, get("9101000003")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_product_batch_mixes_cached_uncached_and_invalid_barcodes() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+        let url = std::env::var("DATABASE_URL").expect("test_pool found DATABASE_URL");
+
+        {
+            let mut conn = pool.get().unwrap();
+            diesel::insert_into(products::table)
+                .values(&off::extract("80000000201", &serde_json::json!({ "product_name": "Batch Test Crackers" })))
+                .execute(&mut conn)
+                .unwrap();
+            ProductLookup::record("80000000203", false, &SystemClock, &mut conn).unwrap();
+        }
+
+        let queue = workers::connect_queue(&url).await.expect("Failed to connect job queue");
+        let clock: web::Data<dyn Clock> = web::Data::from(std::sync::Arc::new(SystemClock) as std::sync::Arc<dyn Clock>);
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(queue)))
+                .app_data(clock)
+                .app_data(web::Data::new(config::get().clone()))
+                .service(get_products_batch),
+        )
+        .await;
+        let post = |barcodes: serde_json::Value| {
+            actix_web::test::TestRequest::post()
+                .uri("/api/products/batch")
+                .set_json(serde_json::json!({ "barcodes": barcodes }))
+                .to_request()
+        };
+
+        // Cached, uncached (twice, once with a space), recently unknown upstream, and invalid
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(
+            &app,
+            post(serde_json::json!(["80000000201", "80000000202", "8000000 0202", "80000000203", "not-a-barcode"])),
+        )
+        .await;
+
+        let mut conn = db::committing_test_connection().expect("DATABASE_URL is set");
+        // The queue commits its tasks, so remove them again
+        let removed = diesel::sql_query(
+            "DELETE FROM fang_tasks WHERE task_type = 'fetch_product' AND metadata->>'barcode' LIKE '800000002%'",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        assert_eq!(removed, 1, "one fetch for the uncached barcode");
+
+        let data = &body["data"];
+        assert_eq!(data["products"]["80000000201"]["product_name"], "Batch Test Crackers");
+        assert_eq!(data["products"]["80000000202"], serde_json::Value::Null);
+        assert_eq!(data["products"]["8000000 0202"], serde_json::Value::Null);
+        assert_eq!(data["products"]["80000000203"], serde_json::Value::Null);
+        assert_eq!(data["products"]["not-a-barcode"], serde_json::Value::Null);
+        assert_eq!(data["products"].as_object().unwrap().len(), 5);
+        assert_eq!(data["fetching"], serde_json::json!(["80000000202", "8000000 0202"]));
+        assert_eq!(data["invalid"], serde_json::json!({ "not-a-barcode": "Barcode must contain only digits" }));
+
+        let too_many: Vec<String> = (0..=MAX_PRODUCT_BATCH_SIZE).map(|n| format!("8000000{:04}", n)).collect();
+        for barcodes in [serde_json::json!([]), serde_json::json!(too_many)] {
+            let resp = actix_web::test::call_service(&app, post(barcodes)).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_product_safety_rolls_up_linked_ingredients() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        {
+            let mut conn = pool.get().unwrap();
+            let product_data = serde_json::json!({
+                "ingredients_text": "Safety Test Rice, Safety Test Oats, Safety Test Salt, Safety Test Mystery"
+            });
+            let product_id = diesel::insert_into(products::table)
+                .values(&off::extract("9102000001", &product_data))
+                .returning(products::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+
+            let seeded = [
+                ("Safety Test Rice", Some(serde_json::json!({ "arsenic": "elevated", "cadmium": "trace" })), Some(serde_json::json!(["glyphosate"]))),
+                ("Safety Test Oats", None, Some(serde_json::json!(["glyphosate", "chlormequat"]))),
+                ("Safety Test Salt", None, None),
+            ];
+            for (rank, (name, heavy_metals, pesticides)) in seeded.into_iter().enumerate() {
+                let ingredient_id = diesel::insert_into(ingredients::table)
+                    .values((
+                        ingredients::name.eq(name),
+                        ingredients::heavy_metals.eq(heavy_metals),
+                        ingredients::pesticides.eq(pesticides),
+                    ))
+                    .returning(ingredients::id)
+                    .get_result::<i32>(&mut conn)
+                    .unwrap();
+                NewProductIngredient {
+                    product_id,
+                    ingredient_id,
+                    rank: rank as i32 + 1,
+                    percent_estimate: None,
+                    percent_source: None,
+                }
+                .link(&mut conn)
+                .unwrap();
+            }
+        }
+
+        let app = actix_web::test::init_service(
+            App::new().app_data(web::Data::new(pool.clone())).service(product_safety),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/9102000001/safety").to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            body["data"],
+            serde_json::json!({
+                "barcode": "9102000001",
+                "flagged": true,
+                "flag_count": 4,
+                "categories": [
+                    {
+                        "category": "heavy_metals",
+                        "flags": ["arsenic", "cadmium"],
+                        "ingredients": ["Safety Test Rice"]
+                    },
+                    {
+                        "category": "pesticides",
+                        "flags": ["glyphosate", "chlormequat"],
+                        "ingredients": ["Safety Test Rice", "Safety Test Oats"]
+                    }
+                ],
+                "coverage": {
+                    "listed_ingredients": 4,
+                    "linked_ingredients": 3,
+                    "assessed_ingredients": 2,
+                    "unresolved_ingredients": 1,
+                    "complete": false
+                }
+            })
+        );
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/9102000003/safety").to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_product_nutrition_estimate_sums_linked_ingredients() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let mystery_id = {
+            let mut conn = pool.get().unwrap();
+            let oats = NewIngredient {
+                gram_protein_per_gram: Some(0.5),
+                gram_carbs_per_gram: Some(0.25),
+                gram_fat_per_gram: Some(0.125),
+                gram_fiber_per_gram: Some(0.0),
+                ..NewIngredient::named("Estimate Test Oats")
+            }
+            .seed(&mut conn);
+            let nuts = NewIngredient {
+                gram_protein_per_gram: Some(0.25),
+                gram_carbs_per_gram: Some(0.5),
+                gram_fat_per_gram: Some(0.0),
+                gram_fiber_per_gram: Some(0.125),
+                ..NewIngredient::named("Estimate Test Nuts")
+            }
+            .seed(&mut conn);
+            let mystery = NewIngredient::named("Estimate Test Mystery").seed(&mut conn);
+
+            let products = [
+                ("9103000001", vec![(oats, 50.0), (nuts, 50.0)]),
+                ("9103000002", vec![(oats, 75.0), (mystery, 25.0)]),
+            ];
+            for (barcode, linked) in products {
+                let product_id = diesel::insert_into(products::table)
+                    .values(&off::extract(barcode, &serde_json::json!({})))
+                    .returning(products::id)
+                    .get_result::<i32>(&mut conn)
+                    .unwrap();
+                for (rank, (ingredient_id, percent)) in linked.into_iter().enumerate() {
+                    NewProductIngredient {
+                        product_id,
+                        ingredient_id,
+                        rank: rank as i32 + 1,
+                        percent_estimate: Some(percent),
+                        percent_source: Some("percent".to_string()),
+                    }
+                    .link(&mut conn)
+                    .unwrap();
+                }
+            }
+            mystery
+        };
+
+        let app = actix_web::test::init_service(
+            App::new().app_data(web::Data::new(pool.clone())).service(product_nutrition_estimate),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/9103000001/nutrition/estimate").to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            body["data"],
+            serde_json::json!({
+                "barcode": "9103000001",
+                "per_100g": { "protein": 37.5, "carbs": 37.5, "fat": 6.25, "fiber": 6.25 },
+                "coverage_percent": 100.0,
+                "missing_nutrition": []
+            })
+        );
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/9103000002/nutrition/estimate").to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            body["data"],
+            serde_json::json!({
+                "barcode": "9103000002",
+                "per_100g": { "protein": 37.5, "carbs": 18.75, "fat": 9.375, "fiber": 0.0 },
+                "coverage_percent": 75.0,
+                "missing_nutrition": [{
+                    "id": mystery_id,
+                    "name": "Estimate Test Mystery",
+                    "missing": ["protein", "carbs", "fat", "fiber"]
+                }]
+            })
+        );
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/9103000003/nutrition/estimate").to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_product_full_composes_every_section() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        {
+            let mut conn = pool.get().unwrap();
+            let product_data = serde_json::json!({
+                "product_name": "Full Test Bar",
+                "ingredients_text": "Full Test Peanuts, Full Test Rice",
+                "labels_tags": ["en:gluten-free"],
+                "ingredients_analysis_tags": ["en:vegan"],
+                "allergens_tags": ["en:peanuts"],
+                "traces_tags": ["en:milk"],
+                "serving_size": "40 g",
+                "nutriments": { "sugars_100g": 25, "proteins_100g": 10 }
+            });
+            let product_id = diesel::insert_into(products::table)
+                .values(&off::extract("80000000001", &product_data))
+                .returning(products::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+            let ingredient_id = diesel::insert_into(ingredients::table)
+                .values((
+                    ingredients::name.eq("Full Test Rice"),
+                    ingredients::heavy_metals.eq(Some(serde_json::json!({ "arsenic": "elevated" }))),
+                ))
+                .returning(ingredients::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+            NewProductIngredient {
+                product_id,
+                ingredient_id,
+                rank: 2,
+                percent_estimate: None,
+                percent_source: None,
+            }
+            .link(&mut conn)
+            .unwrap();
+        }
+
+        let app = actix_web::test::init_service(
+            App::new().app_data(web::Data::new(pool.clone())).service(product_full),
+        )
+        .await;
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/80000000001/full").to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        let full = &body["data"];
+        assert_eq!(full["product"]["product_name"], "Full Test Bar");
+        assert_eq!(full["diet"]["vegan"], true);
+        assert_eq!(full["diet"]["gluten_free"], true);
+        assert_eq!(full["safety"]["flagged"], true);
+        assert_eq!(full["safety"]["coverage"]["linked_ingredients"], 1);
+        assert_eq!(full["allergens"], serde_json::json!({ "allergens": ["peanuts"], "traces": ["milk"] }));
+        assert_eq!(full["nutrition"]["basis"], "100g");
+        assert_eq!(full["nutrition"]["nutrients"]["sugars"], 25.0);
+        assert_eq!(full["errors"], serde_json::json!({}));
+
+        // A section that fails is reported on its own, next to the others
+        let product = {
+            let mut conn = pool.get().unwrap();
+            products::table.filter(products::barcode.eq("80000000001")).first::<Product>(&mut conn).unwrap()
+        };
+        let full = serde_json::to_value(ProductFull::compose(product, Err("connection reset".to_string()))).unwrap();
+        assert_eq!(full["safety"], serde_json::Value::Null);
+        assert_eq!(full["errors"], serde_json::json!({ "safety": "connection reset" }));
+        assert_eq!(full["diet"]["vegan"], true);
+        assert_eq!(full["allergens"]["allergens"], serde_json::json!(["peanuts"]));
+        assert_eq!(full["nutrition"]["nutrients"]["sugars"], 25.0);
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/80000000002/full").to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
     }
 
     #[test]
-    fn test_extract_ingredients_with_other_ingredients_marker() {
-        let text = "Supplement facts. Other Ingredients: Cellulose, Silica. Made in USA.";
-        let result = extract_ingredients_from_text(text);
+    fn test_product_includes_parse() {
+        assert_eq!(ProductIncludes::parse(None), Ok(ProductIncludes::default()));
+        assert_eq!(ProductIncludes::parse(Some("")), Ok(ProductIncludes::default()));
+        assert_eq!(
+            ProductIncludes::parse(Some("nutrition, ingredients")),
+            Ok(ProductIncludes { ingredients: true, nutrition: true })
+        );
+        assert_eq!(
+            ProductIncludes::parse(Some("ingredients,allergens")),
+            Err("Unknown include 'allergens', expected ingredients or nutrition".to_string())
+        );
+    }
 
-        assert!(result.is_some());
-        let ingredients = result.unwrap();
-        assert!(ingredients.contains("Cellulose"));
-        assert!(ingredients.contains("Silica"));
+    #[actix_rt::test]
+    async fn test_get_product_embeds_included_sections() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        {
+            let mut conn = pool.get().unwrap();
+            let product_data = serde_json::json!({
+                "product_name": "Include Test Crackers",
+                "ingredients_text": "Include Test Wheat Flour, Include Test Salt",
+                "nutriments": { "salt_100g": 1.5 }
+            });
+            let product_id = diesel::insert_into(products::table)
+                .values(&off::extract("80000000011", &product_data))
+                .returning(products::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+            for (rank, name) in [(2, "Include Test Salt"), (1, "Include Test Wheat Flour")] {
+                let ingredient_id = diesel::insert_into(ingredients::table)
+                    .values(ingredients::name.eq(name))
+                    .returning(ingredients::id)
+                    .get_result::<i32>(&mut conn)
+                    .unwrap();
+                NewProductIngredient { product_id, ingredient_id, rank, percent_estimate: None, percent_source: None }
+                    .link(&mut conn)
+                    .unwrap();
+            }
+        }
+
+        let clock: web::Data<dyn Clock> = web::Data::from(std::sync::Arc::new(SystemClock) as std::sync::Arc<dyn Clock>);
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .app_data(clock)
+                .app_data(web::Data::new(SourceChain::new(Vec::new())))
+                .app_data(web::Data::new(config::get().clone()))
+                .service(get_product),
+        )
+        .await;
+
+        // Nothing embedded by default
+        let req = actix_web::test::TestRequest::get().uri("/api/products/80000000011").to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["product_name"], "Include Test Crackers");
+        assert!(body["data"].get("ingredients").is_none(), "{}", body);
+        assert!(body["data"].get("nutrition").is_none(), "{}", body);
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/api/products/80000000011?include=ingredients,nutrition")
+            .to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        let product = &body["data"];
+        assert_eq!(product["product_name"], "Include Test Crackers");
+        let names: Vec<&str> = product["ingredients"].as_array().unwrap().iter().map(|i| i["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["Include Test Wheat Flour", "Include Test Salt"]);
+        assert_eq!(product["nutrition"]["nutrients"]["salt"], 1.5);
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/80000000011?include=nutrition").to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert!(body["data"].get("ingredients").is_none(), "{}", body);
+        assert_eq!(body["data"]["nutrition"]["basis"], "100g");
+
+        let req = actix_web::test::TestRequest::get().uri("/api/products/80000000011?include=reviews").to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_product_fetches_from_mocked_openfoodfacts_once() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/5000112637922"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(crate::fixtures::off_response("minimal")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let source_chain = SourceChain::new(vec![Box::new(sources::OpenFoodFactsSource::new(server.uri()))]);
+        let clock: web::Data<dyn Clock> = web::Data::from(std::sync::Arc::new(SystemClock) as std::sync::Arc<dyn Clock>);
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .app_data(clock)
+                .app_data(web::Data::new(source_chain))
+                .app_data(web::Data::new(config::get().clone()))
+                .service(get_product)
+                .service(product_metrics),
+        )
+        .await;
+
+        // The first request goes to OpenFoodFacts and stores the product, the second is served from the database
+        let mut counts = vec![metrics::product_lookup_counts()];
+        for _ in 0..2 {
+            let req = actix_web::test::TestRequest::get().uri("/api/products/5000112637922").to_request();
+            let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+            assert_eq!(body["data"]["barcode"], "5000112637922");
+            assert_eq!(body["data"]["product_name"], "Sparkling water");
+            assert_eq!(body["data"]["data_source"], "openfoodfacts");
+            counts.push(metrics::product_lookup_counts());
+        }
+
+        // Other tests look products up concurrently, so the counters only give lower bounds
+        assert!(counts[1].off_fetches(FetchOutcome::Found) > counts[0].off_fetches(FetchOutcome::Found));
+        assert!(counts[2].cache_hits > counts[1].cache_hits);
+
+        let req = actix_web::test::TestRequest::get().uri("/metrics").to_request();
+        let text = String::from_utf8(actix_web::test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert!(text.contains("# TYPE product_cache_hit_total counter"), "{}", text);
+        assert!(text.contains("product_off_fetch_total{outcome=\"found\"} "), "{}", text);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_product_refetches_stale_products() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        // The clock starts PRODUCT_TTL_DAYS and a day back, so once advanced past the TTL it
+        // is back at the real time the database stamps a refreshed product's verification with
+        let ttl = chrono::Duration::days(30);
+        let clock = std::sync::Arc::new(MockClock::at(chrono::Utc::now().naive_utc() - ttl - chrono::Duration::days(1)));
+        {
+            let mut conn = pool.get().unwrap();
+            let start = clock.now();
+            for (barcode, name, verified_at) in [
+                ("80000000028", "Aging Test Water", start - chrono::Duration::days(1)),
+                // A day old once the clock has moved past the other one's TTL
+                ("80000000035", "Fresh Test Water", start + ttl),
+            ] {
+                diesel::insert_into(products::table)
+                    .values(&off::extract(barcode, &serde_json::json!({ "product_name": name })))
+                    .execute(&mut conn)
+                    .unwrap();
+                diesel::update(products::table.filter(products::barcode.eq(barcode)))
+                    .set((products::updated_at.eq(verified_at), products::last_verified_at.eq(verified_at)))
+                    .execute(&mut conn)
+                    .unwrap();
+            }
+        }
+
+        // Each barcode may reach OpenFoodFacts once: the aging one once it's stale, the fresh one only when forced
+        let server = MockServer::start().await;
+        for barcode in ["80000000028", "80000000035"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/v2/product/{}", barcode)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(crate::fixtures::off_response("minimal")))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let config = Config { product_ttl: Some(ttl), ..config::get().clone() };
+        let source_chain = SourceChain::new(vec![Box::new(sources::OpenFoodFactsSource::new(server.uri()))]);
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .app_data(web::Data::from(clock.clone() as std::sync::Arc<dyn Clock>))
+                .app_data(web::Data::new(source_chain))
+                .app_data(web::Data::new(config))
+                .service(get_product),
+        )
+        .await;
+
+        let product_name = |uri: &'static str| {
+            let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+            let app = &app;
+            async move {
+                let body: serde_json::Value = actix_web::test::call_and_read_body_json(app, req).await;
+                body["data"]["product_name"].as_str().unwrap().to_string()
+            }
+        };
+
+        // Served from the database while fresh
+        assert_eq!(product_name("/api/products/80000000028").await, "Aging Test Water");
+
+        // Refreshed in place once past the TTL, then served from the database again
+        clock.advance(ttl + chrono::Duration::days(1));
+        assert_eq!(product_name("/api/products/80000000028").await, "Sparkling water");
+        assert_eq!(product_name("/api/products/80000000028").await, "Sparkling water");
+
+        // Still fresh, so only refetched when asked to
+        assert_eq!(product_name("/api/products/80000000035").await, "Fresh Test Water");
+        assert_eq!(product_name("/api/products/80000000035?refresh=true").await, "Sparkling water");
+    }
+
+    #[actix_rt::test]
+    async fn test_record_contaminants_validates_category_and_merges() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let ingredient_id = {
+            let mut conn = pool.get().unwrap();
+            diesel::insert_into(ingredients::table)
+                .values((
+                    ingredients::name.eq("Contaminant Entry Test Oats"),
+                    ingredients::pesticides.eq(Some(serde_json::json!(["glyphosate"]))),
+                ))
+                .returning(ingredients::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap()
+        };
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(AdminApiKey::new(Some("contaminant-test-key".to_string()))))
+                .service(record_ingredient_contaminants),
+        )
+        .await;
+        let post = |body: serde_json::Value| {
+            actix_web::test::TestRequest::post()
+                .uri(&format!("/api/ingredients/{}/contaminants", ingredient_id))
+                .insert_header((auth::API_KEY_HEADER, "contaminant-test-key"))
+                .set_json(body)
+                .to_request()
+        };
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(
+            &app,
+            post(serde_json::json!({ "category": "pesticides", "findings": ["chlormequat", "glyphosate"] })),
+        )
+        .await;
+        assert_eq!(body["data"]["pesticides"], serde_json::json!(["glyphosate", "chlormequat"]));
+        assert_eq!(body["data"]["manually_verified"], true);
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(
+            &app,
+            post(serde_json::json!({ "category": "pesticides", "findings": ["none detected"], "replace": true })),
+        )
+        .await;
+        assert_eq!(body["data"]["pesticides"], serde_json::json!(["none detected"]));
+
+        // Only contaminant columns can be written
+        for category in ["dyes", "name", "manually_verified", "pesticides; DROP TABLE ingredients"] {
+            let resp = actix_web::test::call_service(&app, post(serde_json::json!({ "category": category, "findings": {} }))).await;
+            assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST, "{}", category);
+        }
+        let resp = actix_web::test::call_service(&app, post(serde_json::json!({ "category": "heavy_metals", "findings": "lead" }))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_review_queue_lists_flagged_ingredients_until_patched() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let (flagged, dismissed, clean) = {
+            let mut conn = pool.get().unwrap();
+            (
+                NewIngredient {
+                    needs_review: true,
+                    usda_candidate: Some(crate::fixtures::usda_food("branded")),
+                    ..NewIngredient::named("Review Queue Test Nut Butter")
+                }
+                .seed(&mut conn),
+                NewIngredient { needs_review: true, ..NewIngredient::named("Review Queue Test Moringa") }.seed(&mut conn),
+                NewIngredient::named("Review Queue Test Salt").seed(&mut conn),
+            )
+        };
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(AdminApiKey::new(Some("review-test-key".to_string()))))
+                .service(ingredient_review_queue)
+                .service(patch_ingredient),
+        )
+        .await;
+        let queue = || actix_web::test::TestRequest::get().uri("/api/ingredients/review-queue?per_page=100").to_request();
+        let ids = |body: &serde_json::Value| -> Vec<i64> {
+            body["data"]["items"].as_array().unwrap().iter().map(|item| item["id"].as_i64().unwrap()).collect()
+        };
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, queue()).await;
+        let listed = ids(&body);
+        assert!(listed.contains(&(flagged as i64)));
+        assert!(listed.contains(&(dismissed as i64)));
+        assert!(!listed.contains(&(clean as i64)));
+
+        let item = body["data"]["items"].as_array().unwrap().iter().find(|item| item["id"] == flagged).unwrap();
+        assert_eq!(item["needs_review"], true);
+        assert_eq!(item["candidates"][0]["fdc_id"], 2099245);
+        assert_eq!(item["candidates"][0]["description"], "CREAMY PEANUT BUTTER");
+        assert!(item["candidates"][0]["gram_protein_per_gram"].is_number());
+        let candidate = item["candidates"][0].clone();
+        let item = body["data"]["items"].as_array().unwrap().iter().find(|item| item["id"] == dismissed).unwrap();
+        assert_eq!(item["candidates"], serde_json::json!([]));
+
+        // Accepting the candidate, or dismissing the review, takes each off the queue
+        let patch = |id: i32, body: serde_json::Value| {
+            actix_web::test::TestRequest::patch()
+                .uri(&format!("/api/ingredients/{}", id))
+                .insert_header((auth::API_KEY_HEADER, "review-test-key"))
+                .set_json(body)
+                .to_request()
+        };
+        // There's nothing to accept without a candidate
+        let resp = actix_web::test::call_service(&app, patch(dismissed, serde_json::json!({ "accept_usda_candidate": true }))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let accepted: serde_json::Value = actix_web::test::call_and_read_body_json(
+            &app,
+            patch(flagged, serde_json::json!({ "accept_usda_candidate": true, "gram_fat_per_gram": 0.5 })),
+        )
+        .await;
+        assert_eq!(accepted["data"]["needs_review"], false);
+        assert_eq!(accepted["data"]["fdc_id"], 2099245);
+        // The curator's macros win; the candidate's fill in the rest
+        assert_eq!(accepted["data"]["gram_fat_per_gram"], 0.5);
+        assert_eq!(accepted["data"]["gram_protein_per_gram"], candidate["gram_protein_per_gram"]);
+        assert_eq!(accepted["data"]["gram_carbs_per_gram"], candidate["gram_carbs_per_gram"]);
+        {
+            let mut conn = pool.get().unwrap();
+            let (food, leftover) = ingredients::table
+                .find(flagged)
+                .select((ingredients::usda_food, ingredients::usda_candidate))
+                .first::<(Option<serde_json::Value>, Option<serde_json::Value>)>(&mut conn)
+                .unwrap();
+            assert_eq!(food, Some(crate::fixtures::usda_food("branded")));
+            assert_eq!(leftover, None);
+        }
+        let resp = actix_web::test::call_service(&app, patch(flagged, serde_json::json!({ "accept_usda_candidate": true }))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        // A null clears a value the curator got wrong
+        let cleared: serde_json::Value =
+            actix_web::test::call_and_read_body_json(&app, patch(flagged, serde_json::json!({ "gram_fat_per_gram": null }))).await;
+        assert!(cleared["data"]["gram_fat_per_gram"].is_null());
+        assert_eq!(cleared["data"]["gram_protein_per_gram"], candidate["gram_protein_per_gram"]);
+        let resp = actix_web::test::call_service(&app, patch(dismissed, serde_json::json!({ "needs_review": false }))).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, queue()).await;
+        let listed = ids(&body);
+        assert!(!listed.contains(&(flagged as i64)));
+        assert!(!listed.contains(&(dismissed as i64)));
+    }
+
+    #[actix_rt::test]
+    async fn test_reprocess_endpoint_skips_unchanged_ingredients() {
+        let Some(pool) = db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        {
+            let mut conn = pool.get().unwrap();
+            diesel::insert_into(ingredients::table)
+                .values(vec![ingredients::name.eq("Reprocess Test Water"), ingredients::name.eq("Reprocess Test Salt")])
+                .execute(&mut conn)
+                .unwrap();
+            let product_data = serde_json::json!({ "ingredients_text": "Reprocess Test Water, Reprocess Test Salt" });
+            diesel::insert_into(products::table)
+                .values(&off::extract("9104000001", &product_data))
+                .execute(&mut conn)
+                .unwrap();
+            let product_data = serde_json::json!({ "ingredients_text": "Reprocess Test Water, Reprocess Test Unheard-Of Root" });
+            diesel::insert_into(products::table)
+                .values(&off::extract("9104000002", &product_data))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(SharedQueue::new(workers::disconnected_queue("postgres://unused/spoils"))))
+                .service(reprocess_product_ingredients),
+        )
+        .await;
+        let post = |barcode: &str| {
+            actix_web::test::TestRequest::post()
+                .uri(&format!("/api/products/{}/reprocess-ingredients", barcode))
+                .to_request()
+        };
+
+        // Never processed, so the first request does the work and the second has nothing to do
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, post("9104000001")).await;
+        assert_eq!(body["data"], serde_json::json!({ "barcode": "9104000001", "reprocessed": true }));
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, post("9104000001")).await;
+        assert_eq!(body["data"]["reprocessed"], false);
+
+        // Forcing redoes it anyway, e.g. for a product whose last run failed
+        let forced = actix_web::test::TestRequest::post()
+            .uri("/api/products/9104000001/reprocess-ingredients?force=true")
+            .to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, forced).await;
+        assert_eq!(body["data"]["reprocessed"], true);
+
+        // An unknown ingredient goes through the shared queue, which here can't take it
+        let resp = actix_web::test::call_service(&app, post("9104000002")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+        let body: serde_json::Value = actix_web::test::read_body_json(resp).await;
+        assert_eq!(body["error"]["message"], "Failed to enqueue job");
+
+        let resp = actix_web::test::call_service(&app, post("9104000003")).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
     }
 }
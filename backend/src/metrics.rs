@@ -0,0 +1,149 @@
+//! Process-wide counters for how product lookups were answered, exposed in Prometheus
+//! text format at `GET /metrics` to show how much of the traffic the cache absorbs.
+//!
+//! - `product_cache_hit_total`: the product was already in our database
+//! - `product_negative_cache_hit_total`: OFF recently reported a miss, so it wasn't asked again
+//! - `product_off_fetch_total{outcome}`: a round trip to the product sources, by how it went
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static NEGATIVE_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static OFF_FETCHES: [AtomicU64; FetchOutcome::ALL.len()] = [const { AtomicU64::new(0) }; FetchOutcome::ALL.len()];
+
+/// How a round trip to the product sources ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FetchOutcome {
+    /// A source had the product
+    Found,
+    /// Every source answered that it doesn't have it
+    NotFound,
+    /// A source failed (network error, error page)
+    Failed,
+    /// The request deadline passed first
+    Timeout,
+}
+
+impl FetchOutcome {
+    pub const ALL: [FetchOutcome; 4] = [FetchOutcome::Found, FetchOutcome::NotFound, FetchOutcome::Failed, FetchOutcome::Timeout];
+
+    /// The `outcome` label value
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FetchOutcome::Found => "found",
+            FetchOutcome::NotFound => "not_found",
+            FetchOutcome::Failed => "failed",
+            FetchOutcome::Timeout => "timeout",
+        }
+    }
+
+    fn counter(self) -> &'static AtomicU64 {
+        &OFF_FETCHES[self as usize]
+    }
+}
+
+pub fn record_cache_hit() {
+    CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_negative_cache_hit() {
+    NEGATIVE_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_off_fetch(outcome: FetchOutcome) {
+    outcome.counter().fetch_add(1, Ordering::Relaxed);
+}
+
+/// Product lookup counts since the process started
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductLookupCounts {
+    pub cache_hits: u64,
+    pub negative_cache_hits: u64,
+    /// Indexed like [`FetchOutcome::ALL`]
+    pub off_fetches: [u64; FetchOutcome::ALL.len()],
+}
+
+impl ProductLookupCounts {
+    pub fn off_fetches(&self, outcome: FetchOutcome) -> u64 {
+        self.off_fetches[outcome as usize]
+    }
+}
+
+pub fn product_lookup_counts() -> ProductLookupCounts {
+    ProductLookupCounts {
+        cache_hits: CACHE_HITS.load(Ordering::Relaxed),
+        negative_cache_hits: NEGATIVE_CACHE_HITS.load(Ordering::Relaxed),
+        off_fetches: FetchOutcome::ALL.map(|outcome| outcome.counter().load(Ordering::Relaxed)),
+    }
+}
+
+/// The counters in Prometheus text exposition format
+pub fn render(counts: &ProductLookupCounts) -> String {
+    let mut out = String::new();
+    let mut counter = |name: &str, help: &str, samples: &[(Option<&str>, u64)]| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (outcome, value) in samples {
+            match outcome {
+                Some(outcome) => {
+                    let _ = writeln!(out, "{}{{outcome=\"{}\"}} {}", name, outcome, value);
+                }
+                None => {
+                    let _ = writeln!(out, "{} {}", name, value);
+                }
+            }
+        }
+    };
+
+    counter(
+        "product_cache_hit_total",
+        "Product lookups answered from the database",
+        &[(None, counts.cache_hits)],
+    );
+    counter(
+        "product_negative_cache_hit_total",
+        "Product lookups answered 404 from a recent OpenFoodFacts miss",
+        &[(None, counts.negative_cache_hits)],
+    );
+    let fetches: Vec<(Option<&str>, u64)> = FetchOutcome::ALL
+        .iter()
+        .map(|&outcome| (Some(outcome.as_str()), counts.off_fetches(outcome)))
+        .collect();
+    counter(
+        "product_off_fetch_total",
+        "Product lookups that went to the product sources, by outcome",
+        &fetches,
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let counts = ProductLookupCounts { cache_hits: 7, negative_cache_hits: 2, off_fetches: [3, 1, 0, 4] };
+        let text = render(&counts);
+
+        assert!(text.contains("# TYPE product_cache_hit_total counter\nproduct_cache_hit_total 7\n"), "{}", text);
+        assert!(text.contains("\nproduct_negative_cache_hit_total 2\n"), "{}", text);
+        assert!(text.contains("\nproduct_off_fetch_total{outcome=\"found\"} 3\n"), "{}", text);
+        assert!(text.contains("\nproduct_off_fetch_total{outcome=\"not_found\"} 1\n"), "{}", text);
+        assert!(text.contains("\nproduct_off_fetch_total{outcome=\"failed\"} 0\n"), "{}", text);
+        assert!(text.contains("\nproduct_off_fetch_total{outcome=\"timeout\"} 4\n"), "{}", text);
+    }
+
+    #[test]
+    fn test_records_land_in_their_counter() {
+        let before = product_lookup_counts();
+        record_off_fetch(FetchOutcome::Failed);
+        record_negative_cache_hit();
+        let after = product_lookup_counts();
+
+        // Other tests count lookups concurrently, so only a lower bound holds
+        assert!(after.off_fetches(FetchOutcome::Failed) > before.off_fetches(FetchOutcome::Failed));
+        assert!(after.negative_cache_hits > before.negative_cache_hits);
+    }
+}
@@ -2,9 +2,13 @@ use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use chrono::{NaiveDateTime, NaiveDate};
 
-#[derive(Queryable, Serialize, Selectable)]
-#[diesel(table_name = crate::schema::products)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+use crate::clock::Clock;
+use crate::nutrition::{IngredientMacros, IngredientPortion};
+use crate::pagination::{paginate, PageRequest, Paginated};
+
+/// A stored food product. Loaded through [`ProductRow`], so `full_response` holds the OFF
+/// payload whether the row stored it as JSONB or gzip-compressed.
+#[derive(Serialize)]
 pub struct Product {
     pub id: i32,
     pub barcode: String,
@@ -21,10 +25,432 @@ pub struct Product {
     pub full_response: serde_json::Value,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub off_rev: Option<i32>,
+    pub last_verified_at: Option<NaiveDateTime>,
+    pub nutrient_levels: Option<serde_json::Value>,
+    pub labels: Option<serde_json::Value>,
+    pub diet: Option<serde_json::Value>,
+    pub data_source: Option<String>,
+    /// Normalized allergen slugs, e.g. `["peanuts"]`
+    pub allergen_tags: Option<serde_json::Value>,
+    /// Normalized "may contain" slugs, which OFF lists apart from allergens
+    pub trace_tags: Option<serde_json::Value>,
+    /// When ingredient analysis last completed
+    pub analyzed_at: Option<NaiveDateTime>,
+    /// Normalized brand slugs, e.g. `["simply-asia", "thai-kitchen"]`; `brands` keeps the display string
+    pub brand_tags: Option<serde_json::Value>,
+    /// Numeric package size in `product_quantity_unit`; `quantity` keeps the display string
+    pub product_quantity: Option<f64>,
+    pub product_quantity_unit: Option<String>,
+    /// Hash of the ingredient list last processed, see [`crate::off::ingredients_hash`]
+    pub ingredients_hash: Option<String>,
+    /// OFF photo URLs by kind (`front`, `ingredients`, `nutrition`), see [`crate::off::OffProduct::images`]
+    pub images: Option<serde_json::Value>,
+    /// Ingredient list read off the ingredients photo when OFF has none, see [`crate::ocr`]
+    pub ocr_ingredients_text: Option<String>,
 }
 
-#[derive(Insertable)]
-#[diesel(table_name = crate::schema::products)]
+/// A `products` row as stored, with the OFF payload in one of two columns
+#[derive(Queryable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ProductRow {
+    id: i32,
+    barcode: String,
+    product_name: Option<String>,
+    brands: Option<String>,
+    categories: Option<String>,
+    quantity: Option<String>,
+    image_url: Option<String>,
+    nutriscore_grade: Option<String>,
+    nova_group: Option<i32>,
+    ecoscore_grade: Option<String>,
+    ingredients_text: Option<String>,
+    allergens: Option<String>,
+    full_response: Option<serde_json::Value>,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    off_rev: Option<i32>,
+    last_verified_at: Option<NaiveDateTime>,
+    nutrient_levels: Option<serde_json::Value>,
+    labels: Option<serde_json::Value>,
+    diet: Option<serde_json::Value>,
+    data_source: Option<String>,
+    allergen_tags: Option<serde_json::Value>,
+    trace_tags: Option<serde_json::Value>,
+    analyzed_at: Option<NaiveDateTime>,
+    brand_tags: Option<serde_json::Value>,
+    product_quantity: Option<f64>,
+    product_quantity_unit: Option<String>,
+    full_response_gz: Option<Vec<u8>>,
+    ingredients_hash: Option<String>,
+    images: Option<serde_json::Value>,
+    ocr_ingredients_text: Option<String>,
+}
+
+impl<ST> Queryable<ST, diesel::pg::Pg> for Product
+where
+    ProductRow: Queryable<ST, diesel::pg::Pg>,
+{
+    type Row = <ProductRow as Queryable<ST, diesel::pg::Pg>>::Row;
+
+    fn build(row: Self::Row) -> diesel::deserialize::Result<Self> {
+        let row = ProductRow::build(row)?;
+        let full_response = crate::compression::stored_response(row.full_response, row.full_response_gz.as_deref())?;
+
+        Ok(Product {
+            id: row.id,
+            barcode: row.barcode,
+            product_name: row.product_name,
+            brands: row.brands,
+            categories: row.categories,
+            quantity: row.quantity,
+            image_url: row.image_url,
+            nutriscore_grade: row.nutriscore_grade,
+            nova_group: row.nova_group,
+            ecoscore_grade: row.ecoscore_grade,
+            ingredients_text: row.ingredients_text,
+            allergens: row.allergens,
+            full_response,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            off_rev: row.off_rev,
+            last_verified_at: row.last_verified_at,
+            nutrient_levels: row.nutrient_levels,
+            labels: row.labels,
+            diet: row.diet,
+            data_source: row.data_source,
+            allergen_tags: row.allergen_tags,
+            trace_tags: row.trace_tags,
+            analyzed_at: row.analyzed_at,
+            brand_tags: row.brand_tags,
+            product_quantity: row.product_quantity,
+            product_quantity_unit: row.product_quantity_unit,
+            ingredients_hash: row.ingredients_hash,
+            images: row.images,
+            ocr_ingredients_text: row.ocr_ingredients_text,
+        })
+    }
+}
+
+/// What [`Product::apply_refresh`] did with upstream data
+pub enum Refreshed {
+    /// The row now holds the new revision
+    Updated(Box<Product>),
+    /// Upstream still has the stored revision
+    Unchanged,
+    /// Another refresh got to the row first
+    ClaimedElsewhere,
+}
+
+/// Which stored products `GET /api/products` lists
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProductListFilter {
+    /// Case-insensitive substring of the `brands` display string or of one of the
+    /// product's `brand_tags` (matched in slug form, so "Ben & Jerry's" finds `ben-jerry-s`)
+    pub brand: Option<String>,
+    /// Exact grade as stored, see [`crate::off::normalize_grade`]
+    pub nutriscore: Option<String>,
+}
+
+impl ProductListFilter {
+    fn apply<'a>(
+        &self,
+        query: crate::schema::products::BoxedQuery<'a, diesel::pg::Pg>,
+    ) -> crate::schema::products::BoxedQuery<'a, diesel::pg::Pg> {
+        use crate::schema::products::dsl::*;
+
+        let mut query = query;
+        if let Some(brand) = &self.brand {
+            let slug = crate::off::brand_slug(brand);
+            query = if slug.is_empty() {
+                query.filter(brands.ilike(like_substring(brand)))
+            } else {
+                let tag_matches = diesel::dsl::sql::<diesel::sql_types::Bool>(
+                    "EXISTS (SELECT 1 FROM jsonb_array_elements_text(
+                        CASE WHEN jsonb_typeof(products.brand_tags) = 'array' THEN products.brand_tags ELSE '[]'::jsonb END
+                    ) AS tag WHERE tag ILIKE ",
+                )
+                .bind::<diesel::sql_types::Text, _>(like_substring(&slug))
+                .sql(")");
+                query.filter(brands.ilike(like_substring(brand)).or(tag_matches))
+            };
+        }
+        if let Some(grade) = &self.nutriscore {
+            query = query.filter(nutriscore_grade.eq(grade.clone()));
+        }
+        query
+    }
+}
+
+/// `LIKE` pattern matching `text` anywhere, with its own wildcards escaped
+fn like_substring(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+impl Product {
+    /// Up to `limit` stored products matching the filter, newest first, skipping the first `offset`
+    pub fn list(
+        filter: &ProductListFilter,
+        limit: i64,
+        offset: i64,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<Product>, diesel::result::Error> {
+        use crate::schema::products::dsl::*;
+
+        filter
+            .apply(products.into_boxed())
+            .order((created_at.desc(), id.desc()))
+            .limit(limit)
+            .offset(offset)
+            .load::<Product>(conn)
+    }
+
+    /// Store a newly fetched product with its first history entry. If another request
+    /// stored the same barcode first, its row is returned instead, with `false` for
+    /// "not inserted here" so the caller can leave follow-up work to whoever inserted it.
+    pub fn insert_or_get(
+        data: &NewProduct,
+        conn: &mut PgConnection,
+    ) -> Result<(Product, bool), diesel::result::Error> {
+        use crate::schema::products::dsl::*;
+
+        conn.transaction(|conn| {
+            let inserted = diesel::insert_into(products)
+                .values(data)
+                .on_conflict(barcode)
+                .do_nothing()
+                .get_result::<Product>(conn)
+                .optional()?;
+            match inserted {
+                Some(product) => {
+                    ProductHistory::capture(&product, conn)?;
+                    Ok((product, true))
+                }
+                None => Ok((products.filter(barcode.eq(&data.barcode)).first::<Product>(conn)?, false)),
+            }
+        })
+    }
+
+    /// Whether an incoming OpenFoodFacts revision matches the stored one,
+    /// meaning there is nothing new to write
+    pub fn is_unchanged_revision(&self, incoming_rev: Option<i32>) -> bool {
+        matches!((self.off_rev, incoming_rev), (Some(stored), Some(incoming)) if stored == incoming)
+    }
+
+    /// Package size in grams from the stored numeric quantity
+    pub fn package_grams(&self) -> Option<f64> {
+        let quantity = crate::quantity::ProductQuantity {
+            amount: self.product_quantity?,
+            unit: self.product_quantity_unit.clone()?,
+        };
+        quantity.grams()
+    }
+
+    /// Whether OFF's data names the ingredients, as text or as a parsed list
+    pub fn off_lists_ingredients(&self) -> bool {
+        crate::off::ingredients_hash(&self.full_response).is_some()
+            || self.full_response.get("ingredients").is_some_and(|list| list.is_array())
+    }
+
+    /// Product data to process ingredients from: the OFF payload, with the list read off
+    /// the ingredients photo standing in for `ingredients_text` when OFF has none
+    pub fn ingredient_data(&self) -> std::borrow::Cow<'_, serde_json::Value> {
+        match &self.ocr_ingredients_text {
+            Some(text) if !self.off_lists_ingredients() && self.full_response.is_object() => {
+                let mut data = self.full_response.clone();
+                data["ingredients_text"] = serde_json::Value::String(text.clone());
+                std::borrow::Cow::Owned(data)
+            }
+            _ => std::borrow::Cow::Borrowed(&self.full_response),
+        }
+    }
+
+    /// URL of the ingredients photo to OCR, when OFF lists no ingredients but has the photo
+    pub fn ocr_image_url(&self) -> Option<&str> {
+        if self.off_lists_ingredients() {
+            return None;
+        }
+        self.images.as_ref()?.get("ingredients")?.as_str()
+    }
+
+    /// Store the ingredient list recognized in the product's ingredients photo
+    pub fn store_ocr_ingredients(
+        product_id: i32,
+        text: &str,
+        conn: &mut PgConnection,
+    ) -> Result<Product, diesel::result::Error> {
+        use crate::schema::products::dsl::*;
+
+        diesel::update(products.find(product_id))
+            .set((ocr_ingredients_text.eq(text), updated_at.eq(diesel::dsl::now)))
+            .get_result(conn)
+    }
+
+    /// Record that ingredient analysis finished for the product
+    pub fn mark_analyzed(
+        product_id: i32,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::products::dsl::*;
+
+        diesel::update(products.filter(id.eq(product_id)))
+            .set(analyzed_at.eq(diesel::dsl::now))
+            .execute(conn)
+    }
+
+    /// Whether the stored copy is older than `ttl`: it was last checked against upstream
+    /// (or, if never, written) before `now - ttl`
+    pub fn is_stale(&self, now: NaiveDateTime, ttl: chrono::Duration) -> bool {
+        self.last_verified_at.unwrap_or(self.updated_at) < now - ttl
+    }
+
+    /// Bring a stored product up to date with upstream data fetched from `started_at`. An
+    /// unchanged OFF revision is only marked verified, and a product another refresh
+    /// claimed meanwhile (see [`Product::claim_refresh`]) is left alone. A new revision is
+    /// written over the row and captured in its history.
+    pub fn apply_refresh(
+        &self,
+        data: &NewProduct,
+        started_at: NaiveDateTime,
+        now: NaiveDateTime,
+        conn: &mut PgConnection,
+    ) -> Result<Refreshed, diesel::result::Error> {
+        if self.is_unchanged_revision(data.off_rev) {
+            Product::mark_verified(self.id, conn)?;
+            return Ok(Refreshed::Unchanged);
+        }
+
+        conn.transaction(|conn| {
+            if !Product::claim_refresh(self.id, started_at, now, conn)? {
+                return Ok(Refreshed::ClaimedElsewhere);
+            }
+            let product = Product::refresh(self.id, data, conn)?;
+            ProductHistory::capture(&product, conn)?;
+            Ok(Refreshed::Updated(Box::new(product)))
+        })
+    }
+
+    /// Bump `last_verified_at` without touching any product data
+    pub fn mark_verified(
+        product_id: i32,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::products::dsl::*;
+
+        diesel::update(products.filter(id.eq(product_id)))
+            .set(last_verified_at.eq(diesel::dsl::now))
+            .execute(conn)
+    }
+
+    /// Claim the refresh of a product nobody has verified since `verified_before`, by
+    /// stamping `last_verified_at` with `now` in the same statement that checks it. Of two
+    /// refreshes racing on one product only the first gets `true`; the other should leave
+    /// the row alone rather than write the same data again.
+    pub fn claim_refresh(
+        product_id: i32,
+        verified_before: NaiveDateTime,
+        now: NaiveDateTime,
+        conn: &mut PgConnection,
+    ) -> Result<bool, diesel::result::Error> {
+        use crate::schema::products::dsl::*;
+
+        let claimed = diesel::update(
+            products
+                .find(product_id)
+                .filter(last_verified_at.is_null().or(last_verified_at.lt(verified_before))),
+        )
+        .set(last_verified_at.eq(now))
+        .execute(conn)?;
+        Ok(claimed == 1)
+    }
+
+    /// Overwrite a stored product with newer upstream data, keeping its id, `created_at`,
+    /// `ingredients_hash` and `ocr_ingredients_text`
+    pub fn refresh(
+        product_id: i32,
+        data: &NewProduct,
+        conn: &mut PgConnection,
+    ) -> Result<Product, diesel::result::Error> {
+        use crate::schema::products::dsl::*;
+
+        diesel::update(products.find(product_id))
+            .set((
+                data.to_row(crate::config::get().compress_full_response),
+                updated_at.eq(diesel::dsl::now),
+                last_verified_at.eq(diesel::dsl::now),
+            ))
+            .get_result(conn)
+    }
+
+    /// Start rebuilding the product's ingredient links: lock the product row and drop its
+    /// links, unless `hash` (see [`crate::off::ingredients_hash`]) is what was processed last
+    /// time and nothing `force`s it. Returns whether to go ahead; `false` for a missing
+    /// product too. Without a hash there is nothing to compare, so they are always rebuilt.
+    /// Call inside the transaction that relinks the ingredients and then calls
+    /// [`Product::finish_ingredient_processing`].
+    pub fn begin_ingredient_processing(
+        product_id: i32,
+        hash: Option<&str>,
+        force: bool,
+        conn: &mut PgConnection,
+    ) -> Result<bool, diesel::result::Error> {
+        use crate::schema::{product_ingredients, products};
+
+        let Some(processed) = products::table
+            .find(product_id)
+            .select(products::ingredients_hash)
+            .for_update()
+            .first::<Option<String>>(conn)
+            .optional()?
+        else {
+            return Ok(false);
+        };
+        if !force && hash.is_some() && processed.as_deref() == hash {
+            return Ok(false);
+        }
+
+        diesel::delete(product_ingredients::table.filter(product_ingredients::product_id.eq(product_id)))
+            .execute(conn)?;
+        Ok(true)
+    }
+
+    /// Record `hash` as the product's processed ingredient list, in the transaction that
+    /// linked its ingredients
+    pub fn finish_ingredient_processing(
+        product_id: i32,
+        hash: Option<&str>,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::products;
+
+        diesel::update(products::table.find(product_id))
+            .set(products::ingredients_hash.eq(hash))
+            .execute(conn)
+    }
+
+    /// Undo [`finish_ingredient_processing`](Product::finish_ingredient_processing) when the
+    /// ingredients it linked couldn't all be queued for creation, so the next pass runs again.
+    /// A hash recorded since by another run is left alone.
+    pub fn forget_ingredients_hash(
+        product_id: i32,
+        hash: Option<&str>,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::products;
+
+        diesel::update(
+            products::table
+                .find(product_id)
+                .filter(products::ingredients_hash.is_not_distinct_from(hash)),
+        )
+        .set(products::ingredients_hash.eq(None::<String>))
+        .execute(conn)
+    }
+}
+
+/// A product to store. Inserted through [`NewProductRow`], which puts `full_response` in
+/// the column COMPRESS_FULL_RESPONSE selects.
 pub struct NewProduct {
     pub barcode: String,
     pub product_name: Option<String>,
@@ -38,15 +464,308 @@ pub struct NewProduct {
     pub ingredients_text: Option<String>,
     pub allergens: Option<String>,
     pub full_response: serde_json::Value,
+    pub off_rev: Option<i32>,
+    pub nutrient_levels: Option<serde_json::Value>,
+    pub labels: Option<serde_json::Value>,
+    pub diet: Option<serde_json::Value>,
+    /// Name of the product source the data came from (see `sources`)
+    pub data_source: Option<String>,
+    pub allergen_tags: Option<serde_json::Value>,
+    pub trace_tags: Option<serde_json::Value>,
+    pub brand_tags: Option<serde_json::Value>,
+    pub product_quantity: Option<f64>,
+    pub product_quantity_unit: Option<String>,
+    pub images: Option<serde_json::Value>,
+}
+
+/// Also the changeset of a refresh, which must clear fields upstream no longer sends
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::products)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewProductRow<'a> {
+    barcode: &'a str,
+    product_name: Option<&'a str>,
+    brands: Option<&'a str>,
+    categories: Option<&'a str>,
+    quantity: Option<&'a str>,
+    image_url: Option<&'a str>,
+    nutriscore_grade: Option<&'a str>,
+    nova_group: Option<i32>,
+    ecoscore_grade: Option<&'a str>,
+    ingredients_text: Option<&'a str>,
+    allergens: Option<&'a str>,
+    full_response: Option<serde_json::Value>,
+    off_rev: Option<i32>,
+    nutrient_levels: Option<&'a serde_json::Value>,
+    labels: Option<&'a serde_json::Value>,
+    diet: Option<&'a serde_json::Value>,
+    data_source: Option<&'a str>,
+    allergen_tags: Option<&'a serde_json::Value>,
+    trace_tags: Option<&'a serde_json::Value>,
+    brand_tags: Option<&'a serde_json::Value>,
+    product_quantity: Option<f64>,
+    product_quantity_unit: Option<&'a str>,
+    full_response_gz: Option<Vec<u8>>,
+    images: Option<&'a serde_json::Value>,
+}
+
+impl NewProduct {
+    fn to_row(&self, compress: bool) -> NewProductRow<'_> {
+        let (full_response, full_response_gz) = crate::compression::to_columns(&self.full_response, compress);
+
+        NewProductRow {
+            barcode: &self.barcode,
+            product_name: self.product_name.as_deref(),
+            brands: self.brands.as_deref(),
+            categories: self.categories.as_deref(),
+            quantity: self.quantity.as_deref(),
+            image_url: self.image_url.as_deref(),
+            nutriscore_grade: self.nutriscore_grade.as_deref(),
+            nova_group: self.nova_group,
+            ecoscore_grade: self.ecoscore_grade.as_deref(),
+            ingredients_text: self.ingredients_text.as_deref(),
+            allergens: self.allergens.as_deref(),
+            full_response,
+            off_rev: self.off_rev,
+            nutrient_levels: self.nutrient_levels.as_ref(),
+            labels: self.labels.as_ref(),
+            diet: self.diet.as_ref(),
+            data_source: self.data_source.as_deref(),
+            allergen_tags: self.allergen_tags.as_ref(),
+            trace_tags: self.trace_tags.as_ref(),
+            brand_tags: self.brand_tags.as_ref(),
+            product_quantity: self.product_quantity,
+            product_quantity_unit: self.product_quantity_unit.as_deref(),
+            full_response_gz,
+            images: self.images.as_ref(),
+        }
+    }
+}
+
+impl<'a> Insertable<crate::schema::products::table> for &'a NewProduct {
+    type Values = <NewProductRow<'a> as Insertable<crate::schema::products::table>>::Values;
+
+    fn values(self) -> Self::Values {
+        self.to_row(crate::config::get().compress_full_response).values()
+    }
 }
 
+/// OpenFoodFacts product response, normalized to at most one product object.
+///
+/// The v2 product endpoint returns `product` as an object, but some query forms return
+/// an array or the plural `products`; the first product object is taken in those cases.
 #[derive(Deserialize)]
+#[serde(from = "RawOpenFoodFactsResponse")]
 pub struct OpenFoodFactsResponse {
     pub status: i32,
+    #[allow(dead_code)]
     pub code: Option<String>,
     pub product: Option<serde_json::Value>,
 }
 
+#[derive(Deserialize)]
+struct RawOpenFoodFactsResponse {
+    #[serde(default)]
+    status: serde_json::Value,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    product: serde_json::Value,
+    #[serde(default)]
+    products: serde_json::Value,
+}
+
+impl From<RawOpenFoodFactsResponse> for OpenFoodFactsResponse {
+    fn from(raw: RawOpenFoodFactsResponse) -> Self {
+        let product = first_product(raw.product).or_else(|| first_product(raw.products));
+
+        // Numeric in v2, "success"/"failure" strings in v3; absent in search-style responses
+        let status = match &raw.status {
+            serde_json::Value::Number(n) => n.as_i64().and_then(|n| i32::try_from(n).ok()).unwrap_or(0),
+            serde_json::Value::String(s) if s.starts_with("success") => 1,
+            serde_json::Value::Null if product.is_some() => 1,
+            _ => 0,
+        };
+
+        OpenFoodFactsResponse {
+            status,
+            code: raw.code,
+            product,
+        }
+    }
+}
+
+fn first_product(value: serde_json::Value) -> Option<serde_json::Value> {
+    match value {
+        serde_json::Value::Object(_) => Some(value),
+        serde_json::Value::Array(items) => items.into_iter().find(|item| item.is_object()),
+        _ => None,
+    }
+}
+
+/// Snapshot of a product's OpenFoodFacts payload, captured each time it is stored
+#[derive(Queryable, Serialize, Selectable, Debug)]
+#[diesel(table_name = crate::schema::product_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ProductHistory {
+    pub id: i32,
+    pub product_id: i32,
+    pub barcode: String,
+    pub off_rev: Option<i32>,
+    pub full_response: serde_json::Value,
+    pub captured_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::product_history)]
+pub struct NewProductHistory {
+    pub product_id: i32,
+    pub barcode: String,
+    pub off_rev: Option<i32>,
+    pub full_response: serde_json::Value,
+}
+
+impl ProductHistory {
+    /// Record the product's current payload as a new history entry
+    pub fn capture(product: &Product, conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+        let entry = NewProductHistory {
+            product_id: product.id,
+            barcode: product.barcode.clone(),
+            off_rev: product.off_rev,
+            full_response: product.full_response.clone(),
+        };
+
+        diesel::insert_into(crate::schema::product_history::table)
+            .values(&entry)
+            .execute(conn)
+    }
+}
+
+/// Link between a product and one of its ingredients, with the ingredient's label
+/// position and estimated share of the product
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::product_ingredients)]
+pub struct NewProductIngredient {
+    pub product_id: i32,
+    pub ingredient_id: i32,
+    /// 1-based position in the ingredient list
+    pub rank: i32,
+    pub percent_estimate: Option<f32>,
+    /// "percent", "percent_estimate" or "rank" (see `off::PercentSource`)
+    pub percent_source: Option<String>,
+}
+
+impl NewProductIngredient {
+    /// Store the link; an ingredient listed twice keeps its first (highest-ranked) entry
+    pub fn link(&self, conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(crate::schema::product_ingredients::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .execute(conn)
+    }
+}
+
+/// Record of the last OpenFoodFacts lookup for a barcode, used as a negative cache
+#[derive(Queryable, Serialize, Selectable, Debug)]
+#[diesel(table_name = crate::schema::product_lookups)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ProductLookup {
+    pub barcode: String,
+    pub found: bool,
+    pub checked_at: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = crate::schema::product_lookups)]
+pub struct NewProductLookup {
+    pub barcode: String,
+    pub found: bool,
+    pub checked_at: NaiveDateTime,
+}
+
+impl ProductLookup {
+    /// Whether this is a negative lookup still within its TTL, so OFF shouldn't be asked again
+    pub fn is_recent_miss(&self, clock: &dyn Clock, ttl: chrono::Duration) -> bool {
+        !self.found && clock.now() - self.checked_at < ttl
+    }
+
+    pub fn find(
+        lookup_barcode: &str,
+        conn: &mut PgConnection,
+    ) -> Result<Option<ProductLookup>, diesel::result::Error> {
+        use crate::schema::product_lookups::dsl::*;
+
+        product_lookups
+            .filter(barcode.eq(lookup_barcode))
+            .first::<ProductLookup>(conn)
+            .optional()
+    }
+
+    /// Record the outcome of an OpenFoodFacts lookup, replacing any previous result
+    pub fn record(
+        lookup_barcode: &str,
+        was_found: bool,
+        clock: &dyn Clock,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::product_lookups::dsl::*;
+
+        let lookup = NewProductLookup {
+            barcode: lookup_barcode.to_string(),
+            found: was_found,
+            checked_at: clock.now(),
+        };
+
+        diesel::insert_into(product_lookups)
+            .values(&lookup)
+            .on_conflict(barcode)
+            .do_update()
+            .set(&lookup)
+            .execute(conn)
+    }
+}
+
+/// What one USDA backfill run did: how many ingredients it picked and how many got macros
+#[derive(Queryable, Serialize, Selectable, Debug, PartialEq)]
+#[diesel(table_name = crate::schema::usda_backfill_runs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UsdaBackfillRun {
+    pub id: i32,
+    /// Triggered through `POST /api/admin/usda-backfill` rather than scheduled
+    pub manual: bool,
+    pub candidates: i32,
+    pub updated: i32,
+    pub finished_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::usda_backfill_runs)]
+pub struct NewUsdaBackfillRun {
+    pub manual: bool,
+    pub candidates: i32,
+    pub updated: i32,
+}
+
+impl UsdaBackfillRun {
+    pub fn record(run: &NewUsdaBackfillRun, conn: &mut PgConnection) -> Result<UsdaBackfillRun, diesel::result::Error> {
+        diesel::insert_into(crate::schema::usda_backfill_runs::table)
+            .values(run)
+            .returning(UsdaBackfillRun::as_returning())
+            .get_result(conn)
+    }
+
+    /// The last `limit` runs, most recent first
+    pub fn recent(limit: i64, conn: &mut PgConnection) -> Result<Vec<UsdaBackfillRun>, diesel::result::Error> {
+        use crate::schema::usda_backfill_runs::dsl::*;
+
+        usda_backfill_runs
+            .order((finished_at.desc(), id.desc()))
+            .limit(limit)
+            .select(UsdaBackfillRun::as_select())
+            .load(conn)
+    }
+}
+
 #[derive(Queryable, Serialize, Selectable, Debug)]
 #[diesel(table_name = crate::schema::ingredients)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -83,9 +802,194 @@ pub struct Ingredient {
     pub gram_trans_fat_per_gram: Option<f32>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub usda_searched_at: Option<NaiveDateTime>,
+    /// USDA FoodData Central id of the food the macros were taken from
+    pub fdc_id: Option<i32>,
+    /// Raw matched USDA food, served by `/api/ingredients/{id}/usda-raw`
+    #[serde(skip_serializing)]
+    pub usda_food: Option<serde_json::Value>,
+    /// Lowercased, trimmed, whitespace-collapsed `name`; unique, and maintained by Postgres
+    pub canonical_name: String,
+    /// Curated by hand (`PATCH /api/ingredients/{id}`), so enrichment must not overwrite it
+    pub manually_verified: bool,
+    /// CreateIngredientJob has enqueued the sub-ingredients from its USDA ingredient statement
+    pub sub_ingredients_processed: bool,
+    /// USDA's best match was too weak to take macros from (see MIN_USDA_MATCH_CONFIDENCE),
+    /// so the ingredient awaits a curator
+    pub needs_review: bool,
+    /// The raw USDA food that was too weak a match, listed by `/api/ingredients/review-queue`
+    #[serde(skip_serializing)]
+    pub usda_candidate: Option<serde_json::Value>,
+    /// USDA's last answer (at `usda_searched_at`) was that it has no match
+    pub usda_no_match: bool,
 }
 
-#[derive(Insertable)]
+/// Curator correction for an ingredient's nutrition and contaminant data. Omitted fields
+/// are left as they are; an explicit `null` clears the column.
+#[derive(Deserialize, AsChangeset, Debug, Default, Clone, PartialEq)]
+#[diesel(table_name = crate::schema::ingredients)]
+#[serde(deny_unknown_fields)]
+pub struct IngredientPatch {
+    #[serde(default, deserialize_with = "present")]
+    pub gram_protein_per_gram: Option<Option<f32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub gram_carbs_per_gram: Option<Option<f32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub gram_fat_per_gram: Option<Option<f32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub gram_fiber_per_gram: Option<Option<f32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub gram_trans_fat_per_gram: Option<Option<f32>>,
+    #[serde(default, deserialize_with = "present")]
+    pub vitamins: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub minerals: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub essential_fatty_acids: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub essential_amino_acids: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub heavy_metals: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub micro_plastics: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub industrial_chemicals: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub pesticides: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub hormones: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub antibiotics: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub beta_agonists: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub antiparasitics: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub carcinogens: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub natural_toxins: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub radiological: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub historical_issues: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub fraudulent_ingredients: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub dyes: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub emulsifiers: Option<Option<serde_json::Value>>,
+    #[serde(default, deserialize_with = "present")]
+    pub preservatives: Option<Option<serde_json::Value>>,
+    /// `false` dismisses a review without new macros (no USDA food fits), `true` asks for one
+    #[diesel(skip_update)]
+    pub needs_review: Option<bool>,
+    /// Take the ingredient's weak USDA match after all: its food, fdc id and any macros the
+    /// patch doesn't set are stored, and the review is settled
+    #[serde(default)]
+    #[diesel(skip_update)]
+    pub accept_usda_candidate: bool,
+}
+
+/// Why a curator's patch wasn't applied
+#[derive(Debug)]
+pub enum PatchError {
+    Db(diesel::result::Error),
+    /// `accept_usda_candidate` on an ingredient with no USDA candidate
+    NoUsdaCandidate,
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::Db(e) => write!(f, "Database error: {}", e),
+            PatchError::NoUsdaCandidate => write!(f, "Ingredient has no USDA candidate to accept"),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for PatchError {
+    fn from(e: diesel::result::Error) -> Self {
+        PatchError::Db(e)
+    }
+}
+
+/// Deserialize a field that is present in the body, `null` included, as `Some`. With
+/// `#[serde(default)]`, a missing field stays `None`, so the two can be told apart.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl IngredientPatch {
+    /// Patch setting one contaminant column, `None` unless `category` is one of
+    /// `safety::CONTAMINANT_FIELDS`
+    pub fn contaminant(category: &str, findings: serde_json::Value) -> Option<Self> {
+        let mut patch = IngredientPatch::default();
+        let field = match category {
+            "heavy_metals" => &mut patch.heavy_metals,
+            "micro_plastics" => &mut patch.micro_plastics,
+            "industrial_chemicals" => &mut patch.industrial_chemicals,
+            "pesticides" => &mut patch.pesticides,
+            "hormones" => &mut patch.hormones,
+            "antibiotics" => &mut patch.antibiotics,
+            "beta_agonists" => &mut patch.beta_agonists,
+            "antiparasitics" => &mut patch.antiparasitics,
+            "carcinogens" => &mut patch.carcinogens,
+            "natural_toxins" => &mut patch.natural_toxins,
+            "radiological" => &mut patch.radiological,
+            _ => return None,
+        };
+        *field = Some(Some(findings));
+        Some(patch)
+    }
+
+    /// Whether the patch sets any of the per-gram macros
+    pub fn sets_macros(&self) -> bool {
+        self.gram_protein_per_gram.is_some()
+            || self.gram_carbs_per_gram.is_some()
+            || self.gram_fat_per_gram.is_some()
+            || self.gram_fiber_per_gram.is_some()
+    }
+
+    /// Whether the patch names no field at all
+    pub fn is_empty(&self) -> bool {
+        *self == IngredientPatch::default()
+    }
+
+    /// Reject empty patches, which would only mark the ingredient verified, and values no
+    /// real food has: per-gram macros must lie within 0..=1
+    pub fn validate(&self) -> Result<(), String> {
+        if self.is_empty() {
+            return Err("Patch must set at least one field".to_string());
+        }
+        if self.accept_usda_candidate && self.needs_review == Some(true) {
+            return Err("accept_usda_candidate settles the review, so needs_review can't be true".to_string());
+        }
+
+        let macros = [
+            ("gram_protein_per_gram", self.gram_protein_per_gram),
+            ("gram_carbs_per_gram", self.gram_carbs_per_gram),
+            ("gram_fat_per_gram", self.gram_fat_per_gram),
+            ("gram_fiber_per_gram", self.gram_fiber_per_gram),
+            ("gram_trans_fat_per_gram", self.gram_trans_fat_per_gram),
+        ];
+
+        for (field, value) in macros {
+            if let Some(Some(value)) = value
+                && !(0.0..=1.0).contains(&value)
+            {
+                return Err(format!("{} must be between 0 and 1", field));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Insertable, Default)]
 #[diesel(table_name = crate::schema::ingredients)]
 pub struct NewIngredient {
     pub name: String,
@@ -94,114 +998,912 @@ pub struct NewIngredient {
     pub gram_carbs_per_gram: Option<f32>,
     pub gram_fat_per_gram: Option<f32>,
     pub gram_fiber_per_gram: Option<f32>,
+    pub gram_trans_fat_per_gram: Option<f32>,
+    pub fdc_id: Option<i32>,
+    pub usda_food: Option<serde_json::Value>,
+    pub needs_review: bool,
+    pub usda_candidate: Option<serde_json::Value>,
 }
 
-impl Ingredient {
-    /// Find ingredient by name (case-insensitive) in database only
-    /// Returns Option<i32> - ingredient ID if found, None if not found
-    pub fn find_in_db(
-        ingredient_name: &str,
-        conn: &mut PgConnection,
-    ) -> Result<Option<i32>, diesel::result::Error> {
-        use crate::schema::ingredients::dsl::*;
-        use diesel::dsl::sql;
-        use diesel::sql_types::Bool;
-
-        // Try to find with case-insensitive search
-        let found = ingredients
-            .filter(sql::<Bool>(&format!("LOWER(name) = LOWER('{}')", ingredient_name.replace("'", "''"))))
-            .select(id)
-            .first::<i32>(conn)
-            .optional()?;
-
-        if let Some(ingredient_id) = found {
-            log::info!("Found existing ingredient: {} (ID: {})", ingredient_name, ingredient_id);
+#[cfg(test)]
+impl NewIngredient {
+    /// An ingredient with only a name; set other fields with `..NewIngredient::named(name)`
+    pub fn named(name: &str) -> Self {
+        NewIngredient {
+            name: name.to_string(),
+            ..Default::default()
         }
+    }
 
-        Ok(found)
+    /// Insert the ingredient for a test, returning its id
+    pub fn seed(&self, conn: &mut PgConnection) -> i32 {
+        diesel::insert_into(crate::schema::ingredients::table)
+            .values(self)
+            .returning(crate::schema::ingredients::id)
+            .get_result(conn)
+            .expect("Failed to seed ingredient")
     }
+}
 
-    /// Find ingredient by name (case-insensitive) or enqueue job to create it
-    /// Returns Option<i32> - ingredient ID if found, None if enqueued for creation
-    pub fn find_or_enqueue_for_creation(
-        ingredient_name: &str,
-        conn: &mut PgConnection,
-    ) -> Result<Option<i32>, diesel::result::Error> {
-        use crate::schema::ingredients::dsl::*;
-        use diesel::dsl::sql;
-        use diesel::sql_types::Bool;
+/// Inclusive per-gram bounds for one macro; `None` leaves that side open
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MacroRange {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
 
-        // Try to find with case-insensitive search using ILIKE
-        let found = ingredients
-            .filter(sql::<Bool>(&format!("LOWER(name) = LOWER('{}')", ingredient_name.replace("'", "''"))))
-            .select(id)
-            .first::<i32>(conn)
-            .optional()?;
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MacroSort {
+    #[default]
+    Id,
+    Name,
+    Protein,
+    Carbs,
+    Fat,
+    Fiber,
+}
 
-        if let Some(ingredient_id) = found {
-            log::info!("Found existing ingredient: {} (ID: {})", ingredient_name, ingredient_id);
-            return Ok(Some(ingredient_id));
-        }
-
-        // Not found - enqueue job to create it
-        log::info!("Ingredient '{}' not found, enqueueing creation job", ingredient_name);
-
-        // Import job queue dependencies
-        use fang::asynk::async_queue::{AsyncQueue, AsyncQueueable};
-        use fang::NoTls;
-        use crate::jobs::CreateIngredientJob;
-
-        // Spawn async task to enqueue job (don't block the current thread)
-        let ingredient_name_clone = ingredient_name.to_string();
-        tokio::spawn(async move {
-            let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-
-            let mut queue = AsyncQueue::builder()
-                .uri(database_url)
-                .max_pool_size(1_u32)  // Use small pool size to avoid overwhelming DB
-                .build();
-
-            // Use timeout for connection to avoid blocking forever
-            let connect_result = tokio::time::timeout(
-                std::time::Duration::from_secs(5),
-                queue.connect(NoTls)
-            ).await;
-
-            match connect_result {
-                Ok(Ok(_)) => {
-                    let job = CreateIngredientJob {
-                        name: ingredient_name_clone.clone(),
-                    };
-
-                    match queue.insert_task(&job).await {
-                        Ok(_) => {
-                            log::info!("Successfully enqueued CreateIngredientJob for '{}'", ingredient_name_clone);
-                        }
-                        Err(e) => {
-                            log::error!("Failed to enqueue job for '{}': {:?}", ingredient_name_clone, e);
-                        }
-                    }
-                }
-                Ok(Err(e)) => {
-                    log::error!("Failed to connect to job queue for '{}': {:?}", ingredient_name_clone, e);
-                }
-                Err(_) => {
-                    log::error!("Timeout connecting to job queue for '{}'", ingredient_name_clone);
-                }
-            }
-        });
+/// `canonical_name` equals `ingredient_name` canonicalized with the same expression as the
+/// generated column, so "Sugar " finds "sugar". The name is bound, never spliced into SQL.
+fn canonical_name_is(
+    ingredient_name: &str,
+) -> Box<dyn BoxableExpression<crate::schema::ingredients::table, diesel::pg::Pg, SqlType = diesel::sql_types::Bool>> {
+    use crate::schema::ingredients::dsl::*;
+    use diesel::dsl::sql;
+    use diesel::sql_types::Text;
 
-        Ok(None)
-    }
+    Box::new(canonical_name.eq(sql::<Text>("lower(btrim(regexp_replace(")
+        .bind::<Text, _>(ingredient_name.to_string())
+        .sql(", '\\s+', ' ', 'g')))")))
 }
 
-// ============= Non-Food Products =============
+/// Macro thresholds for ingredient search
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IngredientMacroFilter {
+    /// Only the ingredient with this name, ignoring case and extra whitespace
+    pub name: Option<String>,
+    pub protein: MacroRange,
+    pub carbs: MacroRange,
+    pub fat: MacroRange,
+    pub fiber: MacroRange,
+    /// Let ingredients with no value for a bounded macro through instead of skipping them
+    pub include_unknown: bool,
+    pub sort: MacroSort,
+    pub descending: bool,
+}
 
-#[derive(Queryable, Serialize, Selectable, Debug)]
-#[diesel(table_name = crate::schema::products_non_food)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
-pub struct ProductNonFood {
-    pub id: i32,
+/// Which ingredients a bulk re-enrichment searches USDA for again. Conditions combine;
+/// curated (`manually_verified`) ingredients never match.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReenrichFilter {
+    /// Only ingredients no USDA food was taken from (`fdc_id` unset)
+    #[serde(default)]
+    pub without_fdc_id: bool,
+    /// Only ingredients with none of the four macros
+    #[serde(default)]
+    pub without_macros: bool,
+}
+
+impl ReenrichFilter {
+    /// Whether the filter narrows anything down, rather than matching every ingredient
+    pub fn is_empty(&self) -> bool {
+        !self.without_fdc_id && !self.without_macros
+    }
+}
+
+/// Add min/max conditions for one nullable macro column to a boxed ingredients query
+macro_rules! filter_macro_range {
+    ($query:expr, $column:expr, $range:expr, $include_unknown:expr) => {{
+        let mut query = $query;
+        if let Some(min) = $range.min {
+            query = if $include_unknown {
+                query.filter($column.ge(min).or($column.is_null()))
+            } else {
+                query.filter($column.ge(min))
+            };
+        }
+        if let Some(max) = $range.max {
+            query = if $include_unknown {
+                query.filter($column.le(max).or($column.is_null()))
+            } else {
+                query.filter($column.le(max))
+            };
+        }
+        query
+    }};
+}
+
+impl IngredientMacroFilter {
+    fn apply<'a>(
+        &self,
+        query: crate::schema::ingredients::BoxedQuery<'a, diesel::pg::Pg>,
+    ) -> crate::schema::ingredients::BoxedQuery<'a, diesel::pg::Pg> {
+        use crate::schema::ingredients::dsl::*;
+
+        let query = match &self.name {
+            Some(ingredient_name) => query.filter(canonical_name_is(ingredient_name)),
+            None => query,
+        };
+        let query = filter_macro_range!(query, gram_protein_per_gram, self.protein, self.include_unknown);
+        let query = filter_macro_range!(query, gram_carbs_per_gram, self.carbs, self.include_unknown);
+        let query = filter_macro_range!(query, gram_fat_per_gram, self.fat, self.include_unknown);
+        filter_macro_range!(query, gram_fiber_per_gram, self.fiber, self.include_unknown)
+    }
+}
+
+/// Ingredient nothing refers to any more, reported (or removed) by the orphan vacuum
+#[derive(QueryableByName, Serialize, Debug, PartialEq)]
+pub struct OrphanIngredient {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub id: i32,
+    #[diesel(sql_type = diesel::sql_types::Varchar)]
+    pub name: String,
+}
+
+/// An ingredient is an orphan when it has no parents or children (in either direction),
+/// no aliases, and no stored product lists it by name. Products aren't linked to
+/// ingredients by id, so their OFF `ingredients` list and `ingredients_text` are checked;
+/// a substring hit in the text keeps the ingredient, erring on the side of not deleting.
+const ORPHAN_INGREDIENT_CONDITION: &str = "
+    cardinality(i.parent_ingredients) = 0
+    AND cardinality(i.sub_ingredients) = 0
+    AND i.created_at < $1
+    AND NOT EXISTS (
+        SELECT 1 FROM ingredients other
+        WHERE i.id = ANY(other.sub_ingredients) OR i.id = ANY(other.parent_ingredients)
+    )
+    AND NOT EXISTS (SELECT 1 FROM ingredient_aliases a WHERE a.ingredient_id = i.id)
+    AND NOT EXISTS (SELECT 1 FROM product_ingredients pi WHERE pi.ingredient_id = i.id)
+    AND NOT EXISTS (
+        SELECT 1 FROM products p
+        WHERE strpos(lower(p.ingredients_text), lower(i.name)) > 0
+            OR EXISTS (
+                SELECT 1
+                FROM jsonb_array_elements(
+                    CASE WHEN jsonb_typeof(p.full_response->'ingredients') = 'array'
+                        THEN p.full_response->'ingredients'
+                        ELSE '[]'::jsonb
+                    END
+                ) AS elem
+                WHERE lower(trim(elem->>'text')) = lower(i.name)
+            )
+    )";
+
+/// Products stored while one of their ingredients was still waiting to be created: they
+/// list the ingredient by name (as in `ORPHAN_INGREDIENT_CONDITION`) but have no link to it.
+/// A cheap prefilter on the canonical name `$2`, matching a list entry's `text` or its
+/// taxonomy `id` spelled out (`en:sea-salt` as "sea salt"), or a substring of
+/// `ingredients_text` for compressed payloads; the caller checks each hit. Paged by id
+/// after `$4`, since hits the check rejects don't count toward the `$3` wanted.
+const UNLINKED_LISTING_PRODUCTS: &str = "
+    SELECT p.id, p.full_response, p.full_response_gz
+    FROM products p
+    WHERE p.id > $4
+    AND NOT EXISTS (
+        SELECT 1 FROM product_ingredients pi WHERE pi.product_id = p.id AND pi.ingredient_id = $1
+    )
+    AND (
+        strpos(lower(p.ingredients_text), $2) > 0
+        OR EXISTS (
+            SELECT 1
+            FROM jsonb_array_elements(
+                CASE WHEN jsonb_typeof(p.full_response->'ingredients') = 'array'
+                    THEN p.full_response->'ingredients'
+                    ELSE '[]'::jsonb
+                END
+            ) AS elem
+            WHERE trim(regexp_replace(lower(elem->>'text'), '\\s+', ' ', 'g')) = $2
+            OR trim(regexp_replace(regexp_replace(lower(elem->>'id'), '^[a-z]{2,3}:', ''), '[-\\s]+', ' ', 'g')) = $2
+        )
+    )
+    ORDER BY p.id
+    LIMIT $3";
+
+#[derive(QueryableByName)]
+struct ListingProduct {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    id: i32,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Jsonb>)]
+    full_response: Option<serde_json::Value>,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Binary>)]
+    full_response_gz: Option<Vec<u8>>,
+}
+
+/// Name as the `canonical_name` column stores it: lowercase, whitespace runs collapsed, trimmed
+pub fn canonicalize_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Width of `ingredients.name` and `ingredients.canonical_name` (VARCHAR(500))
+pub const INGREDIENT_NAME_COLUMN_LEN: usize = 500;
+
+/// Whether `name` is short enough to become an ingredient, counted in characters as it is
+/// stored: `name` is inserted as given, and its canonical form (which lowercasing can
+/// lengthen) must fit as well. Oversized names are logged and skipped rather than
+/// truncated, since a cut-off blob would still be a bogus ingredient.
+pub fn ingredient_name_fits(name: &str, max_len: usize) -> bool {
+    let len = name.chars().count().max(canonicalize_name(name).chars().count());
+    if len <= max_len {
+        return true;
+    }
+
+    let preview: String = name.trim().chars().take(40).collect();
+    log::warn!("Skipping ingredient name of {} characters (max {}): '{}...'", len, max_len, preview);
+    false
+}
+
+/// Synonym ("ascorbic acid") that resolves to a canonical ingredient ("Vitamin C")
+#[derive(Queryable, Serialize, Selectable, Debug)]
+#[diesel(table_name = crate::schema::ingredient_aliases)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct IngredientAlias {
+    pub alias: String,
+    pub ingredient_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::ingredient_aliases)]
+pub struct NewIngredientAlias {
+    pub alias: String,
+    pub ingredient_id: i32,
+}
+
+impl IngredientAlias {
+    /// Aliases are stored trimmed and lowercase so lookups are case-insensitive
+    pub fn normalize(alias: &str) -> String {
+        alias.trim().to_lowercase()
+    }
+
+    /// Canonical ingredient ID for a synonym, if one is registered
+    pub fn resolve(
+        name: &str,
+        conn: &mut PgConnection,
+    ) -> Result<Option<i32>, diesel::result::Error> {
+        use crate::schema::ingredient_aliases::dsl::*;
+
+        ingredient_aliases
+            .filter(alias.eq(Self::normalize(name)))
+            .select(ingredient_id)
+            .first::<i32>(conn)
+            .optional()
+    }
+
+    pub fn create(
+        name: &str,
+        canonical_id: i32,
+        conn: &mut PgConnection,
+    ) -> Result<IngredientAlias, diesel::result::Error> {
+        use crate::schema::ingredient_aliases::dsl::*;
+
+        diesel::insert_into(ingredient_aliases)
+            .values(&NewIngredientAlias {
+                alias: Self::normalize(name),
+                ingredient_id: canonical_id,
+            })
+            .get_result::<IngredientAlias>(conn)
+    }
+}
+
+/// An ingredient and its sub-ingredients, resolved as deep as asked, see [`Ingredient::tree`]
+#[derive(Serialize, Debug, PartialEq)]
+pub struct IngredientTree {
+    pub id: i32,
+    pub name: String,
+    pub children: Vec<IngredientTree>,
+}
+
+impl IngredientTree {
+    /// Resolve `node` from the loaded `(name, sub_ingredients)` rows. `path` holds the
+    /// ingredients between the root and `node`; a child already on it closes a cycle and
+    /// is left out. Children that no longer exist are left out too.
+    fn build(
+        node: i32,
+        depth: u32,
+        nodes: &std::collections::HashMap<i32, (String, Vec<i32>)>,
+        path: &mut Vec<i32>,
+    ) -> Option<IngredientTree> {
+        let (name, sub_ingredients) = nodes.get(&node)?;
+
+        path.push(node);
+        let children = match depth {
+            0 => Vec::new(),
+            _ => sub_ingredients
+                .iter()
+                .filter_map(|&child| match path.contains(&child) {
+                    true => None,
+                    false => IngredientTree::build(child, depth - 1, nodes, path),
+                })
+                .collect(),
+        };
+        path.pop();
+
+        Some(IngredientTree { id: node, name: name.clone(), children })
+    }
+}
+
+impl Ingredient {
+    /// Find ingredient by canonical name (ignoring case and extra whitespace) in database only, falling back to
+    /// `ingredient_aliases` so synonyms resolve to the canonical ingredient.
+    /// Returns Option<i32> - ingredient ID if found, None if not found
+    pub fn find_in_db(
+        ingredient_name: &str,
+        conn: &mut PgConnection,
+    ) -> Result<Option<i32>, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        let found = ingredients
+            .filter(canonical_name_is(ingredient_name))
+            .select(id)
+            .first::<i32>(conn)
+            .optional()?;
+
+        if let Some(ingredient_id) = found {
+            log::info!("Found existing ingredient: {} (ID: {})", ingredient_name, ingredient_id);
+            return Ok(found);
+        }
+
+        let aliased = IngredientAlias::resolve(ingredient_name, conn)?;
+
+        if let Some(ingredient_id) = aliased {
+            log::info!("Resolved '{}' via alias to ingredient ID: {}", ingredient_name, ingredient_id);
+        }
+
+        Ok(aliased)
+    }
+
+    /// Apply a curator's correction and flag the ingredient as manually verified.
+    /// `None` if there is no such ingredient.
+    pub fn apply_manual_patch(
+        ingredient_id: i32,
+        patch: &IngredientPatch,
+        conn: &mut PgConnection,
+    ) -> Result<Option<Ingredient>, PatchError> {
+        if patch.accept_usda_candidate {
+            return conn.transaction(|conn| Self::accept_usda_candidate(ingredient_id, patch, conn));
+        }
+        Ok(Self::write_correction(ingredient_id, patch, conn)?)
+    }
+
+    /// [`apply_manual_patch`](Self::apply_manual_patch) for a patch that leaves the USDA
+    /// candidate alone
+    fn write_correction(
+        ingredient_id: i32,
+        patch: &IngredientPatch,
+        conn: &mut PgConnection,
+    ) -> Result<Option<Ingredient>, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        // Curated macros settle a weak USDA match unless the patch says otherwise; other
+        // corrections leave the review pending
+        let request_review = patch.needs_review == Some(true);
+        let keep_review = patch.needs_review.is_none() && !patch.sets_macros();
+
+        diesel::update(ingredients.find(ingredient_id))
+            .set((
+                patch,
+                manually_verified.eq(true),
+                needs_review.eq(needs_review
+                    .and(keep_review.into_sql::<diesel::sql_types::Bool>())
+                    .or(request_review.into_sql::<diesel::sql_types::Bool>())),
+                updated_at.eq(diesel::dsl::now),
+            ))
+            .get_result::<Ingredient>(conn)
+            .optional()
+    }
+
+    /// [`apply_manual_patch`](Self::apply_manual_patch) for a patch accepting the USDA
+    /// candidate: the candidate becomes the matched food in the same update that applies
+    /// the patch, with its macros filling in any the curator left out
+    fn accept_usda_candidate(
+        ingredient_id: i32,
+        patch: &IngredientPatch,
+        conn: &mut PgConnection,
+    ) -> Result<Option<Ingredient>, PatchError> {
+        use crate::schema::ingredients::dsl::*;
+
+        let Some(candidate) = ingredients
+            .find(ingredient_id)
+            .select(usda_candidate)
+            .for_update()
+            .first::<Option<serde_json::Value>>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let food = candidate.ok_or(PatchError::NoUsdaCandidate)?;
+
+        let macros = crate::usda_match::per_gram_macros(&food);
+        let candidate_value = |value: Option<f32>| value.map(Some);
+        let accepted = IngredientPatch {
+            gram_protein_per_gram: patch
+                .gram_protein_per_gram
+                .or(candidate_value(macros.and_then(|m| m.protein))),
+            gram_carbs_per_gram: patch.gram_carbs_per_gram.or(candidate_value(macros.and_then(|m| m.carbs))),
+            gram_fat_per_gram: patch.gram_fat_per_gram.or(candidate_value(macros.and_then(|m| m.fat))),
+            gram_fiber_per_gram: patch.gram_fiber_per_gram.or(candidate_value(macros.and_then(|m| m.fiber))),
+            gram_trans_fat_per_gram: patch
+                .gram_trans_fat_per_gram
+                .or(candidate_value(crate::usda_match::per_gram_trans_fat(&food))),
+            ..patch.clone()
+        };
+        let candidate_fdc_id = food
+            .get("fdcId")
+            .and_then(|value| value.as_i64())
+            .and_then(|value| i32::try_from(value).ok());
+
+        let updated = diesel::update(ingredients.find(ingredient_id))
+            .set((
+                &accepted,
+                fdc_id.eq(candidate_fdc_id),
+                usda_food.eq(Some(&food)),
+                usda_candidate.eq(None::<serde_json::Value>),
+                needs_review.eq(false),
+                manually_verified.eq(true),
+                updated_at.eq(diesel::dsl::now),
+            ))
+            .get_result::<Ingredient>(conn)
+            .optional()?;
+        Ok(updated)
+    }
+
+    /// Record a curator's lab findings in one contaminant category, merged into what the
+    /// column already holds (see `safety::merge_findings`) unless `replace`. Flags the
+    /// ingredient manually verified. `None` if there is no such ingredient; a category
+    /// that isn't one of `safety::CONTAMINANT_FIELDS` is a `QueryBuilderError`.
+    pub fn record_contaminants(
+        ingredient_id: i32,
+        category: &str,
+        findings: serde_json::Value,
+        replace: bool,
+        conn: &mut PgConnection,
+    ) -> Result<Option<Ingredient>, diesel::result::Error> {
+        use crate::safety::{self, IngredientContaminants};
+        use crate::schema::ingredients::dsl::*;
+
+        let unknown_category = || {
+            diesel::result::Error::QueryBuilderError(format!("unknown contaminant category {:?}", category).into())
+        };
+        let index = safety::contaminant_index(category).ok_or_else(unknown_category)?;
+
+        conn.transaction(|conn| {
+            let Some(current) = ingredients.find(ingredient_id).for_update().first::<Ingredient>(conn).optional()? else {
+                return Ok(None);
+            };
+
+            let value = if replace {
+                findings
+            } else {
+                safety::merge_findings(IngredientContaminants::from_ingredient(&current).fields[index], findings)
+            };
+            let patch = IngredientPatch::contaminant(category, value).ok_or_else(unknown_category)?;
+
+            Self::write_correction(ingredient_id, &patch, conn)
+        })
+    }
+
+    /// Insert an ingredient unless one with the same canonical name exists.
+    /// Returns the new row, or `None` if an equivalent ingredient was already there.
+    pub fn insert_deduplicated(
+        new_ingredient: &NewIngredient,
+        conn: &mut PgConnection,
+    ) -> Result<Option<Ingredient>, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        diesel::insert_into(ingredients)
+            .values(new_ingredient)
+            .on_conflict(canonical_name)
+            .do_nothing()
+            .get_result::<Ingredient>(conn)
+            .optional()
+    }
+
+    /// The ingredients a product lists but we don't know yet that may be queued for
+    /// creation: none when auto-creation is off. The queue is checked once for all of them,
+    /// and when it is full the refusal is returned so the caller can leave the product to be
+    /// processed again.
+    pub fn admit_for_creation(
+        ingredient_names: Vec<String>,
+        auto_create: bool,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<String>, crate::backpressure::QueueFull> {
+        if !auto_create {
+            for ingredient_name in &ingredient_names {
+                log::info!("Ingredient '{}' not found, auto-creation disabled", ingredient_name);
+            }
+            return Ok(Vec::new());
+        }
+        if !ingredient_names.is_empty() {
+            let class = crate::backpressure::JobClass::Background;
+            crate::backpressure::check(class, class.max_pending(), conn)?;
+        }
+        Ok(ingredient_names)
+    }
+
+    /// One page of ingredients whose per-gram macros fall within the filter's ranges
+    pub fn search_by_macros(
+        filter: &IngredientMacroFilter,
+        page: PageRequest,
+        conn: &mut PgConnection,
+    ) -> Result<Paginated<Ingredient>, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        paginate(
+            page,
+            conn,
+            |conn| filter.apply(ingredients.into_boxed()).count().get_result::<i64>(conn),
+            |conn, limit, offset| {
+                let query = filter.apply(ingredients.into_boxed());
+                let query = match (filter.sort, filter.descending) {
+                    (MacroSort::Id, false) => query.order(id.asc()),
+                    (MacroSort::Id, true) => query.order(id.desc()),
+                    (MacroSort::Name, false) => query.order((name.asc(), id.asc())),
+                    (MacroSort::Name, true) => query.order((name.desc(), id.asc())),
+                    (MacroSort::Protein, false) => query.order((gram_protein_per_gram.asc().nulls_last(), id.asc())),
+                    (MacroSort::Protein, true) => query.order((gram_protein_per_gram.desc().nulls_last(), id.asc())),
+                    (MacroSort::Carbs, false) => query.order((gram_carbs_per_gram.asc().nulls_last(), id.asc())),
+                    (MacroSort::Carbs, true) => query.order((gram_carbs_per_gram.desc().nulls_last(), id.asc())),
+                    (MacroSort::Fat, false) => query.order((gram_fat_per_gram.asc().nulls_last(), id.asc())),
+                    (MacroSort::Fat, true) => query.order((gram_fat_per_gram.desc().nulls_last(), id.asc())),
+                    (MacroSort::Fiber, false) => query.order((gram_fiber_per_gram.asc().nulls_last(), id.asc())),
+                    (MacroSort::Fiber, true) => query.order((gram_fiber_per_gram.desc().nulls_last(), id.asc())),
+                };
+
+                query.limit(limit).offset(offset).load::<Ingredient>(conn)
+            },
+        )
+    }
+
+    /// One page of ingredients flagged `needs_review`, oldest first so the queue is worked in order
+    pub fn review_queue(page: PageRequest, conn: &mut PgConnection) -> Result<Paginated<Ingredient>, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        paginate(
+            page,
+            conn,
+            |conn| ingredients.filter(needs_review).count().get_result::<i64>(conn),
+            |conn, limit, offset| {
+                ingredients
+                    .filter(needs_review)
+                    .order((created_at.asc(), id.asc()))
+                    .limit(limit)
+                    .offset(offset)
+                    .load::<Ingredient>(conn)
+            },
+        )
+    }
+
+    /// Ids of up to `limit` uncurated ingredients matching `filter`, never-searched first,
+    /// then least recently searched. Those USDA answered with no match since `no_match_before`
+    /// are left out, so repeated requests move on to others instead of searching them again.
+    pub fn reenrich_candidates(
+        filter: ReenrichFilter,
+        no_match_before: NaiveDateTime,
+        limit: i64,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<i32>, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        let mut query = ingredients
+            .filter(manually_verified.eq(false))
+            .filter(
+                usda_no_match
+                    .eq(false)
+                    .or(usda_searched_at.is_null())
+                    .or(usda_searched_at.lt(no_match_before)),
+            )
+            .into_boxed();
+        if filter.without_fdc_id {
+            query = query.filter(fdc_id.is_null());
+        }
+        if filter.without_macros {
+            query = query
+                .filter(gram_protein_per_gram.is_null())
+                .filter(gram_carbs_per_gram.is_null())
+                .filter(gram_fat_per_gram.is_null())
+                .filter(gram_fiber_per_gram.is_null());
+        }
+
+        query
+            .order((usda_searched_at.asc().nulls_first(), id.asc()))
+            .select(id)
+            .limit(limit)
+            .load(conn)
+    }
+
+    /// Up to `limit` orphaned ingredients created before `created_before`, lowest id first.
+    /// Unless `dry_run`, they are deleted in the same statement that finds them.
+    pub fn vacuum_orphans(
+        created_before: NaiveDateTime,
+        limit: i64,
+        dry_run: bool,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<OrphanIngredient>, diesel::result::Error> {
+        use diesel::sql_types::{BigInt, Timestamp};
+
+        let candidates = format!(
+            "SELECT i.id, i.name FROM ingredients i WHERE {} ORDER BY i.id LIMIT $2",
+            ORPHAN_INGREDIENT_CONDITION
+        );
+        let query = if dry_run {
+            candidates
+        } else {
+            format!(
+                "DELETE FROM ingredients WHERE id IN (SELECT id FROM ({}) AS orphans) RETURNING id, name",
+                candidates
+            )
+        };
+
+        let mut orphans = diesel::sql_query(query)
+            .bind::<Timestamp, _>(created_before)
+            .bind::<BigInt, _>(limit)
+            .load::<OrphanIngredient>(conn)?;
+        orphans.sort_by_key(|orphan| orphan.id);
+
+        Ok(orphans)
+    }
+
+    /// Fill an ingredient's macros from a whole-food product, but only while it has none,
+    /// no USDA match (`fdc_id`) and hasn't been manually verified, so neither USDA-sourced
+    /// nor curated values are ever overwritten. Returns whether the ingredient was updated.
+    pub fn seed_macros_from_product(
+        ingredient_id: i32,
+        macros: &IngredientMacros,
+        conn: &mut PgConnection,
+    ) -> Result<bool, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        let updated = diesel::update(
+            ingredients
+                .find(ingredient_id)
+                .filter(manually_verified.eq(false))
+                .filter(fdc_id.is_null())
+                .filter(gram_protein_per_gram.is_null())
+                .filter(gram_carbs_per_gram.is_null())
+                .filter(gram_fat_per_gram.is_null())
+                .filter(gram_fiber_per_gram.is_null()),
+        )
+        .set((
+            gram_protein_per_gram.eq(macros.protein),
+            gram_carbs_per_gram.eq(macros.carbs),
+            gram_fat_per_gram.eq(macros.fat),
+            gram_fiber_per_gram.eq(macros.fiber),
+            updated_at.eq(diesel::dsl::now),
+        ))
+        .execute(conn)?;
+
+        Ok(updated == 1)
+    }
+
+    /// Link this ingredient to up to `limit` stored products that list it by name but were
+    /// processed before it existed. Each link gets the rank and share the product's own
+    /// processing would have given it, and a whole-food product of this ingredient lends it
+    /// its macros as processing would have (see [`Ingredient::seed_macros_from_product`]).
+    /// Returns the number of links created.
+    pub fn link_listing_products(&self, limit: i64, conn: &mut PgConnection) -> Result<usize, diesel::result::Error> {
+        use diesel::sql_types::{BigInt, Integer, Text};
+
+        let max_listed = crate::config::get().max_ingredients_per_product;
+        let mut linked = 0;
+        let mut after_id = 0;
+        loop {
+            let products = diesel::sql_query(UNLINKED_LISTING_PRODUCTS)
+                .bind::<Integer, _>(self.id)
+                .bind::<Text, _>(&self.canonical_name)
+                .bind::<BigInt, _>(limit)
+                .bind::<Integer, _>(after_id)
+                .load::<ListingProduct>(conn)?;
+            let Some(last) = products.last() else {
+                return Ok(linked);
+            };
+            after_id = last.id;
+            let exhausted = (products.len() as i64) < limit;
+
+            for product in products {
+                let full_response =
+                    crate::compression::stored_response(product.full_response, product.full_response_gz.as_deref())
+                        .map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))?;
+
+                // A substring hit in the text isn't enough; the ingredient must be a list entry
+                let Some(entry) = crate::off::listed_ingredients(&full_response, max_listed)
+                    .into_iter()
+                    .find(|entry| canonicalize_name(&entry.name) == self.canonical_name)
+                else {
+                    continue;
+                };
+
+                linked += NewProductIngredient {
+                    product_id: product.id,
+                    ingredient_id: self.id,
+                    rank: entry.rank,
+                    percent_estimate: Some(entry.share.percent),
+                    percent_source: Some(entry.share.source.as_str().to_string()),
+                }
+                .link(conn)?;
+
+                if let Some(profile) = crate::nutrition::whole_food_profile(&full_response)
+                    && canonicalize_name(&profile.ingredient) == self.canonical_name
+                    && Self::seed_macros_from_product(self.id, &profile.macros, conn)?
+                {
+                    log::info!("Seeded macros for ingredient '{}' from whole-food product {}", self.name, product.id);
+                }
+                if linked as i64 >= limit {
+                    return Ok(linked);
+                }
+            }
+
+            if exhausted {
+                return Ok(linked);
+            }
+        }
+    }
+
+    /// Ingredients linked to a product, in label order
+    pub fn linked_to_product(product_id: i32, conn: &mut PgConnection) -> Result<Vec<Ingredient>, diesel::result::Error> {
+        use crate::schema::{ingredients, product_ingredients};
+
+        product_ingredients::table
+            .inner_join(ingredients::table)
+            .filter(product_ingredients::product_id.eq(product_id))
+            .order(product_ingredients::rank)
+            .select(Ingredient::as_select())
+            .load(conn)
+    }
+
+    /// Ingredients linked to a product with their estimated share of it, in label order
+    pub fn portions_of_product(
+        product_id: i32,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<IngredientPortion>, diesel::result::Error> {
+        use crate::schema::{ingredients, product_ingredients};
+
+        let linked = product_ingredients::table
+            .inner_join(ingredients::table)
+            .filter(product_ingredients::product_id.eq(product_id))
+            .order(product_ingredients::rank)
+            .select((Ingredient::as_select(), product_ingredients::percent_estimate))
+            .load::<(Ingredient, Option<f32>)>(conn)?;
+
+        Ok(linked
+            .into_iter()
+            .map(|(ingredient, percent)| IngredientPortion {
+                macros: ingredient.macros(),
+                id: ingredient.id,
+                name: ingredient.name,
+                percent,
+            })
+            .collect())
+    }
+
+    pub fn macros(&self) -> IngredientMacros {
+        IngredientMacros {
+            protein: self.gram_protein_per_gram,
+            carbs: self.gram_carbs_per_gram,
+            fat: self.gram_fat_per_gram,
+            fiber: self.gram_fiber_per_gram,
+        }
+    }
+
+    /// Record that USDA was searched for the ingredient at `searched_at`, and whether it
+    /// answered that it has no match, so the backfill knows when to ask again
+    pub fn record_usda_search(
+        ingredient_id: i32,
+        no_match: bool,
+        searched_at: chrono::NaiveDateTime,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        diesel::update(ingredients.find(ingredient_id))
+            .set((usda_searched_at.eq(searched_at), usda_no_match.eq(no_match)))
+            .execute(conn)
+    }
+
+    /// Claim enqueueing the ingredient's sub-ingredients by flagging it, only if it isn't
+    /// flagged yet. Of two jobs racing on one ingredient only the first gets `true`; inside
+    /// a transaction the second waits on the row until the first commits or rolls back.
+    pub fn claim_sub_ingredients(
+        ingredient_id: i32,
+        conn: &mut PgConnection,
+    ) -> Result<bool, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        let claimed = diesel::update(ingredients.find(ingredient_id).filter(sub_ingredients_processed.eq(false)))
+            .set(sub_ingredients_processed.eq(true))
+            .returning(id)
+            .get_result::<i32>(conn)
+            .optional()?;
+        Ok(claimed.is_some())
+    }
+
+    /// The ingredient with its sub-ingredients resolved `depth` levels down, loading one
+    /// level per query. `None` if there is no such ingredient.
+    pub fn tree(
+        root_id: i32,
+        depth: u32,
+        conn: &mut PgConnection,
+    ) -> Result<Option<IngredientTree>, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        let mut nodes = std::collections::HashMap::new();
+        let mut level = vec![root_id];
+        for _ in 0..=depth {
+            // Ingredients reached again (shared children, cycles) were loaded already
+            level.retain(|node| !nodes.contains_key(node));
+            level.sort_unstable();
+            level.dedup();
+            if level.is_empty() {
+                break;
+            }
+
+            let rows = ingredients
+                .filter(id.eq_any(&level))
+                .select((id, name, sub_ingredients))
+                .load::<(i32, String, Vec<i32>)>(conn)?;
+            level = rows.iter().flat_map(|(_, _, children)| children.iter().copied()).collect();
+            nodes.extend(rows.into_iter().map(|(node, node_name, children)| (node, (node_name, children))));
+        }
+
+        Ok(IngredientTree::build(root_id, depth, &nodes, &mut Vec::new()))
+    }
+
+    /// Record `child_id` as a sub-ingredient of `parent_id`: in the parent's `sub_ingredients`
+    /// and the child's `parent_ingredients`, together. Linking a pair again changes nothing.
+    pub fn link_sub_ingredient(
+        parent_id: i32,
+        child_id: i32,
+        conn: &mut PgConnection,
+    ) -> Result<(), diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        // An ingredient statement that lists the ingredient itself isn't a hierarchy
+        if parent_id == child_id {
+            return Ok(());
+        }
+
+        conn.transaction(|conn| {
+            diesel::update(ingredients.find(parent_id).filter(diesel::dsl::not(sub_ingredients.contains(vec![child_id]))))
+                .set(sub_ingredients.eq(sub_ingredients.concat(vec![child_id])))
+                .execute(conn)?;
+            diesel::update(ingredients.find(child_id).filter(diesel::dsl::not(parent_ingredients.contains(vec![parent_id]))))
+                .set(parent_ingredients.eq(parent_ingredients.concat(vec![parent_id])))
+                .execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Whether a curator has overridden this ingredient, in which case enrichment leaves it alone
+    pub fn is_manually_verified(ingredient_id: i32, conn: &mut PgConnection) -> Result<bool, diesel::result::Error> {
+        use crate::schema::ingredients::dsl::*;
+
+        ingredients.find(ingredient_id).select(manually_verified).first(conn)
+    }
+
+    /// Enqueue a CreateIngredientJob for each missing ingredient through `queue`, the
+    /// process's shared queue. Stops at the first failure.
+    pub async fn enqueue_creation(
+        ingredient_names: &[String],
+        queue: &mut dyn fang::asynk::async_queue::AsyncQueueable,
+    ) -> Result<(), fang::asynk::async_queue::AsyncQueueError> {
+        for ingredient_name in ingredient_names {
+            log::info!("Ingredient '{}' not found, enqueueing creation job", ingredient_name);
+            let job = crate::jobs::CreateIngredientJob {
+                name: ingredient_name.clone(),
+                parent_id: None,
+            };
+            queue.insert_task(&job).await?;
+        }
+        Ok(())
+    }
+}
+
+// ============= Non-Food Products =============
+
+#[derive(Queryable, Serialize, Selectable, Debug)]
+#[diesel(table_name = crate::schema::products_non_food)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ProductNonFood {
+    pub id: i32,
     pub barcode: Option<String>,
     pub upc: Option<String>,
     pub sku: Option<String>,
@@ -261,6 +1963,72 @@ pub struct ProductNonFood {
     pub last_verified_at: Option<NaiveDateTime>,
 }
 
+impl ProductNonFood {
+    /// Whether the category is one whose description lists ingredients worth extracting
+    /// (supplements, beauty products)
+    pub fn has_ingredient_category(&self) -> bool {
+        let Some(category) = &self.category else {
+            return false;
+        };
+        let category = category.to_lowercase();
+        ["supplement", "beauty", "cosmetic", "skincare", "vitamin"]
+            .iter()
+            .any(|kind| category.contains(kind))
+    }
+
+    /// Apply a correction, returning the product as it was and as it is now. `None` if
+    /// there is no such product.
+    pub fn apply_patch(
+        product_id: i32,
+        patch: &ProductNonFoodPatch,
+        conn: &mut PgConnection,
+    ) -> Result<Option<(ProductNonFood, ProductNonFood)>, diesel::result::Error> {
+        use crate::schema::products_non_food::dsl::*;
+
+        conn.transaction(|conn| {
+            let Some(before) = products_non_food
+                .find(product_id)
+                .for_update()
+                .first::<ProductNonFood>(conn)
+                .optional()?
+            else {
+                return Ok(None);
+            };
+
+            let after = diesel::update(products_non_food.find(product_id))
+                .set((patch, updated_at.eq(diesel::dsl::now)))
+                .get_result::<ProductNonFood>(conn)?;
+
+            Ok(Some((before, after)))
+        })
+    }
+
+    /// Record that enrichment last ran for this product
+    pub fn mark_verified(
+        product_id: i32,
+        conn: &mut PgConnection,
+    ) -> Result<usize, diesel::result::Error> {
+        use crate::schema::products_non_food::dsl::*;
+
+        diesel::update(products_non_food.filter(id.eq(product_id)))
+            .set(last_verified_at.eq(diesel::dsl::now))
+            .execute(conn)
+    }
+}
+
+/// Correction to a non-food product's details. Omitted fields are left as they are.
+#[derive(Deserialize, AsChangeset, Debug, Default)]
+#[diesel(table_name = crate::schema::products_non_food)]
+#[serde(deny_unknown_fields)]
+pub struct ProductNonFoodPatch {
+    pub barcode: Option<String>,
+    pub name: Option<String>,
+    pub brand: Option<String>,
+    pub category: Option<String>,
+    pub description: Option<String>,
+    pub data_source: Option<String>,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::products_non_food)]
 pub struct NewProductNonFood {
@@ -276,6 +2044,58 @@ pub struct NewProductNonFood {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
+    use crate::db::test_connection;
+
+    fn sample_product(off_rev: Option<i32>) -> Product {
+        let now = chrono::Utc::now().naive_utc();
+        Product {
+            id: 1,
+            barcode: "3017620422003".to_string(),
+            product_name: Some("Nutella".to_string()),
+            brands: None,
+            categories: None,
+            quantity: None,
+            image_url: None,
+            nutriscore_grade: None,
+            nova_group: None,
+            ecoscore_grade: None,
+            ingredients_text: None,
+            allergens: None,
+            full_response: serde_json::json!({}),
+            created_at: now,
+            updated_at: now,
+            off_rev,
+            last_verified_at: Some(now),
+            nutrient_levels: None,
+            labels: None,
+            diet: None,
+            data_source: Some("openfoodfacts".to_string()),
+            allergen_tags: None,
+            trace_tags: None,
+            brand_tags: None,
+            product_quantity: None,
+            product_quantity_unit: None,
+            analyzed_at: None,
+            ingredients_hash: None,
+            images: None,
+            ocr_ingredients_text: None,
+        }
+    }
+
+    #[test]
+    fn test_unchanged_revision_produces_no_update() {
+        let product = sample_product(Some(42));
+        assert!(product.is_unchanged_revision(Some(42)));
+    }
+
+    #[test]
+    fn test_changed_or_missing_revision_requires_update() {
+        assert!(!sample_product(Some(42)).is_unchanged_revision(Some(43)));
+        assert!(!sample_product(Some(42)).is_unchanged_revision(None));
+        assert!(!sample_product(None).is_unchanged_revision(Some(42)));
+        assert!(!sample_product(None).is_unchanged_revision(None));
+    }
 
     #[test]
     fn test_new_product_creation() {
@@ -292,6 +2112,17 @@ mod tests {
             ingredients_text: Some("water, salt".to_string()),
             allergens: None,
             full_response: serde_json::json!({}),
+            off_rev: None,
+            nutrient_levels: None,
+            labels: None,
+            diet: None,
+            data_source: None,
+            allergen_tags: None,
+            trace_tags: None,
+            brand_tags: None,
+            product_quantity: None,
+            product_quantity_unit: None,
+            images: None,
         };
 
         assert_eq!(product.barcode, "123456789");
@@ -299,30 +2130,95 @@ mod tests {
         assert_eq!(product.brands, Some("Test Brand".to_string()));
     }
 
+    fn lookup_checked_at(clock: &MockClock, found: bool) -> ProductLookup {
+        ProductLookup {
+            barcode: "0000000000000".to_string(),
+            found,
+            checked_at: clock.now(),
+        }
+    }
+
+    #[test]
+    fn test_negative_lookup_expires_as_clock_advances() {
+        let clock = MockClock::at(chrono::Utc::now().naive_utc());
+        let ttl = chrono::Duration::hours(24);
+        let lookup = lookup_checked_at(&clock, false);
+
+        assert!(lookup.is_recent_miss(&clock, ttl));
+
+        clock.advance(chrono::Duration::hours(2));
+        assert!(lookup.is_recent_miss(&clock, ttl));
+
+        clock.advance(chrono::Duration::hours(22));
+        assert!(!lookup.is_recent_miss(&clock, ttl));
+    }
+
+    #[test]
+    fn test_positive_lookup_is_never_a_miss() {
+        let clock = MockClock::at(chrono::Utc::now().naive_utc());
+        let lookup = lookup_checked_at(&clock, true);
+
+        assert!(!lookup.is_recent_miss(&clock, chrono::Duration::hours(24)));
+    }
+
+    #[test]
+    fn test_recorded_miss_goes_stale_on_mock_clock() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let start = chrono::NaiveDate::from_ymd_opt(2025, 11, 14).unwrap().and_hms_opt(9, 0, 0).unwrap();
+        let clock = MockClock::at(start);
+        let ttl = chrono::Duration::hours(24);
+
+        ProductLookup::record("clock-test-0001", false, &clock, &mut conn).unwrap();
+        let lookup = ProductLookup::find("clock-test-0001", &mut conn).unwrap().unwrap();
+        assert_eq!(lookup.checked_at, start);
+        assert!(lookup.is_recent_miss(&clock, ttl));
+
+        clock.advance(ttl);
+        assert!(!lookup.is_recent_miss(&clock, ttl));
+
+        // Checking again restarts the TTL from the (mock) time of the new lookup
+        ProductLookup::record("clock-test-0001", false, &clock, &mut conn).unwrap();
+        let lookup = ProductLookup::find("clock-test-0001", &mut conn).unwrap().unwrap();
+        assert_eq!(lookup.checked_at, start + ttl);
+        assert!(lookup.is_recent_miss(&clock, ttl));
+    }
+
+    #[test]
+    fn test_no_job_enqueued_when_auto_create_disabled() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let names = vec!["Salt".to_string()];
+        assert!(Ingredient::admit_for_creation(names.clone(), false, &mut conn).unwrap().is_empty());
+        assert_eq!(Ingredient::admit_for_creation(names.clone(), true, &mut conn).unwrap(), names);
+    }
+
     #[test]
     fn test_new_ingredient_creation() {
         let ingredient = NewIngredient {
             name: "Salt".to_string(),
-            branded: false,
-            gram_protein_per_gram: None,
-            gram_carbs_per_gram: None,
-            gram_fat_per_gram: None,
-            gram_fiber_per_gram: None,
+            ..Default::default()
         };
 
         assert_eq!(ingredient.name, "Salt");
-        assert_eq!(ingredient.branded, false);
+        assert!(!ingredient.branded);
     }
 
     #[test]
     fn test_new_ingredient_with_nutrition() {
         let ingredient = NewIngredient {
             name: "Chicken Breast".to_string(),
-            branded: false,
             gram_protein_per_gram: Some(0.31),
             gram_carbs_per_gram: Some(0.0),
             gram_fat_per_gram: Some(0.037),
             gram_fiber_per_gram: Some(0.0),
+            ..Default::default()
         };
 
         assert_eq!(ingredient.name, "Chicken Breast");
@@ -349,17 +2245,814 @@ mod tests {
 
     #[test]
     fn test_openfoodfacts_response_parsing() {
+        let json_data = crate::fixtures::off_response("multilingual").to_string();
+
+        let response: OpenFoodFactsResponse = serde_json::from_str(&json_data).unwrap();
+        assert_eq!(response.status, 1);
+        assert_eq!(response.code, Some("3017620422003".to_string()));
+        assert_eq!(response.product.unwrap()["product_name"], "Nutella");
+    }
+
+    #[test]
+    fn test_openfoodfacts_product_array_uses_first_product() {
         let json_data = r#"{
             "status": 1,
             "code": "3017620422003",
-            "product": {
-                "product_name": "Nutella"
-            }
+            "product": [{ "product_name": "Nutella" }, { "product_name": "Nutella 750g" }]
         }"#;
 
         let response: OpenFoodFactsResponse = serde_json::from_str(json_data).unwrap();
         assert_eq!(response.status, 1);
-        assert_eq!(response.code, Some("3017620422003".to_string()));
-        assert!(response.product.is_some());
+        assert_eq!(response.product.unwrap()["product_name"], "Nutella");
+    }
+
+    #[test]
+    fn test_openfoodfacts_plural_products_without_status() {
+        let json_data = r#"{
+            "count": 1,
+            "page": 1,
+            "products": [{ "code": "3017620422003", "product_name": "Nutella" }]
+        }"#;
+
+        let response: OpenFoodFactsResponse = serde_json::from_str(json_data).unwrap();
+        assert_eq!(response.status, 1);
+        assert_eq!(response.product.unwrap()["product_name"], "Nutella");
+    }
+
+    #[test]
+    fn test_openfoodfacts_not_found_shapes() {
+        let not_found: OpenFoodFactsResponse =
+            serde_json::from_str(&crate::fixtures::off_response("not_found").to_string()).unwrap();
+        assert_eq!(not_found.status, 0);
+        assert!(not_found.product.is_none());
+
+        let empty_search: OpenFoodFactsResponse = serde_json::from_str(r#"{ "count": 0, "products": [] }"#).unwrap();
+        assert_eq!(empty_search.status, 0);
+        assert!(empty_search.product.is_none());
+
+        let v3_failure: OpenFoodFactsResponse =
+            serde_json::from_str(r#"{ "status": "failure", "code": "123", "product": null }"#).unwrap();
+        assert_eq!(v3_failure.status, 0);
+        assert!(v3_failure.product.is_none());
+    }
+
+    #[test]
+    fn test_alias_normalization() {
+        assert_eq!(IngredientAlias::normalize("  Ascorbic Acid "), "ascorbic acid");
+        assert_eq!(IngredientAlias::normalize("SODIUM CHLORIDE"), "sodium chloride");
+    }
+
+    #[test]
+    fn test_full_response_round_trips_compressed_and_plain() {
+        use crate::schema::products;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let payload = crate::fixtures::off_product("multilingual");
+        for (barcode, compress) in [("gz-test-compressed", true), ("gz-test-plain", false)] {
+            let new_product = crate::off::extract(barcode, &payload);
+            diesel::insert_into(products::table)
+                .values(new_product.to_row(compress))
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        for (barcode, compressed) in [("gz-test-compressed", true), ("gz-test-plain", false)] {
+            let (json, gz): (Option<serde_json::Value>, Option<Vec<u8>>) = products::table
+                .filter(products::barcode.eq(barcode))
+                .select((products::full_response, products::full_response_gz))
+                .first(&mut conn)
+                .unwrap();
+            assert_eq!((json.is_none(), gz.is_some()), (compressed, compressed), "{}", barcode);
+
+            let product: Product = products::table.filter(products::barcode.eq(barcode)).first(&mut conn).unwrap();
+            assert_eq!(product.full_response, payload, "{}", barcode);
+            assert_eq!(product.product_name.as_deref(), Some("Nutella"));
+        }
+
+        // The ingredient linking that reads stored payloads sees compressed rows too
+        let palm_oil = NewIngredient {
+            name: "Huile de palme".to_string(),
+            ..Default::default()
+        };
+        let palm_oil = Ingredient::insert_deduplicated(&palm_oil, &mut conn).unwrap().unwrap();
+        assert_eq!(palm_oil.link_listing_products(10, &mut conn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_link_listing_products_pages_past_false_positives() {
+        use crate::schema::{product_ingredients, products};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        // Substring hits in the text that the list itself doesn't bear out, stored first
+        for index in 0..3 {
+            let product_data = serde_json::json!({ "ingredients_text": "Keyset Test Salted Butter, Water" });
+            diesel::insert_into(products::table)
+                .values(&crate::off::extract(&format!("keyset-test-miss-{}", index), &product_data))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        let listings = [
+            // Listed by taxonomy id only, with no text to find it in
+            serde_json::json!({ "ingredients": [{ "id": "en:water" }, { "id": "en:keyset-test-salt" }] }),
+            serde_json::json!({ "ingredients": [{ "text": "Keyset  Test SALT" }] }),
+        ];
+        let mut listing_ids = Vec::new();
+        for (index, product_data) in listings.iter().enumerate() {
+            let product_id = diesel::insert_into(products::table)
+                .values(&crate::off::extract(&format!("keyset-test-hit-{}", index), product_data))
+                .returning(products::id)
+                .get_result::<i32>(&mut conn)
+                .unwrap();
+            listing_ids.push(product_id);
+        }
+
+        let salt: Ingredient = diesel::insert_into(crate::schema::ingredients::table)
+            .values(crate::schema::ingredients::name.eq("Keyset Test Salt"))
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(salt.link_listing_products(2, &mut conn).unwrap(), 2);
+
+        let linked: Vec<(i32, i32)> = product_ingredients::table
+            .filter(product_ingredients::ingredient_id.eq(salt.id))
+            .order(product_ingredients::product_id)
+            .select((product_ingredients::product_id, product_ingredients::rank))
+            .load(&mut conn)
+            .unwrap();
+        assert_eq!(linked, vec![(listing_ids[0], 2), (listing_ids[1], 1)]);
+    }
+
+    #[test]
+    fn test_linking_a_new_ingredient_seeds_whole_food_macros() {
+        use crate::schema::{ingredients, products};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        // Stored while its only ingredient was still waiting to be created
+        let product_data = serde_json::json!({
+            "ingredients": [{ "text": "Seed Test Plantains", "percent_estimate": 100 }],
+            "nutriments": { "proteins_100g": 1.3, "carbohydrates_100g": 31.9, "fat_100g": 0.4, "fiber_100g": 2.3 }
+        });
+        diesel::insert_into(products::table)
+            .values(&crate::off::extract("seed-test-1", &product_data))
+            .execute(&mut conn)
+            .unwrap();
+
+        let plantains: Ingredient = diesel::insert_into(ingredients::table)
+            .values(ingredients::name.eq("Seed Test Plantains"))
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(plantains.link_listing_products(10, &mut conn).unwrap(), 1);
+
+        let macros: (Option<f32>, Option<f32>) = ingredients::table
+            .find(plantains.id)
+            .select((ingredients::gram_protein_per_gram, ingredients::gram_fiber_per_gram))
+            .first(&mut conn)
+            .unwrap();
+        assert!((macros.0.unwrap() - 0.013).abs() < 1e-6);
+        assert!((macros.1.unwrap() - 0.023).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_barcodes_are_unique_and_back_on_conflict() {
+        use crate::schema::{products, products_non_food};
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let product = crate::off::extract("unique-test", &serde_json::json!({ "product_name": "Unique Test Tea" }));
+        let insert = |conn: &mut PgConnection| {
+            diesel::insert_into(products::table)
+                .values(&product)
+                .on_conflict(products::barcode)
+                .do_nothing()
+                .execute(conn)
+        };
+        assert_eq!(insert(&mut conn).unwrap(), 1);
+        assert_eq!(insert(&mut conn).unwrap(), 0);
+
+        // Non-food products may have no barcode, and any number of them can lack one
+        for barcode in [None, None, Some("unique-test-nf".to_string()), Some("unique-test-nf".to_string())] {
+            diesel::insert_into(products_non_food::table)
+                .values(&NewProductNonFood {
+                    barcode,
+                    name: "Unique Test Soap".to_string(),
+                    brand: None,
+                    category: None,
+                    description: None,
+                    full_response: None,
+                    data_source: None,
+                })
+                .on_conflict(products_non_food::barcode)
+                .do_nothing()
+                .execute(&mut conn)
+                .unwrap();
+        }
+        let stored: i64 = products_non_food::table
+            .filter(products_non_food::name.eq("Unique Test Soap"))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(stored, 3);
+    }
+
+    #[test]
+    fn test_insert_or_get_returns_the_row_already_stored() {
+        use crate::schema::product_history;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        // The second of two requests that both missed the cache
+        let first = crate::off::extract("upsert-test", &serde_json::json!({ "product_name": "Upsert Test Soda" }));
+        let second = crate::off::extract("upsert-test", &serde_json::json!({ "product_name": "Upsert Test Soda 2" }));
+        let (inserted, was_inserted) = Product::insert_or_get(&first, &mut conn).unwrap();
+        assert!(was_inserted);
+        let (existing, was_inserted) = Product::insert_or_get(&second, &mut conn).unwrap();
+        assert!(!was_inserted);
+        assert_eq!(existing.id, inserted.id);
+        assert_eq!(existing.product_name.as_deref(), Some("Upsert Test Soda"));
+
+        let revisions: i64 = product_history::table
+            .filter(product_history::product_id.eq(inserted.id))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(revisions, 1);
+    }
+
+    #[test]
+    fn test_ingredient_tree_resolves_levels_and_stops_at_cycles() {
+        use crate::schema::ingredients;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let bar = NewIngredient::named("Tree Test Bar").seed(&mut conn);
+        let chocolate = NewIngredient::named("Tree Test Chocolate").seed(&mut conn);
+        let oats = NewIngredient::named("Tree Test Oats").seed(&mut conn);
+        let cocoa = NewIngredient::named("Tree Test Cocoa").seed(&mut conn);
+        let sugar = NewIngredient::named("Tree Test Sugar").seed(&mut conn);
+        for (parent, child) in [(bar, chocolate), (bar, oats), (chocolate, cocoa), (chocolate, sugar)] {
+            Ingredient::link_sub_ingredient(parent, child, &mut conn).unwrap();
+        }
+
+        let leaf = |id: i32, name: &str| IngredientTree { id, name: name.to_string(), children: vec![] };
+        let tree = Ingredient::tree(bar, 3, &mut conn).unwrap().unwrap();
+        assert_eq!(
+            tree,
+            IngredientTree {
+                id: bar,
+                name: "Tree Test Bar".to_string(),
+                children: vec![
+                    IngredientTree {
+                        id: chocolate,
+                        name: "Tree Test Chocolate".to_string(),
+                        children: vec![leaf(cocoa, "Tree Test Cocoa"), leaf(sugar, "Tree Test Sugar")],
+                    },
+                    leaf(oats, "Tree Test Oats"),
+                ],
+            }
+        );
+
+        // Only as deep as asked
+        let shallow = Ingredient::tree(bar, 1, &mut conn).unwrap().unwrap();
+        assert!(shallow.children.iter().all(|child| child.children.is_empty()));
+        assert!(Ingredient::tree(bar, 0, &mut conn).unwrap().unwrap().children.is_empty());
+
+        // Cocoa listing chocolate, and sugar listing itself, must not loop
+        Ingredient::link_sub_ingredient(cocoa, chocolate, &mut conn).unwrap();
+        diesel::update(ingredients::table.find(sugar))
+            .set(ingredients::sub_ingredients.eq(vec![sugar]))
+            .execute(&mut conn)
+            .unwrap();
+        let cyclic = Ingredient::tree(bar, 10, &mut conn).unwrap().unwrap();
+        assert_eq!(cyclic, tree);
+        let from_cocoa = Ingredient::tree(cocoa, 10, &mut conn).unwrap().unwrap();
+        assert_eq!(from_cocoa.children[0].id, chocolate);
+        assert_eq!(from_cocoa.children[0].children, vec![leaf(sugar, "Tree Test Sugar")]);
+
+        assert!(Ingredient::tree(sugar + 1_000_000, 3, &mut conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_only_the_first_racing_refresh_claims_a_product() {
+        use crate::schema::products;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let now = chrono::Utc::now().naive_utc();
+        let product_id = diesel::insert_into(products::table)
+            .values(&crate::off::extract("claim-test", &serde_json::json!({ "product_name": "Claim Test Cola" })))
+            .returning(products::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+        diesel::update(products::table.find(product_id))
+            .set(products::last_verified_at.eq(now - chrono::Duration::days(40)))
+            .execute(&mut conn)
+            .unwrap();
+
+        // Both saw the product stale; the second finds it already verified
+        let stale_before = now - chrono::Duration::days(30);
+        assert!(Product::claim_refresh(product_id, stale_before, now, &mut conn).unwrap());
+        assert!(!Product::claim_refresh(product_id, stale_before, now, &mut conn).unwrap());
+
+        let verified: Option<NaiveDateTime> =
+            products::table.find(product_id).select(products::last_verified_at).first(&mut conn).unwrap();
+        assert_eq!(verified.map(|at| at.and_utc().timestamp()), Some(now.and_utc().timestamp()));
+
+        // Once that verification is itself old enough, a refresh can claim it again
+        let later = now + chrono::Duration::days(31);
+        assert!(Product::claim_refresh(product_id, later - chrono::Duration::days(30), later, &mut conn).unwrap());
+    }
+
+    #[test]
+    fn test_reenrich_candidates_over_mixed_ingredients() {
+        use crate::schema::ingredients;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let bare = NewIngredient::named("Reenrich Test Bare").seed(&mut conn);
+        let seeded_macros = NewIngredient {
+            gram_protein_per_gram: Some(0.1),
+            ..NewIngredient::named("Reenrich Test Seeded Macros")
+        }
+        .seed(&mut conn);
+        let matched = NewIngredient {
+            fdc_id: Some(173904),
+            gram_protein_per_gram: Some(0.2),
+            ..NewIngredient::named("Reenrich Test Matched")
+        }
+        .seed(&mut conn);
+        let curated = NewIngredient::named("Reenrich Test Curated").seed(&mut conn);
+        let patch = IngredientPatch { gram_protein_per_gram: Some(Some(0.3)), ..Default::default() };
+        Ingredient::apply_manual_patch(curated, &patch, &mut conn).unwrap().unwrap();
+        diesel::update(ingredients::table.find(curated))
+            .set(ingredients::gram_protein_per_gram.eq(None::<f32>))
+            .execute(&mut conn)
+            .unwrap();
+
+        let ours = [bare, seeded_macros, matched, curated];
+        let now = chrono::Utc::now().naive_utc();
+        let no_match_before = now - chrono::Duration::hours(168);
+        let candidates = |filter: ReenrichFilter, limit: i64, conn: &mut PgConnection| -> Vec<i32> {
+            Ingredient::reenrich_candidates(filter, no_match_before, limit, conn)
+                .unwrap()
+                .into_iter()
+                .filter(|id| ours.contains(id))
+                .collect()
+        };
+
+        let without_fdc_id = ReenrichFilter { without_fdc_id: true, ..Default::default() };
+        let without_macros = ReenrichFilter { without_macros: true, ..Default::default() };
+        let both = ReenrichFilter { without_fdc_id: true, without_macros: true };
+        assert_eq!(candidates(without_fdc_id, 100_000, &mut conn), vec![bare, seeded_macros]);
+        assert_eq!(candidates(without_macros, 100_000, &mut conn), vec![bare]);
+        assert_eq!(candidates(both, 100_000, &mut conn), vec![bare]);
+        assert!(ReenrichFilter::default().is_empty());
+
+        // Searched ones go to the back, and a recent no-match drops out until its TTL is up
+        Ingredient::record_usda_search(bare, false, now, &mut conn).unwrap();
+        assert_eq!(candidates(without_fdc_id, 100_000, &mut conn), vec![seeded_macros, bare]);
+        Ingredient::record_usda_search(seeded_macros, true, now, &mut conn).unwrap();
+        assert_eq!(candidates(without_fdc_id, 100_000, &mut conn), vec![bare]);
+        Ingredient::record_usda_search(seeded_macros, true, no_match_before - chrono::Duration::hours(1), &mut conn).unwrap();
+        assert_eq!(candidates(without_fdc_id, 100_000, &mut conn), vec![seeded_macros, bare]);
+
+        // Never-searched first, so a capped run doesn't keep taking the same ones
+        let first = Ingredient::reenrich_candidates(without_fdc_id, no_match_before, 1, &mut conn).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_ne!(first[0], bare);
+    }
+
+    #[test]
+    fn test_alias_lookup_returns_canonical_ingredient() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let canonical_id = diesel::insert_into(crate::schema::ingredients::table)
+            .values(&NewIngredient {
+                name: "Zinc".to_string(),
+                ..Default::default()
+            })
+            .returning(crate::schema::ingredients::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+        IngredientAlias::create("Zinc Gluconate", canonical_id, &mut conn).unwrap();
+
+        assert_eq!(Ingredient::find_in_db("ZINC GLUCONATE", &mut conn).unwrap(), Some(canonical_id));
+        assert_eq!(Ingredient::find_in_db("zinc gluconate ", &mut conn).unwrap(), Some(canonical_id));
+        assert_eq!(Ingredient::find_in_db("Zinc Picolinate", &mut conn).unwrap(), None);
+
+        // Seeded synonyms resolve to the same row as the canonical name
+        let vitamin_c = Ingredient::find_in_db("Vitamin C", &mut conn).unwrap();
+        assert!(vitamin_c.is_some());
+        assert_eq!(Ingredient::find_in_db("Ascorbic Acid", &mut conn).unwrap(), vitamin_c);
+    }
+
+    #[test]
+    fn test_search_by_macros_over_seeded_ingredients() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let whey = NewIngredient {
+            gram_protein_per_gram: Some(0.8),
+            gram_fat_per_gram: Some(0.05),
+            ..NewIngredient::named("Macro Test Whey")
+        }
+        .seed(&mut conn);
+        let lentils = NewIngredient {
+            gram_protein_per_gram: Some(0.26),
+            gram_fat_per_gram: Some(0.0),
+            ..NewIngredient::named("Macro Test Lentils")
+        }
+        .seed(&mut conn);
+        let rice = NewIngredient {
+            gram_protein_per_gram: Some(0.07),
+            gram_fat_per_gram: Some(0.0),
+            ..NewIngredient::named("Macro Test Rice")
+        }
+        .seed(&mut conn);
+        let mystery = NewIngredient::named("Macro Test Mystery").seed(&mut conn);
+        let seeded = [whey, lentils, rice, mystery];
+
+        let all = PageRequest { page: 1, per_page: 100 };
+        let mut search = |filter: IngredientMacroFilter| -> Vec<i32> {
+            let page = Ingredient::search_by_macros(&filter, all, &mut conn).unwrap();
+            page.items.into_iter().map(|i| i.id).filter(|id| seeded.contains(id)).collect()
+        };
+
+        let high_protein = IngredientMacroFilter {
+            protein: MacroRange { min: Some(0.25), max: None },
+            sort: MacroSort::Protein,
+            descending: true,
+            ..Default::default()
+        };
+        assert_eq!(search(high_protein.clone()), vec![whey, lentils]);
+
+        let fat_free = IngredientMacroFilter {
+            fat: MacroRange { min: None, max: Some(0.0) },
+            ..Default::default()
+        };
+        assert_eq!(search(fat_free.clone()), vec![lentils, rice]);
+
+        let fat_free_or_unknown = IngredientMacroFilter {
+            include_unknown: true,
+            ..fat_free
+        };
+        assert_eq!(search(fat_free_or_unknown), vec![lentils, rice, mystery]);
+
+        let first_page = Ingredient::search_by_macros(&high_protein, PageRequest { page: 1, per_page: 1 }, &mut conn).unwrap();
+        assert_eq!(first_page.items.len(), 1);
+        assert!(first_page.total >= 2);
+        assert_eq!(first_page.total_pages, first_page.total);
+    }
+
+    #[test]
+    fn test_ingredient_patch_parsing_and_validation() {
+        let patch: IngredientPatch = serde_json::from_value(serde_json::json!({
+            "gram_protein_per_gram": 0.25,
+            "pesticides": ["glyphosate"]
+        }))
+        .unwrap();
+        assert_eq!(patch.gram_protein_per_gram, Some(Some(0.25)));
+        assert_eq!(patch.gram_fat_per_gram, None);
+        assert!(patch.validate().is_ok());
+
+        // An explicit null clears the column; leaving the field out keeps it
+        let clearing: IngredientPatch = serde_json::from_value(serde_json::json!({ "gram_fiber_per_gram": null })).unwrap();
+        assert_eq!(clearing.gram_fiber_per_gram, Some(None));
+        assert_eq!(clearing.gram_fat_per_gram, None);
+        assert!(clearing.validate().is_ok());
+
+        let empty: IngredientPatch = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.validate(), Err("Patch must set at least one field".to_string()));
+        let dismissal: IngredientPatch = serde_json::from_value(serde_json::json!({ "needs_review": false })).unwrap();
+        assert!(dismissal.validate().is_ok());
+        let acceptance: IngredientPatch = serde_json::from_value(serde_json::json!({ "accept_usda_candidate": true })).unwrap();
+        assert!(acceptance.accept_usda_candidate);
+        assert!(acceptance.validate().is_ok());
+        let contradictory = IngredientPatch {
+            accept_usda_candidate: true,
+            needs_review: Some(true),
+            ..Default::default()
+        };
+        assert!(contradictory.validate().is_err());
+
+        let too_much = IngredientPatch {
+            gram_fat_per_gram: Some(Some(1.5)),
+            ..Default::default()
+        };
+        assert_eq!(too_much.validate(), Err("gram_fat_per_gram must be between 0 and 1".to_string()));
+
+        // Typos are rejected rather than silently ignored
+        assert!(serde_json::from_value::<IngredientPatch>(serde_json::json!({ "protein": 0.2 })).is_err());
+    }
+
+    #[test]
+    fn test_oversized_ingredient_names_are_skipped() {
+        assert!(ingredient_name_fits("Sea Salt", 200));
+        assert!(ingredient_name_fits(&"a".repeat(200), 200));
+        assert!(!ingredient_name_fits(&"a".repeat(201), 200));
+        // Counted as stored, so padding and whitespace runs count too, and in characters
+        // rather than bytes
+        let padded = format!("  {}  ", "b  ".repeat(50));
+        assert_eq!(padded.chars().count(), 154);
+        assert!(!ingredient_name_fits(&padded, 153));
+        assert!(ingredient_name_fits(&padded, 154));
+        assert!(ingredient_name_fits(&"é".repeat(200), 200));
+        // Lowercasing 'İ' takes two characters, so the canonical form is the longer one
+        assert!(!ingredient_name_fits(&"İ".repeat(200), 200));
+        assert!(ingredient_name_fits(&"İ".repeat(200), 400));
+
+        assert!(crate::config::get().max_ingredient_name_len <= INGREDIENT_NAME_COLUMN_LEN);
+    }
+
+    #[test]
+    fn test_longest_accepted_name_fits_the_column() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let longest = "n".repeat(INGREDIENT_NAME_COLUMN_LEN);
+        assert!(ingredient_name_fits(&longest, INGREDIENT_NAME_COLUMN_LEN));
+        let created = Ingredient::insert_deduplicated(
+            &NewIngredient {
+                name: longest.clone(),
+                ..Default::default()
+            },
+            &mut conn,
+        )
+        .unwrap()
+        .expect("ingredient is new");
+        assert_eq!(created.name, longest);
+    }
+
+    #[test]
+    fn test_contaminant_patch_covers_exactly_the_contaminant_columns() {
+        for category in crate::safety::CONTAMINANT_FIELDS {
+            assert!(IngredientPatch::contaminant(category, serde_json::json!({})).is_some(), "{}", category);
+        }
+        assert!(IngredientPatch::contaminant("dyes", serde_json::json!({})).is_none());
+        assert!(IngredientPatch::contaminant("name", serde_json::json!({})).is_none());
+
+        let patch = IngredientPatch::contaminant("hormones", serde_json::json!(["rbst"])).unwrap();
+        assert_eq!(patch.hormones, Some(Some(serde_json::json!(["rbst"]))));
+        assert_eq!(patch.pesticides, None);
+    }
+
+    #[test]
+    fn test_record_contaminants_merges_or_replaces() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let ingredient_id = diesel::insert_into(crate::schema::ingredients::table)
+            .values((
+                crate::schema::ingredients::name.eq("Contaminant Test Rice"),
+                crate::schema::ingredients::heavy_metals.eq(Some(serde_json::json!({ "arsenic": "0.1 ppm", "lead": "trace" }))),
+            ))
+            .returning(crate::schema::ingredients::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        let merged = Ingredient::record_contaminants(ingredient_id, "heavy_metals", serde_json::json!({ "arsenic": "0.3 ppm", "cadmium": "0.02 ppm" }), false, &mut conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            merged.heavy_metals,
+            Some(serde_json::json!({ "arsenic": "0.3 ppm", "lead": "trace", "cadmium": "0.02 ppm" }))
+        );
+        assert!(merged.manually_verified);
+
+        let replaced = Ingredient::record_contaminants(ingredient_id, "heavy_metals", serde_json::json!({ "mercury": "none detected" }), true, &mut conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(replaced.heavy_metals, Some(serde_json::json!({ "mercury": "none detected" })));
+
+        assert!(Ingredient::record_contaminants(-1, "pesticides", serde_json::json!([]), false, &mut conn).unwrap().is_none());
+
+        // A category without a contaminant column is an error, not a panic
+        let unknown = Ingredient::record_contaminants(ingredient_id, "dyes", serde_json::json!([]), false, &mut conn);
+        assert!(matches!(unknown, Err(diesel::result::Error::QueryBuilderError(_))));
+    }
+
+    #[test]
+    fn test_name_variants_collapse_to_one_ingredient() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let named = |name: &str| NewIngredient {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        let created = Ingredient::insert_deduplicated(&named("Canon  Test Sugar"), &mut conn)
+            .unwrap()
+            .expect("first insert creates the ingredient");
+        assert_eq!(created.name, "Canon  Test Sugar");
+        assert_eq!(created.canonical_name, "canon test sugar");
+
+        for variant in ["canon test sugar ", "CANON TEST SUGAR", " Canon\tTest   Sugar"] {
+            assert!(Ingredient::insert_deduplicated(&named(variant), &mut conn).unwrap().is_none(), "{:?}", variant);
+            assert_eq!(Ingredient::find_in_db(variant, &mut conn).unwrap(), Some(created.id), "{:?}", variant);
+        }
+
+        // A plain insert can't sneak a variant past the unique index either
+        let plain = diesel::insert_into(crate::schema::ingredients::table)
+            .values(&named("canon test sugar"))
+            .execute(&mut conn);
+        assert!(matches!(
+            plain,
+            Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _))
+        ));
+    }
+
+    #[test]
+    fn test_vacuum_orphans_only_removes_unreferenced_ingredients() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let orphan = NewIngredient::named("Vacuum Test Orphan").seed(&mut conn);
+        let parent = NewIngredient::named("Vacuum Test Parent").seed(&mut conn);
+        let child = NewIngredient::named("Vacuum Test Child").seed(&mut conn);
+        let aliased = NewIngredient::named("Vacuum Test Aliased").seed(&mut conn);
+        let listed = NewIngredient::named("Vacuum Test Listed").seed(&mut conn);
+        let in_text = NewIngredient::named("Vacuum Test Text").seed(&mut conn);
+
+        {
+            use crate::schema::ingredients::dsl::*;
+            diesel::update(ingredients.find(parent))
+                .set(sub_ingredients.eq(vec![child]))
+                .execute(&mut conn)
+                .unwrap();
+        }
+        IngredientAlias::create("Vacuum Test Synonym", aliased, &mut conn).unwrap();
+        diesel::insert_into(crate::schema::products::table)
+            .values(&crate::off::extract(
+                "vacuum-test-0001",
+                &serde_json::json!({
+                    "ingredients": [{ "id": "en:vacuum-test-listed", "text": "vacuum test listed" }],
+                    "ingredients_text": "Water, Vacuum Test Text (2%)"
+                }),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+
+        let seeded = [orphan, parent, child, aliased, listed, in_text];
+        let later = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+        let seeded_orphans = |orphans: Vec<OrphanIngredient>| -> Vec<i32> {
+            orphans.into_iter().map(|o| o.id).filter(|id| seeded.contains(id)).collect()
+        };
+
+        // Nothing is old enough yet
+        let earlier = chrono::Utc::now().naive_utc() - chrono::Duration::days(1);
+        assert!(seeded_orphans(Ingredient::vacuum_orphans(earlier, 1000, true, &mut conn).unwrap()).is_empty());
+
+        let dry_run = Ingredient::vacuum_orphans(later, 1000, true, &mut conn).unwrap();
+        assert_eq!(seeded_orphans(dry_run), vec![orphan]);
+        assert_eq!(Ingredient::find_in_db("Vacuum Test Orphan", &mut conn).unwrap(), Some(orphan));
+
+        let deleted = Ingredient::vacuum_orphans(later, 1000, false, &mut conn).unwrap();
+        assert_eq!(seeded_orphans(deleted), vec![orphan]);
+        assert_eq!(Ingredient::find_in_db("Vacuum Test Orphan", &mut conn).unwrap(), None);
+        assert_eq!(Ingredient::find_in_db("Vacuum Test Child", &mut conn).unwrap(), Some(child));
+        assert_eq!(Ingredient::find_in_db("Vacuum Test Listed", &mut conn).unwrap(), Some(listed));
+    }
+
+    #[test]
+    fn test_product_macros_never_overwrite_usda_data() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let unmatched = NewIngredient::named("Whole Food Test Banana").seed(&mut conn);
+        let usda_matched = NewIngredient {
+            fdc_id: Some(1750340),
+            ..NewIngredient::named("Whole Food Test Apple")
+        }
+        .seed(&mut conn);
+        let has_macros = NewIngredient {
+            gram_protein_per_gram: Some(0.004),
+            ..NewIngredient::named("Whole Food Test Pear")
+        }
+        .seed(&mut conn);
+
+        let macros = IngredientMacros {
+            protein: Some(0.0109),
+            carbs: Some(0.2284),
+            fat: Some(0.0033),
+            fiber: Some(0.026),
+        };
+
+        assert!(Ingredient::seed_macros_from_product(unmatched, &macros, &mut conn).unwrap());
+        assert!(!Ingredient::seed_macros_from_product(usda_matched, &macros, &mut conn).unwrap());
+        assert!(!Ingredient::seed_macros_from_product(has_macros, &macros, &mut conn).unwrap());
+        // Once seeded, a second product doesn't replace the values either
+        assert!(!Ingredient::seed_macros_from_product(unmatched, &macros, &mut conn).unwrap());
+
+        let protein_of = |ingredient_id: i32, conn: &mut PgConnection| {
+            crate::schema::ingredients::table
+                .find(ingredient_id)
+                .select(crate::schema::ingredients::gram_protein_per_gram)
+                .first::<Option<f32>>(conn)
+                .unwrap()
+        };
+        assert_eq!(protein_of(unmatched, &mut conn), Some(0.0109));
+        assert_eq!(protein_of(usda_matched, &mut conn), None);
+        assert_eq!(protein_of(has_macros, &mut conn), Some(0.004));
+    }
+
+    #[test]
+    fn test_product_macros_never_overwrite_verified_ingredient() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let unverified = NewIngredient::named("Verified Guard Test Plum").seed(&mut conn);
+        let verified = NewIngredient::named("Verified Guard Test Fig").seed(&mut conn);
+
+        // An empty patch still marks the fig as verified, with its macros deliberately left empty
+        Ingredient::apply_manual_patch(verified, &IngredientPatch::default(), &mut conn).unwrap().unwrap();
+        assert!(Ingredient::is_manually_verified(verified, &mut conn).unwrap());
+        assert!(!Ingredient::is_manually_verified(unverified, &mut conn).unwrap());
+
+        let macros = IngredientMacros {
+            protein: Some(0.007),
+            carbs: Some(0.114),
+            fat: Some(0.0028),
+            fiber: Some(0.014),
+        };
+        assert!(Ingredient::seed_macros_from_product(unverified, &macros, &mut conn).unwrap());
+        assert!(!Ingredient::seed_macros_from_product(verified, &macros, &mut conn).unwrap());
+
+        let fiber_of = |ingredient_id: i32, conn: &mut PgConnection| {
+            crate::schema::ingredients::table
+                .find(ingredient_id)
+                .select(crate::schema::ingredients::gram_fiber_per_gram)
+                .first::<Option<f32>>(conn)
+                .unwrap()
+        };
+        assert_eq!(fiber_of(unverified, &mut conn), Some(0.014));
+        assert_eq!(fiber_of(verified, &mut conn), None);
+    }
+
+    #[test]
+    fn test_tiny_macro_values_round_trip_through_float4() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        // Vitamin B12 in g per 100g, stored per gram
+        let tiny = crate::nutrition::per_gram(2.4e-6);
+        let stored = diesel::insert_into(crate::schema::ingredients::table)
+            .values(&NewIngredient {
+                name: "Precision Test Liver".to_string(),
+                gram_protein_per_gram: Some(crate::nutrition::per_gram(20.4)),
+                gram_fiber_per_gram: Some(tiny),
+                ..Default::default()
+            })
+            .get_result::<Ingredient>(&mut conn)
+            .unwrap();
+
+        assert_eq!(stored.gram_fiber_per_gram, Some(tiny));
+        assert!(stored.gram_fiber_per_gram.unwrap() > 0.0);
+        assert_eq!(stored.gram_protein_per_gram, Some(0.204));
     }
 }
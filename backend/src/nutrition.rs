@@ -0,0 +1,468 @@
+use std::borrow::Cow;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{off, quantity};
+
+/// Basis nutrition values are reported in
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum NutritionBasis {
+    #[serde(rename = "100g")]
+    Per100g,
+    #[serde(rename = "serving")]
+    Serving,
+    /// The whole package, by the product's numeric quantity
+    #[serde(rename = "package")]
+    Package,
+}
+
+impl NutritionBasis {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "100g" => Some(NutritionBasis::Per100g),
+            "serving" => Some(NutritionBasis::Serving),
+            "package" => Some(NutritionBasis::Package),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct NutritionFacts {
+    pub basis: NutritionBasis,
+    /// True when per-serving or per-package was requested but that size is unknown
+    pub basis_fallback: bool,
+    pub serving_size: Option<String>,
+    pub serving_grams: Option<f64>,
+    pub package_grams: Option<f64>,
+    /// Nutrient name (OFF naming, e.g. "sugars", "energy-kcal") to amount in the nutrient's unit
+    pub nutrients: serde_json::Map<String, Value>,
+}
+
+/// Nutrition facts from an OFF product's per-100g `nutriments`, scaled to a serving or to
+/// the whole package (`package_grams`, from the stored numeric quantity) when requested.
+/// Falls back to per-100g (with `basis_fallback`) if that size is unknown.
+pub fn from_off_product(product_data: &Value, requested: NutritionBasis, package_grams: Option<f64>) -> NutritionFacts {
+    let serving_size = product_data
+        .get("serving_size")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let serving_grams = serving_size.as_deref().and_then(quantity::parse_grams);
+
+    let (basis, scale) = match (requested, serving_grams, package_grams) {
+        (NutritionBasis::Serving, Some(grams), _) => (NutritionBasis::Serving, grams / 100.0),
+        (NutritionBasis::Package, _, Some(grams)) => (NutritionBasis::Package, grams / 100.0),
+        _ => (NutritionBasis::Per100g, 1.0),
+    };
+
+    let nutrients = product_data
+        .get("nutriments")
+        .and_then(|n| n.as_object())
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| {
+            let name = key.strip_suffix("_100g")?;
+            let amount = value.as_f64()? * scale;
+            Some((name.to_string(), serde_json::json!(amount)))
+        })
+        .collect();
+
+    NutritionFacts {
+        basis,
+        basis_fallback: requested != basis,
+        serving_size,
+        serving_grams,
+        package_grams,
+        nutrients,
+    }
+}
+
+/// Share of a product, in percent, one ingredient must make up for the product to count
+/// as that ingredient
+const DOMINANT_INGREDIENT_PERCENT: f64 = 90.0;
+
+/// Per-gram macros in the shape `Ingredient` stores them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IngredientMacros {
+    pub protein: Option<f32>,
+    pub carbs: Option<f32>,
+    pub fat: Option<f32>,
+    pub fiber: Option<f32>,
+}
+
+/// A whole-food product ("bananas") that is essentially one ingredient, with the
+/// product's macros standing in for that ingredient's
+#[derive(Debug, PartialEq)]
+pub struct WholeFoodProfile {
+    /// Ingredient name as product processing looks it up
+    pub ingredient: String,
+    pub macros: IngredientMacros,
+}
+
+/// Profile for a product made of a single dominant ingredient that has per-100g macros.
+///
+/// The ingredient is the only entry in OFF's `ingredients` (or one estimated at 90%+),
+/// or, without that array, an `ingredients_text` with no commas.
+pub fn whole_food_profile(product_data: &Value) -> Option<WholeFoodProfile> {
+    let ingredient = dominant_ingredient(product_data)?;
+    let macros = macros_per_gram(product_data)?;
+    Some(WholeFoodProfile { ingredient, macros })
+}
+
+fn dominant_ingredient(product_data: &Value) -> Option<String> {
+    let name = match product_data.get("ingredients").and_then(|v| v.as_array()) {
+        Some(ingredients) => {
+            let first = ingredients.first()?;
+            let percent = first
+                .get("percent")
+                .or_else(|| first.get("percent_estimate"))
+                .and_then(|v| v.as_f64());
+            if ingredients.len() > 1 && !percent.is_some_and(|p| p >= DOMINANT_INGREDIENT_PERCENT) {
+                return None;
+            }
+            off::ingredient_name(first)?
+        }
+        None => {
+            let text = product_data.get("ingredients_text")?.as_str()?;
+            if text.contains(',') {
+                return None;
+            }
+            Cow::Borrowed(text)
+        }
+    };
+
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Convert an amount per 100g to the per-gram `f32` the ingredient columns (`Float4`) hold.
+///
+/// The division happens in `f64` and only the result is narrowed, so the stored value is
+/// within f32's ~7 significant digits (relative error below 1e-7) of the exact one. f32
+/// reaches down to ~1e-38 (normal range), far below any real micronutrient in g/g (vitamin B12
+/// is ~1e-8), so tiny values keep their magnitude; a positive amount that would still
+/// underflow becomes the smallest positive f32 rather than zero.
+pub fn per_gram(amount_per_100g: f64) -> f32 {
+    let narrowed = (amount_per_100g / 100.0) as f32;
+    if narrowed == 0.0 && amount_per_100g > 0.0 {
+        f32::from_bits(1)
+    } else {
+        narrowed
+    }
+}
+
+/// The product's per-100g macros as grams per gram; None if it has none of them
+fn macros_per_gram(product_data: &Value) -> Option<IngredientMacros> {
+    let nutriments = product_data.get("nutriments")?;
+    let per_gram = |key: &str| {
+        nutriments
+            .get(key)
+            .and_then(|v| v.as_f64())
+            .filter(|grams| (0.0..=100.0).contains(grams))
+            .map(per_gram)
+    };
+
+    let macros = IngredientMacros {
+        protein: per_gram("proteins_100g"),
+        carbs: per_gram("carbohydrates_100g"),
+        fat: per_gram("fat_100g"),
+        fiber: per_gram("fiber_100g"),
+    };
+
+    let any = macros.protein.is_some() || macros.carbs.is_some() || macros.fat.is_some() || macros.fiber.is_some();
+    any.then_some(macros)
+}
+
+/// One of a product's linked ingredients, with its estimated share of the product
+#[derive(Debug, Clone)]
+pub struct IngredientPortion {
+    pub id: i32,
+    pub name: String,
+    /// Percent of the product by weight; None when the label gave nothing to estimate from
+    pub percent: Option<f32>,
+    pub macros: IngredientMacros,
+}
+
+/// Grams of each macro in 100g of product
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct MacrosPer100g {
+    pub protein: f64,
+    pub carbs: f64,
+    pub fat: f64,
+    pub fiber: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MissingNutrition {
+    pub id: i32,
+    pub name: String,
+    /// Macros the ingredient has no value for: "protein", "carbs", "fat", "fiber"
+    pub missing: Vec<&'static str>,
+}
+
+/// A product's macros summed from its ingredients'
+#[derive(Serialize, Debug, PartialEq)]
+pub struct IngredientMacroEstimate {
+    pub per_100g: MacrosPer100g,
+    /// Percent of the product (by weight) made of ingredients with all four macros known
+    pub coverage_percent: f64,
+    /// Ingredients lacking one or more macros; each counts as zero for the ones it lacks
+    pub missing_nutrition: Vec<MissingNutrition>,
+}
+
+/// Estimate a product's per-100g macros from its ingredients' per-gram macros, weighting
+/// each ingredient by its share of the product.
+///
+/// Ingredients without a share split whatever the known shares leave of 100% evenly (all of
+/// it when none is known). A missing macro adds nothing and lowers `coverage_percent`.
+pub fn estimate_from_ingredients(portions: &[IngredientPortion]) -> IngredientMacroEstimate {
+    let known: f64 = portions.iter().filter_map(|p| p.percent).map(f64::from).sum();
+    let unknown = portions.iter().filter(|p| p.percent.is_none()).count();
+    let unknown_share = match unknown {
+        0 => 0.0,
+        n => (100.0 - known).max(0.0) / n as f64,
+    };
+
+    let mut per_100g = MacrosPer100g::default();
+    let mut coverage_percent = 0.0;
+    let mut missing_nutrition = Vec::new();
+    for portion in portions {
+        let share = portion.percent.map_or(unknown_share, f64::from);
+        let IngredientMacros { protein, carbs, fat, fiber } = portion.macros;
+
+        let mut missing = Vec::new();
+        for (name, per_gram, total) in [
+            ("protein", protein, &mut per_100g.protein),
+            ("carbs", carbs, &mut per_100g.carbs),
+            ("fat", fat, &mut per_100g.fat),
+            ("fiber", fiber, &mut per_100g.fiber),
+        ] {
+            match per_gram {
+                // share is grams of ingredient per 100g of product
+                Some(per_gram) => *total += share * f64::from(per_gram),
+                None => missing.push(name),
+            }
+        }
+
+        if missing.is_empty() {
+            coverage_percent += share;
+        } else {
+            missing_nutrition.push(MissingNutrition { id: portion.id, name: portion.name.clone(), missing });
+        }
+    }
+
+    IngredientMacroEstimate {
+        per_100g,
+        coverage_percent: coverage_percent.min(100.0),
+        missing_nutrition,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn noodle_kit(serving_size: &str) -> Value {
+        let mut product = crate::fixtures::off_product("full");
+        product["serving_size"] = json!(serving_size);
+        product
+    }
+
+    #[test]
+    fn test_per_100g_basis() {
+        let facts = from_off_product(&noodle_kit("0.333 PACKAGE (52 g)"), NutritionBasis::Per100g, None);
+
+        assert_eq!(facts.basis, NutritionBasis::Per100g);
+        assert!(!facts.basis_fallback);
+        assert_eq!(facts.nutrients["carbohydrates"], 71.15);
+        assert_eq!(facts.nutrients["energy-kcal"], 385.0);
+        // Only the *_100g entries, without the suffix
+        assert_eq!(facts.nutrients.len(), 5);
+        assert!(!facts.nutrients.contains_key("carbohydrates_serving"));
+    }
+
+    #[test]
+    fn test_serving_basis_scales_by_serving_weight() {
+        let facts = from_off_product(&noodle_kit("0.333 PACKAGE (52 g)"), NutritionBasis::Serving, None);
+
+        assert_eq!(facts.basis, NutritionBasis::Serving);
+        assert!(!facts.basis_fallback);
+        assert_eq!(facts.serving_grams, Some(52.0));
+        let kcal = facts.nutrients["energy-kcal"].as_f64().unwrap();
+        assert!((kcal - 200.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_unparseable_serving_falls_back_to_100g() {
+        let facts = from_off_product(&noodle_kit("1 package"), NutritionBasis::Serving, None);
+
+        assert_eq!(facts.basis, NutritionBasis::Per100g);
+        assert!(facts.basis_fallback);
+        assert_eq!(facts.nutrients["carbohydrates"], 71.15);
+
+        let no_serving = from_off_product(&json!({ "nutriments": {} }), NutritionBasis::Serving, None);
+        assert!(no_serving.basis_fallback);
+        assert!(no_serving.nutrients.is_empty());
+    }
+
+    #[test]
+    fn test_package_basis_scales_by_package_weight() {
+        let facts = from_off_product(&noodle_kit("0.333 PACKAGE (52 g)"), NutritionBasis::Package, Some(155.0));
+
+        assert_eq!(facts.basis, NutritionBasis::Package);
+        assert!(!facts.basis_fallback);
+        let kcal = facts.nutrients["energy-kcal"].as_f64().unwrap();
+        assert!((kcal - 596.75).abs() < 1e-9);
+
+        let unknown_size = from_off_product(&noodle_kit("52 g"), NutritionBasis::Package, None);
+        assert_eq!(unknown_size.basis, NutritionBasis::Per100g);
+        assert!(unknown_size.basis_fallback);
+    }
+
+    #[test]
+    fn test_tiny_amounts_survive_per_gram_conversion() {
+        // g per 100g: vitamin B12, vitamin D, selenium, and an extreme trace
+        for amount in [2.4e-6, 1.1e-6, 5.5e-5, 1.0e-30] {
+            let stored = per_gram(amount);
+            let exact = amount / 100.0;
+            assert!(stored > 0.0, "{} rounded to zero", amount);
+            assert!(((stored as f64 - exact) / exact).abs() < 1e-7, "{} lost precision: {}", amount, stored);
+        }
+
+        // Below f32's range: clamped to the smallest positive value, never zero
+        assert_eq!(per_gram(1e-45), f32::from_bits(1));
+        assert_eq!(per_gram(0.0), 0.0);
+        assert_eq!(per_gram(71.15), 0.7115);
+    }
+
+    fn bananas() -> Value {
+        json!({
+            "product_name": "Bananas",
+            "ingredients_text": "Bananas",
+            "ingredients": [{ "id": "en:banana", "text": "Bananas", "percent_estimate": 100 }],
+            "nutriments": {
+                "proteins_100g": 1.09,
+                "carbohydrates_100g": 22.84,
+                "fat_100g": 0.33,
+                "fiber_100g": 2.6,
+                "energy-kcal_100g": 89
+            }
+        })
+    }
+
+    #[test]
+    fn test_single_ingredient_product_profile() {
+        let profile = whole_food_profile(&bananas()).unwrap();
+
+        assert_eq!(profile.ingredient, "Bananas");
+        assert!((profile.macros.protein.unwrap() - 0.0109).abs() < 1e-6);
+        assert!((profile.macros.carbs.unwrap() - 0.2284).abs() < 1e-6);
+        assert!((profile.macros.fat.unwrap() - 0.0033).abs() < 1e-6);
+        assert!((profile.macros.fiber.unwrap() - 0.026).abs() < 1e-6);
+
+        // Without the parsed array, a comma-free ingredients_text is the single ingredient
+        let mut text_only = bananas();
+        text_only.as_object_mut().unwrap().remove("ingredients");
+        assert_eq!(whole_food_profile(&text_only).unwrap().ingredient, "Bananas");
+    }
+
+    #[test]
+    fn test_dominant_ingredient_by_percent() {
+        let mut juice = bananas();
+        juice["ingredients"] = json!([
+            { "text": "Orange juice", "percent_estimate": 95 },
+            { "text": "Pulp", "percent_estimate": 5 }
+        ]);
+        assert_eq!(whole_food_profile(&juice).unwrap().ingredient, "Orange juice");
+
+        juice["ingredients"][0]["percent_estimate"] = json!(60);
+        assert_eq!(whole_food_profile(&juice), None);
+    }
+
+    #[test]
+    fn test_no_profile_for_composite_or_macro_less_products() {
+        assert_eq!(whole_food_profile(&noodle_kit("52 g")), None);
+
+        let mut no_macros = bananas();
+        no_macros["nutriments"] = json!({ "energy-kcal_100g": 89 });
+        assert_eq!(whole_food_profile(&no_macros), None);
+
+        let composite = json!({
+            "ingredients_text": "Rice noodles, peanut, sugar",
+            "nutriments": { "proteins_100g": 10 }
+        });
+        assert_eq!(whole_food_profile(&composite), None);
+    }
+
+    fn portion(id: i32, name: &str, percent: Option<f32>, macros: [Option<f32>; 4]) -> IngredientPortion {
+        let [protein, carbs, fat, fiber] = macros;
+        IngredientPortion {
+            id,
+            name: name.to_string(),
+            percent,
+            macros: IngredientMacros { protein, carbs, fat, fiber },
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-4, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_estimate_weights_ingredients_by_share() {
+        let estimate = estimate_from_ingredients(&[
+            portion(1, "Oats", Some(60.0), [Some(0.17), Some(0.66), Some(0.07), Some(0.1)]),
+            portion(2, "Honey", Some(30.0), [Some(0.003), Some(0.82), Some(0.0), Some(0.002)]),
+            portion(3, "Almonds", Some(10.0), [Some(0.21), Some(0.22), Some(0.5), Some(0.12)]),
+        ]);
+
+        assert_close(estimate.per_100g.protein, 60.0 * 0.17 + 30.0 * 0.003 + 10.0 * 0.21);
+        assert_close(estimate.per_100g.carbs, 60.0 * 0.66 + 30.0 * 0.82 + 10.0 * 0.22);
+        assert_close(estimate.per_100g.fat, 60.0 * 0.07 + 10.0 * 0.5);
+        assert_close(estimate.per_100g.fiber, 60.0 * 0.1 + 30.0 * 0.002 + 10.0 * 0.12);
+        assert_close(estimate.coverage_percent, 100.0);
+        assert!(estimate.missing_nutrition.is_empty());
+    }
+
+    #[test]
+    fn test_estimate_flags_ingredients_missing_macros() {
+        let estimate = estimate_from_ingredients(&[
+            portion(1, "Oats", Some(70.0), [Some(0.17), Some(0.66), Some(0.07), Some(0.1)]),
+            portion(2, "Mystery syrup", Some(20.0), [None, None, None, None]),
+            portion(3, "Cocoa", Some(10.0), [Some(0.2), Some(0.58), Some(0.14), None]),
+        ]);
+
+        // Missing values count as zero
+        assert_close(estimate.per_100g.protein, 70.0 * 0.17 + 10.0 * 0.2);
+        assert_close(estimate.per_100g.fiber, 70.0 * 0.1);
+        assert_close(estimate.coverage_percent, 70.0);
+        assert_eq!(
+            estimate.missing_nutrition,
+            vec![
+                MissingNutrition { id: 2, name: "Mystery syrup".to_string(), missing: vec!["protein", "carbs", "fat", "fiber"] },
+                MissingNutrition { id: 3, name: "Cocoa".to_string(), missing: vec!["fiber"] },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_estimate_splits_unknown_shares_evenly() {
+        let full = [Some(0.1), Some(0.2), Some(0.3), Some(0.4)];
+
+        // No shares known: each of the two is half the product
+        let even = estimate_from_ingredients(&[portion(1, "A", None, full), portion(2, "B", None, [Some(0.3); 4])]);
+        assert_close(even.per_100g.protein, 50.0 * 0.1 + 50.0 * 0.3);
+        assert_close(even.coverage_percent, 100.0);
+
+        // The unknown one gets what the known one leaves
+        let rest = estimate_from_ingredients(&[portion(1, "A", Some(80.0), full), portion(2, "B", None, [Some(0.5); 4])]);
+        assert_close(rest.per_100g.protein, 80.0 * 0.1 + 20.0 * 0.5);
+
+        let empty = estimate_from_ingredients(&[]);
+        assert_eq!(empty.per_100g, MacrosPer100g::default());
+        assert_eq!(empty.coverage_percent, 0.0);
+    }
+}
@@ -0,0 +1,215 @@
+//! Reading ingredient lists off OFF's ingredients-panel photo, for products OFF has no
+//! `ingredients_text` for. Needs an external OCR service, so it only runs with OCR_ENABLED
+//! (off by default).
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::http_client::{self, Upstream};
+
+/// Limit on each call to the OCR service, which is slower than a plain lookup
+const OCR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest photo downloaded for OCR. OFF's full-size photos stay far below this.
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+/// Something that turns a photo into text
+#[async_trait]
+pub trait OcrBackend: Send + Sync {
+    /// All text recognized in the image, `Err` when the service couldn't be asked
+    async fn recognize(&self, image: &[u8]) -> Result<String, String>;
+}
+
+/// OCR service at OCR_SERVICE_URL. The image is POSTed as the request body, with
+/// OCR_API_KEY as a bearer token when set, and the answer is JSON with the `text` found.
+pub struct HttpOcrBackend {
+    url: String,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OcrResponse {
+    text: String,
+}
+
+impl HttpOcrBackend {
+    pub fn new(url: impl Into<String>, api_key: Option<String>) -> Self {
+        HttpOcrBackend { url: url.into(), api_key }
+    }
+
+    /// Backend for OCR_SERVICE_URL, `None` when it isn't set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let url = config.ocr_service_url.clone()?;
+        Some(HttpOcrBackend::new(url, config.ocr_api_key.clone()))
+    }
+}
+
+#[async_trait]
+impl OcrBackend for HttpOcrBackend {
+    async fn recognize(&self, image: &[u8]) -> Result<String, String> {
+        let mut request = http_client::client()
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .timeout(OCR_TIMEOUT)
+            .body(image.to_vec());
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await.map_err(|e| format!("Failed to call OCR service: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("OCR service answered {}", response.status()));
+        }
+        let body = http_client::json::<OcrResponse>(response)
+            .await
+            .map_err(|e| format!("Unusable OCR response: {}", e))?;
+
+        Ok(body.text)
+    }
+}
+
+/// Download a product photo from OFF's image server. A photo over `MAX_IMAGE_BYTES` is
+/// refused by its `Content-Length` before reading, or as soon as the body read so far
+/// passes the limit, so an oversized or unannounced body is never held whole.
+pub async fn download_image(url: &str) -> Result<Vec<u8>, String> {
+    let mut response = http_client::get(Upstream::OpenFoodFacts, url)
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Downloading {} answered {}", url, response.status()));
+    }
+    if let Some(length) = response.content_length().filter(|&length| length > MAX_IMAGE_BYTES as u64) {
+        return Err(too_large(url, length as usize));
+    }
+
+    let mut image = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| format!("Failed to download {}: {}", url, e))? {
+        if image.len() + chunk.len() > MAX_IMAGE_BYTES {
+            return Err(too_large(url, image.len() + chunk.len()));
+        }
+        image.extend_from_slice(&chunk);
+    }
+
+    Ok(image)
+}
+
+fn too_large(url: &str, bytes: usize) -> String {
+    format!("{} is at least {} bytes, more than the {} accepted", url, bytes, MAX_IMAGE_BYTES)
+}
+
+/// Ingredients text from what OCR recognized on an ingredients panel: line breaks and
+/// runs of spaces become single spaces, and the panel's "Ingredients:" heading and a
+/// closing full stop are dropped. `None` when nothing is left.
+pub fn ingredients_text(recognized: &str) -> Option<String> {
+    let text = recognized.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let heading = "ingredients";
+    let text = match text.get(..heading.len()) {
+        Some(start) if start.eq_ignore_ascii_case(heading) => &text[heading.len()..],
+        _ => text.as_str(),
+    };
+    let text = text.trim_start_matches([':', ' ']).trim_end_matches(['.', ' ']);
+
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ingredients_text_from_recognized_panel() {
+        assert_eq!(
+            ingredients_text("INGREDIENTS: Rolled oats,\n  honey (12%),\nalmonds.\n").as_deref(),
+            Some("Rolled oats, honey (12%), almonds")
+        );
+        assert_eq!(ingredients_text("Ingredients : water, salt").as_deref(), Some("water, salt"));
+        assert_eq!(ingredients_text("sugar, cocoa butter").as_deref(), Some("sugar, cocoa butter"));
+        assert_eq!(ingredients_text("Ingrédients: sucre").as_deref(), Some("Ingrédients: sucre"));
+        assert_eq!(ingredients_text(" Ingredients:\n"), None);
+        assert_eq!(ingredients_text(""), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_http_backend_against_mock_service() {
+        use wiremock::matchers::{body_bytes, header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/ocr"))
+            .and(header("authorization", "Bearer secret"))
+            .and(body_bytes(b"jpeg bytes".to_vec()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "text": "Ingredients: water" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/down"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let backend = HttpOcrBackend::new(format!("{}/ocr", server.uri()), Some("secret".to_string()));
+        assert_eq!(backend.recognize(b"jpeg bytes").await.as_deref(), Ok("Ingredients: water"));
+
+        let down = HttpOcrBackend::new(format!("{}/down", server.uri()), None);
+        assert!(down.recognize(b"jpeg bytes").await.unwrap_err().contains("503"));
+    }
+
+    #[actix_rt::test]
+    async fn test_download_image_refuses_oversized_photos() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/front.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"jpeg bytes".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/huge.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0; MAX_IMAGE_BYTES + 1]))
+            .mount(&server)
+            .await;
+
+        let photo = download_image(&format!("{}/front.jpg", server.uri())).await;
+        assert_eq!(photo.as_deref(), Ok(&b"jpeg bytes"[..]));
+
+        let refused = download_image(&format!("{}/huge.jpg", server.uri())).await.unwrap_err();
+        assert!(refused.contains("more than the 10485760 accepted"), "{}", refused);
+    }
+
+    #[actix_rt::test]
+    async fn test_download_image_stops_reading_an_unannounced_oversized_body() {
+        use std::io::{Read, Write};
+
+        // Chunked, so there's no Content-Length to refuse up front; the server would send
+        // twice the limit if it were all read
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/huge.jpg", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let _ = socket.read(&mut [0; 4096]);
+            let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n");
+            let chunk = vec![0; 1024 * 1024];
+            for _ in 0..(2 * MAX_IMAGE_BYTES / chunk.len()) {
+                let sent = socket
+                    .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                    .and_then(|_| socket.write_all(&chunk))
+                    .and_then(|_| socket.write_all(b"\r\n"));
+                if sent.is_err() {
+                    return;
+                }
+            }
+            let _ = socket.write_all(b"0\r\n\r\n");
+        });
+
+        let refused = download_image(&url).await.unwrap_err();
+        assert!(refused.contains("more than the 10485760 accepted"), "{}", refused);
+    }
+}
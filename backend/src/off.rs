@@ -0,0 +1,945 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::allergens;
+use crate::diet;
+use crate::models::NewProduct;
+use crate::quantity;
+
+/// The single-valued fields we read from an OFF `product` object.
+///
+/// Lenient like the rest of OFF handling: a missing, empty or wrongly typed field is
+/// `None` rather than a failed product. Strings are trimmed, and integers also accept
+/// OFF's occasional numeric strings ("4") and whole floats (4.0).
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct OffProduct {
+    #[serde(deserialize_with = "lenient_string")]
+    pub product_name: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub brands: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub categories: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub quantity: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub image_url: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub image_front_url: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub image_ingredients_url: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub image_nutrition_url: Option<String>,
+    /// As OFF sent it; see [`normalize_grade`]
+    #[serde(deserialize_with = "lenient_string")]
+    pub nutriscore_grade: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub ecoscore_grade: Option<String>,
+    /// 1 to 4; anything else is dropped
+    #[serde(deserialize_with = "nova_group")]
+    pub nova_group: Option<i32>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub ingredients_text: Option<String>,
+    #[serde(deserialize_with = "lenient_string")]
+    pub allergens: Option<String>,
+    /// OFF's revision counter for the product, used to skip refreshes that changed nothing
+    #[serde(deserialize_with = "lenient_int")]
+    pub rev: Option<i32>,
+}
+
+impl OffProduct {
+    /// The typed fields of an OFF `product` object; all `None` if it isn't an object
+    pub fn from_value(product_data: &Value) -> Self {
+        OffProduct::deserialize(product_data).unwrap_or_default()
+    }
+
+    /// URLs of the product photos OFF has, keyed by kind (`{"front": ..., "ingredients": ...}`).
+    /// Kinds without a photo are left out, and `front` falls back to `image_url`, which older
+    /// products carry alone. `None` without any photo.
+    pub fn images(&self) -> Option<Value> {
+        let kinds = [
+            ("front", self.image_front_url.as_ref().or(self.image_url.as_ref())),
+            ("ingredients", self.image_ingredients_url.as_ref()),
+            ("nutrition", self.image_nutrition_url.as_ref()),
+        ];
+
+        let images: serde_json::Map<String, Value> = kinds
+            .into_iter()
+            .filter_map(|(kind, url)| Some((kind.to_string(), Value::String(url?.clone()))))
+            .collect();
+        (!images.is_empty()).then_some(Value::Object(images))
+    }
+}
+
+/// Non-empty, trimmed string; anything else is `None`
+fn lenient_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+        _ => None,
+    })
+}
+
+/// Integer, from a number or a numeric string. Fractions and values outside `i32` are
+/// dropped rather than truncated.
+fn lenient_int<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i32>, D::Error> {
+    let number = match Value::deserialize(deserializer)? {
+        Value::Number(n) => n
+            .as_i64()
+            .or_else(|| n.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64)),
+        Value::String(s) => s.trim().parse::<i64>().ok(),
+        _ => None,
+    };
+
+    Ok(number.and_then(|n| i32::try_from(n).ok()))
+}
+
+fn nova_group<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i32>, D::Error> {
+    Ok(lenient_int(deserializer)?.filter(|group| (1..=4).contains(group)))
+}
+
+/// Map an OpenFoodFacts `product` object onto the columns we store.
+///
+/// Lenient: missing, empty or wrongly typed fields become `None` instead of failing
+/// the whole product (see [`OffProduct`]). The raw object is kept in `full_response`,
+/// less any fields [`StoredFields`] leaves out. Grades without a
+/// score are stored according to UNKNOWN_GRADES (see [`UnknownGrades`]).
+pub fn extract(barcode: &str, product_data: &Value) -> NewProduct {
+    let config = crate::config::get();
+    let unknown_grades = config.unknown_grades;
+    let product = OffProduct::from_value(product_data);
+    let grade = |grade: &Option<String>| grade.as_deref().and_then(|grade| normalize_grade(grade, unknown_grades));
+
+    // Certification labels override OFF's ingredient-based diet inference
+    let label_slugs = product_data
+        .get("labels_tags")
+        .map(diet::normalize_labels)
+        .unwrap_or_default();
+    let package_size = quantity::from_off(product_data);
+    let diet_flags = diet::classify(
+        &label_slugs,
+        product_data.get("ingredients_analysis_tags").unwrap_or(&Value::Null),
+    );
+
+    NewProduct {
+        barcode: barcode.to_string(),
+        nutriscore_grade: grade(&product.nutriscore_grade),
+        ecoscore_grade: grade(&product.ecoscore_grade),
+        nova_group: product.nova_group,
+        full_response: config.stored_fields.project(product_data),
+        off_rev: product.rev,
+        nutrient_levels: nutrient_levels(product_data),
+        labels: (!label_slugs.is_empty()).then(|| serde_json::json!(label_slugs)),
+        diet: serde_json::to_value(&diet_flags).ok(),
+        data_source: None,
+        allergen_tags: allergens::from_off(product_data, "allergens_tags", "allergens"),
+        trace_tags: allergens::from_off(product_data, "traces_tags", "traces"),
+        brand_tags: brand_tags(product_data),
+        product_quantity: package_size.as_ref().map(|size| size.amount),
+        product_quantity_unit: package_size.map(|size| size.unit),
+        images: product.images(),
+        product_name: product.product_name,
+        brands: product.brands,
+        categories: product.categories,
+        quantity: product.quantity,
+        image_url: product.image_url,
+        ingredients_text: product.ingredients_text,
+        allergens: product.allergens,
+    }
+}
+
+/// Brand slugs from OFF's `brands_tags`, or slugified from the comma-separated `brands`
+/// text when OFF didn't send tags. `None` when there is neither.
+pub fn brand_tags(product_data: &Value) -> Option<Value> {
+    let slugs = match product_data.get("brands_tags").filter(|tags| tags.is_array()) {
+        Some(tags) => diet::normalize_labels(tags),
+        None => {
+            let brands = product_data.get("brands").and_then(|v| v.as_str())?;
+            let mut slugs: Vec<String> = Vec::new();
+            for slug in brands.split(',').map(brand_slug).filter(|slug| !slug.is_empty()) {
+                if !slugs.contains(&slug) {
+                    slugs.push(slug);
+                }
+            }
+            slugs
+        }
+    };
+
+    Some(serde_json::json!(slugs))
+}
+
+/// OFF-style slug for a brand name: lowercase, runs of anything but letters and digits
+/// collapsed to a single `-` ("Ben & Jerry's" -> "ben-jerry-s")
+pub fn brand_slug(name: &str) -> String {
+    let lower = name.to_lowercase();
+    lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// How grades OFF couldn't compute ("unknown", "not-applicable") are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownGrades {
+    /// Store NULL, same as a product with no grade at all
+    Null,
+    /// Store the "unknown" sentinel so "OFF has no score" stays distinguishable from "never fetched".
+    /// The default, and what migration `normalize_product_grades` wrote for existing rows.
+    Sentinel,
+}
+
+impl UnknownGrades {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "null" => Some(UnknownGrades::Null),
+            "unknown" => Some(UnknownGrades::Sentinel),
+            _ => None,
+        }
+    }
+}
+
+/// Top-level OFF fields read back from a stored `full_response` (ingredient processing,
+/// diet flags, nutrition, safety coverage), so [`StoredFields`] never strips them
+pub const REQUIRED_STORED_FIELDS: [&str; 5] =
+    ["ingredients", "ingredients_text", "ingredients_analysis_tags", "nutriments", "serving_size"];
+
+/// Which top-level fields of an OFF product are kept in `full_response`
+/// (FULL_RESPONSE_KEEP_FIELDS, FULL_RESPONSE_DROP_FIELDS). Everything by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoredFields {
+    /// Only these fields (plus [`REQUIRED_STORED_FIELDS`]) when set
+    pub keep: Option<Vec<String>>,
+    /// Fields left out, unless required
+    pub drop: Vec<String>,
+}
+
+impl StoredFields {
+    /// The product as it should be stored. Fields extracted into columns are read from
+    /// the original, so this only trims what `full_response` keeps for later.
+    pub fn project(&self, product_data: &Value) -> Value {
+        let Some(fields) = product_data.as_object() else {
+            return product_data.clone();
+        };
+        if self.keep.is_none() && self.drop.is_empty() {
+            return product_data.clone();
+        }
+
+        let stored = fields
+            .iter()
+            .filter(|(key, _)| {
+                REQUIRED_STORED_FIELDS.contains(&key.as_str())
+                    || (self.keep.as_ref().is_none_or(|keep| keep.contains(key)) && !self.drop.contains(key))
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Value::Object(stored)
+    }
+}
+
+/// Canonical lowercase grade "a"–"e", so filters can compare stored values directly.
+/// "unknown" and "not-applicable" follow `unknown`; anything else unrecognised is dropped.
+pub fn normalize_grade(grade: &str, unknown: UnknownGrades) -> Option<String> {
+    let grade = grade.trim().to_lowercase();
+    match grade.as_str() {
+        "a" | "b" | "c" | "d" | "e" => Some(grade),
+        "unknown" | "not-applicable" => match unknown {
+            UnknownGrades::Null => None,
+            UnknownGrades::Sentinel => Some("unknown".to_string()),
+        },
+        _ => None,
+    }
+}
+
+/// Extract OFF's qualitative `nutrient_levels` (fat/saturated-fat/sugars/salt -> low/moderate/high)
+fn nutrient_levels(product_data: &Value) -> Option<Value> {
+    let levels = product_data.get("nutrient_levels")?.as_object()?;
+
+    let normalized: serde_json::Map<String, Value> = levels
+        .iter()
+        .filter_map(|(nutrient, level)| {
+            let level = level.as_str()?.trim().to_lowercase();
+            matches!(level.as_str(), "low" | "moderate" | "high")
+                .then(|| (nutrient.clone(), Value::String(level)))
+        })
+        .collect();
+
+    if normalized.is_empty() {
+        None
+    } else {
+        Some(Value::Object(normalized))
+    }
+}
+
+/// Hex SHA-256 of the ingredient list product processing reads: OFF's `ingredients`
+/// objects when present (each one's name, taxonomy `id` and share, so a changed
+/// `percent_estimate` counts as a change), otherwise `ingredients_text`. Names are
+/// lowercased with whitespace collapsed and blank text entries dropped, so re-spaced or
+/// re-cased lists hash the same. `None` without any list.
+pub fn ingredients_hash(product_data: &Value) -> Option<String> {
+    let canonical_name = |name: &str| name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+
+    let canonical = match product_data.get("ingredients").and_then(|v| v.as_array()) {
+        Some(ingredients) => {
+            let shares = ingredient_shares(&ingredients.iter().collect::<Vec<_>>());
+            ingredients
+                .iter()
+                .zip(shares)
+                .map(|(ingredient, share)| {
+                    format!(
+                        "{}|{}|{}|{:?}",
+                        ingredient_name(ingredient).map(|name| canonical_name(&name)).unwrap_or_default(),
+                        ingredient.get("id").and_then(|v| v.as_str()).unwrap_or(""),
+                        share.percent,
+                        share.source
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(";")
+        }
+        None => product_data
+            .get("ingredients_text")?
+            .as_str()?
+            .split(',')
+            .map(canonical_name)
+            .filter(|entry| !entry.is_empty())
+            .collect::<Vec<_>>()
+            .join(","),
+    };
+
+    (!canonical.is_empty()).then(|| format!("{:x}", Sha256::digest(canonical.as_bytes())))
+}
+
+/// Name of one of OFF's `ingredients` objects: its `text`, else its `id` read as a name
+/// (see [`taxonomy_name`]), else a translated `text_en`/`text_<lang>`, else a plain
+/// `name`. Some entries carry none of these (only `vignette` or `ciqual_food_code`), and are `None`.
+pub fn ingredient_name(ingredient: &Value) -> Option<Cow<'_, str>> {
+    let named = |key: &str| ingredient.get(key).and_then(|v| v.as_str()).filter(|name| !name.trim().is_empty());
+
+    if let Some(text) = named("text") {
+        return Some(Cow::Borrowed(text));
+    }
+    if let Some(id) = named("id") {
+        return Some(Cow::Owned(taxonomy_name(id)));
+    }
+
+    named("text_en")
+        .or_else(|| {
+            ingredient
+                .as_object()?
+                .keys()
+                .filter(|key| key.starts_with("text_"))
+                .find_map(|key| named(key))
+        })
+        .or_else(|| named("name"))
+        .map(Cow::Borrowed)
+}
+
+/// Readable name for an OFF taxonomy id: the language prefix dropped and the slug
+/// spelled out, so "en:cocoa-butter" becomes "Cocoa Butter" rather than being stored as is
+pub fn taxonomy_name(id: &str) -> String {
+    let slug = match id.split_once(':') {
+        Some((lang, rest)) if (2..=3).contains(&lang.len()) && lang.chars().all(|c| c.is_ascii_lowercase()) => rest,
+        _ => id,
+    };
+
+    slug.split('-')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Keys of an ingredient object, for logging entries [`ingredient_name`] found no name in
+pub fn ingredient_keys(ingredient: &Value) -> String {
+    match ingredient.as_object() {
+        Some(fields) => fields.keys().map(String::as_str).collect::<Vec<_>>().join(","),
+        None => format!("(not an object: {})", ingredient),
+    }
+}
+
+/// One named entry of a product's ingredient list
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedIngredient {
+    pub name: String,
+    /// 1-based position in the list
+    pub rank: i32,
+    pub share: IngredientShare,
+}
+
+/// The first `max` entries of a product's ingredient list, the way product processing
+/// links them: OFF's `ingredients` objects (named by [`ingredient_name`]) or, without
+/// them, `ingredients_text` split on commas. Unnamed entries are skipped but keep their rank.
+pub fn listed_ingredients(product_data: &Value, max: usize) -> Vec<ListedIngredient> {
+    let (names, shares): (Vec<Option<Cow<str>>>, Vec<IngredientShare>) =
+        match product_data.get("ingredients").and_then(|v| v.as_array()) {
+            Some(ingredients) => {
+                let ingredients: Vec<&Value> = ingredients.iter().take(max).collect();
+                let names = ingredients
+                    .iter()
+                    .map(|i| ingredient_name(i))
+                    .collect();
+                (names, ingredient_shares(&ingredients))
+            }
+            None => {
+                let text = product_data.get("ingredients_text").and_then(|v| v.as_str()).unwrap_or("");
+                let names: Vec<Option<Cow<str>>> = text.split(',').take(max).map(|name| Some(Cow::Borrowed(name))).collect();
+                let shares = rank_shares(names.len());
+                (names, shares)
+            }
+        };
+
+    names
+        .into_iter()
+        .zip(shares)
+        .enumerate()
+        .filter_map(|(index, (name, share))| {
+            let name = name?;
+            let name = name.trim();
+            (!name.is_empty()).then(|| ListedIngredient {
+                name: name.to_string(),
+                rank: index as i32 + 1,
+                share,
+            })
+        })
+        .collect()
+}
+
+/// Where an ingredient's share of the product came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PercentSource {
+    /// The `percent` printed on the label, as entered on OFF
+    Declared,
+    /// OFF's own `percent_estimate`
+    Estimated,
+    /// Our fallback from the ingredient's position in the list
+    Rank,
+}
+
+impl PercentSource {
+    /// Value stored in `product_ingredients.percent_source`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PercentSource::Declared => "percent",
+            PercentSource::Estimated => "percent_estimate",
+            PercentSource::Rank => "rank",
+        }
+    }
+}
+
+/// Estimated percentage of the product made up by one ingredient
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IngredientShare {
+    pub percent: f32,
+    pub source: PercentSource,
+}
+
+/// Shares for a product's ingredient objects, in list order.
+///
+/// Each entry uses OFF's `percent`, else its `percent_estimate`. Whatever the known
+/// figures leave of 100% is split over the remaining entries by rank (1, 1/2, 1/3, ...),
+/// since labels list ingredients by decreasing weight.
+pub fn ingredient_shares(ingredients: &[&Value]) -> Vec<IngredientShare> {
+    shares_from(ingredients.iter().map(|ingredient| declared_share(ingredient)).collect())
+}
+
+/// Rank-only shares for `count` ingredients that carry no figures (e.g. split from `ingredients_text`)
+pub fn rank_shares(count: usize) -> Vec<IngredientShare> {
+    shares_from(vec![None; count])
+}
+
+fn declared_share(ingredient: &Value) -> Option<IngredientShare> {
+    [("percent", PercentSource::Declared), ("percent_estimate", PercentSource::Estimated)]
+        .into_iter()
+        .find_map(|(key, source)| {
+            let percent = match ingredient.get(key)? {
+                Value::Number(n) => n.as_f64()?,
+                Value::String(s) => s.trim().trim_end_matches('%').trim().parse().ok()?,
+                _ => return None,
+            };
+            (0.0..=100.0)
+                .contains(&percent)
+                .then_some(IngredientShare {
+                    percent: percent as f32,
+                    source,
+                })
+        })
+}
+
+fn shares_from(declared: Vec<Option<IngredientShare>>) -> Vec<IngredientShare> {
+    let known: f32 = declared.iter().flatten().map(|share| share.percent).sum();
+    let remaining = (100.0 - known).max(0.0);
+    let rank_weight = |index: usize| 1.0 / (index + 1) as f32;
+    let unknown_weight: f32 = declared
+        .iter()
+        .enumerate()
+        .filter(|(_, share)| share.is_none())
+        .map(|(index, _)| rank_weight(index))
+        .sum();
+
+    declared
+        .iter()
+        .enumerate()
+        .map(|(index, share)| {
+            share.unwrap_or_else(|| IngredientShare {
+                percent: remaining * rank_weight(index) / unknown_weight,
+                source: PercentSource::Rank,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use serde_json::json;
+
+    #[test]
+    fn test_extract_present_fields() {
+        let full = fixtures::off_product("full");
+        let product = extract("0737628064502", &full);
+
+        assert_eq!(product.barcode, "0737628064502");
+        assert_eq!(product.brands.as_deref(), Some("Simply Asia, Thai Kitchen"));
+        assert_eq!(product.quantity.as_deref(), Some("155 g"));
+        assert_eq!(product.product_quantity, Some(155.0));
+        assert_eq!(product.product_quantity_unit.as_deref(), Some("g"));
+        assert_eq!(product.nutriscore_grade.as_deref(), Some("d"));
+        assert_eq!(product.nova_group, Some(4));
+        assert_eq!(product.allergens.as_deref(), Some("en:peanuts"));
+        assert_eq!(product.off_rev, Some(42));
+        assert_eq!(product.labels, Some(json!(["no-gluten"])));
+        assert_eq!(product.diet.unwrap()["gluten_free"], true);
+        assert_eq!(product.nutrient_levels.unwrap()["sugars"], "high");
+        assert_eq!(product.brand_tags, Some(json!(["simply-asia", "thai-kitchen"])));
+        assert_eq!(product.allergen_tags, Some(json!(["peanuts"])));
+        assert_eq!(product.trace_tags, Some(json!(["soybeans"])));
+        assert_eq!(product.full_response, full);
+    }
+
+    #[test]
+    fn test_extract_minimal_product() {
+        let product = extract("5000112637922", &fixtures::off_product("minimal"));
+
+        assert_eq!(product.product_name.as_deref(), Some("Sparkling water"));
+        assert!(product.brands.is_none());
+        assert!(product.quantity.is_none());
+        assert!(product.product_quantity.is_none());
+        assert!(product.ingredients_text.is_none());
+        assert!(product.allergen_tags.is_none());
+        assert!(product.brand_tags.is_none());
+        assert!(product.nutrient_levels.is_none());
+    }
+
+    #[test]
+    fn test_extract_multilingual_product() {
+        let product = extract("3017620422003", &fixtures::off_product("multilingual"));
+
+        // The unsuffixed fields are in the product's main language, kept as OFF sent them
+        assert_eq!(product.product_name.as_deref(), Some("Nutella"));
+        assert!(product.ingredients_text.unwrap().starts_with("Sucre, huile de palme, NOISETTES 13%"));
+        assert_eq!(
+            product.categories.as_deref(),
+            Some("Petit-déjeuners, Produits à tartiner sucrés, Pâtes à tartiner aux noisettes")
+        );
+        // Tags lose their language prefix whatever the language
+        assert_eq!(product.labels, Some(json!(["triman", "sustainable-palm-oil"])));
+        assert_eq!(product.allergen_tags, Some(json!(["milk", "nuts", "soybeans"])));
+        assert_eq!(product.trace_tags, Some(json!([])));
+        assert_eq!(product.product_quantity, Some(400.0));
+        assert_eq!(product.off_rev, Some(318));
+        assert_eq!(product.diet.unwrap()["vegan"], false);
+    }
+
+    #[test]
+    fn test_brand_tags_for_multi_and_single_brand_products() {
+        let multi = json!({
+            "brands": "Simply Asia, Thai Kitchen",
+            "brands_tags": ["simply-asia", "thai-kitchen", "xx:Thai-Kitchen"]
+        });
+        let product = extract("0737628064502", &multi);
+        assert_eq!(product.brand_tags, Some(json!(["simply-asia", "thai-kitchen"])));
+        assert_eq!(product.brands.as_deref(), Some("Simply Asia, Thai Kitchen"));
+
+        let single = json!({ "brands": "Ferrero", "brands_tags": ["ferrero"] });
+        assert_eq!(brand_tags(&single), Some(json!(["ferrero"])));
+    }
+
+    #[test]
+    fn test_brand_tags_fall_back_to_brands_text() {
+        assert_eq!(
+            brand_tags(&json!({ "brands": "Ben & Jerry's, Unilever ,, ben & jerry's" })),
+            Some(json!(["ben-jerry-s", "unilever"]))
+        );
+        assert_eq!(brand_tags(&json!({ "brands": "Nestlé" })), Some(json!(["nestlé"])));
+        assert_eq!(brand_tags(&json!({ "brands": ["Ferrero"] })), None);
+        assert_eq!(brand_tags(&json!({})), None);
+    }
+
+    #[test]
+    fn test_listed_ingredients_keep_list_rank() {
+        let product = json!({
+            "ingredients": [
+                { "text": "Rice noodles", "percent_estimate": 60 },
+                { "percent_estimate": 10 },
+                { "id": "en:peanut" }
+            ]
+        });
+        let listed = listed_ingredients(&product, 10);
+        assert_eq!(listed.len(), 2);
+        assert_eq!((listed[0].name.as_str(), listed[0].rank), ("Rice noodles", 1));
+        assert_eq!((listed[1].name.as_str(), listed[1].rank), ("Peanut", 3));
+        assert_eq!(listed[1].share.source, PercentSource::Rank);
+
+        let text_only = json!({ "ingredients_text": "Water, , Salt, Pepper" });
+        let listed = listed_ingredients(&text_only, 3);
+        assert_eq!(listed.iter().map(|l| (l.name.as_str(), l.rank)).collect::<Vec<_>>(), vec![("Water", 1), ("Salt", 3)]);
+    }
+
+    #[test]
+    fn test_nameless_ingredient_entries() {
+        let product = crate::fixtures::off_product("nameless_ingredients");
+        let entries = product["ingredients"].as_array().unwrap();
+        let names: Vec<_> = entries.iter().map(ingredient_name).collect();
+        assert_eq!(
+            names.iter().map(Option::as_deref).collect::<Vec<_>>(),
+            vec![Some("Oat flakes"), Some("honey"), Some("sunflower oil"), None, None, Some("Honey")]
+        );
+        assert_eq!(ingredient_keys(&entries[3]), "ciqual_food_code,percent_estimate,vignette");
+        assert_eq!(ingredient_name(&json!({ "text": " ", "id": "en:salt" })).as_deref(), Some("Salt"));
+        assert_eq!(ingredient_name(&json!({ "text_fr": "sel" })).as_deref(), Some("sel"));
+
+        // The named entries keep their place in the list
+        let listed = listed_ingredients(&product, 10);
+        assert_eq!(
+            listed.iter().map(|l| (l.name.as_str(), l.rank)).collect::<Vec<_>>(),
+            vec![("Oat flakes", 1), ("honey", 2), ("sunflower oil", 3), ("Honey", 6)]
+        );
+    }
+
+    #[test]
+    fn test_taxonomy_ids_read_as_names() {
+        assert_eq!(taxonomy_name("en:cocoa-butter"), "Cocoa Butter");
+        assert_eq!(taxonomy_name("fr:crème-fraîche"), "Crème Fraîche");
+        assert_eq!(taxonomy_name("sugar"), "Sugar");
+        assert_eq!(taxonomy_name("en:e330"), "E330");
+
+        // Only without `text`
+        assert_eq!(ingredient_name(&json!({ "id": "en:cocoa-butter" })).as_deref(), Some("Cocoa Butter"));
+        assert_eq!(
+            ingredient_name(&json!({ "id": "en:cocoa-butter", "text": "cocoa butter" })).as_deref(),
+            Some("cocoa butter")
+        );
+    }
+
+    #[test]
+    fn test_stored_fields_strip_and_keep() {
+        let product = json!({
+            "product_name": "Granola",
+            "ingredients_text": "Oats, honey",
+            "ingredients_hierarchy": ["en:oats", "en:honey"],
+            "debug_info": "..."
+        });
+
+        assert_eq!(StoredFields::default().project(&product), product);
+
+        let dropping = StoredFields {
+            keep: None,
+            drop: vec!["ingredients_hierarchy".to_string(), "debug_info".to_string()],
+        };
+        assert_eq!(
+            dropping.project(&product),
+            json!({ "product_name": "Granola", "ingredients_text": "Oats, honey" })
+        );
+
+        // Required fields survive an allow-list that forgets them
+        let keeping = StoredFields { keep: Some(vec!["product_name".to_string()]), drop: Vec::new() };
+        assert_eq!(
+            keeping.project(&product),
+            json!({ "product_name": "Granola", "ingredients_text": "Oats, honey" })
+        );
+    }
+
+    #[test]
+    fn test_off_product_from_recorded_payloads() {
+        let full = OffProduct::from_value(&fixtures::off_product("full"));
+        assert_eq!(full.brands.as_deref(), Some("Simply Asia, Thai Kitchen"));
+        assert_eq!(full.nova_group, Some(4));
+        assert_eq!(full.rev, Some(42));
+        assert_eq!(full.ecoscore_grade.as_deref(), Some("unknown"));
+
+        let minimal = OffProduct::from_value(&fixtures::off_product("minimal"));
+        assert_eq!(
+            minimal,
+            OffProduct { product_name: Some("Sparkling water".to_string()), ..OffProduct::default() }
+        );
+
+        // The same fields as strings, or of the wrong type altogether
+        let older = OffProduct::from_value(&fixtures::off_product("string_numbers"));
+        assert_eq!(older.product_name.as_deref(), Some("Pâte à tartiner aux noisettes"));
+        assert_eq!(older.nova_group, Some(4));
+        assert_eq!(older.rev, Some(57));
+        assert_eq!(older.nutriscore_grade.as_deref(), Some("E"));
+        assert_eq!(older.ecoscore_grade, None);
+        assert_eq!(older.categories, None);
+
+        assert_eq!(OffProduct::from_value(&json!("not a product")), OffProduct::default());
+    }
+
+    #[test]
+    fn test_nova_group_typing() {
+        for (nova_group, expected) in [
+            (json!(4), Some(4)),
+            (json!("4"), Some(4)),
+            (json!(" 2 "), Some(2)),
+            (json!(1.0), Some(1)),
+            (json!("unknown"), None),
+            (json!(""), None),
+            (json!(null), None),
+            (json!(2.5), None),
+            (json!(0), None),
+            (json!("5"), None),
+            (json!([4]), None),
+            (json!(true), None),
+        ] {
+            let product = OffProduct::from_value(&json!({ "product_name": "Nova Test", "nova_group": nova_group }));
+            assert_eq!(product.nova_group, expected, "nova_group {}", nova_group);
+            // A bad nova_group never costs the other fields
+            assert_eq!(product.product_name.as_deref(), Some("Nova Test"));
+        }
+    }
+
+    #[test]
+    fn test_extract_absent_fields() {
+        let product = extract("123", &json!({}));
+
+        assert_eq!(product.barcode, "123");
+        assert!(product.product_name.is_none());
+        assert!(product.nova_group.is_none());
+        assert!(product.off_rev.is_none());
+        assert!(product.labels.is_none());
+        assert!(product.nutrient_levels.is_none());
+        assert_eq!(product.diet.unwrap()["vegan"], Value::Null);
+    }
+
+    #[test]
+    fn test_extract_wrong_types() {
+        let product = extract(
+            "123",
+            &json!({
+                "product_name": 12,
+                "brands": ["Ferrero"],
+                "quantity": "   ",
+                "nova_group": "4",
+                "rev": "17",
+                "nutriscore_grade": "E"
+            }),
+        );
+
+        assert!(product.product_name.is_none());
+        assert!(product.brands.is_none());
+        assert!(product.quantity.is_none());
+        assert_eq!(product.nova_group, Some(4));
+        assert_eq!(product.off_rev, Some(17));
+        assert_eq!(product.nutriscore_grade.as_deref(), Some("e"));
+    }
+
+    #[test]
+    fn test_grades_are_normalized() {
+        for (input, expected) in [
+            ("a", Some("a")),
+            ("B", Some("b")),
+            (" c ", Some("c")),
+            ("d", Some("d")),
+            ("E", Some("e")),
+            ("f", None),
+            ("a-plus", None),
+            ("", None),
+        ] {
+            assert_eq!(normalize_grade(input, UnknownGrades::Null).as_deref(), expected, "input {:?}", input);
+            assert_eq!(normalize_grade(input, UnknownGrades::Sentinel).as_deref(), expected, "input {:?}", input);
+        }
+
+        let product = extract("123", &json!({ "nutriscore_grade": "D", "ecoscore_grade": "not-applicable" }));
+        assert_eq!(product.nutriscore_grade.as_deref(), Some("d"));
+        // UNKNOWN_GRADES defaults to the sentinel the grade migration stored
+        assert_eq!(product.ecoscore_grade.as_deref(), Some("unknown"));
+    }
+
+    #[test]
+    fn test_unscored_grades_follow_configured_handling() {
+        for input in ["unknown", "Unknown", "not-applicable", "NOT-APPLICABLE"] {
+            assert_eq!(normalize_grade(input, UnknownGrades::Null), None, "input {:?}", input);
+            assert_eq!(
+                normalize_grade(input, UnknownGrades::Sentinel).as_deref(),
+                Some("unknown"),
+                "input {:?}",
+                input
+            );
+        }
+
+        assert_eq!(UnknownGrades::parse("null"), Some(UnknownGrades::Null));
+        assert_eq!(UnknownGrades::parse(" Unknown "), Some(UnknownGrades::Sentinel));
+        assert_eq!(UnknownGrades::parse("none"), None);
+    }
+
+    #[test]
+    fn test_extract_rejects_lossy_integers() {
+        let product = extract("123", &json!({ "nova_group": 4.5, "rev": 4_294_967_296_i64 }));
+        assert!(product.nova_group.is_none());
+        assert!(product.off_rev.is_none());
+
+        assert!(extract("123", &json!({ "nova_group": 7 })).nova_group.is_none());
+        assert_eq!(extract("123", &json!({ "nova_group": 3.0 })).nova_group, Some(3));
+    }
+
+    #[test]
+    fn test_nutrient_levels() {
+        let product = json!({
+            "nutrient_levels": {
+                "fat": "moderate",
+                "salt": "moderate",
+                "saturated-fat": "moderate",
+                "sugars": "High"
+            }
+        });
+
+        let levels = nutrient_levels(&product).unwrap();
+        assert_eq!(levels["fat"], "moderate");
+        assert_eq!(levels["sugars"], "high");
+        assert_eq!(levels.as_object().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_nutrient_levels_missing_or_invalid() {
+        assert!(nutrient_levels(&json!({ "product_name": "Water" })).is_none());
+        assert!(nutrient_levels(&json!({ "nutrient_levels": [] })).is_none());
+        assert!(nutrient_levels(&json!({ "nutrient_levels": { "fat": "unknown", "salt": 3 } })).is_none());
+    }
+
+    #[test]
+    fn test_ingredient_shares_prefer_off_figures() {
+        let ingredients = [
+            json!({ "text": "Tomatoes", "percent": 60 }),
+            json!({ "text": "Water", "percent_estimate": 25.5 }),
+            json!({ "text": "Salt", "percent": "2 %" }),
+            json!({ "text": "Herbs", "percent": 140, "percent_estimate": 1.5 }),
+        ];
+        let shares = ingredient_shares(&ingredients.iter().collect::<Vec<_>>());
+
+        assert_eq!(
+            shares,
+            vec![
+                IngredientShare { percent: 60.0, source: PercentSource::Declared },
+                IngredientShare { percent: 25.5, source: PercentSource::Estimated },
+                IngredientShare { percent: 2.0, source: PercentSource::Declared },
+                IngredientShare { percent: 1.5, source: PercentSource::Estimated },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ingredient_shares_fall_back_to_rank() {
+        let ingredients = [
+            json!({ "text": "Flour", "percent": 40 }),
+            json!({ "text": "Sugar" }),
+            json!({ "text": "Butter" }),
+        ];
+        let shares = ingredient_shares(&ingredients.iter().collect::<Vec<_>>());
+
+        // 60% is left for ranks 2 and 3, weighted 1/2 : 1/3
+        assert_eq!(shares[0].source, PercentSource::Declared);
+        assert_eq!(shares[1].source, PercentSource::Rank);
+        assert!((shares[1].percent - 36.0).abs() < 0.01);
+        assert!((shares[2].percent - 24.0).abs() < 0.01);
+
+        let ranked = rank_shares(3);
+        assert!(ranked.iter().all(|share| share.source == PercentSource::Rank));
+        assert!(ranked.windows(2).all(|pair| pair[0].percent > pair[1].percent));
+        assert!((ranked.iter().map(|share| share.percent).sum::<f32>() - 100.0).abs() < 0.01);
+
+        // Declared figures that already add up to 100% leave nothing for the rest
+        let full = [json!({ "percent": 100 }), json!({ "text": "Trace" })];
+        assert_eq!(ingredient_shares(&full.iter().collect::<Vec<_>>())[1].percent, 0.0);
+    }
+
+    #[test]
+    fn test_ingredients_hash_ignores_case_and_spacing_only() {
+        let hash = |text: &str| ingredients_hash(&json!({ "ingredients_text": text }));
+
+        let original = hash("Sugar, Cocoa Butter, Milk");
+        assert_eq!(original.as_deref().map(str::len), Some(64));
+        assert_eq!(hash("sugar ,cocoa  butter, , MILK "), original);
+        assert_ne!(hash("Sugar, Cocoa Butter, Skimmed Milk"), original);
+        assert_ne!(hash("Cocoa Butter, Sugar, Milk"), original);
+
+        assert_eq!(hash(" , "), None);
+        assert_eq!(ingredients_hash(&json!({ "ingredients": [] })), None);
+    }
+
+    #[test]
+    fn test_ingredients_hash_covers_the_parsed_list() {
+        let product = |estimate: f64| {
+            json!({
+                "ingredients_text": "Sugar, Cocoa Butter",
+                "ingredients": [
+                    { "id": "en:sugar", "text": "Sugar", "percent_estimate": estimate },
+                    { "id": "en:cocoa-butter", "text": "Cocoa Butter", "percent_estimate": 100.0 - estimate }
+                ]
+            })
+        };
+
+        let original = ingredients_hash(&product(60.0));
+        assert!(original.is_some());
+        assert_eq!(ingredients_hash(&product(60.0)), original);
+        // Only an estimate changed: the shares processing stores differ, so it must run again
+        assert_ne!(ingredients_hash(&product(55.0)), original);
+
+        let mut respelled = product(60.0);
+        respelled["ingredients"][0]["text"] = json!(" SUGAR ");
+        assert_eq!(ingredients_hash(&respelled), original);
+
+        let mut retagged = product(60.0);
+        retagged["ingredients"][0]["id"] = json!("en:cane-sugar");
+        assert_ne!(ingredients_hash(&retagged), original);
+
+        // The text isn't read when the parsed list is there
+        let mut retexted = product(60.0);
+        retexted["ingredients_text"] = json!("Sugar, Cocoa Butter, Milk");
+        assert_eq!(ingredients_hash(&retexted), original);
+    }
+
+    #[test]
+    fn test_images_keep_each_available_kind() {
+        let product = extract("0737628064502", &fixtures::off_product("full"));
+        let base = "https://images.openfoodfacts.org/images/products/073/762/806/4502";
+        assert_eq!(
+            product.images,
+            Some(json!({
+                "front": format!("{}/front_en.6.400.jpg", base),
+                "ingredients": format!("{}/ingredients_en.9.400.jpg", base),
+                "nutrition": format!("{}/nutrition_en.11.400.jpg", base),
+            }))
+        );
+
+        // Only an ingredients photo, and an older product with nothing but image_url
+        let partial = json!({ "image_ingredients_url": "https://img/ingredients.jpg", "image_nutrition_url": " " });
+        assert_eq!(OffProduct::from_value(&partial).images(), Some(json!({ "ingredients": "https://img/ingredients.jpg" })));
+        let legacy = json!({ "image_url": "https://img/front.jpg", "image_nutrition_url": "https://img/nutrition.jpg" });
+        assert_eq!(
+            OffProduct::from_value(&legacy).images(),
+            Some(json!({ "front": "https://img/front.jpg", "nutrition": "https://img/nutrition.jpg" }))
+        );
+
+        assert_eq!(extract("5000112637922", &fixtures::off_product("minimal")).images, None);
+    }
+}
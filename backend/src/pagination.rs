@@ -0,0 +1,180 @@
+use serde::Serialize;
+
+/// Page size used when a list request doesn't specify `per_page`
+pub const DEFAULT_PER_PAGE: i64 = 20;
+
+/// Validated 1-based page request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageRequest {
+    pub page: i64,
+    pub per_page: i64,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        PageRequest {
+            page: 1,
+            per_page: DEFAULT_PER_PAGE,
+        }
+    }
+}
+
+impl PageRequest {
+    /// Build from optional `?page=&per_page=` query values, rejecting out-of-range ones
+    pub fn from_query(page: Option<i64>, per_page: Option<i64>) -> Result<Self, String> {
+        let max_per_page = crate::config::get().max_per_page;
+        let page = page.unwrap_or(1);
+        let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE.min(max_per_page));
+
+        if page < 1 {
+            return Err("page must be at least 1".to_string());
+        }
+        if !(1..=max_per_page).contains(&per_page) {
+            return Err(format!("per_page must be between 1 and {}", max_per_page));
+        }
+        // The offset has to fit the i64 Postgres takes for OFFSET
+        if (page - 1).checked_mul(per_page).is_none() {
+            return Err("page is too large".to_string());
+        }
+
+        Ok(PageRequest { page, per_page })
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.per_page
+    }
+
+    /// Saturates for a request built by hand; [`from_query`](PageRequest::from_query) rejects those
+    pub fn offset(&self) -> i64 {
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+}
+
+/// Response envelope shared by every list endpoint
+#[derive(Serialize, Debug)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub total_pages: i64,
+    /// Whether rows exist beyond this page, so clients never mistake a capped page for everything
+    pub has_more: bool,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(items: Vec<T>, request: PageRequest, total: i64) -> Self {
+        let total_pages = if total <= 0 { 0 } else { (total + request.per_page - 1) / request.per_page };
+        let has_more = request.offset().saturating_add(items.len() as i64) < total;
+
+        Paginated {
+            items,
+            page: request.page,
+            per_page: request.per_page,
+            total,
+            total_pages,
+            has_more,
+        }
+    }
+
+    /// The same page with each item converted
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Paginated<U> {
+        Paginated {
+            items: self.items.into_iter().map(f).collect(),
+            page: self.page,
+            per_page: self.per_page,
+            total: self.total,
+            total_pages: self.total_pages,
+            has_more: self.has_more,
+        }
+    }
+}
+
+/// Run a count query and a page query (given limit and offset) on one connection and
+/// wrap the results
+pub fn paginate<C, T, E>(
+    request: PageRequest,
+    conn: &mut C,
+    count: impl FnOnce(&mut C) -> Result<i64, E>,
+    load: impl FnOnce(&mut C, i64, i64) -> Result<Vec<T>, E>,
+) -> Result<Paginated<T>, E> {
+    let total = count(conn)?;
+    let items = load(conn, request.limit(), request.offset())?;
+    Ok(Paginated::new(items, request, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_request_defaults_and_offsets() {
+        let request = PageRequest::from_query(None, None).unwrap();
+        assert_eq!(request, PageRequest::default());
+        assert_eq!(request.offset(), 0);
+
+        let request = PageRequest::from_query(Some(3), Some(25)).unwrap();
+        assert_eq!(request.limit(), 25);
+        assert_eq!(request.offset(), 50);
+    }
+
+    #[test]
+    fn test_page_request_rejects_out_of_range() {
+        assert!(PageRequest::from_query(Some(0), None).is_err());
+        assert!(PageRequest::from_query(None, Some(0)).is_err());
+        assert!(PageRequest::from_query(None, Some(crate::config::get().max_per_page + 1)).is_err());
+        assert_eq!(PageRequest::from_query(Some(i64::MAX), Some(2)), Err("page is too large".to_string()));
+        assert_eq!(PageRequest { page: i64::MAX, per_page: 2 }.offset(), i64::MAX);
+    }
+
+    #[test]
+    fn test_total_pages() {
+        let request = PageRequest { page: 1, per_page: 20 };
+
+        assert_eq!(Paginated::<i32>::new(vec![], request, 0).total_pages, 0);
+        assert_eq!(Paginated::<i32>::new(vec![], request, 20).total_pages, 1);
+        assert_eq!(Paginated::<i32>::new(vec![], request, 21).total_pages, 2);
+    }
+
+    #[test]
+    fn test_paginate_passes_limit_and_offset() {
+        let request = PageRequest { page: 2, per_page: 10 };
+        let mut calls = Vec::new();
+        let page = paginate::<_, i64, ()>(
+            request,
+            &mut calls,
+            |calls| {
+                calls.push("count");
+                Ok(35)
+            },
+            |calls, limit, offset| {
+                calls.push("load");
+                Ok(vec![limit, offset])
+            },
+        )
+        .unwrap();
+
+        assert_eq!(calls, vec!["count", "load"]);
+        assert_eq!(page.items, vec![10, 10]);
+        assert_eq!(page.total, 35);
+        assert_eq!(page.total_pages, 4);
+        assert_eq!(
+            serde_json::to_value(&page).unwrap(),
+            serde_json::json!({ "items": [10, 10], "page": 2, "per_page": 10, "total": 35, "total_pages": 4, "has_more": true })
+        );
+    }
+
+    #[test]
+    fn test_has_more_when_total_exceeds_page() {
+        let first = PageRequest { page: 1, per_page: 2 };
+        assert!(Paginated::new(vec![1, 2], first, 5).has_more);
+        assert!(!Paginated::new(vec![1, 2], first, 2).has_more);
+
+        let last = PageRequest { page: 3, per_page: 2 };
+        assert!(!Paginated::new(vec![5], last, 5).has_more);
+
+        // Past the end: nothing here and nothing further
+        let beyond = PageRequest { page: 9, per_page: 2 };
+        assert!(!Paginated::<i32>::new(vec![], beyond, 5).has_more);
+    }
+}
@@ -0,0 +1,492 @@
+//! Linking a stored food product to its ingredients.
+
+use diesel::prelude::*;
+use fang::asynk::async_queue::{AsyncQueueError, AsyncQueueable};
+
+use crate::backpressure::QueueFull;
+use crate::db::DbPool;
+use crate::models::{self, Ingredient, NewProductIngredient, Product};
+use crate::{config, nutrition, off};
+
+/// Why a product's ingredients weren't processed. Nothing was recorded either way, so the
+/// next pass over the product processes them again.
+#[derive(Debug)]
+pub enum ProcessingError {
+    Db(diesel::result::Error),
+    /// Ingredients need creating but the job queue is full
+    QueueFull(QueueFull),
+    /// Ingredients need creating but enqueueing their jobs failed
+    Enqueue(AsyncQueueError),
+}
+
+impl std::fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessingError::Db(e) => write!(f, "Database error: {}", e),
+            ProcessingError::QueueFull(full) => write!(f, "Job queue is full: {}", full),
+            ProcessingError::Enqueue(e) => write!(f, "Failed to enqueue ingredient creation: {}", e),
+        }
+    }
+}
+
+impl From<diesel::result::Error> for ProcessingError {
+    fn from(e: diesel::result::Error) -> Self {
+        ProcessingError::Db(e)
+    }
+}
+
+impl From<QueueFull> for ProcessingError {
+    fn from(full: QueueFull) -> Self {
+        ProcessingError::QueueFull(full)
+    }
+}
+
+/// Keep only the first `max` ingredients so pathological inputs can't flood the job queue
+pub fn cap_ingredients<T>(mut ingredients: Vec<T>, max: usize) -> Vec<T> {
+    if ingredients.len() > max {
+        log::warn!(
+            "Product lists {} ingredients, only processing the first {}",
+            ingredients.len(),
+            max
+        );
+        ingredients.truncate(max);
+    }
+    ingredients
+}
+
+/// Process the product's ingredients unless its `ingredients_text` is the one processed
+/// last time. Returns whether they were processed. Failures are logged rather than
+/// returned; see [`process`] for what a failure leaves behind.
+pub async fn process_if_changed(
+    product_data: &serde_json::Value,
+    product_id: i32,
+    pool: &DbPool,
+    queue: &mut dyn AsyncQueueable,
+) -> bool {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            log::error!("Failed to get DB connection for ingredient processing: {}", e);
+            return false;
+        }
+    };
+
+    match process(product_data, product_id, false, &mut conn, queue).await {
+        Ok(true) => true,
+        Ok(false) => {
+            log::info!("Ingredients of product {} unchanged, skipping processing", product_id);
+            false
+        }
+        Err(e) => {
+            log::error!("Failed to process ingredients of product {}: {}", product_id, e);
+            false
+        }
+    }
+}
+
+/// Rebuild the product's ingredient links unless its ingredient list hashes (see
+/// [`off::ingredients_hash`]) to what was processed last time, or always when `force`.
+/// The new links and the hash are committed together once every ingredient was handled,
+/// so a failed run keeps the old links and hash and the next pass tries again. Unknown
+/// ingredients are then queued for creation through `queue`; if that fails the hash is
+/// dropped again, so the next pass retries them.
+pub async fn process(
+    product_data: &serde_json::Value,
+    product_id: i32,
+    force: bool,
+    conn: &mut PgConnection,
+    queue: &mut dyn AsyncQueueable,
+) -> Result<bool, ProcessingError> {
+    let hash = off::ingredients_hash(product_data);
+
+    let missing = conn.transaction(|conn| {
+        if !Product::begin_ingredient_processing(product_id, hash.as_deref(), force, conn)? {
+            return Ok::<_, ProcessingError>(None);
+        }
+        let missing = process_product_ingredients(product_data, product_id, conn)?;
+        Product::finish_ingredient_processing(product_id, hash.as_deref(), conn)?;
+        Ok(Some(missing))
+    })?;
+    let Some(missing) = missing else {
+        return Ok(false);
+    };
+
+    if let Err(e) = Ingredient::enqueue_creation(&missing, queue).await {
+        Product::forget_ingredients_hash(product_id, hash.as_deref(), conn)?;
+        return Err(ProcessingError::Enqueue(e));
+    }
+    Ok(true)
+}
+
+/// Process ingredients from product data, linking the ones we know to the product.
+/// Returns the rest, to be enqueued for creation.
+pub fn process_product_ingredients(
+    product_data: &serde_json::Value,
+    product_id: i32,
+    conn: &mut PgConnection,
+) -> Result<Vec<String>, ProcessingError> {
+    // Try to get ingredients array from OpenFoodFacts data
+    let ingredients_array = product_data
+        .get("ingredients")
+        .and_then(|v| v.as_array());
+
+    // Whole foods ("bananas") can lend their macros to an ingredient USDA had nothing for
+    let whole_food = nutrition::whole_food_profile(product_data);
+    let mut missing = Vec::new();
+
+    if let Some(ingredients) = ingredients_array {
+        log::info!("Processing {} ingredients from product", ingredients.len());
+        let ingredients = cap_ingredients(ingredients.iter().collect(), config::get().max_ingredients_per_product);
+        let shares = off::ingredient_shares(&ingredients);
+
+        // Process each ingredient
+        for (index, (ingredient, share)) in ingredients.into_iter().zip(shares).enumerate() {
+            // Extract ingredient name (can be "text", "id", or other fields)
+            let Some(name) = off::ingredient_name(ingredient) else {
+                // Still counted among the product's listed ingredients, it just can't be linked
+                log::warn!(
+                    "Ingredient {} of product {} has no name, skipping it (keys: {})",
+                    index + 1,
+                    product_id,
+                    off::ingredient_keys(ingredient)
+                );
+                continue;
+            };
+
+            missing.extend(link_if_known(&name, product_id, index, share, whole_food.as_ref(), conn)?);
+        }
+    } else if let Some(ingredients_text) = product_data
+        .get("ingredients_text")
+        .and_then(|v| v.as_str())
+    {
+        // Fallback: parse ingredients_text (comma-separated string)
+        log::info!("Processing ingredients from text: {}", ingredients_text);
+
+        // Split by commas and process each ingredient
+        let ingredient_names = cap_ingredients(
+            ingredients_text.split(',').collect(),
+            config::get().max_ingredients_per_product,
+        );
+
+        let shares = off::rank_shares(ingredient_names.len());
+
+        for (index, (ingredient_name, share)) in ingredient_names.into_iter().zip(shares).enumerate() {
+            missing.extend(link_if_known(ingredient_name, product_id, index, share, whole_food.as_ref(), conn)?);
+        }
+    } else {
+        log::info!("No ingredients data found in product");
+    }
+
+    Ok(Ingredient::admit_for_creation(missing, config::get().auto_create_ingredients, conn)?)
+}
+
+/// Link the ingredient at `index` of the product's list if we know it. Returns its
+/// cleaned-up name when we don't, for creation. Blank and oversized names are skipped.
+fn link_if_known(
+    name: &str,
+    product_id: i32,
+    index: usize,
+    share: off::IngredientShare,
+    whole_food: Option<&nutrition::WholeFoodProfile>,
+    conn: &mut PgConnection,
+) -> Result<Option<String>, diesel::result::Error> {
+    // Clean up the ingredient name
+    let clean_name = name.trim();
+    if clean_name.is_empty() || !models::ingredient_name_fits(clean_name, config::get().max_ingredient_name_len) {
+        return Ok(None);
+    }
+
+    log::info!("Processing ingredient: {}", clean_name);
+
+    match Ingredient::find_in_db(clean_name, conn)? {
+        Some(id) => {
+            log::info!("Ingredient '{}' found with ID: {}", clean_name, id);
+            seed_whole_food_macros(whole_food, clean_name, id, conn)?;
+            link_product_ingredient(product_id, id, index, share, conn)?;
+            Ok(None)
+        }
+        None => Ok(Some(clean_name.to_string())),
+    }
+}
+
+/// Record that the product contains the ingredient at `index` (0-based) in its list
+fn link_product_ingredient(
+    product_id: i32,
+    ingredient_id: i32,
+    index: usize,
+    share: off::IngredientShare,
+    conn: &mut PgConnection,
+) -> Result<(), diesel::result::Error> {
+    let link = NewProductIngredient {
+        product_id,
+        ingredient_id,
+        rank: index as i32 + 1,
+        percent_estimate: Some(share.percent),
+        percent_source: Some(share.source.as_str().to_string()),
+    };
+
+    link.link(conn).map(|_| ())
+}
+
+/// Copy a whole-food product's macros onto its ingredient when it is that product's
+/// only ingredient and has no macros of its own yet
+fn seed_whole_food_macros(
+    whole_food: Option<&nutrition::WholeFoodProfile>,
+    ingredient_name: &str,
+    ingredient_id: i32,
+    conn: &mut PgConnection,
+) -> Result<(), diesel::result::Error> {
+    let Some(profile) = whole_food.filter(|p| p.ingredient == ingredient_name) else {
+        return Ok(());
+    };
+
+    if Ingredient::seed_macros_from_product(ingredient_id, &profile.macros, conn)? {
+        log::info!("Seeded macros for ingredient '{}' from its whole-food product", ingredient_name);
+    } else if Ingredient::is_manually_verified(ingredient_id, conn)? {
+        log::info!("Not seeding macros for '{}': ingredient is manually verified", ingredient_name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_connection;
+    use crate::schema::{self, products};
+
+    #[test]
+    fn test_cap_ingredients_truncates_oversized_list() {
+        let text = (0..500).map(|i| format!("ingredient {}", i)).collect::<Vec<_>>().join(", ");
+        let capped = cap_ingredients(text.split(',').collect(), config::DEFAULT_MAX_INGREDIENTS_PER_PRODUCT);
+
+        assert_eq!(capped.len(), config::DEFAULT_MAX_INGREDIENTS_PER_PRODUCT);
+        assert_eq!(capped[0].trim(), "ingredient 0");
+        assert_eq!(capped[199].trim(), "ingredient 199");
+    }
+
+    #[test]
+    fn test_cap_ingredients_leaves_short_list_alone() {
+        let capped = cap_ingredients(vec!["Salt", "Pepper"], 200);
+        assert_eq!(capped, vec!["Salt", "Pepper"]);
+    }
+
+    #[test]
+    fn test_product_ingredients_keep_off_percent_estimates() {
+        use crate::models::NewIngredient;
+
+        let Some(pool) = crate::db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let mut conn = pool.get().unwrap();
+
+        for name in ["Percent Test Tomatoes", "Percent Test Water", "Percent Test Onion", "Percent Test Garlic"] {
+            diesel::insert_into(schema::ingredients::table)
+                .values(&NewIngredient {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let product_data = serde_json::json!({
+            "product_name": "Percent Test Sauce",
+            "ingredients": [
+                { "id": "en:tomato", "text": "Percent Test Tomatoes", "percent": 55 },
+                { "id": "en:water", "text": "Percent Test Water", "percent_estimate": 30 },
+                { "id": "en:onion", "text": "Percent Test Onion" },
+                { "id": "en:garlic", "text": "Percent Test Garlic" }
+            ]
+        });
+        let product_id = diesel::insert_into(products::table)
+            .values(&off::extract("percent-test-1", &product_data))
+            .returning(products::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        process_product_ingredients(&product_data, product_id, &mut conn).unwrap();
+
+        let links: Vec<(String, i32, Option<f32>, Option<String>)> = schema::product_ingredients::table
+            .inner_join(schema::ingredients::table)
+            .filter(schema::product_ingredients::product_id.eq(product_id))
+            .order(schema::product_ingredients::rank)
+            .select((
+                schema::ingredients::name,
+                schema::product_ingredients::rank,
+                schema::product_ingredients::percent_estimate,
+                schema::product_ingredients::percent_source,
+            ))
+            .load(&mut conn)
+            .unwrap();
+
+        let summary: Vec<(&str, i32, Option<&str>)> = links
+            .iter()
+            .map(|(name, rank, _, source)| (name.as_str(), *rank, source.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Percent Test Tomatoes", 1, Some("percent")),
+                ("Percent Test Water", 2, Some("percent_estimate")),
+                ("Percent Test Onion", 3, Some("rank")),
+                ("Percent Test Garlic", 4, Some("rank")),
+            ]
+        );
+
+        // The 15% OFF leaves unaccounted is split 1/3 : 1/4 between ranks 3 and 4
+        let percents: Vec<f32> = links.iter().map(|(_, _, percent, _)| percent.unwrap()).collect();
+        assert_eq!(&percents[..2], &[55.0, 30.0]);
+        assert!((percents[2] - 15.0 * 4.0 / 7.0).abs() < 0.01);
+        assert!((percents[3] - 15.0 * 3.0 / 7.0).abs() < 0.01);
+    }
+
+    #[actix_rt::test]
+    async fn test_unchanged_ingredients_text_skips_processing() {
+        use crate::models::NewIngredient;
+
+        let Some(pool) = crate::db::test_pool() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        let mut conn = pool.get().unwrap();
+
+        for name in ["Hash Test Oats", "Hash Test Honey", "Hash Test Almonds"] {
+            diesel::insert_into(schema::ingredients::table)
+                .values(&NewIngredient {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .execute(&mut conn)
+                .unwrap();
+        }
+
+        let product_data = serde_json::json!({ "ingredients_text": "Hash Test Oats, Hash Test Honey" });
+        let product_id = diesel::insert_into(products::table)
+            .values(&off::extract("hash-test-1", &product_data))
+            .returning(products::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+        drop(conn);
+
+        let linked = || -> Vec<String> {
+            schema::product_ingredients::table
+                .inner_join(schema::ingredients::table)
+                .filter(schema::product_ingredients::product_id.eq(product_id))
+                .order(schema::product_ingredients::rank)
+                .select(schema::ingredients::name)
+                .load(&mut pool.get().unwrap())
+                .unwrap()
+        };
+
+        // Every ingredient is known, so nothing is enqueued through the unconnected queue
+        let mut queue = crate::queue::disconnected_queue("postgres://unused/spoils", 1);
+        assert!(process_if_changed(&product_data, product_id, &pool, &mut queue).await);
+        assert_eq!(linked(), ["Hash Test Oats", "Hash Test Honey"]);
+
+        // The same list re-cased and re-spaced is skipped, so a link removed meanwhile stays removed
+        diesel::delete(schema::product_ingredients::table.filter(schema::product_ingredients::product_id.eq(product_id)))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+        let respaced = serde_json::json!({ "ingredients_text": "hash test oats ,  Hash Test HONEY" });
+        assert!(!process_if_changed(&respaced, product_id, &pool, &mut queue).await);
+        assert!(linked().is_empty());
+
+        // A changed list is processed again, replacing the links made from the old one
+        let changed = serde_json::json!({ "ingredients_text": "Hash Test Almonds, Hash Test Oats" });
+        assert!(process_if_changed(&changed, product_id, &pool, &mut queue).await);
+        assert_eq!(linked(), ["Hash Test Almonds", "Hash Test Oats"]);
+        assert!(!process_if_changed(&changed, product_id, &pool, &mut queue).await);
+
+        // A run that fails part-way rolls back with the links, so the old ones and their
+        // hash stay and the list is not mistaken for processed
+        let failed = pool.get().unwrap().transaction::<(), _, _>(|conn| {
+            assert!(Product::begin_ingredient_processing(product_id, Some("a new list"), false, conn)?);
+            Err(diesel::result::Error::RollbackTransaction)
+        });
+        assert!(failed.is_err());
+        assert_eq!(linked(), ["Hash Test Almonds", "Hash Test Oats"]);
+        assert!(!process_if_changed(&changed, product_id, &pool, &mut queue).await);
+
+        // Forcing rebuilds the links of an unchanged list
+        diesel::delete(schema::product_ingredients::table.filter(schema::product_ingredients::product_id.eq(product_id)))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+        assert!(process(&changed, product_id, true, &mut pool.get().unwrap(), &mut queue).await.unwrap());
+        assert_eq!(linked(), ["Hash Test Almonds", "Hash Test Oats"]);
+    }
+
+    #[actix_rt::test]
+    async fn test_full_queue_leaves_the_product_unprocessed() {
+        use crate::backpressure::JobClass;
+
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        diesel::sql_query(
+            "INSERT INTO fang_tasks (metadata, state, task_type) \
+             SELECT '{}', 'new', 'create_ingredient' FROM generate_series(1, $1)",
+        )
+        .bind::<diesel::sql_types::BigInt, _>(JobClass::Background.max_pending())
+        .execute(&mut conn)
+        .unwrap();
+        diesel::insert_into(schema::ingredients::table)
+            .values(schema::ingredients::name.eq("Queue Full Test Salt"))
+            .execute(&mut conn)
+            .unwrap();
+
+        let product_data = serde_json::json!({ "ingredients_text": "Queue Full Test Salt, Queue Full Test Unheard-Of Root" });
+        let product_id = diesel::insert_into(products::table)
+            .values(&off::extract("queue-full-test-1", &product_data))
+            .returning(products::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        // The unknown ingredient can't be queued, so the product isn't marked processed
+        // and keeps no partial links; the next pass tries again
+        let mut queue = crate::queue::disconnected_queue("postgres://unused/spoils", 1);
+        let result = process(&product_data, product_id, false, &mut conn, &mut queue).await;
+        assert!(matches!(result, Err(ProcessingError::QueueFull(_))), "{:?}", result);
+
+        let hash: Option<String> = products::table.find(product_id).select(products::ingredients_hash).first(&mut conn).unwrap();
+        assert_eq!(hash, None);
+        let links: i64 = schema::product_ingredients::table
+            .filter(schema::product_ingredients::product_id.eq(product_id))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(links, 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_enqueue_forgets_the_hash() {
+        let Some(mut conn) = test_connection() else {
+            eprintln!("DATABASE_URL not set, skipping");
+            return;
+        };
+
+        diesel::insert_into(schema::ingredients::table)
+            .values(schema::ingredients::name.eq("Enqueue Fail Test Salt"))
+            .execute(&mut conn)
+            .unwrap();
+        let product_data = serde_json::json!({ "ingredients_text": "Enqueue Fail Test Salt, Enqueue Fail Test Unheard-Of Root" });
+        let product_id = diesel::insert_into(products::table)
+            .values(&off::extract("enqueue-fail-test-1", &product_data))
+            .returning(products::id)
+            .get_result::<i32>(&mut conn)
+            .unwrap();
+
+        // The unknown ingredient can't be enqueued, so the product isn't left marked as
+        // processed and the next pass queues it again
+        let mut queue = crate::queue::disconnected_queue("postgres://unused/spoils", 1);
+        let result = process(&product_data, product_id, false, &mut conn, &mut queue).await;
+        assert!(matches!(result, Err(ProcessingError::Enqueue(_))), "{:?}", result);
+
+        let hash: Option<String> = products::table.find(product_id).select(products::ingredients_hash).first(&mut conn).unwrap();
+        assert_eq!(hash, None);
+    }
+}
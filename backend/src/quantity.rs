@@ -0,0 +1,181 @@
+use serde_json::Value;
+
+/// Grams per unit for the mass/volume units OFF uses in `quantity` and `serving_size`.
+/// Volumes assume the density of water, matching OFF's own per-100ml handling.
+const UNITS: [(&str, f64); 10] = [
+    ("mg", 0.001),
+    ("g", 1.0),
+    ("gr", 1.0),
+    ("kg", 1000.0),
+    ("ml", 1.0),
+    ("cl", 10.0),
+    ("dl", 100.0),
+    ("l", 1000.0),
+    ("oz", 28.3495),
+    ("lb", 453.592),
+];
+
+/// Parse a free-text quantity ("155 g", "1,5 kg", "0.333 PACKAGE (52 g)") into grams.
+///
+/// Takes the first number followed by a known unit, so household measures like
+/// "2 cookies (30 g)" resolve to their metric weight. A multipack ("6 x 25 cl") counts
+/// every item. Returns None when nothing usable is found.
+pub fn parse_grams(text: &str) -> Option<f64> {
+    let lower = text.to_lowercase();
+    let chars: Vec<char> = lower.chars().collect();
+    let mut i = 0;
+    // Items in a multipack, from the "6 x" before the per-item amount
+    let mut items: Option<f64> = None;
+
+    while i < chars.len() {
+        if !chars[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == ',') {
+            i += 1;
+        }
+        let number: String = chars[start..i].iter().collect();
+
+        let mut j = i;
+        while j < chars.len() && chars[j] == ' ' {
+            j += 1;
+        }
+        let unit_start = j;
+        while j < chars.len() && chars[j].is_ascii_alphabetic() {
+            j += 1;
+        }
+        let unit: String = chars[unit_start..j].iter().collect();
+        let value = number.replace(',', ".").parse::<f64>().ok().filter(|value| *value > 0.0);
+
+        if unit == "x" || (unit.is_empty() && matches!(chars.get(j), Some('×' | '*'))) {
+            items = value;
+            continue;
+        }
+        if let Some((_, factor)) = UNITS.iter().find(|(name, _)| *name == unit)
+            && let Some(value) = value
+        {
+            return Some(value * factor * items.unwrap_or(1.0));
+        }
+        items = None;
+    }
+
+    None
+}
+
+/// Grams in one `unit`, if it is a mass or volume unit we know
+fn grams_per_unit(unit: &str) -> Option<f64> {
+    let unit = unit.trim().to_lowercase();
+    UNITS.iter().find(|(name, _)| *name == unit).map(|(_, factor)| *factor)
+}
+
+/// Package size as an amount in a mass or volume unit, e.g. 330 ml
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductQuantity {
+    pub amount: f64,
+    pub unit: String,
+}
+
+impl ProductQuantity {
+    /// The package size in grams (volumes at the density of water)
+    pub fn grams(&self) -> Option<f64> {
+        grams_per_unit(&self.unit).map(|factor| self.amount * factor)
+    }
+}
+
+/// Package size from OFF's numeric `product_quantity` and `product_quantity_unit`
+/// (grams when the unit is missing, as in older OFF data). Falls back to parsing the
+/// free-text `quantity` into grams when the numeric fields are absent or unusable.
+pub fn from_off(product_data: &Value) -> Option<ProductQuantity> {
+    let amount = match product_data.get("product_quantity") {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::String(s)) => s.trim().replace(',', ".").parse::<f64>().ok(),
+        _ => None,
+    };
+    let unit = product_data
+        .get("product_quantity_unit")
+        .and_then(|v| v.as_str())
+        .map(|unit| unit.trim().to_lowercase())
+        .filter(|unit| !unit.is_empty())
+        .unwrap_or_else(|| "g".to_string());
+
+    if let Some(amount) = amount.filter(|a| a.is_finite() && *a > 0.0)
+        && grams_per_unit(&unit).is_some()
+    {
+        return Some(ProductQuantity { amount, unit });
+    }
+
+    let grams = product_data.get("quantity").and_then(|v| v.as_str()).and_then(parse_grams)?;
+    Some(ProductQuantity { amount: grams, unit: "g".to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_simple_quantities() {
+        assert_eq!(parse_grams("155 g"), Some(155.0));
+        assert_eq!(parse_grams("52g"), Some(52.0));
+        assert_eq!(parse_grams("1,5 kg"), Some(1500.0));
+        assert_eq!(parse_grams("330 ml"), Some(330.0));
+        assert_eq!(parse_grams("33 cl"), Some(330.0));
+        assert_eq!(parse_grams("500 MG"), Some(0.5));
+    }
+
+    #[test]
+    fn test_parse_household_measure_with_weight() {
+        assert_eq!(parse_grams("0.333 PACKAGE (52 g)"), Some(52.0));
+        assert_eq!(parse_grams("2 cookies (30 g)"), Some(30.0));
+        assert_eq!(parse_grams("1 cup (240ml)"), Some(240.0));
+    }
+
+    #[test]
+    fn test_parse_multipacks() {
+        assert_eq!(parse_grams("6 x 25 cl (1,5 l)"), Some(1500.0));
+        assert_eq!(parse_grams("4x125g"), Some(500.0));
+        assert_eq!(parse_grams("6 × 33 cl"), Some(1980.0));
+        assert_eq!(parse_grams("2 * 1,5 kg"), Some(3000.0));
+        // The count belongs to the next number only
+        assert_eq!(parse_grams("6 x 4 sachets (20 g)"), Some(20.0));
+    }
+
+    #[test]
+    fn test_parse_unusable_quantities() {
+        assert_eq!(parse_grams(""), None);
+        assert_eq!(parse_grams("1 package"), None);
+        assert_eq!(parse_grams("0 g"), None);
+        assert_eq!(parse_grams("a handful"), None);
+    }
+
+    #[test]
+    fn test_numeric_product_quantity_preferred() {
+        let can = json!({ "quantity": "33 cl", "product_quantity": "330", "product_quantity_unit": "ml" });
+        let parsed = from_off(&can).unwrap();
+        assert_eq!(parsed, ProductQuantity { amount: 330.0, unit: "ml".to_string() });
+        assert_eq!(parsed.grams(), Some(330.0));
+
+        // OFF sends product_quantity as a number or a string; older products have no unit
+        let jar = json!({ "quantity": "a jar", "product_quantity": 750 });
+        assert_eq!(from_off(&jar).unwrap().grams(), Some(750.0));
+
+        let bag = json!({ "product_quantity": "1,5", "product_quantity_unit": "KG" });
+        assert_eq!(from_off(&bag).unwrap().grams(), Some(1500.0));
+    }
+
+    #[test]
+    fn test_quantity_text_fallback() {
+        let no_numeric = json!({ "quantity": "155 g" });
+        assert_eq!(from_off(&no_numeric), Some(ProductQuantity { amount: 155.0, unit: "g".to_string() }));
+
+        // Unusable numeric fields fall back to the text as well
+        let odd_unit = json!({ "quantity": "6 x 25 cl (1,5 l)", "product_quantity": "6", "product_quantity_unit": "cans" });
+        assert_eq!(from_off(&odd_unit).unwrap().grams(), Some(1500.0));
+
+        assert_eq!(from_off(&json!({ "product_quantity": "0", "quantity": "1 package" })), None);
+        assert_eq!(from_off(&json!({})), None);
+    }
+}
@@ -0,0 +1,297 @@
+//! Connecting fang's job queue to Postgres, over TLS when DB_REQUIRE_TLS is set.
+//!
+//! A local Postgres takes plain TCP, but managed providers refuse anything unencrypted.
+//! [`QueueTls`] is the connector for both: plain, or rustls verifying the server against
+//! the system's CA bundle (SSL_CERT_FILE points at another one). Every queue is built
+//! through [`connect_queue`] or [`disconnected_queue`], so the choice is made in one place.
+//!
+//! The flag, not DATABASE_URL, decides: the queue's URI gets `sslmode=require` or
+//! `sslmode=disable` appended to match the connector. Diesel's connections read
+//! DATABASE_URL as written, so a provider needing TLS wants `sslmode=require` there too.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::task::{Context, Poll};
+
+use fang::asynk::async_queue::{AsyncQueue, AsyncQueueError};
+use fang::asynk::async_worker_pool::AsyncWorkerPool;
+use fang::RetentionMode;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, InvalidDnsNameError, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_postgres::tls::{ChannelBinding, MakeTlsConnect, TlsConnect, TlsStream};
+use tokio_postgres::Socket;
+use tokio_rustls::TlsConnector;
+
+/// Workers started for each task type. A fang worker only fetches tasks of the type it
+/// was built for, so every job's `task_type` needs an entry here or its tasks never run.
+pub const WORKER_POOLS: [(&str, u32); 10] = [
+    ("fetch_product", 2),
+    ("create_ingredient", 2),
+    ("analyze_ingredients", 1),
+    ("ocr_ingredients", 1),
+    ("enrich_non_food", 1),
+    ("send_notification", 1),
+    ("cleanup", 1),
+    ("failure_alert", 1),
+    ("usda_backfill", 1),
+    ("usda_reenrich", 1),
+];
+
+/// Workers `workers::start_worker_pool` starts in all. Here rather than beside it so the
+/// job connection pool can be sized from it too.
+pub const WORKERS: u32 = {
+    let mut total = 0;
+    let mut i = 0;
+    while i < WORKER_POOLS.len() {
+        total += WORKER_POOLS[i].1;
+        i += 1;
+    }
+    total
+};
+
+/// `workers` workers running the tasks of `task_type` off `queue`. Tasks that run out of
+/// retries are kept as `failed`, for the failure listing and alert to find; finished ones
+/// are removed.
+pub fn worker_pool(queue: JobQueue, task_type: &str, workers: u32) -> AsyncWorkerPool<JobQueue> {
+    AsyncWorkerPool::builder()
+        .number_of_workers(workers)
+        .task_type(task_type)
+        .retention_mode(RetentionMode::RemoveFinished)
+        .queue(queue)
+        .build()
+}
+
+/// The job queue, connected once at startup and shared by the handlers (as `web::Data`)
+/// and the worker pool. Clones share its connection pool.
+pub type JobQueue = AsyncQueue<QueueTls>;
+
+/// The job queue as the handlers hold it. After a degraded start it is a disconnected
+/// queue until Postgres answers, then it's swapped for the connected one, so handlers
+/// take a fresh clone per request instead of keeping one.
+#[derive(Clone)]
+pub struct SharedQueue(Arc<RwLock<JobQueue>>);
+
+impl SharedQueue {
+    pub fn new(queue: JobQueue) -> SharedQueue {
+        SharedQueue(Arc::new(RwLock::new(queue)))
+    }
+
+    /// The current queue; clones share its connection pool
+    pub fn get(&self) -> JobQueue {
+        self.0.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Swap in `queue` for every later [`get`](SharedQueue::get)
+    pub fn replace(&self, queue: JobQueue) {
+        *self.0.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = queue;
+    }
+}
+
+/// How the queue's connections reach Postgres
+#[derive(Clone)]
+pub enum QueueTls {
+    /// Plain TCP, for a local or otherwise trusted database
+    Disabled,
+    /// TLS through rustls, verifying the server certificate
+    Rustls(Arc<ClientConfig>),
+}
+
+impl QueueTls {
+    /// The connector DB_REQUIRE_TLS asks for. Fails when TLS is wanted but no CA
+    /// certificates could be loaded, since every connection would then be refused.
+    pub fn for_config(config: &crate::config::Config) -> Result<QueueTls, QueueConnectError> {
+        if !config.db_require_tls {
+            return Ok(QueueTls::Disabled);
+        }
+        // The CA bundle is read once, not on every enqueue site's connect
+        static CLIENT_CONFIG: OnceLock<Result<Arc<ClientConfig>, String>> = OnceLock::new();
+        CLIENT_CONFIG
+            .get_or_init(|| client_config().map(Arc::new))
+            .clone()
+            .map(QueueTls::Rustls)
+            .map_err(QueueConnectError::Tls)
+    }
+
+    /// The `sslmode` that makes tokio-postgres use (or skip) this connector
+    fn sslmode(&self) -> &'static str {
+        match self {
+            QueueTls::Disabled => "disable",
+            QueueTls::Rustls(_) => "require",
+        }
+    }
+
+    /// `database_url` with its `sslmode` pinned to this connector. A repeated parameter
+    /// overrides the earlier one, so whatever the URL already says is simply outvoted.
+    fn uri(&self, database_url: &str) -> String {
+        let separator = if database_url.contains('?') { '&' } else { '?' };
+        format!("{}{}sslmode={}", database_url, separator, self.sslmode())
+    }
+}
+
+/// rustls on the ring provider, trusting the system's CA bundle
+fn client_config() -> Result<ClientConfig, String> {
+    let bundle = openssl_probe::probe()
+        .cert_file
+        .ok_or("no CA bundle found for DB_REQUIRE_TLS; set SSL_CERT_FILE")?;
+    let certs = CertificateDer::pem_file_iter(&bundle)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("failed to read CA bundle {}: {}", bundle.display(), e))?;
+
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(certs);
+    if added == 0 {
+        return Err(format!("CA bundle {} holds no usable certificates", bundle.display()));
+    }
+
+    ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())
+        .map(|builder| builder.with_root_certificates(roots).with_no_client_auth())
+}
+
+/// Why the queue couldn't be connected
+#[derive(Debug)]
+pub enum QueueConnectError {
+    /// DB_REQUIRE_TLS is set but no TLS client could be built
+    Tls(String),
+    /// fang couldn't open its pool
+    Queue(AsyncQueueError),
+}
+
+impl std::fmt::Display for QueueConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueConnectError::Tls(e) => write!(f, "TLS setup failed: {}", e),
+            QueueConnectError::Queue(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Connect a queue with up to `max_pool_size` connections, over TLS if DB_REQUIRE_TLS is set
+pub async fn connect_queue(database_url: &str, max_pool_size: u32) -> Result<JobQueue, QueueConnectError> {
+    let tls = QueueTls::for_config(crate::config::get())?;
+    let mut queue = AsyncQueue::builder()
+        .uri(tls.uri(database_url))
+        .max_pool_size(max_pool_size)
+        .build();
+    queue.connect(tls).await.map_err(QueueConnectError::Queue)?;
+    Ok(queue)
+}
+
+/// A queue that was never connected: every enqueue fails with `NotConnectedError`
+pub fn disconnected_queue(database_url: &str, max_pool_size: u32) -> JobQueue {
+    AsyncQueue::builder()
+        .uri(database_url)
+        .max_pool_size(max_pool_size)
+        .build()
+}
+
+impl MakeTlsConnect<Socket> for QueueTls {
+    type Stream = QueueTlsStream;
+    type TlsConnect = QueueTlsConnect;
+    type Error = InvalidDnsNameError;
+
+    fn make_tls_connect(&mut self, domain: &str) -> Result<QueueTlsConnect, InvalidDnsNameError> {
+        match self {
+            QueueTls::Disabled => Ok(QueueTlsConnect(None)),
+            QueueTls::Rustls(config) => {
+                let server = ServerName::try_from(domain)?.to_owned();
+                Ok(QueueTlsConnect(Some((TlsConnector::from(config.clone()), server))))
+            }
+        }
+    }
+}
+
+/// One connection's handshake; `None` for [`QueueTls::Disabled`], which `sslmode=disable`
+/// keeps tokio-postgres from ever asking for
+pub struct QueueTlsConnect(Option<(TlsConnector, ServerName<'static>)>);
+
+impl TlsConnect<Socket> for QueueTlsConnect {
+    type Stream = QueueTlsStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<QueueTlsStream>> + Send>>;
+
+    fn connect(self, stream: Socket) -> Self::Future {
+        Box::pin(async move {
+            let (connector, server) =
+                self.0.ok_or_else(|| io::Error::other("TLS requested but DB_REQUIRE_TLS is not set"))?;
+            connector.connect(server, stream).await.map(QueueTlsStream)
+        })
+    }
+}
+
+/// An encrypted queue connection
+pub struct QueueTlsStream(tokio_rustls::client::TlsStream<Socket>);
+
+impl TlsStream for QueueTlsStream {
+    // No channel binding, so SCRAM authenticates without the -PLUS variant
+    fn channel_binding(&self) -> ChannelBinding {
+        ChannelBinding::none()
+    }
+}
+
+impl AsyncRead for QueueTlsStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QueueTlsStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn config_with(vars: &[(&str, &str)]) -> Config {
+        let vars: Vec<(String, String)> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::load(|key| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())).0
+    }
+
+    #[test]
+    fn test_db_require_tls_picks_the_connector() {
+        let url = ("DATABASE_URL", "postgres://spoils@localhost/spoils");
+
+        let plain = QueueTls::for_config(&config_with(&[url])).unwrap();
+        assert!(matches!(plain, QueueTls::Disabled));
+        let plain = QueueTls::for_config(&config_with(&[url, ("DB_REQUIRE_TLS", "false")])).unwrap();
+        assert!(matches!(plain, QueueTls::Disabled));
+
+        let tls = QueueTls::for_config(&config_with(&[url, ("DB_REQUIRE_TLS", "true")])).unwrap();
+        assert!(matches!(tls, QueueTls::Rustls(_)));
+    }
+
+    #[test]
+    fn test_uri_pins_sslmode_to_the_connector() {
+        assert_eq!(
+            QueueTls::Disabled.uri("postgres://spoils@localhost/spoils"),
+            "postgres://spoils@localhost/spoils?sslmode=disable"
+        );
+
+        let tls = QueueTls::Rustls(Arc::new(client_config().unwrap()));
+        assert_eq!(
+            tls.uri("postgres://spoils@db.example.com/spoils?sslmode=prefer"),
+            "postgres://spoils@db.example.com/spoils?sslmode=prefer&sslmode=require"
+        );
+
+        // The parameter that wins is the appended one
+        let config: tokio_postgres::Config = tls.uri("postgres://db.example.com/spoils?sslmode=disable").parse().unwrap();
+        assert_eq!(config.get_ssl_mode(), tokio_postgres::config::SslMode::Require);
+    }
+}
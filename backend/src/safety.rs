@@ -0,0 +1,294 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::Ingredient;
+
+/// Ingredient fields holding contamination data, in report order. Additives (`dyes`,
+/// `emulsifiers`, `preservatives`) and `historical_issues`/`fraudulent_ingredients` are
+/// not contaminants and stay out of the rollup.
+pub const CONTAMINANT_FIELDS: [&str; 11] = [
+    "heavy_metals",
+    "micro_plastics",
+    "industrial_chemicals",
+    "pesticides",
+    "hormones",
+    "antibiotics",
+    "beta_agonists",
+    "antiparasitics",
+    "carcinogens",
+    "natural_toxins",
+    "radiological",
+];
+
+/// Position of a contaminant category in `CONTAMINANT_FIELDS`, `None` for anything else
+pub fn contaminant_index(category: &str) -> Option<usize> {
+    CONTAMINANT_FIELDS.iter().position(|field| *field == category)
+}
+
+/// Combine newly entered findings with what a contaminant field already holds. Objects
+/// are merged key by key (new values win), arrays gain the entries they don't have yet,
+/// and anything else is replaced by the new findings.
+pub fn merge_findings(existing: Option<&Value>, incoming: Value) -> Value {
+    match (existing, incoming) {
+        (Some(Value::Object(existing)), Value::Object(incoming)) => {
+            let mut merged = existing.clone();
+            merged.extend(incoming);
+            Value::Object(merged)
+        }
+        (Some(Value::Array(existing)), Value::Array(incoming)) => {
+            let mut merged = existing.clone();
+            for item in incoming {
+                if !merged.contains(&item) {
+                    merged.push(item);
+                }
+            }
+            Value::Array(merged)
+        }
+        (_, incoming) => incoming,
+    }
+}
+
+/// One ingredient's contaminant fields, in `CONTAMINANT_FIELDS` order
+pub struct IngredientContaminants<'a> {
+    pub name: &'a str,
+    pub fields: [Option<&'a Value>; 11],
+}
+
+impl<'a> IngredientContaminants<'a> {
+    pub fn from_ingredient(ingredient: &'a Ingredient) -> Self {
+        IngredientContaminants {
+            name: &ingredient.name,
+            fields: [
+                ingredient.heavy_metals.as_ref(),
+                ingredient.micro_plastics.as_ref(),
+                ingredient.industrial_chemicals.as_ref(),
+                ingredient.pesticides.as_ref(),
+                ingredient.hormones.as_ref(),
+                ingredient.antibiotics.as_ref(),
+                ingredient.beta_agonists.as_ref(),
+                ingredient.antiparasitics.as_ref(),
+                ingredient.carcinogens.as_ref(),
+                ingredient.natural_toxins.as_ref(),
+                ingredient.radiological.as_ref(),
+            ],
+        }
+    }
+
+    /// Whether anyone has looked at this ingredient's contaminants. An empty `{}` or `[]`
+    /// means "checked, nothing found", while NULL means no data.
+    fn assessed(&self) -> bool {
+        self.fields.iter().any(|field| field.is_some_and(|value| !value.is_null()))
+    }
+}
+
+/// The specific flags in one contaminant field: the keys of an object (`{"lead": "trace"}`)
+/// whose value isn't `false` or null, the strings of an array (`["glyphosate"]`), or a
+/// bare string. Lowercased and trimmed.
+pub fn flags(value: &Value) -> Vec<String> {
+    let names: Vec<&str> = match value {
+        Value::Object(map) => map
+            .iter()
+            .filter(|(_, v)| !matches!(v, Value::Null | Value::Bool(false)))
+            .map(|(k, _)| k.as_str())
+            .collect(),
+        Value::Array(items) => items.iter().filter_map(|item| item.as_str()).collect(),
+        Value::String(s) => vec![s.as_str()],
+        _ => Vec::new(),
+    };
+
+    names
+        .into_iter()
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Everything flagged in one contaminant category across a product's ingredients
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CategorySummary {
+    pub category: &'static str,
+    /// Distinct flags, in the order first seen
+    pub flags: Vec<String>,
+    /// Ingredients contributing at least one flag
+    pub ingredients: Vec<String>,
+}
+
+/// How much of the product the report can speak for
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SafetyCoverage {
+    /// Distinct ingredients on the label
+    pub listed_ingredients: usize,
+    /// Ingredients resolved and linked to the product
+    pub linked_ingredients: usize,
+    /// Linked ingredients with any contaminant data (even an empty "nothing found")
+    pub assessed_ingredients: usize,
+    /// Listed ingredients not linked yet (still being created, or unrecognized)
+    pub unresolved_ingredients: usize,
+    /// Every listed ingredient is linked and assessed, so no flags can be missing
+    pub complete: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SafetyReport {
+    pub flagged: bool,
+    /// Distinct flags across all categories
+    pub flag_count: usize,
+    /// Only the categories with at least one flag
+    pub categories: Vec<CategorySummary>,
+    pub coverage: SafetyCoverage,
+}
+
+/// Roll the contaminant data of a product's linked ingredients up into one report.
+/// `listed` is the number of distinct ingredients on the label.
+pub fn summarize(ingredients: &[IngredientContaminants], listed: usize) -> SafetyReport {
+    let mut categories = Vec::new();
+    for (index, category) in CONTAMINANT_FIELDS.iter().enumerate() {
+        let mut summary = CategorySummary {
+            category,
+            flags: Vec::new(),
+            ingredients: Vec::new(),
+        };
+
+        for ingredient in ingredients {
+            let found = ingredient.fields[index].map(flags).unwrap_or_default();
+            if found.is_empty() {
+                continue;
+            }
+            for flag in found {
+                if !summary.flags.contains(&flag) {
+                    summary.flags.push(flag);
+                }
+            }
+            summary.ingredients.push(ingredient.name.to_string());
+        }
+
+        if !summary.flags.is_empty() {
+            categories.push(summary);
+        }
+    }
+
+    let linked = ingredients.len();
+    let assessed = ingredients.iter().filter(|i| i.assessed()).count();
+    let unresolved = listed.saturating_sub(linked);
+    let flag_count = categories.iter().map(|c| c.flags.len()).sum();
+
+    SafetyReport {
+        flagged: flag_count > 0,
+        flag_count,
+        categories,
+        coverage: SafetyCoverage {
+            listed_ingredients: listed,
+            linked_ingredients: linked,
+            assessed_ingredients: assessed,
+            unresolved_ingredients: unresolved,
+            complete: unresolved == 0 && assessed == linked,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn contaminants<'a>(name: &'a str, fields: &'a [(usize, Value)]) -> IngredientContaminants<'a> {
+        let mut by_field = [None; 11];
+        for (index, value) in fields {
+            by_field[*index] = Some(value);
+        }
+        IngredientContaminants { name, fields: by_field }
+    }
+
+    const HEAVY_METALS: usize = 0;
+    const PESTICIDES: usize = 3;
+
+    #[test]
+    fn test_flags_from_each_shape() {
+        assert_eq!(flags(&json!({ "lead": "trace", "Cadmium": 0.02, "mercury": false, "arsenic": null })), vec!["cadmium", "lead"]);
+        assert_eq!(flags(&json!(["glyphosate", " Chlorpyrifos ", 3, ""])), vec!["glyphosate", "chlorpyrifos"]);
+        assert_eq!(flags(&json!("bpa")), vec!["bpa"]);
+        assert!(flags(&json!({})).is_empty());
+        assert!(flags(&json!([])).is_empty());
+        assert!(flags(&json!(true)).is_empty());
+    }
+
+    #[test]
+    fn test_contaminant_index_only_knows_contaminant_columns() {
+        assert_eq!(contaminant_index("heavy_metals"), Some(HEAVY_METALS));
+        assert_eq!(contaminant_index("pesticides"), Some(PESTICIDES));
+        assert_eq!(contaminant_index("dyes"), None);
+        assert_eq!(contaminant_index("name"), None);
+        assert_eq!(contaminant_index("Heavy_Metals"), None);
+    }
+
+    #[test]
+    fn test_merge_findings() {
+        let existing = json!({ "lead": "trace", "cadmium": "0.01 ppm" });
+        assert_eq!(
+            merge_findings(Some(&existing), json!({ "lead": "0.2 ppm", "arsenic": "0.05 ppm" })),
+            json!({ "lead": "0.2 ppm", "cadmium": "0.01 ppm", "arsenic": "0.05 ppm" })
+        );
+
+        let existing = json!(["glyphosate"]);
+        assert_eq!(merge_findings(Some(&existing), json!(["atrazine", "glyphosate"])), json!(["glyphosate", "atrazine"]));
+
+        // Nothing stored yet, or a shape that can't be merged: the new findings win
+        assert_eq!(merge_findings(None, json!({ "lead": "trace" })), json!({ "lead": "trace" }));
+        assert_eq!(merge_findings(Some(&Value::Null), json!(["bpa"])), json!(["bpa"]));
+        assert_eq!(merge_findings(Some(&json!(["lead"])), json!({ "lead": "trace" })), json!({ "lead": "trace" }));
+    }
+
+    #[test]
+    fn test_summary_merges_flags_across_ingredients() {
+        let rice_fields = [(HEAVY_METALS, json!({ "arsenic": "elevated" })), (PESTICIDES, json!(["glyphosate"]))];
+        let oats_fields = [(PESTICIDES, json!(["glyphosate", "chlormequat"]))];
+        let salt_fields = [(HEAVY_METALS, json!({}))];
+        let ingredients = [
+            contaminants("Rice", &rice_fields),
+            contaminants("Oats", &oats_fields),
+            contaminants("Salt", &salt_fields),
+        ];
+
+        let report = summarize(&ingredients, 3);
+
+        assert!(report.flagged);
+        assert_eq!(report.flag_count, 3);
+        assert_eq!(
+            report.categories,
+            vec![
+                CategorySummary {
+                    category: "heavy_metals",
+                    flags: vec!["arsenic".to_string()],
+                    ingredients: vec!["Rice".to_string()],
+                },
+                CategorySummary {
+                    category: "pesticides",
+                    flags: vec!["glyphosate".to_string(), "chlormequat".to_string()],
+                    ingredients: vec!["Rice".to_string(), "Oats".to_string()],
+                },
+            ]
+        );
+        assert!(report.coverage.complete);
+    }
+
+    #[test]
+    fn test_coverage_reports_unresolved_and_unassessed_ingredients() {
+        let rice_fields = [(PESTICIDES, json!([]))];
+        let ingredients = [contaminants("Rice", &rice_fields), contaminants("Water", &[])];
+
+        let report = summarize(&ingredients, 4);
+
+        assert!(!report.flagged);
+        assert!(report.categories.is_empty());
+        assert_eq!(
+            report.coverage,
+            SafetyCoverage {
+                listed_ingredients: 4,
+                linked_ingredients: 2,
+                assessed_ingredients: 1,
+                unresolved_ingredients: 2,
+                complete: false,
+            }
+        );
+    }
+}
@@ -1,5 +1,13 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    ingredient_aliases (alias) {
+        alias -> Varchar,
+        ingredient_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     ingredients (id) {
         id -> Int4,
@@ -34,6 +42,45 @@ diesel::table! {
         gram_trans_fat_per_gram -> Nullable<Float4>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        usda_searched_at -> Nullable<Timestamp>,
+        fdc_id -> Nullable<Int4>,
+        usda_food -> Nullable<Jsonb>,
+        canonical_name -> Varchar,
+        manually_verified -> Bool,
+        sub_ingredients_processed -> Bool,
+        needs_review -> Bool,
+        usda_candidate -> Nullable<Jsonb>,
+        usda_no_match -> Bool,
+    }
+}
+
+diesel::table! {
+    product_history (id) {
+        id -> Int4,
+        product_id -> Int4,
+        barcode -> Varchar,
+        off_rev -> Nullable<Int4>,
+        full_response -> Jsonb,
+        captured_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    product_ingredients (product_id, ingredient_id) {
+        product_id -> Int4,
+        ingredient_id -> Int4,
+        rank -> Int4,
+        percent_estimate -> Nullable<Float4>,
+        percent_source -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    product_lookups (barcode) {
+        barcode -> Varchar,
+        found -> Bool,
+        checked_at -> Timestamp,
     }
 }
 
@@ -51,9 +98,25 @@ diesel::table! {
         ecoscore_grade -> Nullable<Varchar>,
         ingredients_text -> Nullable<Text>,
         allergens -> Nullable<Text>,
-        full_response -> Jsonb,
+        full_response -> Nullable<Jsonb>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        off_rev -> Nullable<Int4>,
+        last_verified_at -> Nullable<Timestamp>,
+        nutrient_levels -> Nullable<Jsonb>,
+        labels -> Nullable<Jsonb>,
+        diet -> Nullable<Jsonb>,
+        data_source -> Nullable<Varchar>,
+        allergen_tags -> Nullable<Jsonb>,
+        trace_tags -> Nullable<Jsonb>,
+        analyzed_at -> Nullable<Timestamp>,
+        brand_tags -> Nullable<Jsonb>,
+        product_quantity -> Nullable<Float8>,
+        product_quantity_unit -> Nullable<Varchar>,
+        full_response_gz -> Nullable<Bytea>,
+        ingredients_hash -> Nullable<Text>,
+        images -> Nullable<Jsonb>,
+        ocr_ingredients_text -> Nullable<Text>,
     }
 }
 
@@ -120,8 +183,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    usda_backfill_runs (id) {
+        id -> Int4,
+        manual -> Bool,
+        candidates -> Int4,
+        updated -> Int4,
+        finished_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(ingredient_aliases -> ingredients (ingredient_id));
+diesel::joinable!(product_history -> products (product_id));
+diesel::joinable!(product_ingredients -> ingredients (ingredient_id));
+diesel::joinable!(product_ingredients -> products (product_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    ingredient_aliases,
     ingredients,
+    product_history,
+    product_ingredients,
+    product_lookups,
     products,
     products_non_food,
+    usda_backfill_runs,
 );
@@ -0,0 +1,198 @@
+//! Warnings for slow HTTP requests and slow OpenFoodFacts/USDA calls, for performance
+//! triage (e.g. the cache-miss path of a product lookup) without full tracing.
+//!
+//! Each line is `slow <kind>: key=value ...` with the elapsed time and threshold in ms,
+//! logged under the `slow` target so it can be filtered on its own. The thresholds are
+//! SLOW_REQUEST_MS and SLOW_UPSTREAM_MS, both high enough by default that nothing is logged.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+
+/// Warn about a `kind` operation described by `fields` when it took longer than
+/// `threshold`, returning whether it did
+pub fn report(kind: &str, fields: &str, elapsed: Duration, threshold: Duration) -> bool {
+    if elapsed <= threshold {
+        return false;
+    }
+
+    log::warn!(
+        target: "slow",
+        "slow {}: {} elapsed_ms={} threshold_ms={}",
+        kind,
+        fields,
+        elapsed.as_millis(),
+        threshold.as_millis()
+    );
+    true
+}
+
+/// Await `work`, reporting it if it took longer than `threshold`
+pub async fn timed<F: Future>(kind: &str, fields: &str, threshold: Duration, work: F) -> F::Output {
+    let started = Instant::now();
+    let output = work.await;
+    report(kind, fields, started.elapsed(), threshold);
+    output
+}
+
+/// URL to log for an upstream call: scheme, host and path (where OFF puts the barcode).
+/// The query is left out, since USDA's carries the API key.
+pub fn loggable_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => format!("{}://{}{}", parsed.scheme(), parsed.host_str().unwrap_or(""), parsed.path()),
+        Err(_) => url.split('?').next().unwrap_or_default().to_string(),
+    }
+}
+
+/// Middleware timing every request against SLOW_REQUEST_MS
+pub async fn requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    requests_past(crate::config::get().slow_request_threshold, req, next).await
+}
+
+/// [`requests`] with the threshold given rather than SLOW_REQUEST_MS
+async fn requests_past(
+    threshold: Duration,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req.path().to_string();
+
+    let response = next.call(req).await;
+
+    let status = match &response {
+        Ok(response) => response.status().as_u16().to_string(),
+        Err(e) => e.as_response_error().status_code().as_u16().to_string(),
+    };
+    report(
+        "request",
+        &format!("method={} path={} status={}", method, path, status),
+        started.elapsed(),
+        threshold,
+    );
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Keeps every `slow` line logged by any test, so a test can look for its own
+    struct Capture(Mutex<Vec<String>>);
+
+    impl log::Log for Capture {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "slow"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    fn captured(needle: &str) -> Vec<String> {
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(log::LevelFilter::Warn);
+        CAPTURE.0.lock().unwrap().iter().filter(|line| line.contains(needle)).cloned().collect()
+    }
+
+    #[test]
+    fn test_report_only_past_the_threshold() {
+        let threshold = Duration::from_millis(100);
+        assert!(!report("request", "path=/fast", Duration::from_millis(100), threshold));
+        assert!(report("request", "path=/slow", Duration::from_millis(101), threshold));
+    }
+
+    #[test]
+    fn test_loggable_url_drops_the_query() {
+        assert_eq!(
+            loggable_url("https://api.nal.usda.gov/fdc/v1/foods/search?query=oats&api_key=secret"),
+            "https://api.nal.usda.gov/fdc/v1/foods/search"
+        );
+        assert_eq!(
+            loggable_url("https://world.openfoodfacts.org/api/v2/product/3017620422003.json"),
+            "https://world.openfoodfacts.org/api/v2/product/3017620422003.json"
+        );
+        assert_eq!(loggable_url("not a url?key=secret"), "not a url");
+    }
+
+    #[actix_rt::test]
+    async fn test_slow_upstream_mock_triggers_the_warning() {
+        use crate::http_client::{self, Upstream};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        captured("");
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/5000000000017.json"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(1500)))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/5000000000024.json"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        // Wide margins, so a loaded test machine can't make the fast call look slow
+        let threshold = Duration::from_millis(750);
+        for barcode in ["5000000000017", "5000000000024"] {
+            let url = format!("{}/api/v2/product/{}.json", server.uri(), barcode);
+            let response = http_client::get_reporting_past(Upstream::OpenFoodFacts, &url, threshold).await;
+            assert_eq!(response.unwrap().status(), reqwest::StatusCode::OK);
+        }
+
+        let slow = captured("5000000000017");
+        assert_eq!(slow.len(), 1, "{:?}", slow);
+        assert!(slow[0].starts_with("WARN slow upstream: upstream=OFF url=http://127.0.0.1"), "{}", slow[0]);
+        assert!(slow[0].contains("threshold_ms=750"), "{}", slow[0]);
+        assert!(captured("5000000000024").is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_slow_request_middleware_warns_past_the_threshold() {
+        use actix_web::{web, App, HttpResponse};
+
+        captured("");
+        let threshold = Duration::from_millis(750);
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::from_fn(move |req, next| requests_past(threshold, req, next)))
+                .route(
+                    "/slow-log-test/slow",
+                    web::get().to(|| async {
+                        tokio::time::sleep(Duration::from_millis(1500)).await;
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .route("/slow-log-test/fast", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        for uri in ["/slow-log-test/slow", "/slow-log-test/fast"] {
+            let resp = actix_web::test::call_service(&app, actix_web::test::TestRequest::get().uri(uri).to_request()).await;
+            assert!(resp.status().is_success());
+        }
+
+        let slow = captured("path=/slow-log-test/slow");
+        assert_eq!(slow.len(), 1, "{:?}", slow);
+        assert!(slow[0].starts_with("WARN slow request: method=GET path=/slow-log-test/slow status=200"), "{}", slow[0]);
+        assert!(captured("path=/slow-log-test/fast").is_empty());
+    }
+}
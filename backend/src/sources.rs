@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+
+use crate::config::Config;
+use crate::http_client::{self, Upstream};
+use crate::models::OpenFoodFactsResponse;
+
+/// Somewhere product data can be looked up by barcode. Sources return products in
+/// OpenFoodFacts' `product` shape so `off::extract` can store them.
+#[async_trait]
+pub trait ProductSource: Send + Sync {
+    /// Identifier used in PRODUCT_SOURCES and stored in `products.data_source`
+    fn name(&self) -> &'static str;
+
+    /// `Ok(None)` when the source has no such product, `Err` when it couldn't be asked
+    async fn lookup(&self, barcode: &str) -> Result<Option<Value>, String>;
+}
+
+/// OpenFoodFacts product API, world.openfoodfacts.org unless OFF_BASE_URL or OFF_STAGING
+/// says otherwise
+pub struct OpenFoodFactsSource {
+    base_url: String,
+}
+
+impl OpenFoodFactsSource {
+    /// Source querying the OFF instance at `base_url`, e.g. a mock server in tests
+    pub fn new(base_url: impl Into<String>) -> Self {
+        OpenFoodFactsSource { base_url: base_url.into() }
+    }
+}
+
+#[async_trait]
+impl ProductSource for OpenFoodFactsSource {
+    fn name(&self) -> &'static str {
+        "openfoodfacts"
+    }
+
+    async fn lookup(&self, barcode: &str) -> Result<Option<Value>, String> {
+        let url = format!("{}/api/v2/product/{}", self.base_url, barcode);
+
+        let response = http_client::get(Upstream::OpenFoodFacts, &url)
+            .await
+            .map_err(|e| format!("Failed to query OpenFoodFacts: {}", e))?;
+        let data = http_client::json::<OpenFoodFactsResponse>(response)
+            .await
+            .map_err(|e| format!("Unusable OpenFoodFacts response: {}", e))?;
+
+        Ok(data.product.filter(|_| data.status == 1))
+    }
+}
+
+/// Names PRODUCT_SOURCES may list
+const SOURCE_NAMES: [&str; 1] = ["openfoodfacts"];
+
+/// Build a source from its PRODUCT_SOURCES name, calling the upstream `config` points it at
+fn source_named(name: &str, config: &Config) -> Option<Box<dyn ProductSource>> {
+    match name {
+        "openfoodfacts" => Some(Box::new(OpenFoodFactsSource::new(
+            config.upstream(Upstream::OpenFoodFacts).base_url.clone(),
+        ))),
+        _ => None,
+    }
+}
+
+/// Source names from a comma-separated list, normalized, rejecting unknown or empty lists
+pub fn parse_names(names: &str) -> Result<Vec<String>, String> {
+    let names: Vec<String> = names.split(',').map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()).collect();
+
+    if let Some(unknown) = names.iter().find(|name| !SOURCE_NAMES.contains(&name.as_str())) {
+        return Err(format!("unknown product source {:?}", unknown));
+    }
+    if names.is_empty() {
+        return Err("at least one product source is required".to_string());
+    }
+
+    Ok(names)
+}
+
+/// Result of walking the chain for one barcode
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainLookup {
+    /// The first source that had the product, and its data
+    Found { source: &'static str, product: Value },
+    /// Every source answered and none had the product
+    NotFound,
+    /// No source had the product and at least one couldn't be asked, so the miss isn't trustworthy
+    Failed,
+}
+
+/// One lookup's hold on a walk in [`SourceChain::in_flight`], releasing it when the lookup
+/// finishes or its future is dropped midway
+struct InFlight<'a> {
+    chain: &'a SourceChain,
+    barcode: &'a str,
+    walk: Option<Arc<OnceCell<ChainLookup>>>,
+    finished: bool,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.chain.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(walk) = self.walk.take() else {
+            return;
+        };
+
+        // The first one back retires the walk, so the next lookup asks the sources again.
+        // An abandoned lookup only does when no other holds the walk: the map and this one
+        // are the only references, as clones are taken under the lock.
+        let retire = self.finished || Arc::strong_count(&walk) == 2;
+        if retire && in_flight.get(self.barcode).is_some_and(|current| Arc::ptr_eq(current, &walk)) {
+            in_flight.remove(self.barcode);
+        }
+        // Released under the lock, so the next lookup to let go counts without this one
+        drop(walk);
+    }
+}
+
+/// Product sources in the order they are tried
+pub struct SourceChain {
+    sources: Vec<Box<dyn ProductSource>>,
+    /// Walks currently under way, by barcode, for concurrent lookups to wait on
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<ChainLookup>>>>,
+}
+
+impl SourceChain {
+    pub fn new(sources: Vec<Box<dyn ProductSource>>) -> Self {
+        SourceChain { sources, in_flight: Mutex::new(HashMap::new()) }
+    }
+
+    /// Chain from a comma-separated list of source names, rejecting unknown or empty lists
+    pub fn from_names(names: &str, config: &Config) -> Result<Self, String> {
+        let sources = parse_names(names)?
+            .iter()
+            .map(|name| source_named(name, config).expect("parse_names only lets known sources through"))
+            .collect();
+
+        Ok(SourceChain::new(sources))
+    }
+
+    /// Chain ordered by PRODUCT_SOURCES, built once at startup and shared with handlers as app data
+    pub fn from_config(config: &Config) -> Self {
+        SourceChain::from_names(&config.product_sources, config).expect("PRODUCT_SOURCES is validated when the config loads")
+    }
+
+    /// Names of the sources, in lookup order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.sources.iter().map(|source| source.name()).collect()
+    }
+
+    /// Try each source in order until one has the product. Concurrent lookups of the same
+    /// barcode share one walk, so a burst of requests for it costs a single upstream call.
+    /// If the lookup doing the walk is abandoned, one of the waiting ones takes it over.
+    pub async fn lookup(&self, barcode: &str) -> ChainLookup {
+        let walk = self.in_flight.lock().unwrap().entry(barcode.to_string()).or_default().clone();
+        let mut held = InFlight { chain: self, barcode, walk: Some(walk), finished: false };
+        let walk = held.walk.as_ref().expect("only taken on drop");
+        let lookup = walk.get_or_init(|| self.walk(barcode)).await.clone();
+        held.finished = true;
+        lookup
+    }
+
+    async fn walk(&self, barcode: &str) -> ChainLookup {
+        let mut failed = false;
+
+        for source in &self.sources {
+            match source.lookup(barcode).await {
+                Ok(Some(product)) => {
+                    log::info!("Product {} found via {}", barcode, source.name());
+                    return ChainLookup::Found {
+                        source: source.name(),
+                        product,
+                    };
+                }
+                Ok(None) => log::info!("Product {} not found via {}", barcode, source.name()),
+                Err(e) => {
+                    log::error!("Product source {} failed for {}: {}", source.name(), barcode, e);
+                    failed = true;
+                }
+            }
+        }
+
+        if failed { ChainLookup::Failed } else { ChainLookup::NotFound }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Source that answers every barcode the same way and counts how often it is asked
+    struct MockSource {
+        name: &'static str,
+        answer: Result<Option<Value>, String>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl MockSource {
+        fn boxed(name: &'static str, answer: Result<Option<Value>, String>) -> (Box<dyn ProductSource>, Arc<AtomicUsize>) {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let source = MockSource {
+                name,
+                answer,
+                calls: calls.clone(),
+            };
+            (Box::new(source), calls)
+        }
+    }
+
+    #[async_trait]
+    impl ProductSource for MockSource {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn lookup(&self, _barcode: &str) -> Result<Option<Value>, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.answer.clone()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_first_miss_falls_through_to_second_hit() {
+        let (first, first_calls) = MockSource::boxed("first", Ok(None));
+        let (second, second_calls) = MockSource::boxed("second", Ok(Some(json!({ "product_name": "Lip Balm" }))));
+        let (third, third_calls) = MockSource::boxed("third", Ok(Some(json!({ "product_name": "Other" }))));
+        let chain = SourceChain::new(vec![first, second, third]);
+
+        assert_eq!(
+            chain.lookup("0123456789012").await,
+            ChainLookup::Found {
+                source: "second",
+                product: json!({ "product_name": "Lip Balm" })
+            }
+        );
+        assert_eq!(first_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(second_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(third_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[actix_rt::test]
+    async fn test_failing_source_is_skipped_but_taints_a_miss() {
+        let (broken, _) = MockSource::boxed("broken", Err("connection refused".to_string()));
+        let (backup, _) = MockSource::boxed("backup", Ok(Some(json!({ "product_name": "Soap" }))));
+        let found = SourceChain::new(vec![broken, backup]).lookup("1").await;
+        assert!(matches!(found, ChainLookup::Found { source: "backup", .. }));
+
+        let (broken, _) = MockSource::boxed("broken", Err("timeout".to_string()));
+        let (empty, _) = MockSource::boxed("empty", Ok(None));
+        assert_eq!(SourceChain::new(vec![broken, empty]).lookup("1").await, ChainLookup::Failed);
+
+        let (empty, _) = MockSource::boxed("empty", Ok(None));
+        assert_eq!(SourceChain::new(vec![empty]).lookup("1").await, ChainLookup::NotFound);
+    }
+
+    #[test]
+    fn test_chain_from_names() {
+        let config = Config::default();
+        assert_eq!(SourceChain::from_names("openfoodfacts", &config).unwrap().names(), vec!["openfoodfacts"]);
+        assert_eq!(SourceChain::from_names(" OpenFoodFacts , ", &config).unwrap().names(), vec!["openfoodfacts"]);
+        assert!(SourceChain::from_names("openfoodfacts,beautyfacts", &config).is_err());
+        assert!(SourceChain::from_names(" , ", &config).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_openfoodfacts_source_against_mock_server() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/0737628064502"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(crate::fixtures::off_response("full")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/0000000000000"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(crate::fixtures::off_response("not_found")))
+            .mount(&server)
+            .await;
+
+        let source = OpenFoodFactsSource::new(server.uri());
+        let product = source.lookup("0737628064502").await.unwrap().expect("mock has the product");
+        assert_eq!(product, crate::fixtures::off_product("full"));
+        assert_eq!(source.lookup("0000000000000").await, Ok(None));
+    }
+
+    #[actix_rt::test]
+    async fn test_configured_base_url_is_used_for_lookups() {
+        use wiremock::matchers::{basic_auth, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/0737628064502"))
+            .and(basic_auth("off", "off"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(crate::fixtures::off_response("full")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Credentials in the base URL, as for OFF's staging server, are sent as basic auth
+        let base_url = server.uri().replacen("://", "://off:off@", 1);
+        let (config, _) = Config::load(|name| (name == "OFF_BASE_URL").then(|| base_url.clone()));
+        assert_eq!(config.off.base_url, base_url);
+
+        let found = SourceChain::from_config(&config).lookup("0737628064502").await;
+        assert!(matches!(found, ChainLookup::Found { source: "openfoodfacts", .. }), "{:?}", found);
+    }
+
+    #[actix_rt::test]
+    async fn test_simultaneous_lookups_share_one_upstream_call() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/0737628064502"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(crate::fixtures::off_response("full"))
+                    .set_delay(std::time::Duration::from_millis(200)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let chain = SourceChain::new(vec![Box::new(OpenFoodFactsSource::new(server.uri()))]);
+        let (first, second, third) = tokio::join!(
+            chain.lookup("0737628064502"),
+            chain.lookup("0737628064502"),
+            chain.lookup("0737628064502"),
+        );
+
+        assert!(matches!(first, ChainLookup::Found { source: "openfoodfacts", .. }));
+        assert_eq!(first, second);
+        assert_eq!(first, third);
+        assert!(chain.in_flight.lock().unwrap().is_empty());
+        server.verify().await;
+    }
+
+    #[actix_rt::test]
+    async fn test_abandoned_lookups_leave_nothing_in_flight() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v2/product/0737628064502"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(crate::fixtures::off_response("full"))
+                    .set_delay(std::time::Duration::from_millis(200)),
+            )
+            .mount(&server)
+            .await;
+        let chain = SourceChain::new(vec![Box::new(OpenFoodFactsSource::new(server.uri()))]);
+        let timeout = std::time::Duration::from_millis(50);
+
+        // A lone lookup dropped midway takes its walk with it
+        assert!(tokio::time::timeout(timeout, chain.lookup("0737628064502")).await.is_err());
+        assert!(chain.in_flight.lock().unwrap().is_empty());
+
+        // One of two dropped leaves the walk to the other, which retires it when done
+        let (abandoned, finished) = tokio::join!(
+            tokio::time::timeout(timeout, chain.lookup("0737628064502")),
+            chain.lookup("0737628064502"),
+        );
+        assert!(abandoned.is_err());
+        assert!(matches!(finished, ChainLookup::Found { source: "openfoodfacts", .. }));
+        assert!(chain.in_flight.lock().unwrap().is_empty());
+
+        // Both dropped: the second to go retires it
+        let (first, second) = tokio::join!(
+            tokio::time::timeout(timeout, chain.lookup("0737628064502")),
+            tokio::time::timeout(timeout * 2, chain.lookup("0737628064502")),
+        );
+        assert!(first.is_err() && second.is_err());
+        assert!(chain.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_lookup_after_a_finished_one_asks_again() {
+        let (source, calls) = MockSource::boxed("only", Ok(None));
+        let chain = SourceChain::new(vec![source]);
+
+        assert_eq!(chain.lookup("1").await, ChainLookup::NotFound);
+        assert_eq!(chain.lookup("1").await, ChainLookup::NotFound);
+        assert_eq!(chain.lookup("2").await, ChainLookup::NotFound);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
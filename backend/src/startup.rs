@@ -0,0 +1,76 @@
+use diesel::prelude::*;
+
+use crate::config::Config;
+
+/// Confirm the database is reachable with a `SELECT 1`
+pub fn check_database(database_url: &str, timeout_secs: u64) -> Result<(), String> {
+    // Bound the connect so an unroutable host fails fast instead of hanging startup
+    let url = with_connect_timeout(database_url, timeout_secs);
+
+    let mut conn = PgConnection::establish(&url).map_err(|e| format!("Cannot connect to database: {}", e))?;
+    diesel::sql_query("SELECT 1")
+        .execute(&mut conn)
+        .map_err(|e| format!("Database did not answer SELECT 1: {}", e))?;
+
+    Ok(())
+}
+
+fn with_connect_timeout(database_url: &str, timeout_secs: u64) -> String {
+    if database_url.contains("connect_timeout=") {
+        return database_url.to_string();
+    }
+    let separator = if database_url.contains('?') { '&' } else { '?' };
+    format!("{}{}connect_timeout={}", database_url, separator, timeout_secs)
+}
+
+/// Check the loaded config's `problems` (see [`Config::load`]) and the database before the
+/// server binds its port. Exits with status 1 on any problem unless ALLOW_DEGRADED_START
+/// is set, in which case problems are logged.
+pub fn ensure_ready(config: &Config, mut problems: Vec<String>) {
+    // Only probe the database once its URL is known to be usable
+    if problems.is_empty()
+        && let Some(database_url) = &config.database_url
+        && let Err(e) = check_database(database_url, config.db_startup_check_timeout_secs)
+    {
+        problems.push(e);
+    }
+
+    if problems.is_empty() {
+        log::info!("Startup checks passed");
+        return;
+    }
+
+    if config.allow_degraded_start {
+        for problem in &problems {
+            log::warn!("Startup check failed (continuing, ALLOW_DEGRADED_START is set): {}", problem);
+        }
+        return;
+    }
+
+    for problem in &problems {
+        log::error!("Startup check failed: {}", problem);
+    }
+    eprintln!("Refusing to start: {} configuration problem(s), see log above", problems.len());
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_timeout_is_appended_once() {
+        assert_eq!(
+            with_connect_timeout("postgres://localhost/spoils", 5),
+            "postgres://localhost/spoils?connect_timeout=5"
+        );
+        assert_eq!(
+            with_connect_timeout("postgres://localhost/spoils?sslmode=require", 5),
+            "postgres://localhost/spoils?sslmode=require&connect_timeout=5"
+        );
+        assert_eq!(
+            with_connect_timeout("postgres://localhost/spoils?connect_timeout=2", 5),
+            "postgres://localhost/spoils?connect_timeout=2"
+        );
+    }
+}
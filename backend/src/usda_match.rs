@@ -0,0 +1,247 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::nutrition::IngredientMacros;
+
+/// Share of recall in the score. Descriptions carry qualifiers ("Salt, table"), so words of
+/// the ingredient missing from the description count far more than extra description words.
+const RECALL_WEIGHT: f64 = 0.8;
+
+/// Words of a name or description, lowercased and crudely singularized so "tomatoes" meets
+/// "tomato". Numbers and one-letter fragments carry no meaning here and are dropped.
+fn words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1 && !w.chars().all(|c| c.is_ascii_digit()))
+        .map(|w| singular(&w.to_lowercase()))
+        .collect();
+    words.dedup();
+    words
+}
+
+fn singular(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies").filter(|stem| stem.len() > 1) {
+        format!("{}y", stem)
+    } else if let Some(stem) = word.strip_suffix("oes") {
+        format!("{}o", stem)
+    } else if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") {
+        word[..word.len() - 1].to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+/// How well a USDA food's description matches the ingredient name searched for, from 0
+/// (no word in common) to 1 (same words). Mostly the share of the ingredient's words the
+/// description contains, with a smaller part for how few other words it has.
+pub fn confidence(ingredient: &str, food: &Value) -> f64 {
+    let description = food.get("description").and_then(|d| d.as_str()).unwrap_or("");
+    let wanted = words(ingredient);
+    let offered = words(description);
+
+    if wanted.is_empty() || offered.is_empty() {
+        return 0.0;
+    }
+
+    let shared = wanted.iter().filter(|w| offered.contains(w)).count() as f64;
+    let recall = shared / wanted.len() as f64;
+    let precision = shared / offered.len() as f64;
+
+    RECALL_WEIGHT * recall + (1.0 - RECALL_WEIGHT) * precision
+}
+
+/// Added to the match score of reference foods (USDA's own analyses: `Foundation` and
+/// `SR Legacy`), so they beat a branded product whose description fits about as well
+const REFERENCE_FOOD_BONUS: f64 = 0.15;
+
+fn is_reference_food(food: &Value) -> bool {
+    matches!(food.get("dataType").and_then(|t| t.as_str()), Some("Foundation" | "SR Legacy"))
+}
+
+/// The food in a USDA search's `foods` that best fits the ingredient: the highest
+/// [`confidence`], plus [`REFERENCE_FOOD_BONUS`] for reference foods. USDA's ranking breaks
+/// ties. A query like "salt" often ranks branded snacks first.
+pub fn best_match<'a>(ingredient: &str, foods: &'a [Value]) -> Option<&'a Value> {
+    let score = |food: &Value| {
+        let bonus = if is_reference_food(food) { REFERENCE_FOOD_BONUS } else { 0.0 };
+        confidence(ingredient, food) + bonus
+    };
+
+    let mut best: Option<(&Value, f64)> = None;
+    for food in foods {
+        let food_score = score(food);
+        if best.is_none_or(|(_, best_score)| food_score > best_score) {
+            best = Some((food, food_score));
+        }
+    }
+    best.map(|(food, _)| food)
+}
+
+/// Per-gram protein, carbs, fat and fiber from a USDA food's per-100g `foodNutrients`,
+/// `None` when the food has no nutrient list
+pub fn per_gram_macros(food: &Value) -> Option<IngredientMacros> {
+    let nutrients = food.get("foodNutrients").and_then(|n| n.as_array())?;
+
+    let mut macros = IngredientMacros {
+        protein: None,
+        carbs: None,
+        fat: None,
+        fiber: None,
+    };
+
+    // USDA nutrient IDs (from FoodData Central)
+    // 1003 = Protein, 1005 = Carbs, 1004 = Fat, 1079 = Fiber
+    for nutrient in nutrients {
+        if let Some(nutrient_id) = nutrient.get("nutrientId").and_then(|id| id.as_i64())
+            && let Some(value) = nutrient.get("value").and_then(|v| v.as_f64())
+        {
+            // Convert from per 100g to per 1g
+            let value_per_gram = crate::nutrition::per_gram(value);
+
+            match nutrient_id {
+                1003 => macros.protein = Some(value_per_gram),
+                1005 => macros.carbs = Some(value_per_gram),
+                1004 => macros.fat = Some(value_per_gram),
+                1079 => macros.fiber = Some(value_per_gram),
+                _ => {}
+            }
+        }
+    }
+
+    Some(macros)
+}
+
+/// USDA nutrient ID of total trans fatty acids
+const TRANS_FAT_NUTRIENT_ID: i64 = 1257;
+
+/// Per-gram trans fat from a USDA food's per-100g `foodNutrients`, `None` when USDA
+/// doesn't list it. Kept apart from [`per_gram_macros`], since an ingredient with only
+/// trans fat known still counts as having no macros.
+pub fn per_gram_trans_fat(food: &Value) -> Option<f32> {
+    food.get("foodNutrients")?.as_array()?.iter().find_map(|nutrient| {
+        let id = nutrient.get("nutrientId")?.as_i64()?;
+        let value = nutrient.get("value")?.as_f64()?;
+        (id == TRANS_FAT_NUTRIENT_ID).then(|| crate::nutrition::per_gram(value))
+    })
+}
+
+/// A USDA food offered to a curator for an ingredient awaiting review. The macro fields
+/// are named as on the ingredient, so a `PATCH` can take some of them and correct others.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct UsdaCandidate {
+    pub fdc_id: Option<i64>,
+    pub description: Option<String>,
+    pub data_type: Option<String>,
+    pub brand_owner: Option<String>,
+    /// How well `description` matches the ingredient name, see [`confidence`]
+    pub confidence: f64,
+    pub gram_protein_per_gram: Option<f32>,
+    pub gram_carbs_per_gram: Option<f32>,
+    pub gram_fat_per_gram: Option<f32>,
+    pub gram_fiber_per_gram: Option<f32>,
+    pub gram_trans_fat_per_gram: Option<f32>,
+}
+
+impl UsdaCandidate {
+    pub fn new(ingredient: &str, food: &Value) -> Self {
+        let text = |key: &str| food.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let macros = per_gram_macros(food);
+
+        UsdaCandidate {
+            fdc_id: food.get("fdcId").and_then(|id| id.as_i64()),
+            description: text("description"),
+            data_type: text("dataType"),
+            brand_owner: text("brandOwner"),
+            confidence: confidence(ingredient, food),
+            gram_protein_per_gram: macros.and_then(|m| m.protein),
+            gram_carbs_per_gram: macros.and_then(|m| m.carbs),
+            gram_fat_per_gram: macros.and_then(|m| m.fat),
+            gram_fiber_per_gram: macros.and_then(|m| m.fiber),
+            gram_trans_fat_per_gram: per_gram_trans_fat(food),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_MIN_USDA_MATCH_CONFIDENCE;
+    use crate::fixtures;
+    use serde_json::json;
+
+    fn food(description: &str) -> Value {
+        json!({ "description": description })
+    }
+
+    #[test]
+    fn test_good_matches_clear_the_default_threshold() {
+        let threshold = DEFAULT_MIN_USDA_MATCH_CONFIDENCE;
+
+        assert!(confidence("salt", &fixtures::usda_food("foundation")) >= threshold);
+        assert!(confidence("peanut butter", &fixtures::usda_food("branded")) >= threshold);
+        assert!(confidence("Tomatoes", &food("Tomatoes, red, ripe, raw")) >= threshold);
+        assert!(confidence("blueberries", &food("Blueberry, raw")) >= threshold);
+        assert!(confidence("Whole Milk", &food("milk, whole")) > 0.999);
+    }
+
+    #[test]
+    fn test_weak_matches_fall_below_the_default_threshold() {
+        let threshold = DEFAULT_MIN_USDA_MATCH_CONFIDENCE;
+
+        assert!(confidence("dragon fruit", &food("Fruit cocktail, canned, heavy syrup")) < threshold);
+        assert!(confidence("xanthan gum", &food("Gum, chewing")) < threshold);
+        assert_eq!(confidence("spirulina", &food("Seaweed, kelp, raw")), 0.0);
+        assert_eq!(confidence("salt", &json!({ "fdcId": 1 })), 0.0);
+    }
+
+    #[test]
+    fn test_best_match_prefers_reference_foods_over_branded_first_results() {
+        let search = fixtures::usda_search("branded_first");
+        let foods = search["foods"].as_array().unwrap();
+        assert_eq!(foods[0]["dataType"], "Branded");
+
+        // Beats the chips USDA ranked first, and the branded "SALT" with the exact name
+        let best = best_match("salt", foods).unwrap();
+        assert_eq!(best["fdcId"], 2346404);
+        assert_eq!(best["dataType"], "Foundation");
+    }
+
+    #[test]
+    fn test_best_match_goes_by_description_then_usda_rank() {
+        let foods = [
+            json!({ "fdcId": 1, "description": "Butter, salted", "dataType": "SR Legacy" }),
+            json!({ "fdcId": 2, "description": "Peanut butter, smooth style", "dataType": "SR Legacy" }),
+            json!({ "fdcId": 3, "description": "Peanut butter, chunk style", "dataType": "SR Legacy" }),
+        ];
+        assert_eq!(best_match("peanut butter", &foods).unwrap()["fdcId"], 2);
+
+        // A branded food still wins when it's the only one that fits
+        let foods = [
+            json!({ "fdcId": 1, "description": "Seaweed, kelp, raw", "dataType": "Foundation" }),
+            json!({ "fdcId": 2, "description": "SPIRULINA POWDER", "dataType": "Branded" }),
+        ];
+        assert_eq!(best_match("spirulina", &foods).unwrap()["fdcId"], 2);
+
+        assert_eq!(best_match("salt", &[]), None);
+    }
+
+    #[test]
+    fn test_candidate_summarizes_the_food() {
+        let candidate = UsdaCandidate::new("peanut butter", &fixtures::usda_food("branded"));
+
+        assert_eq!(candidate.fdc_id, Some(2099245));
+        assert_eq!(candidate.description.as_deref(), Some("CREAMY PEANUT BUTTER"));
+        assert_eq!(candidate.data_type.as_deref(), Some("Branded"));
+        assert_eq!(candidate.gram_protein_per_gram, Some(0.219));
+        assert_eq!(candidate.gram_fiber_per_gram, Some(0.062));
+        assert_eq!(candidate.gram_trans_fat_per_gram, None);
+        assert!(candidate.confidence > DEFAULT_MIN_USDA_MATCH_CONFIDENCE);
+
+        let shortening = UsdaCandidate::new("shortening", &fixtures::usda_food("trans_fat"));
+        assert!((shortening.gram_trans_fat_per_gram.unwrap() - 0.0417).abs() < 1e-6);
+
+        let bare = UsdaCandidate::new("salt", &json!({ "description": "Salt, table" }));
+        assert_eq!(bare.fdc_id, None);
+        assert_eq!(bare.gram_protein_per_gram, None);
+    }
+}
@@ -1,32 +1,70 @@
-use fang::asynk::async_queue::AsyncQueue;
-use fang::asynk::async_worker_pool::AsyncWorkerPool;
-use fang::NoTls;
+use fang::asynk::async_queue::AsyncQueueable;
 
-pub async fn start_worker_pool() {
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+use crate::jobs::{CleanupJob, FailureAlertJob, UsdaBackfillJob};
+use std::time::Duration;
 
-    log::info!("Connecting to database for job queue: {}", database_url);
+use crate::queue::{self, JobQueue, QueueConnectError, SharedQueue, WORKERS, WORKER_POOLS};
+
+/// First wait between reconnect attempts after a degraded start, doubling up to [`MAX_RECONNECT_DELAY`]
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(300);
 
-    // Create async queue
-    let max_pool_size: u32 = 3;
-    let mut queue = AsyncQueue::builder()
-        .uri(database_url)
-        .max_pool_size(max_pool_size)
-        .build();
+/// Connections in the shared queue's pool: one per worker, plus a few for handlers enqueueing
+const QUEUE_POOL_SIZE: u32 = WORKERS + 5;
+
+/// Connect the queue that every handler and worker enqueues through
+pub async fn connect_queue(database_url: &str) -> Result<JobQueue, QueueConnectError> {
+    log::info!("Connecting to database for job queue: {}", database_url);
 
-    queue.connect(NoTls).await.expect("Failed to connect to database for job queue");
+    let queue = queue::connect_queue(database_url, QUEUE_POOL_SIZE).await?;
 
     log::info!("Job queue connected successfully");
+    Ok(queue)
+}
+
+/// A queue that was never connected, for a degraded start: every enqueue fails with
+/// `NotConnectedError` and the handlers answer 500 instead of the server refusing to start
+pub fn disconnected_queue(database_url: &str) -> JobQueue {
+    queue::disconnected_queue(database_url, QUEUE_POOL_SIZE)
+}
 
-    // Start worker pool with 5 workers
-    let mut pool: AsyncWorkerPool<AsyncQueue<NoTls>> = AsyncWorkerPool::builder()
-        .number_of_workers(5_u32)
-        .queue(queue.clone())
-        .build();
+/// Retry connecting the queue after a degraded start. Once it connects, the handlers'
+/// `shared` queue is swapped for it and the worker pool starts on it.
+pub async fn reconnect(database_url: String, shared: SharedQueue) {
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        tokio::time::sleep(delay).await;
+        match connect_queue(&database_url).await {
+            Ok(queue) => {
+                shared.replace(queue.clone());
+                start_worker_pool(queue).await;
+                return;
+            }
+            Err(e) => {
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                log::warn!("Job queue still unavailable, retrying in {:?}: {}", delay, e);
+            }
+        }
+    }
+}
 
-    log::info!("Starting worker pool with 5 workers");
+/// Schedule the recurring jobs and start the workers of every task type, see [`WORKER_POOLS`]
+pub async fn start_worker_pool(mut queue: JobQueue) {
+    // Schedule recurring jobs
+    if let Err(e) = queue.schedule_task(&CleanupJob { recurring: true }).await {
+        log::error!("Failed to schedule cleanup job: {:?}", e);
+    }
+    if let Err(e) = queue.schedule_task(&FailureAlertJob {}).await {
+        log::error!("Failed to schedule failure alert job: {:?}", e);
+    }
+    if let Err(e) = queue.schedule_task(&UsdaBackfillJob { recurring: true }).await {
+        log::error!("Failed to schedule USDA backfill job: {:?}", e);
+    }
 
-    pool.start().await;
+    for (task_type, workers) in WORKER_POOLS {
+        queue::worker_pool(queue.clone(), task_type, workers).start().await;
+    }
 
-    log::info!("Worker pool started successfully");
+    log::info!("Worker pool started successfully with {} workers", WORKERS);
 }
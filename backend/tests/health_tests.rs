@@ -1,5 +1,5 @@
 use actix_web::{test, App};
-use backend::{health, hello};
+use backend::{health, hello, ping};
 
 #[actix_rt::test]
 async fn test_health_endpoint() {
@@ -15,10 +15,9 @@ async fn test_health_endpoint() {
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
 
-    let body = test::read_body(resp).await;
-    let body_str = std::str::from_utf8(&body).unwrap();
-    assert!(body_str.contains("ok"));
-    assert!(body_str.contains("Spoils API is running"));
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["status"], "ok");
+    assert_eq!(body["data"]["message"], "Spoils API is running");
 }
 
 #[actix_rt::test]
@@ -35,7 +34,25 @@ async fn test_hello_endpoint() {
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
 
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["data"]["message"], "Hello from Spoils API!");
+}
+
+#[actix_rt::test]
+async fn test_ping_endpoint() {
+    let app = test::init_service(
+        App::new()
+            .service(ping)
+    ).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/ping")
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get("content-type").unwrap(), "text/plain");
+
     let body = test::read_body(resp).await;
-    let body_str = std::str::from_utf8(&body).unwrap();
-    assert!(body_str.contains("Hello from Spoils API"));
+    assert_eq!(&body[..], b"pong");
 }
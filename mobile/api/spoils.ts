@@ -13,38 +13,45 @@ export interface Product {
   categories?: string;
   quantity?: string;
   image_url?: string;
+  images?: { front?: string; ingredients?: string; nutrition?: string };
   nutriscore_grade?: string;
   nova_group?: number;
   ecoscore_grade?: string;
   ingredients_text?: string;
+  ocr_ingredients_text?: string;
   allergens?: string;
   full_response: any;
   created_at: string;
   updated_at: string;
 }
 
+/** Success bodies are `{ data }`, failures `{ error: { message } }` */
+interface ApiErrorBody {
+  error: { message: string; [context: string]: unknown };
+}
+
+async function unwrap<T>(response: Response, fallback: string): Promise<T> {
+  const body = await response.json().catch(() => null);
+  if (!response.ok) {
+    const message = (body as ApiErrorBody | null)?.error?.message;
+    throw new Error(message ? `${fallback}: ${message}` : fallback);
+  }
+  return (body as { data: T }).data;
+}
+
 export const api = {
   async health(): Promise<HealthResponse> {
     const response = await fetch(`${API_URL}/health`);
-    if (!response.ok) {
-      throw new Error('Health check failed');
-    }
-    return response.json();
+    return unwrap(response, 'Health check failed');
   },
 
   async hello(): Promise<{ message: string }> {
     const response = await fetch(`${API_URL}/api/hello`);
-    if (!response.ok) {
-      throw new Error('API call failed');
-    }
-    return response.json();
+    return unwrap(response, 'API call failed');
   },
 
   async getProduct(barcode: string): Promise<Product> {
     const response = await fetch(`${API_URL}/api/products/${barcode}`);
-    if (!response.ok) {
-      throw new Error(`Product lookup failed: ${response.statusText}`);
-    }
-    return response.json();
+    return unwrap(response, 'Product lookup failed');
   },
 };